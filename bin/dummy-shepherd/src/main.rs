@@ -28,53 +28,100 @@
 
 use anyhow::Context;
 use rand::prelude::*;
-use std::{
-    collections::{HashMap, HashSet},
-    io::{self, prelude::*},
-};
+use std::collections::{HashMap, HashSet};
 
 use shepherd_lib::{
     feed::Feed,
-    shepherd::{ShepherdEvent, SimulationEvent},
-    simulation::Epoch,
+    ids::{ItemId, SheepId},
+    sdk::{self, Handler},
+    shepherd::{Capability, SimulationEvent},
+    simulation::{Epoch, RunConfig},
 };
 
-fn main() -> anyhow::Result<()> {
-    let mut items = HashSet::new();
-    let mut sheep_seen = HashMap::new();
-    let mut stdout = io::stdout();
+/// A shepherd which shows each sheep random items it has not been shown yet
+///
+/// When feeds are built in two stages, it retrieves random items the sheep
+/// has not been shown yet, and ranks them in the order they were given. Items
+/// dropped for being beyond its access budget are forgotten until it is told
+/// about them again
+#[derive(Default)]
+struct Dummy {
+    feed_size: Option<usize>,
+    items: HashSet<ItemId>,
+    sheep_seen: HashMap<SheepId, HashSet<ItemId>>,
+}
+
+impl Handler for Dummy {
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![
+            Capability::ResponseBatches,
+            Capability::FeedBatches,
+            Capability::Heartbeats,
+            Capability::TwoStageFeeds,
+        ]
+    }
 
-    for event in serde_json::Deserializer::from_reader(io::stdin())
-        .into_iter::<SimulationEvent>()
-    {
-        let event = event
-            .context("Unable to retrieve an event from standard input")?;
+    fn handle(&mut self, event: SimulationEvent) -> anyhow::Result<()> {
         match event {
+            SimulationEvent::RunConfig(RunConfig { feed_size, .. }) => {
+                self.feed_size = Some(feed_size);
+            }
             SimulationEvent::BeginEpoch {
-                data:
-                    Epoch {
-                        items: new_items, ..
-                    },
+                data: Epoch { items, .. },
                 ..
-            } => items.extend(new_items.into_iter().map(|(id, _)| id)),
-            SimulationEvent::FeedRequest { sheep } => {
-                let seen =
-                    sheep_seen.entry(sheep).or_insert_with(HashSet::new);
-                let chosen = items
-                    .difference(seen)
-                    .copied()
-                    .choose_multiple(&mut rand::thread_rng(), 10);
-                seen.extend(chosen.iter().copied());
-                serde_json::to_writer(
-                    &mut stdout,
-                    &ShepherdEvent::Feed(Feed(chosen)),
-                )
-                .context("Unable to write an event to stdout")?;
-                stdout.flush().context("Unable to flush stdout")?;
+            } => self.items.extend(items.into_iter().map(|(id, _)| id)),
+            SimulationEvent::BudgetExceeded { items, .. } => {
+                for item in items {
+                    self.items.remove(&item);
+                }
             }
             _ => (),
         }
+
+        Ok(())
     }
 
-    Ok(())
+    fn build_feed(&mut self, sheep: SheepId) -> anyhow::Result<Feed> {
+        let seen = self.sheep_seen.entry(sheep).or_default();
+        let chosen = self.items.difference(seen).copied().choose_multiple(
+            &mut rand::thread_rng(),
+            self.feed_size.context(
+                "A feed was requested before the run configuration was received",
+            )?,
+        );
+        seen.extend(chosen.iter().copied());
+        Ok(Feed(chosen))
+    }
+
+    fn retrieve_candidates(
+        &mut self,
+        sheep: SheepId,
+        n_candidates: usize,
+    ) -> anyhow::Result<Vec<ItemId>> {
+        let seen = self.sheep_seen.entry(sheep).or_default();
+        Ok(self
+            .items
+            .difference(seen)
+            .copied()
+            .choose_multiple(&mut rand::thread_rng(), n_candidates))
+    }
+
+    fn rank_candidates(
+        &mut self,
+        sheep: SheepId,
+        mut candidates: Vec<ItemId>,
+    ) -> anyhow::Result<Feed> {
+        candidates.truncate(self.feed_size.context(
+            "A feed was requested before the run configuration was received",
+        )?);
+        self.sheep_seen
+            .entry(sheep)
+            .or_default()
+            .extend(candidates.iter().copied());
+        Ok(Feed(candidates))
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    sdk::run(Dummy::default())
 }
@@ -1,26 +1,199 @@
-use anyhow::Context;
+use anyhow::{anyhow, bail, ensure, Context};
 use lexopt::prelude::*;
-use std::{env, path::PathBuf, process};
+#[cfg(feature = "wasm")]
+use std::path::Path;
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    mem,
+    path::PathBuf,
+    process,
+    time::Duration,
+};
 
-use shepherd_lib::shepherd::Shepherd;
+use crate::{assertions::Assertion, leaderboard};
+use shepherd_lib::{
+    baseline::Baseline,
+    collections::HashSet,
+    graph::{EdgeSemantics, GraphGenerator},
+    limits::ResourceLimits,
+    sheep::{DistanceOracle, Noise},
+    shepherd::{Heartbeat, Shepherd},
+    simulation::{Degradation, FallbackFeed, FeedValidation, Retention},
+};
+
+/// The command given on the command line
+pub enum Command {
+    /// Run a simulation
+    Run(Box<Args>),
+
+    /// Compare the metrics of two runs
+    CheckRegression(RegressionArgs),
+
+    /// Print the JSON Schema of the events exchanged with shepherds
+    Schema(SchemaArgs),
+
+    /// Replay a transcript into a shepherd
+    Replay(ReplayArgs),
+
+    /// Run the same simulation from several seeds and aggregate the results
+    Experiment(ExperimentArgs),
+}
+
+pub struct RegressionArgs {
+    pub baseline: PathBuf,
+    pub candidate: PathBuf,
+    pub threshold: f64,
+    pub tolerance: f64,
+}
+
+pub struct SchemaArgs {
+    pub events: Events,
+}
+
+/// The direction of the events whose schema is printed
+pub enum Events {
+    /// Events sent from the simulation to shepherds
+    Simulation,
+
+    /// Events sent from shepherds to the simulation
+    Shepherd,
+
+    /// Events in either direction, as they appear in a transcript
+    Transcript,
+}
+
+pub struct ReplayArgs {
+    pub transcript: PathBuf,
+    pub output: Option<PathBuf>,
+    pub program: OsString,
+    pub args: Vec<OsString>,
+}
+
+pub struct ExperimentArgs {
+    pub n_seeds: u64,
+    pub first_seed: u64,
+    pub n_jobs: usize,
+    pub directory: Option<PathBuf>,
+    pub args: Vec<OsString>,
+}
 
 #[derive(Default)]
-pub struct Args<'de> {
-    pub n_epochs: usize,
-    pub shepherds: Vec<Shepherd<'de>>,
+pub struct Args {
+    pub teaching: bool,
+    pub tui: bool,
+    pub stress: Option<usize>,
+    pub seed: Option<u64>,
+    pub baselines: Vec<Baseline>,
+    pub profile: Option<PathBuf>,
+    pub n_epochs: Option<usize>,
+    pub feed_size: Option<usize>,
+    pub shepherds: Vec<Shepherd>,
     pub database_file: Option<PathBuf>,
+    pub noisy_sheep_fraction: f64,
+    pub noise: Noise,
+    pub hidden_tag_fraction: f64,
+    pub graph_generator: GraphGenerator,
+    pub edge_semantics: EdgeSemantics,
+    pub parent_tag_probability: f64,
+    pub group_merge_probability: f64,
+    pub group_split_probability: f64,
+    pub serendipity_probability: f64,
+    pub fatigue_rate: f64,
+    pub interest_decay: f64,
+    pub temperature_bounds: Option<(f64, f64)>,
+    pub distance_oracle: DistanceOracle,
+    pub reinforcement_probability: f64,
+    pub explain_responses: bool,
+    pub compute_budget: Option<Duration>,
+    pub output_quota: Option<usize>,
+    pub request_timeout: Option<Duration>,
+    pub heartbeat: Option<Heartbeat>,
+    pub timeout_fallback: FallbackFeed,
+    pub feed_validation: FeedValidation,
+    pub abandon_on_timeout: bool,
+    pub n_candidates: Option<usize>,
+    pub item_access_budget: Option<usize>,
+    pub max_nodes: Option<usize>,
+    pub max_edges: Option<usize>,
+    pub epoch_summaries: bool,
+    pub ranking_metrics: bool,
+    pub oracle_regret: bool,
+    pub diversity_metrics: bool,
+    pub drift_metrics: bool,
+    pub cold_start_metrics: bool,
+    pub graph_diffs: bool,
+    pub tag_group_snapshots: bool,
+    pub under_delivery_penalty: bool,
+    pub under_delivery_notices: bool,
+    pub response_batch_interval: Option<usize>,
+    pub degradations: Vec<Degradation>,
+    pub retention: Option<Retention>,
+    pub seed_graph_file: Option<PathBuf>,
+    #[cfg(feature = "movielens")]
+    pub movielens_directory: Option<PathBuf>,
+    pub graph_file: Option<PathBuf>,
+    pub metrics_file: Option<PathBuf>,
+    pub report_file: Option<PathBuf>,
+    pub leaderboard_metric: leaderboard::Metric,
+    pub affinity_prefix: Option<PathBuf>,
+    pub affinity_epochs: Vec<usize>,
+    pub assertions: Vec<Assertion>,
+    pub emit_metrics_stream: bool,
+    pub metrics_stream_file: Option<PathBuf>,
+    #[cfg(feature = "prometheus")]
+    pub prometheus_address: Option<String>,
+    pub anomaly_threshold: Option<f64>,
+    pub transcript_directory: Option<PathBuf>,
 }
 
 fn usage() {
     println!(
-        "usage: {} [-h|--help] [-n|--n-epochs=EPOCHS] [-d|--database=DATABASE_FILE] [shepherds...]",
-        env::args().next().as_deref().unwrap_or("shepherd")
+        "usage: {} [-h|--help] [--teaching] [--tui] [--stress=SHEPHERDS] [--seed=SEED] [--baseline=random|popular|recent,...] [--profile=TRACE_FILE] [-n|--n-epochs=EPOCHS] [-k|--feed-size=ITEMS] [-d|--database=DATABASE_FILE] [--noisy-sheep=FRACTION] [--noise=uniform|inverted] [--hidden-tags=FRACTION] [--generator=poisson|sbm:WITHIN:BETWEEN|pa:EDGES] [--edges=asymmetric|symmetric|undirected] [--parent-tags=PROBABILITY] [--group-merges=PROBABILITY] [--group-splits=PROBABILITY] [--serendipity=PROBABILITY] [--fatigue=RATE] [--interest-decay=RATE] [--temperature=MIN:MAX] [--landmarks=LANDMARKS:EPOCHS] [--reinforcement=PROBABILITY] [--explain] [--compute-budget=SECONDS] [--output-quota=BYTES] [--request-timeout=SECONDS] [--heartbeat=SECONDS:MISSES] [--timeout-fallback=empty|random] [--feed-validation=report|sanitize|strict] [--abandon-on-timeout] [--two-stage=CANDIDATES] [--item-budget=ITEMS] [--max-nodes=NODES] [--max-edges=EDGES] [--epoch-summaries] [--ranking-metrics] [--oracle-regret] [--diversity-metrics] [--drift-metrics] [--cold-start-metrics] [--graph-diffs] [--tag-group-snapshots] [--penalize-under-delivery] [--under-delivery-notices] [--response-batches=EPOCHS] [--degradation=FIRST:LAST:DROP:DELAY]... [--retention=SMOOTHING:THRESHOLD] [--graph=GRAPH_FILE] {}[--export-graph=GRAPH_FILE] [--metrics-out=METRICS_FILE] [--report=REPORT_FILE] [--leaderboard=METRIC] [--export-affinities=PREFIX] [--affinity-epochs=EPOCH,...] [--assert=METRIC:SHEPHERD:MIN:MAX]... [--emit-metrics-stream] [--metrics-stream=FILE|fd:N] {}[--anomaly-sigma=SIGMA] [--transcripts=DIRECTORY] [[--shepherd-env=KEY=VALUE]... [--shepherd-dir=DIRECTORY] [--shepherd-cpu-time=SECONDS] [--shepherd-memory=BYTES] '[NAME=]SHEPHERD [ARGS...]'...]",
+        env::args().next().as_deref().unwrap_or("shepherd"),
+        if cfg!(feature = "movielens") {
+            "[--movielens=DIRECTORY] "
+        } else {
+            ""
+        },
+        if cfg!(feature = "prometheus") {
+            "[--prometheus=ADDRESS] "
+        } else {
+            ""
+        }
+    );
+    println!(
+        "       {} check-regression --baseline=DATABASE_FILE --candidate=DATABASE_FILE [--threshold=FRACTION] [--tolerance=AMOUNT]",
+        env::args().next().as_deref().unwrap_or("shepherd"),
+    );
+    println!(
+        "       {} schema --events=simulation|shepherd|transcript",
+        env::args().next().as_deref().unwrap_or("shepherd"),
+    );
+    println!(
+        "       {} replay [--output=TRANSCRIPT_FILE] TRANSCRIPT_FILE [--] SHEPHERD [ARGS...]",
+        env::args().next().as_deref().unwrap_or("shepherd"),
+    );
+    println!(
+        "       {} experiment [--seeds=RUNS] [--first-seed=SEED] [--jobs=JOBS] [--directory=DIRECTORY] -- RUN_ARGS...",
+        env::args().next().as_deref().unwrap_or("shepherd"),
     );
 }
 
-pub fn parse_args<'de>() -> anyhow::Result<Args<'de>> {
-    let mut args = Args::default();
-    let mut parser = lexopt::Parser::from_env();
+fn parse_threshold(threshold: &str) -> anyhow::Result<f64> {
+    // thresholds may be given either as fractions or as percentages
+    if let Some(percentage) = threshold.strip_suffix('%') {
+        Ok(percentage.parse::<f64>()? / 100.0)
+    } else {
+        Ok(threshold.parse()?)
+    }
+}
+
+fn parse_regression_args(
+    mut parser: lexopt::Parser,
+) -> anyhow::Result<RegressionArgs> {
+    let (mut baseline, mut candidate, mut threshold, mut tolerance) =
+        (None, None, 0.0, 0.0);
     while let Some(arg) = parser.next()? {
         match arg {
             Short('h') | Long("help") => {
@@ -28,12 +201,674 @@ pub fn parse_args<'de>() -> anyhow::Result<Args<'de>> {
                 #[allow(clippy::exit)]
                 process::exit(0);
             }
-            Short('n') | Long("n-epochs") => {
-                args.n_epochs = parser
+            Long("baseline") => {
+                baseline = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --baseline")?
+                        .into(),
+                );
+            }
+            Long("candidate") => {
+                candidate = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --candidate")?
+                        .into(),
+                );
+            }
+            Long("threshold") => {
+                threshold = parse_threshold(
+                    &parser
+                        .value()
+                        .context("No argument given to --threshold")?
+                        .string()
+                        .context("Invalid argument to --threshold")?,
+                )
+                .context("Invalid argument to --threshold")?;
+            }
+            Long("tolerance") => {
+                tolerance = parser
                     .value()
-                    .context("No argument given to -n or --n-epochs")?
+                    .context("No argument given to --tolerance")?
+                    .string()
+                    .context("Invalid argument to --tolerance")?
+                    .parse::<f64>()
+                    .context("Invalid argument to --tolerance")?;
+                anyhow::ensure!(
+                    tolerance >= 0.0,
+                    "The tolerance given to --tolerance is negative"
+                );
+            }
+            a => {
+                println!("unknown argument: {:?}", a);
+                usage();
+                #[allow(clippy::exit)]
+                process::exit(1);
+            }
+        }
+    }
+
+    Ok(RegressionArgs {
+        baseline: baseline.context("No baseline run was given")?,
+        candidate: candidate.context("No candidate run was given")?,
+        threshold,
+        tolerance,
+    })
+}
+
+fn parse_schema_args(
+    mut parser: lexopt::Parser,
+) -> anyhow::Result<SchemaArgs> {
+    let mut events = None;
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Short('h') | Long("help") => {
+                usage();
+                #[allow(clippy::exit)]
+                process::exit(0);
+            }
+            Long("events") => {
+                events = Some(parse_events(
+                    &parser
+                        .value()
+                        .context("No argument given to --events")?
+                        .string()
+                        .context("Invalid argument to --events")?,
+                )?);
+            }
+            a => {
+                println!("unknown argument: {:?}", a);
+                usage();
+                #[allow(clippy::exit)]
+                process::exit(1);
+            }
+        }
+    }
+
+    Ok(SchemaArgs {
+        events: events.context("No direction of events was given")?,
+    })
+}
+
+fn parse_replay_args(
+    mut parser: lexopt::Parser,
+) -> anyhow::Result<ReplayArgs> {
+    let (mut transcript, mut output, mut command) = (None, None, None);
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Short('h') | Long("help") => {
+                usage();
+                #[allow(clippy::exit)]
+                process::exit(0);
+            }
+            Long("output") => {
+                output = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --output")?
+                        .into(),
+                );
+            }
+            Value(value) if transcript.is_none() => {
+                transcript = Some(value.into());
+            }
+            Value(program) => {
+                // everything after the shepherd belongs to its own command
+                // line, so it is never parsed as an option
+                command = Some((program, parser.raw_args()?.collect()));
+                break;
+            }
+            a => {
+                println!("unknown argument: {:?}", a);
+                usage();
+                #[allow(clippy::exit)]
+                process::exit(1);
+            }
+        }
+    }
+
+    let (program, args) = command
+        .context("No shepherd to replay the transcript into was given")?;
+    Ok(ReplayArgs {
+        transcript: transcript.context("No transcript was given")?,
+        output,
+        program,
+        args,
+    })
+}
+
+fn parse_experiment_args(
+    mut parser: lexopt::Parser,
+) -> anyhow::Result<ExperimentArgs> {
+    let mut args = ExperimentArgs {
+        n_seeds: 10,
+        first_seed: 0,
+        n_jobs: 1,
+        directory: None,
+        args: Vec::new(),
+    };
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Short('h') | Long("help") => {
+                usage();
+                #[allow(clippy::exit)]
+                process::exit(0);
+            }
+            Long("seeds") => {
+                args.n_seeds = parser
+                    .value()
+                    .context("No argument given to --seeds")?
+                    .parse()
+                    .context("Invalid argument to --seeds")?;
+            }
+            Long("first-seed") => {
+                args.first_seed = parser
+                    .value()
+                    .context("No argument given to --first-seed")?
+                    .parse()
+                    .context("Invalid argument to --first-seed")?;
+            }
+            Long("jobs") => {
+                args.n_jobs = parser
+                    .value()
+                    .context("No argument given to --jobs")?
                     .parse()
-                    .context("Invalid argument to -n or --n-epochs")?;
+                    .context("Invalid argument to --jobs")?;
+            }
+            Long("directory") => {
+                args.directory = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --directory")?
+                        .into(),
+                );
+            }
+            Value(value) => {
+                // everything after the separator belongs to the command line
+                // of each run, so it is never parsed as an option
+                args.args.push(value);
+                args.args.extend(parser.raw_args()?);
+                break;
+            }
+            a => {
+                println!("unknown argument: {:?}", a);
+                usage();
+                #[allow(clippy::exit)]
+                process::exit(1);
+            }
+        }
+    }
+
+    anyhow::ensure!(args.n_seeds > 0, "At least one seed must be run");
+    anyhow::ensure!(args.n_jobs > 0, "At least one job must be allowed");
+
+    // each run is given a seed and a database of its own, so neither may be
+    // picked for it
+    if let Some(arg) = args.args.iter().find(|arg| {
+        arg.to_str().is_some_and(|arg| {
+            ["--seed", "--database", "-d", "--teaching", "--stress"]
+                .iter()
+                .any(|option| arg.starts_with(option))
+        })
+    }) {
+        bail!("{:?} may not be given to the runs of an experiment", arg);
+    }
+
+    Ok(args)
+}
+
+fn parse_degradation(spec: &str) -> anyhow::Result<Degradation> {
+    let [first, last, drop, delay] = spec
+        .split(':')
+        .collect::<Vec<_>>()
+        .try_into()
+        .map_err(|_| {
+            anyhow!("A degradation must be of the form FIRST:LAST:DROP:DELAY")
+        })?;
+
+    Ok(Degradation {
+        epochs: (
+            first
+                .parse()
+                .context("Invalid first epoch of a degradation")?,
+            last.parse()
+                .context("Invalid last epoch of a degradation")?,
+        ),
+        drop_probability: drop
+            .parse()
+            .context("Invalid drop probability of a degradation")?,
+        delay_probability: delay
+            .parse()
+            .context("Invalid delay probability of a degradation")?,
+    })
+}
+
+fn parse_retention(spec: &str) -> anyhow::Result<Retention> {
+    let (smoothing, churn_threshold) = spec.split_once(':').context(
+        "A retention model must be of the form SMOOTHING:THRESHOLD",
+    )?;
+
+    Ok(Retention {
+        smoothing: smoothing
+            .parse()
+            .context("Invalid smoothing of a retention model")?,
+        churn_threshold: churn_threshold
+            .parse()
+            .context("Invalid churn threshold of a retention model")?,
+    })
+}
+
+fn parse_temperature_bounds(spec: &str) -> anyhow::Result<(f64, f64)> {
+    let (min, max) = spec
+        .split_once(':')
+        .context("Temperature bounds must be of the form MIN:MAX")?;
+    let bounds = (
+        min.parse().context("Invalid lower temperature bound")?,
+        max.parse().context("Invalid upper temperature bound")?,
+    );
+    ensure!(
+        bounds.0 > 0.0 && bounds.0 <= bounds.1,
+        "Temperature bounds must be positive and ordered"
+    );
+
+    Ok(bounds)
+}
+
+fn parse_generator(spec: &str) -> anyhow::Result<GraphGenerator> {
+    match spec.split(':').collect::<Vec<_>>()[..] {
+        ["poisson"] => Ok(GraphGenerator::PoissonGroups),
+        ["sbm", within, between] => Ok(GraphGenerator::StochasticBlock {
+            within_probability: within
+                .parse()
+                .context("Invalid within-group probability of a generator")?,
+            between_probability: between
+                .parse()
+                .context("Invalid between-group probability of a generator")?,
+        }),
+        ["pa", n_edges] => Ok(GraphGenerator::PreferentialAttachment {
+            n_edges: n_edges
+                .parse()
+                .context("Invalid number of edges of a generator")?,
+        }),
+        _ => Err(anyhow!(
+            "A generator must be of the form poisson, sbm:WITHIN:BETWEEN or pa:EDGES"
+        )),
+    }
+}
+
+fn parse_edge_semantics(spec: &str) -> anyhow::Result<EdgeSemantics> {
+    match spec {
+        "asymmetric" => Ok(EdgeSemantics::DirectedAsymmetric),
+        "symmetric" => Ok(EdgeSemantics::DirectedSymmetric),
+        "undirected" => Ok(EdgeSemantics::Undirected),
+        _ => Err(anyhow!(
+            "Edge semantics must be one of asymmetric, symmetric or undirected"
+        )),
+    }
+}
+
+fn parse_noise(spec: &str) -> anyhow::Result<Noise> {
+    match spec {
+        "uniform" => Ok(Noise::Uniform),
+        "inverted" => Ok(Noise::Inverted),
+        _ => Err(anyhow!("Noise must be either uniform or inverted")),
+    }
+}
+
+fn parse_events(spec: &str) -> anyhow::Result<Events> {
+    match spec {
+        "simulation" => Ok(Events::Simulation),
+        "shepherd" => Ok(Events::Shepherd),
+        "transcript" => Ok(Events::Transcript),
+        _ => Err(anyhow!(
+            "Events must be one of simulation, shepherd or transcript"
+        )),
+    }
+}
+
+fn parse_baseline(spec: &str) -> anyhow::Result<Baseline> {
+    match spec {
+        "random" => Ok(Baseline::Random),
+        "popular" => Ok(Baseline::Popular),
+        "recent" => Ok(Baseline::Recent),
+        _ => Err(anyhow!(
+            "Baselines must be one of random, popular or recent"
+        )),
+    }
+}
+
+fn parse_metrics_stream(spec: OsString) -> anyhow::Result<PathBuf> {
+    match spec.to_str().and_then(|spec| spec.strip_prefix("fd:")) {
+        #[cfg(unix)]
+        Some(fd) => Ok(PathBuf::from(format!(
+            "/dev/fd/{}",
+            fd.parse::<u32>().context(
+                "Invalid file descriptor given to --metrics-stream"
+            )?
+        ))),
+        #[cfg(not(unix))]
+        Some(_) => bail!(
+            "Streaming metrics to a file descriptor is only supported on unix"
+        ),
+        None => Ok(spec.into()),
+    }
+}
+
+fn parse_timeout_fallback(spec: &str) -> anyhow::Result<FallbackFeed> {
+    match spec {
+        "empty" => Ok(FallbackFeed::Empty),
+        "random" => Ok(FallbackFeed::Random),
+        _ => Err(anyhow!("Timeout fallbacks must be either empty or random")),
+    }
+}
+
+fn parse_feed_validation(spec: &str) -> anyhow::Result<FeedValidation> {
+    match spec {
+        "report" => Ok(FeedValidation::Report),
+        "sanitize" => Ok(FeedValidation::Sanitize),
+        "strict" => Ok(FeedValidation::Strict),
+        _ => Err(anyhow!(
+            "Feed validation must be one of report, sanitize or strict"
+        )),
+    }
+}
+
+fn parse_heartbeat(spec: &str) -> anyhow::Result<Heartbeat> {
+    let (interval, miss_threshold) = spec
+        .split_once(':')
+        .context("A heartbeat must be of the form SECONDS:MISSES")?;
+    let interval = Duration::try_from_secs_f64(
+        interval
+            .parse()
+            .context("Invalid interval of a heartbeat")?,
+    )
+    .context("Invalid interval of a heartbeat")?;
+    let miss_threshold = miss_threshold
+        .parse()
+        .context("Invalid number of missed pings of a heartbeat")?;
+    if interval.is_zero() {
+        return Err(anyhow!("The interval of a heartbeat must be positive"));
+    }
+    if miss_threshold == 0 {
+        return Err(anyhow!(
+            "At least one ping must be missed for a heartbeat to fail"
+        ));
+    }
+
+    Ok(Heartbeat {
+        interval,
+        miss_threshold,
+    })
+}
+
+fn parse_landmarks(spec: &str) -> anyhow::Result<DistanceOracle> {
+    let (n_landmarks, refresh_interval) = spec
+        .split_once(':')
+        .context("Landmarks must be of the form LANDMARKS:EPOCHS")?;
+    let n_landmarks =
+        n_landmarks.parse().context("Invalid number of landmarks")?;
+    let refresh_interval = refresh_interval
+        .parse()
+        .context("Invalid number of epochs between landmark refreshes")?;
+    if n_landmarks == 0 {
+        return Err(anyhow!("At least one landmark must be used"));
+    }
+
+    Ok(DistanceOracle::Landmarks {
+        n_landmarks,
+        refresh_interval,
+    })
+}
+
+fn parse_assertion(spec: &str) -> anyhow::Result<Assertion> {
+    // shepherd names are paths, so the shepherd is taken to be everything
+    // between the metric and the bounds
+    let format_error = || {
+        anyhow!("An assertion must be of the form METRIC:SHEPHERD:MIN:MAX")
+    };
+    let (metric, rest) = spec.split_once(':').ok_or_else(format_error)?;
+    let (rest, max) = rest.rsplit_once(':').ok_or_else(format_error)?;
+    let (shepherd, min) = rest.rsplit_once(':').ok_or_else(format_error)?;
+
+    Ok(Assertion {
+        metric: metric.parse().context("Invalid metric of an assertion")?,
+        shepherd: shepherd.to_string(),
+        bounds: (
+            min.parse().context("Invalid lower bound of an assertion")?,
+            max.parse().context("Invalid upper bound of an assertion")?,
+        ),
+    })
+}
+
+/// The options a shepherd run as a process is spawned with, given by the
+/// options preceding it on the command line
+#[derive(Default)]
+struct ProcessOptions {
+    vars: Vec<(OsString, OsString)>,
+    dir: Option<PathBuf>,
+    limits: ResourceLimits,
+}
+
+impl ProcessOptions {
+    fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+            && self.dir.is_none()
+            && self.limits.is_unlimited()
+    }
+}
+
+fn parse_env_var(spec: OsString) -> anyhow::Result<(OsString, OsString)> {
+    let spec = spec
+        .into_string()
+        .map_err(|_| anyhow!("Invalid argument to --shepherd-env"))?;
+    let (key, value) = spec
+        .split_once('=')
+        .filter(|(key, _)| !key.is_empty())
+        .context("Environment variables must be given as KEY=VALUE")?;
+    Ok((key.into(), value.into()))
+}
+
+/// Split a shepherd given on the command line into the name it was labelled
+/// with and its command line, if it was labelled with one
+fn split_label(spec: &OsStr) -> Option<(&str, &str)> {
+    spec.to_str()
+        .and_then(|spec| spec.split_once('='))
+        .filter(|(name, _)| {
+            !name.is_empty()
+                && !name.contains(|c: char| c == '/' || c.is_whitespace())
+        })
+}
+
+fn parse_shepherd(
+    spec: OsString,
+    options: ProcessOptions,
+    labels: &mut HashSet<String>,
+) -> anyhow::Result<Shepherd> {
+    // a shepherd may be given a name of its own, in which case the rest of
+    // the argument is its command line, split on whitespace
+    let Some((name, command)) = split_label(&spec) else {
+        return build_shepherd(spec, options);
+    };
+
+    // labels are how shepherds are told apart in the database and every
+    // report, so the same one can't be given twice
+    ensure!(
+        labels.insert(name.to_string()),
+        "The shepherd label {:?} was given more than once",
+        name
+    );
+
+    let mut words = command.split_whitespace();
+    let program = words
+        .next()
+        .context("A named shepherd must be given a command")?;
+    let args = words.collect::<Vec<_>>();
+    let shepherd = if args.is_empty() {
+        build_shepherd(program.into(), options)?
+    } else {
+        spawn_shepherd(program.as_ref(), args, options)?
+    };
+
+    Ok(shepherd.named(name))
+}
+
+fn spawn_shepherd(
+    program: &OsStr,
+    args: Vec<&str>,
+    options: ProcessOptions,
+) -> anyhow::Result<Shepherd> {
+    // only shepherds run as processes have an environment or limits of
+    // their own, so any other kind of shepherd is never considered here
+    let mut command =
+        Shepherd::command(program).args(args).envs(options.vars);
+    if let Some(dir) = options.dir {
+        command = command.current_dir(dir);
+    }
+    if let Some(limit) = options.limits.cpu_time {
+        command = command.cpu_time_limit(limit);
+    }
+    if let Some(limit) = options.limits.memory {
+        command = command.memory_limit(limit);
+    }
+
+    command
+        .spawn()
+        .context("Unable to build a shepherd from a given command")
+}
+
+fn build_shepherd(
+    spec: OsString,
+    options: ProcessOptions,
+) -> anyhow::Result<Shepherd> {
+    // modules run in the simulation's own process, so the memory limit is
+    // the only option which applies to them, and caps their memory instead
+    #[cfg(feature = "wasm")]
+    if Path::new(&spec)
+        .extension()
+        .is_some_and(|extension| extension == "wasm")
+    {
+        return Shepherd::wasm(spec, options.limits.memory)
+            .context("Unable to build a shepherd from a given module");
+    }
+
+    if !options.is_empty() {
+        return spawn_shepherd(&spec, Vec::new(), options);
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(endpoint) =
+        spec.to_str().and_then(|spec| spec.strip_prefix("grpc://"))
+    {
+        return Shepherd::connect_grpc(format!("http://{endpoint}"))
+            .context("Unable to connect to a shepherd at a given endpoint");
+    }
+
+    Shepherd::new(spec)
+        .context("Unable to build a shepherd from a given path")
+}
+
+pub fn parse_args() -> anyhow::Result<Command> {
+    let mut parser = lexopt::Parser::from_env();
+    if env::args_os()
+        .nth(1)
+        .is_some_and(|command| command == "check-regression")
+    {
+        parser.next()?;
+        return parse_regression_args(parser).map(Command::CheckRegression);
+    }
+    if env::args_os()
+        .nth(1)
+        .is_some_and(|command| command == "schema")
+    {
+        parser.next()?;
+        return parse_schema_args(parser).map(Command::Schema);
+    }
+    if env::args_os()
+        .nth(1)
+        .is_some_and(|command| command == "replay")
+    {
+        parser.next()?;
+        return parse_replay_args(parser).map(Command::Replay);
+    }
+    if env::args_os()
+        .nth(1)
+        .is_some_and(|command| command == "experiment")
+    {
+        parser.next()?;
+        return parse_experiment_args(parser).map(Command::Experiment);
+    }
+
+    let mut args = Args::default();
+    let mut options = ProcessOptions::default();
+    let mut labels = HashSet::default();
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Short('h') | Long("help") => {
+                usage();
+                #[allow(clippy::exit)]
+                process::exit(0);
+            }
+            Long("teaching") => {
+                args.teaching = true;
+            }
+            Long("tui") => {
+                args.tui = true;
+            }
+            Long("stress") => {
+                args.stress = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --stress")?
+                        .parse()
+                        .context("Invalid argument to --stress")?,
+                );
+            }
+            Long("baseline") => {
+                args.baselines = parser
+                    .value()
+                    .context("No argument given to --baseline")?
+                    .string()
+                    .context("Invalid argument to --baseline")?
+                    .split(',')
+                    .map(parse_baseline)
+                    .collect::<anyhow::Result<_>>()
+                    .context("Invalid argument to --baseline")?;
+            }
+            Long("profile") => {
+                args.profile = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --profile")?
+                        .into(),
+                );
+            }
+            Short('n') | Long("n-epochs") => {
+                args.n_epochs = Some(
+                    parser
+                        .value()
+                        .context("No argument given to -n or --n-epochs")?
+                        .parse()
+                        .context("Invalid argument to -n or --n-epochs")?,
+                );
+            }
+            Short('k') | Long("feed-size") => {
+                args.feed_size = Some(
+                    parser
+                        .value()
+                        .context("No argument given to -k or --feed-size")?
+                        .parse()
+                        .context("Invalid argument to -k or --feed-size")?,
+                );
+            }
+            Long("seed") => {
+                args.seed = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --seed")?
+                        .parse()
+                        .context("Invalid argument to --seed")?,
+                );
             }
             Short('d') | Long("database") => {
                 args.database_file = Some(
@@ -43,9 +878,435 @@ pub fn parse_args<'de>() -> anyhow::Result<Args<'de>> {
                         .into(),
                 );
             }
+            Long("noisy-sheep") => {
+                args.noisy_sheep_fraction = parser
+                    .value()
+                    .context("No argument given to --noisy-sheep")?
+                    .parse()
+                    .context("Invalid argument to --noisy-sheep")?;
+            }
+            Long("noise") => {
+                args.noise = parse_noise(
+                    &parser
+                        .value()
+                        .context("No argument given to --noise")?
+                        .string()
+                        .context("Invalid argument to --noise")?,
+                )?;
+            }
+            Long("hidden-tags") => {
+                args.hidden_tag_fraction = parser
+                    .value()
+                    .context("No argument given to --hidden-tags")?
+                    .parse()
+                    .context("Invalid argument to --hidden-tags")?;
+            }
+            Long("generator") => {
+                args.graph_generator = parse_generator(
+                    &parser
+                        .value()
+                        .context("No argument given to --generator")?
+                        .string()
+                        .context("Invalid argument to --generator")?,
+                )?;
+            }
+            Long("edges") => {
+                args.edge_semantics = parse_edge_semantics(
+                    &parser
+                        .value()
+                        .context("No argument given to --edges")?
+                        .string()
+                        .context("Invalid argument to --edges")?,
+                )?;
+            }
+            Long("parent-tags") => {
+                args.parent_tag_probability = parser
+                    .value()
+                    .context("No argument given to --parent-tags")?
+                    .parse()
+                    .context("Invalid argument to --parent-tags")?;
+            }
+            Long("group-merges") => {
+                args.group_merge_probability = parser
+                    .value()
+                    .context("No argument given to --group-merges")?
+                    .parse()
+                    .context("Invalid argument to --group-merges")?;
+            }
+            Long("group-splits") => {
+                args.group_split_probability = parser
+                    .value()
+                    .context("No argument given to --group-splits")?
+                    .parse()
+                    .context("Invalid argument to --group-splits")?;
+            }
+            Long("serendipity") => {
+                args.serendipity_probability = parser
+                    .value()
+                    .context("No argument given to --serendipity")?
+                    .parse()
+                    .context("Invalid argument to --serendipity")?;
+                anyhow::ensure!(
+                    (0.0..=1.0).contains(&args.serendipity_probability),
+                    "The argument to --serendipity must be within [0, 1]"
+                );
+            }
+            Long("fatigue") => {
+                args.fatigue_rate = parser
+                    .value()
+                    .context("No argument given to --fatigue")?
+                    .parse()
+                    .context("Invalid argument to --fatigue")?;
+            }
+            Long("interest-decay") => {
+                args.interest_decay = parser
+                    .value()
+                    .context("No argument given to --interest-decay")?
+                    .parse()
+                    .context("Invalid argument to --interest-decay")?;
+            }
+            Long("temperature") => {
+                args.temperature_bounds = Some(parse_temperature_bounds(
+                    &parser
+                        .value()
+                        .context("No argument given to --temperature")?
+                        .string()
+                        .context("Invalid argument to --temperature")?,
+                )?);
+            }
+            Long("landmarks") => {
+                args.distance_oracle = parse_landmarks(
+                    &parser
+                        .value()
+                        .context("No argument given to --landmarks")?
+                        .string()
+                        .context("Invalid argument to --landmarks")?,
+                )?;
+            }
+            Long("reinforcement") => {
+                args.reinforcement_probability = parser
+                    .value()
+                    .context("No argument given to --reinforcement")?
+                    .parse()
+                    .context("Invalid argument to --reinforcement")?;
+            }
+            Long("explain") => {
+                args.explain_responses = true;
+            }
+            Long("compute-budget") => {
+                args.compute_budget = Some(Duration::from_secs_f64(
+                    parser
+                        .value()
+                        .context("No argument given to --compute-budget")?
+                        .parse()
+                        .context("Invalid argument to --compute-budget")?,
+                ));
+            }
+            Long("output-quota") => {
+                args.output_quota = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --output-quota")?
+                        .parse()
+                        .context("Invalid argument to --output-quota")?,
+                );
+            }
+            Long("request-timeout") => {
+                args.request_timeout = Some(Duration::from_secs_f64(
+                    parser
+                        .value()
+                        .context("No argument given to --request-timeout")?
+                        .parse()
+                        .context("Invalid argument to --request-timeout")?,
+                ));
+            }
+            Long("heartbeat") => {
+                args.heartbeat = Some(parse_heartbeat(
+                    &parser
+                        .value()
+                        .context("No argument given to --heartbeat")?
+                        .string()
+                        .context("Invalid argument to --heartbeat")?,
+                )?);
+            }
+            Long("timeout-fallback") => {
+                args.timeout_fallback = parse_timeout_fallback(
+                    &parser
+                        .value()
+                        .context("No argument given to --timeout-fallback")?
+                        .string()
+                        .context("Invalid argument to --timeout-fallback")?,
+                )?;
+            }
+            Long("feed-validation") => {
+                args.feed_validation = parse_feed_validation(
+                    &parser
+                        .value()
+                        .context("No argument given to --feed-validation")?
+                        .string()
+                        .context("Invalid argument to --feed-validation")?,
+                )?;
+            }
+            Long("abandon-on-timeout") => {
+                args.abandon_on_timeout = true;
+            }
+            Long("two-stage") => {
+                args.n_candidates = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --two-stage")?
+                        .parse()
+                        .context("Invalid argument to --two-stage")?,
+                );
+            }
+            Long("item-budget") => {
+                args.item_access_budget = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --item-budget")?
+                        .parse()
+                        .context("Invalid argument to --item-budget")?,
+                );
+            }
+            Long("max-nodes") => {
+                args.max_nodes = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --max-nodes")?
+                        .parse()
+                        .context("Invalid argument to --max-nodes")?,
+                );
+            }
+            Long("max-edges") => {
+                args.max_edges = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --max-edges")?
+                        .parse()
+                        .context("Invalid argument to --max-edges")?,
+                );
+            }
+            Long("epoch-summaries") => {
+                args.epoch_summaries = true;
+            }
+            Long("ranking-metrics") => {
+                args.ranking_metrics = true;
+            }
+            Long("oracle-regret") => {
+                args.oracle_regret = true;
+            }
+            Long("diversity-metrics") => {
+                args.diversity_metrics = true;
+            }
+            Long("drift-metrics") => {
+                args.drift_metrics = true;
+            }
+            Long("cold-start-metrics") => {
+                args.cold_start_metrics = true;
+            }
+            Long("graph-diffs") => {
+                args.graph_diffs = true;
+            }
+            Long("tag-group-snapshots") => {
+                args.tag_group_snapshots = true;
+            }
+            Long("penalize-under-delivery") => {
+                args.under_delivery_penalty = true;
+            }
+            Long("under-delivery-notices") => {
+                args.under_delivery_notices = true;
+            }
+            Long("response-batches") => {
+                args.response_batch_interval = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --response-batches")?
+                        .parse()
+                        .context("Invalid argument to --response-batches")?,
+                );
+            }
+            Long("degradation") => {
+                args.degradations.push(parse_degradation(
+                    &parser
+                        .value()
+                        .context("No argument given to --degradation")?
+                        .string()
+                        .context("Invalid argument to --degradation")?,
+                )?);
+            }
+            Long("retention") => {
+                args.retention = Some(parse_retention(
+                    &parser
+                        .value()
+                        .context("No argument given to --retention")?
+                        .string()
+                        .context("Invalid argument to --retention")?,
+                )?);
+            }
+            Long("graph") => {
+                args.seed_graph_file = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --graph")?
+                        .into(),
+                );
+            }
+            #[cfg(feature = "movielens")]
+            Long("movielens") => {
+                args.movielens_directory = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --movielens")?
+                        .into(),
+                );
+            }
+            Long("export-graph") => {
+                args.graph_file = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --export-graph")?
+                        .into(),
+                );
+            }
+            Long("metrics-out") => {
+                let metrics_file = PathBuf::from(
+                    parser
+                        .value()
+                        .context("No argument given to --metrics-out")?,
+                );
+
+                // this is checked up front so that a long run isn't wasted
+                // on metrics which can't be written out at the end of it
+                match metrics_file.extension().and_then(|e| e.to_str()) {
+                    Some("csv") => {}
+                    Some("parquet") if cfg!(feature = "arrow") => {}
+                    Some("parquet") => bail!(
+                        "Writing metrics as Parquet requires the arrow feature"
+                    ),
+                    _ => bail!(
+                        "The metrics file must be named as a CSV or Parquet \
+                         file"
+                    ),
+                }
+                args.metrics_file = Some(metrics_file);
+            }
+            Long("report") => {
+                args.report_file = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --report")?
+                        .into(),
+                );
+            }
+            Long("leaderboard") => {
+                args.leaderboard_metric = parser
+                    .value()
+                    .context("No argument given to --leaderboard")?
+                    .parse()
+                    .context("Invalid argument to --leaderboard")?;
+            }
+            Long("export-affinities") => {
+                args.affinity_prefix = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --export-affinities")?
+                        .into(),
+                );
+            }
+            Long("affinity-epochs") => {
+                args.affinity_epochs = parser
+                    .value()
+                    .context("No argument given to --affinity-epochs")?
+                    .string()
+                    .context("Invalid argument to --affinity-epochs")?
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .context("Invalid argument to --affinity-epochs")?;
+            }
+            Long("assert") => {
+                args.assertions.push(parse_assertion(
+                    &parser
+                        .value()
+                        .context("No argument given to --assert")?
+                        .string()
+                        .context("Invalid argument to --assert")?,
+                )?);
+            }
+            Long("emit-metrics-stream") => {
+                args.emit_metrics_stream = true;
+            }
+            #[cfg(feature = "prometheus")]
+            Long("prometheus") => {
+                args.prometheus_address = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --prometheus")?
+                        .string()
+                        .context("Invalid argument to --prometheus")?,
+                );
+            }
+            Long("metrics-stream") => {
+                args.metrics_stream_file =
+                    Some(parse_metrics_stream(parser.value().context(
+                        "No argument given to --metrics-stream",
+                    )?)?);
+            }
+            Long("anomaly-sigma") => {
+                args.anomaly_threshold = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --anomaly-sigma")?
+                        .parse()
+                        .context("Invalid argument to --anomaly-sigma")?,
+                );
+            }
+            Long("transcripts") => {
+                args.transcript_directory = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --transcripts")?
+                        .into(),
+                );
+            }
+            Long("shepherd-env") => {
+                options.vars.push(parse_env_var(
+                    parser
+                        .value()
+                        .context("No argument given to --shepherd-env")?,
+                )?);
+            }
+            Long("shepherd-dir") => {
+                options.dir = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --shepherd-dir")?
+                        .into(),
+                );
+            }
+            Long("shepherd-cpu-time") => {
+                options.limits.cpu_time = Some(Duration::from_secs_f64(
+                    parser
+                        .value()
+                        .context("No argument given to --shepherd-cpu-time")?
+                        .parse()
+                        .context("Invalid argument to --shepherd-cpu-time")?,
+                ));
+            }
+            Long("shepherd-memory") => {
+                options.limits.memory = Some(
+                    parser
+                        .value()
+                        .context("No argument given to --shepherd-memory")?
+                        .parse()
+                        .context("Invalid argument to --shepherd-memory")?,
+                );
+            }
             Value(shepherd) => {
-                args.shepherds.push(Shepherd::new(shepherd).context(
-                    "Unable to build a shepherd from a given path",
+                args.shepherds.push(parse_shepherd(
+                    shepherd,
+                    mem::take(&mut options),
+                    &mut labels,
                 )?);
             }
             a => {
@@ -57,5 +1318,60 @@ pub fn parse_args<'de>() -> anyhow::Result<Args<'de>> {
         }
     }
 
-    Ok(args)
+    if !options.is_empty() {
+        bail!(
+            "Options given to a shepherd must precede the shepherd they \
+             apply to"
+        );
+    }
+
+    Ok(Command::Run(Box::new(args)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temperature_bounds_must_be_positive_and_ordered() {
+        assert_eq!(parse_temperature_bounds("0.5:2").unwrap(), (0.5, 2.0));
+        assert_eq!(parse_temperature_bounds("1:1").unwrap(), (1.0, 1.0));
+        assert!(parse_temperature_bounds("2:0.5").is_err());
+        assert!(parse_temperature_bounds("0:1").is_err());
+        assert!(parse_temperature_bounds("1").is_err());
+    }
+
+    #[test]
+    fn labels_are_split_from_commands() {
+        assert_eq!(
+            split_label(OsStr::new("pop=python3 popular.py")),
+            Some(("pop", "python3 popular.py"))
+        );
+        assert_eq!(split_label(OsStr::new("a=b=c")), Some(("a", "b=c")));
+    }
+
+    #[test]
+    fn commands_without_labels_are_left_whole() {
+        assert_eq!(split_label(OsStr::new("./shepherd")), None);
+        assert_eq!(split_label(OsStr::new("=./shepherd")), None);
+        assert_eq!(split_label(OsStr::new("./bin/a=b")), None);
+        assert_eq!(split_label(OsStr::new("env A=b ./shepherd")), None);
+    }
+
+    #[test]
+    fn duplicate_labels_are_rejected() {
+        let mut labels = HashSet::default();
+        labels.insert("pop".to_string());
+
+        // the label is checked before anything is spawned, so the command
+        // is never run
+        let Err(error) = parse_shepherd(
+            "pop=./does-not-exist".into(),
+            ProcessOptions::default(),
+            &mut labels,
+        ) else {
+            panic!("a duplicate label was accepted");
+        };
+        assert!(error.to_string().contains("more than once"));
+    }
 }
@@ -0,0 +1,149 @@
+use anyhow::Context;
+use duckdb::{params, Connection};
+use std::{fmt, str::FromStr};
+
+use shepherd_lib::feed::Response;
+
+/// A metric which may be asserted upon, computed per shepherd from the
+/// ratings recorded over the course of a run
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Metric {
+    /// The fraction of ratings with the given response
+    ///
+    /// Only items the shepherd delivered are rated, so missing items never
+    /// count as negative responses here, even with
+    /// `--penalize-under-delivery`, which only applies to the epoch
+    /// summaries and the metrics stream. They are measured by
+    /// [`Metric::UnderDeliveryRate`] instead
+    ResponseRate(Response),
+
+    /// The fraction of the items requested from the shepherd which were
+    /// missing from the feeds it built
+    ///
+    /// A request which timed out counts as a feed with every item missing,
+    /// even if the sheep was given a fallback feed in its place, as fallback
+    /// feeds aren't rated on the shepherd's behalf
+    UnderDeliveryRate,
+}
+
+impl FromStr for Metric {
+    type Err = anyhow::Error;
+
+    fn from_str(metric: &str) -> anyhow::Result<Self> {
+        match metric {
+            "positive_rate" => Ok(Self::ResponseRate(Response::Positive)),
+            "neutral_rate" => Ok(Self::ResponseRate(Response::Neutral)),
+            "negative_rate" => Ok(Self::ResponseRate(Response::Negative)),
+            "under_delivery_rate" => Ok(Self::UnderDeliveryRate),
+            _ => anyhow::bail!("Unknown metric {:?}", metric),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ResponseRate(Response::Positive) => {
+                write!(f, "positive_rate")
+            }
+            Self::ResponseRate(Response::Neutral) => {
+                write!(f, "neutral_rate")
+            }
+            Self::ResponseRate(Response::Negative) => {
+                write!(f, "negative_rate")
+            }
+            Self::UnderDeliveryRate => write!(f, "under_delivery_rate"),
+        }
+    }
+}
+
+impl Metric {
+    /// Compute the metric for the named shepherd from the ratings in the
+    /// database
+    ///
+    /// [`None`] is returned if the shepherd has no ratings, or for
+    /// [`Metric::UnderDeliveryRate`], if it was never asked for any items
+    pub fn evaluate(
+        &self,
+        duckdb: &Connection,
+        shepherd: &str,
+    ) -> anyhow::Result<Option<f64>> {
+        let Self::ResponseRate(response) = self else {
+            // the delivered items are those which were rated
+            return duckdb
+                .query_row(
+                    "
+                    SELECT CAST(missing.n AS DOUBLE)
+                        / nullif(missing.n + delivered.n, 0)
+                    FROM (
+                        SELECT coalesce(sum(under_deliveries.n_missing), 0) AS n
+                        FROM under_deliveries
+                        JOIN shepherds ON under_deliveries.shepherd = shepherds.id
+                        WHERE shepherds.name = ?
+                    ) AS missing, (
+                        SELECT count(*) AS n
+                        FROM ratings
+                        JOIN shepherds ON ratings.shepherd = shepherds.id
+                        WHERE shepherds.name = ?
+                    ) AS delivered
+                    ",
+                    params![shepherd, shepherd],
+                    |row| row.get(0),
+                )
+                .context("Unable to compute a metric");
+        };
+        let response = match response {
+            Response::Positive => "positive",
+            Response::Neutral => "neutral",
+            Response::Negative => "negative",
+        };
+
+        duckdb
+            .query_row(
+                "
+                SELECT avg(CASE WHEN ratings.response = ? THEN 1.0 ELSE 0.0 END)
+                FROM ratings
+                JOIN shepherds ON ratings.shepherd = shepherds.id
+                WHERE shepherds.name = ?
+                ",
+                params![response, shepherd],
+                |row| row.get(0),
+            )
+            .context("Unable to compute a metric")
+    }
+}
+
+/// An assertion that a metric of a shepherd lies within some bounds once the
+/// run has finished
+#[derive(Clone, PartialEq, Debug)]
+pub struct Assertion {
+    /// The metric being asserted upon
+    pub metric: Metric,
+
+    /// The name of the shepherd the metric is computed for
+    pub shepherd: String,
+
+    /// The inclusive bounds the metric must lie within
+    pub bounds: (f64, f64),
+}
+
+impl Assertion {
+    /// Compute the asserted metric from the ratings in the database
+    ///
+    /// [`None`] is returned if the shepherd has no ratings
+    pub fn evaluate(
+        &self,
+        duckdb: &Connection,
+    ) -> anyhow::Result<Option<f64>> {
+        self.metric
+            .evaluate(duckdb, &self.shepherd)
+            .context("Unable to compute the metric of an assertion")
+    }
+
+    /// Check whether a computed value of the metric satisfies the assertion
+    pub fn holds(&self, value: Option<f64>) -> bool {
+        value.is_some_and(|value| {
+            (self.bounds.0..=self.bounds.1).contains(&value)
+        })
+    }
+}
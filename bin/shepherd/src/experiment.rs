@@ -0,0 +1,180 @@
+use anyhow::Context;
+use duckdb::{params, Connection};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env,
+    fs::{self, File},
+    process::{self, Child, Stdio},
+};
+use tracing::{info, warn};
+
+use crate::{args::ExperimentArgs, assertions::Metric};
+use shepherd_lib::feed::Response;
+
+/// The metrics aggregated over the runs of an experiment
+const METRICS: [Metric; 4] = [
+    Metric::ResponseRate(Response::Positive),
+    Metric::ResponseRate(Response::Neutral),
+    Metric::ResponseRate(Response::Negative),
+    Metric::UnderDeliveryRate,
+];
+
+/// The two-sided 95% critical values of Student's t-distribution, indexed
+/// by degrees of freedom less one
+const T_CRITICAL: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+    2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+    2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+];
+
+/// The critical value used past the end of [`T_CRITICAL`], where the
+/// t-distribution is close enough to the normal distribution
+const Z_CRITICAL: f64 = 1.960;
+
+/// The mean of a metric over the runs of an experiment, along with the
+/// half-width of its 95% confidence interval, which is [`None`] if there
+/// were too few runs to estimate it
+fn summarize(values: &[f64]) -> (f64, Option<f64>) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 {
+        return (mean, None);
+    }
+
+    let variance = values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0);
+    let critical = T_CRITICAL
+        .get(values.len() - 2)
+        .copied()
+        .unwrap_or(Z_CRITICAL);
+
+    (mean, Some(critical * (variance / n).sqrt()))
+}
+
+/// Wait on a run of an experiment to finish, returning whether it succeeded
+fn finish(seed: u64, mut child: Child) -> anyhow::Result<bool> {
+    let status = child.wait().context("Unable to wait on a run")?;
+    if !status.success() {
+        warn!(
+            seed,
+            status = %status,
+            "a run failed, so it is left out of the results"
+        );
+    }
+
+    Ok(status.success())
+}
+
+/// Run the same simulation once for each of a range of seeds, as separate
+/// processes, and print the mean of each shepherd's metrics over the runs
+/// along with their 95% confidence intervals
+///
+/// Each run writes its database and its log to the experiment's directory,
+/// named after its seed, so that any one of them can be looked into
+/// afterwards. Runs which fail are left out of the results
+pub fn run(args: &ExperimentArgs) -> anyhow::Result<()> {
+    let executable =
+        env::current_exe().context("Unable to locate the simulator")?;
+    let directory = args.directory.clone().unwrap_or_else(|| {
+        env::temp_dir().join(format!("shepherd-experiment-{}", process::id()))
+    });
+    fs::create_dir_all(&directory)
+        .context("Unable to create the experiment's directory")?;
+
+    let mut running = VecDeque::new();
+    let mut databases = Vec::new();
+    for seed in (0..args.n_seeds).map(|i| args.first_seed.wrapping_add(i)) {
+        if running.len() == args.n_jobs {
+            let (seed, database, child) = running
+                .pop_front()
+                .expect("there is always a run to wait on");
+            if finish(seed, child)? {
+                databases.push((seed, database));
+            }
+        }
+
+        // databases left over from an earlier experiment in the same
+        // directory are replaced, rather than added to
+        let database = directory.join(format!("seed-{seed}.duckdb"));
+        if database.exists() {
+            fs::remove_file(&database)
+                .context("Unable to replace the database of a run")?;
+        }
+        let log = File::create(directory.join(format!("seed-{seed}.log")))
+            .context("Unable to create the log of a run")?;
+
+        // the graph is printed on standard output at the end of each run,
+        // which is of no use here
+        let child = process::Command::new(&executable)
+            .arg(format!("--seed={seed}"))
+            .arg("--database")
+            .arg(&database)
+            .args(&args.args)
+            .stdout(Stdio::null())
+            .stderr(log)
+            .spawn()
+            .context("Unable to start a run")?;
+        info!(seed, "started a run");
+        running.push_back((seed, database, child));
+    }
+    for (seed, database, child) in running {
+        if finish(seed, child)? {
+            databases.push((seed, database));
+        }
+    }
+
+    anyhow::ensure!(
+        !databases.is_empty(),
+        "Every run of the experiment failed"
+    );
+
+    let mut values = BTreeMap::<_, Vec<f64>>::new();
+    for (seed, database) in &databases {
+        let duckdb = Connection::open(database).with_context(|| {
+            format!("Unable to open the database of the run with seed {seed}")
+        })?;
+        let shepherds = duckdb
+            .prepare("SELECT DISTINCT name FROM shepherds ORDER BY name")
+            .context("Unable to prepare a statement")?
+            .query_map(params![], |row| row.get(0))
+            .context("Unable to retrieve the shepherds of a run")?
+            .collect::<Result<Vec<String>, _>>()
+            .context("Unable to retrieve the shepherds of a run")?;
+
+        for shepherd in shepherds {
+            for (i, metric) in METRICS.iter().enumerate() {
+                if let Some(value) = metric.evaluate(&duckdb, &shepherd)? {
+                    values
+                        .entry((shepherd.clone(), i))
+                        .or_default()
+                        .push(value);
+                }
+            }
+        }
+    }
+
+    for ((shepherd, i), values) in values {
+        let (mean, half_width) = summarize(&values);
+        println!(
+            "{} {}: {}{} ({} runs)",
+            shepherd,
+            METRICS[i],
+            mean,
+            half_width.map_or_else(String::new, |half_width| format!(
+                " ± {half_width}"
+            )),
+            values.len()
+        );
+    }
+
+    info!(
+        directory = %directory.display(),
+        n_runs = databases.len(),
+        "the experiment has finished"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,427 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+    time::Duration,
+};
+
+use shepherd_lib::{
+    collections::HashMap,
+    ids::ShepherdId,
+    metrics::{
+        EpochStats, Latencies, LatencyPercentiles, MetricReports, Stats,
+    },
+};
+
+/// A metric shepherds may be ranked by, computed from their feeds over the
+/// whole run
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum Metric {
+    /// The fraction of responses which were positive
+    #[default]
+    PositiveRate,
+
+    /// The fraction of responses which were negative
+    NegativeRate,
+
+    /// The fraction of the items requested which were delivered
+    FillRate,
+
+    /// The number of requests for feeds which weren't answered in time
+    Timeouts,
+
+    /// The mean normalized discounted cumulative gain of the feeds
+    Ndcg,
+
+    /// The mean regret of the feeds against the oracle's
+    Regret,
+
+    /// The mean intra-list diversity of the feeds
+    Diversity,
+
+    /// The mean novelty of the feeds
+    Novelty,
+
+    /// The mean entropy of the tags of the feeds
+    TagEntropy,
+
+    /// The mean drift of the sheep from their original interests
+    Drift,
+
+    /// The mean disparity of the exposure given to each tag group
+    Disparity,
+
+    /// The median time taken to build a feed
+    LatencyP50,
+
+    /// The 95th percentile of the time taken to build a feed
+    LatencyP95,
+}
+
+impl Metric {
+    /// Every metric, in the order they are shown as columns
+    pub const ALL: [Self; 13] = [
+        Self::PositiveRate,
+        Self::NegativeRate,
+        Self::FillRate,
+        Self::Timeouts,
+        Self::Ndcg,
+        Self::Regret,
+        Self::Diversity,
+        Self::Novelty,
+        Self::TagEntropy,
+        Self::Drift,
+        Self::Disparity,
+        Self::LatencyP50,
+        Self::LatencyP95,
+    ];
+
+    /// Whether higher values of the metric rank a shepherd higher
+    pub fn higher_is_better(self) -> bool {
+        !matches!(
+            self,
+            Self::NegativeRate
+                | Self::Timeouts
+                | Self::Regret
+                | Self::Drift
+                | Self::Disparity
+                | Self::LatencyP50
+                | Self::LatencyP95
+        )
+    }
+
+    /// Format a value of the metric for display, showing latencies as
+    /// durations
+    pub fn format(self, value: f64) -> String {
+        match self {
+            Self::LatencyP50 | Self::LatencyP95 => {
+                format!("{:.1?}", Duration::from_secs_f64(value))
+            }
+            Self::Timeouts => format!("{value}"),
+            _ => format!("{value:.4}"),
+        }
+    }
+
+    /// Compute the metric from a shepherd's stats and latency over the whole
+    /// run, or [`None`] if it was never measured
+    fn evaluate(
+        self,
+        total: &EpochStats,
+        latency: Option<LatencyPercentiles>,
+    ) -> Option<f64> {
+        match self {
+            Self::PositiveRate => Some(total.positive_rate()),
+            Self::NegativeRate => Some(total.negative_rate()),
+            Self::FillRate => Some(total.fill_rate()),
+            Self::Timeouts => Some(total.n_timeouts as f64),
+            Self::Ndcg => total.ndcg(),
+            Self::Regret => total.regret(),
+            Self::Diversity => total.diversity(),
+            Self::Novelty => total.novelty(),
+            Self::TagEntropy => total.tag_entropy(),
+            Self::Drift => total.drift(),
+            Self::Disparity => total.disparity(),
+            Self::LatencyP50 => latency.map(|l| l.p50.as_secs_f64()),
+            Self::LatencyP95 => latency.map(|l| l.p95.as_secs_f64()),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = anyhow::Error;
+
+    fn from_str(metric: &str) -> anyhow::Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.to_string() == metric)
+            .ok_or_else(|| anyhow::anyhow!("Unknown metric {:?}", metric))
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PositiveRate => "positive_rate",
+            Self::NegativeRate => "negative_rate",
+            Self::FillRate => "fill_rate",
+            Self::Timeouts => "timeouts",
+            Self::Ndcg => "ndcg",
+            Self::Regret => "regret",
+            Self::Diversity => "diversity",
+            Self::Novelty => "novelty",
+            Self::TagEntropy => "tag_entropy",
+            Self::Drift => "drift",
+            Self::Disparity => "disparity",
+            Self::LatencyP50 => "latency_p50",
+            Self::LatencyP95 => "latency_p95",
+        })
+    }
+}
+
+/// A column of a [`Leaderboard`]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Column<'a> {
+    /// One of the metrics shepherds may be ranked by
+    Metric(Metric),
+
+    /// A custom metric, as of the last value it reported for each shepherd
+    Custom(&'a str),
+}
+
+impl Column<'_> {
+    /// Format a value of the column for display
+    pub fn format(self, value: f64) -> String {
+        match self {
+            Self::Metric(metric) => metric.format(value),
+            Self::Custom(_) => format!("{value:.4}"),
+        }
+    }
+}
+
+impl fmt::Display for Column<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Metric(metric) => metric.fmt(f),
+            // custom metrics are prefixed as they are in the metrics file,
+            // so as not to be mistaken for a metric of the same name
+            Self::Custom(name) => write!(f, "metric_{name}"),
+        }
+    }
+}
+
+/// The shepherds of a finished run, ranked by a primary metric, along with
+/// every other metric as a secondary column
+pub struct Leaderboard {
+    /// The metric the shepherds are ranked by
+    pub primary: Metric,
+
+    /// The name of each custom metric which reported a value during the run
+    pub custom: Vec<String>,
+
+    /// The name of each shepherd and its value of each of [`Metric::ALL`]
+    /// followed by each custom metric, from the highest ranked shepherd to
+    /// the lowest
+    pub rows: Vec<(String, Vec<Option<f64>>)>,
+}
+
+impl Leaderboard {
+    /// Rank the shepherds of a run by a metric
+    ///
+    /// Shepherds without a value for the metric are ranked below every
+    /// shepherd with one, and ties are left in the order the shepherds were
+    /// added in
+    pub fn new(
+        primary: Metric,
+        shepherd_names: &HashMap<ShepherdId, String>,
+        stats: &Stats,
+        latencies: &Latencies,
+        custom_metrics: &MetricReports,
+    ) -> Self {
+        let mut shepherds = shepherd_names.iter().collect::<Vec<_>>();
+        shepherds.sort_unstable_by_key(|(ShepherdId(id), _)| *id);
+        let custom = custom_metrics
+            .metrics()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        let mut rows = shepherds
+            .into_iter()
+            .map(|(id, name)| {
+                let total = stats.total(*id);
+                let latency = latencies.total(*id);
+                (
+                    name.clone(),
+                    Metric::ALL
+                        .iter()
+                        .map(|metric| metric.evaluate(&total, latency))
+                        .chain(
+                            custom
+                                .iter()
+                                .map(|name| custom_metrics.latest(*id, name)),
+                        )
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let column = Metric::ALL
+            .iter()
+            .position(|metric| *metric == primary)
+            .expect("every metric is a column");
+        rows.sort_by(|(_, a), (_, b)| match (a[column], b[column]) {
+            (Some(a), Some(b)) if primary.higher_is_better() => {
+                b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+            }
+            (Some(a), Some(b)) => {
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+
+        Self {
+            primary,
+            custom,
+            rows,
+        }
+    }
+
+    /// Print the leaderboard as a table, with the primary metric as its
+    /// first column
+    pub fn print(&self, mut writer: impl Write) -> io::Result<()> {
+        let columns = self.columns();
+        let width = self
+            .rows
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0)
+            .max("shepherd".len());
+
+        // custom metrics may have names longer than the usual column width
+        let widths = columns
+            .iter()
+            .map(|(column, _)| column.to_string().len().max(13))
+            .collect::<Vec<_>>();
+
+        write!(writer, "{:>4} {:<width$}", "rank", "shepherd")?;
+        for ((column, _), width) in columns.iter().zip(&widths) {
+            write!(writer, " {:>width$}", column.to_string())?;
+        }
+        writeln!(writer)?;
+
+        for (rank, (name, values)) in self.rows.iter().enumerate() {
+            write!(writer, "{:>4} {:<width$}", rank + 1, name)?;
+            for ((column, i), width) in columns.iter().zip(&widths) {
+                write!(
+                    writer,
+                    " {:>width$}",
+                    values[*i].map_or_else(
+                        || "-".to_string(),
+                        |value| column.format(value)
+                    )
+                )?;
+            }
+            writeln!(writer)?;
+        }
+
+        writer.flush()
+    }
+
+    /// The leaderboard as a JSON object, naming the primary metric and
+    /// listing the shepherds from the highest ranked to the lowest
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "metric": self.primary.to_string(),
+            "shepherds": self
+                .rows
+                .iter()
+                .enumerate()
+                .map(|(rank, (name, values))| {
+                    let mut row = serde_json::Map::new();
+                    row.insert("rank".to_string(), (rank + 1).into());
+                    row.insert("name".to_string(), name.clone().into());
+                    for (metric, value) in Metric::ALL.iter().zip(values) {
+                        row.insert(metric.to_string(), (*value).into());
+                    }
+                    // custom metrics are nested as they are in the rest of
+                    // the metrics stream
+                    row.insert(
+                        "metrics".to_string(),
+                        self.custom
+                            .iter()
+                            .zip(&values[Metric::ALL.len()..])
+                            .map(|(name, value)| (name.clone(), (*value).into()))
+                            .collect::<serde_json::Map<_, _>>()
+                            .into(),
+                    );
+                    serde_json::Value::Object(row)
+                })
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// The metrics shown as columns along with their indices in each row,
+    /// starting with the primary metric and leaving out any which no
+    /// shepherd has a value for
+    pub fn columns(&self) -> Vec<(Column<'_>, usize)> {
+        let primary = Column::Metric(self.primary);
+        let mut columns = Metric::ALL
+            .into_iter()
+            .map(Column::Metric)
+            .chain(self.custom.iter().map(|name| Column::Custom(name)))
+            .enumerate()
+            .filter(|(i, column)| {
+                *column == primary
+                    || self
+                        .rows
+                        .iter()
+                        .any(|(_, values)| values[*i].is_some())
+            })
+            .map(|(i, column)| (column, i))
+            .collect::<Vec<_>>();
+        columns.sort_by_key(|(column, _)| *column != primary);
+        columns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shepherd_lib::{
+        feed::Responses,
+        ids::{EpochId, SheepId},
+        metrics,
+    };
+
+    struct Clicks;
+
+    impl metrics::Metric for Clicks {
+        fn name(&self) -> &str {
+            "clicks"
+        }
+
+        fn update(&mut self, _: ShepherdId, _: SheepId, _: &Responses) {}
+
+        fn report(&self) -> Vec<(ShepherdId, f64)> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn custom_metrics_are_shown_as_of_their_latest_value() {
+        let mut shepherd_names = HashMap::default();
+        shepherd_names.insert(ShepherdId(0), "a".to_string());
+        shepherd_names.insert(ShepherdId(1), "b".to_string());
+        let mut custom_metrics = MetricReports::default();
+        custom_metrics.record(ShepherdId(0), EpochId(1), &Clicks, 1.0);
+        custom_metrics.record(ShepherdId(0), EpochId(2), &Clicks, 2.0);
+
+        let leaderboard = Leaderboard::new(
+            Metric::PositiveRate,
+            &shepherd_names,
+            &Stats::default(),
+            &Latencies::default(),
+            &custom_metrics,
+        );
+
+        let columns = leaderboard.columns();
+        let (_, i) = columns
+            .iter()
+            .find(|(column, _)| *column == Column::Custom("clicks"))
+            .expect("the custom metric is a column");
+        assert_eq!(leaderboard.rows[0].1[*i], Some(2.0));
+        assert_eq!(leaderboard.rows[1].1[*i], None);
+
+        let mut table = Vec::new();
+        leaderboard.print(&mut table).unwrap();
+        assert!(String::from_utf8(table).unwrap().contains("metric_clicks"));
+        assert_eq!(
+            leaderboard.to_json()["shepherds"][0]["metrics"]["clicks"],
+            2.0
+        );
+    }
+}
@@ -28,33 +28,429 @@
 
 use anyhow::Context;
 use duckdb::{params, Connection};
-use petgraph::dot::Dot;
+use rand::prelude::*;
 use std::{
-    collections::HashMap,
-    io,
+    cell::{Cell, RefCell},
+    env,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, IsTerminal, Write},
+    path::Path,
     sync::atomic::{AtomicUsize, Ordering},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+use tracing_chrome::ChromeLayerBuilder;
+use tracing_subscriber::{
+    filter::LevelFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt,
+    Layer,
 };
-use tracing::info;
 
-use crate::args::Args;
+use crate::args::{Args, Command};
 use shepherd_lib::{
-    feed::Response,
+    collections::HashMap,
+    feed::{Explanation, Justification, Response, ResponseCounts},
     graph::Simulation as SimulationGraph,
-    ids::{GraphId, ShepherdId},
+    ids::{EpochId, GraphId, ShepherdId},
+    metrics::{self, AnomalyMonitor},
+    sheep::DistanceBehavior,
+    shepherd::Shepherd,
     simulation::{Settings, Simulation, SimulationParts},
 };
 
 mod args;
+mod assertions;
+mod experiment;
+mod leaderboard;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod regression;
+mod replay;
+mod report;
+mod schema;
+mod stress;
+mod teaching;
+mod tui;
 
-fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt().with_writer(io::stderr).init();
+/// The largest affinity matrix which will be exported, in cells
+const AFFINITY_MAX_CELLS: usize = 1_000_000;
+
+/// Export the affinity matrix of the simulation at the given epoch to a
+/// `.npy` file, alongside a JSON file labelling its rows and columns
+fn export_affinities(
+    simulation: &Simulation,
+    prefix: &Path,
+    epoch: usize,
+) -> anyhow::Result<()> {
+    let mut matrix_path = prefix.as_os_str().to_owned();
+    matrix_path.push(format!("-{}.npy", epoch));
+    let mut labels_path = prefix.as_os_str().to_owned();
+    labels_path.push(format!("-{}.json", epoch));
+
+    let (sheep, items) = simulation.export_affinities(
+        BufWriter::new(
+            File::create(matrix_path)
+                .context("Unable to create an affinity matrix file")?,
+        ),
+        AFFINITY_MAX_CELLS,
+    )?;
+    serde_json::to_writer(
+        BufWriter::new(
+            File::create(labels_path)
+                .context("Unable to create an affinity label file")?,
+        ),
+        &serde_json::json!({ "sheep": sheep, "items": items }),
+    )
+    .context("Unable to write the affinity labels")?;
+
+    Ok(())
+}
+
+/// The highest rating in `MovieLens` datasets
+#[cfg(feature = "movielens")]
+const MOVIELENS_MAX_RATING: f64 = 5.0;
+
+/// The number of ratings a `MovieLens` user must have given items with a tag
+/// to be connected to it
+#[cfg(feature = "movielens")]
+const MOVIELENS_MIN_RATINGS: usize = 3;
+
+/// Import the `movies.csv` and `ratings.csv` files of a `MovieLens` dataset
+/// from a directory as a graph
+#[cfg(feature = "movielens")]
+fn import_movielens(directory: &Path) -> anyhow::Result<SimulationGraph> {
+    let dataset = shepherd_lib::movielens::import(
+        BufReader::new(
+            File::open(directory.join("movies.csv"))
+                .context("Unable to open the MovieLens items")?,
+        ),
+        BufReader::new(
+            File::open(directory.join("ratings.csv"))
+                .context("Unable to open the MovieLens ratings")?,
+        ),
+        MOVIELENS_MAX_RATING,
+        MOVIELENS_MIN_RATINGS,
+    )?;
+    info!(
+        n_tags = dataset.tags.len(),
+        n_items = dataset.items.len(),
+        n_sheep = dataset.sheep.len(),
+        "imported a MovieLens dataset"
+    );
+
+    Ok(dataset.graph)
+}
+
+/// The number of preceding epochs each epoch's metrics are compared against
+/// when looking for anomalies
+const ANOMALY_WINDOW: usize = 10;
+
+/// Record a transcript of every event exchanged with each shepherd into a
+/// file of its own in the given directory
+///
+/// Each transcript is named after the shepherd, the run it belongs to and
+/// the shepherd's id, as two shepherds may share a name. Runs are
+/// identified by the time they started, in seconds since the unix epoch
+fn record_transcripts(
+    shepherds: &mut [Shepherd],
+    directory: &Path,
+) -> anyhow::Result<()> {
+    let run_id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("Unable to determine when the run started")?
+        .as_secs();
+    fs::create_dir_all(directory)
+        .context("Unable to create the transcript directory")?;
 
+    for (id, shepherd) in shepherds.iter_mut().enumerate() {
+        // names may well be commands or addresses, so anything which could
+        // be mistaken for part of a path is replaced
+        let label = shepherd
+            .name()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let file = File::create(
+            directory.join(format!("{}-{}-{}.ndjson", label, run_id, id)),
+        )
+        .context("Unable to create a transcript file")?;
+        shepherd.record_transcript(file);
+    }
+
+    info!(run_id, "recording a transcript of every shepherd");
+    Ok(())
+}
+
+/// Write a JSON record of the feeds each shepherd built, the responses they
+/// received, the novelty and serendipity of their feeds, the time they took
+/// to build them, and their custom metrics during an epoch to a metrics
+/// stream, one per line
+fn emit_metrics(
+    stream: &mut dyn Write,
+    epoch: usize,
+    shepherd_names: &HashMap<ShepherdId, String>,
+    metrics: &HashMap<ShepherdId, (usize, ResponseCounts)>,
+    stats: &metrics::Stats,
+    latencies: &metrics::Latencies,
+    custom_metrics: &metrics::MetricReports,
+) -> anyhow::Result<()> {
+    let mut shepherds = shepherd_names.iter().collect::<Vec<_>>();
+    shepherds.sort_unstable_by_key(|(ShepherdId(id), _)| *id);
+
+    for (id, name) in shepherds {
+        let (n_feeds, responses) =
+            metrics.get(id).copied().unwrap_or_default();
+        let stats = stats.get(*id, EpochId(epoch));
+        let latency = latencies.get(*id, EpochId(epoch));
+        serde_json::to_writer(
+            &mut *stream,
+            &serde_json::json!({
+                "epoch": epoch,
+                "shepherd": id.0,
+                "name": name,
+                "feeds": n_feeds,
+                "timeouts": stats.map(|stats| stats.n_timeouts),
+                "responses": responses,
+                "novelty": stats.and_then(metrics::EpochStats::novelty),
+                "serendipity": stats.map(metrics::EpochStats::serendipity),
+                "regret": stats.and_then(metrics::EpochStats::regret),
+                "tag_entropy": stats.and_then(metrics::EpochStats::tag_entropy),
+                "drift": stats.and_then(metrics::EpochStats::drift),
+                "disparity": stats.and_then(metrics::EpochStats::disparity),
+                "item_latency":
+                    stats.and_then(metrics::EpochStats::item_latency),
+                "sheep_latency":
+                    stats.and_then(metrics::EpochStats::sheep_latency),
+                "latency_p50": latency.map(|l| l.p50.as_secs_f64()),
+                "latency_p95": latency.map(|l| l.p95.as_secs_f64()),
+                "latency_p99": latency.map(|l| l.p99.as_secs_f64()),
+                "metrics": custom_metrics
+                    .get(*id, EpochId(epoch))
+                    .map(|(name, value)| (name.to_string(), value.into()))
+                    .collect::<serde_json::Map<_, _>>(),
+            }),
+        )
+        .context("Unable to write a metrics record")?;
+        writeln!(stream).context("Unable to write a metrics record")?;
+    }
+
+    stream.flush().context("Unable to flush the metrics stream")
+}
+
+fn main() -> anyhow::Result<()> {
     let Args {
+        teaching,
+        tui,
+        stress,
+        seed,
+        baselines,
+        profile,
         n_epochs,
-        shepherds,
+        feed_size,
+        mut shepherds,
         database_file,
-    } = args::parse_args().context("Unable to parse arguments")?;
-    let mut shepherd_names = HashMap::new();
+        noisy_sheep_fraction,
+        noise,
+        hidden_tag_fraction,
+        graph_generator,
+        edge_semantics,
+        parent_tag_probability,
+        group_merge_probability,
+        group_split_probability,
+        serendipity_probability,
+        fatigue_rate,
+        interest_decay,
+        temperature_bounds,
+        distance_oracle,
+        reinforcement_probability,
+        explain_responses,
+        compute_budget,
+        output_quota,
+        request_timeout,
+        heartbeat,
+        timeout_fallback,
+        feed_validation,
+        abandon_on_timeout,
+        n_candidates,
+        item_access_budget,
+        max_nodes,
+        max_edges,
+        epoch_summaries,
+        ranking_metrics,
+        oracle_regret,
+        diversity_metrics,
+        drift_metrics,
+        cold_start_metrics,
+        graph_diffs,
+        tag_group_snapshots,
+        under_delivery_penalty,
+        under_delivery_notices,
+        response_batch_interval,
+        degradations,
+        retention,
+        seed_graph_file,
+        #[cfg(feature = "movielens")]
+        movielens_directory,
+        graph_file,
+        metrics_file,
+        report_file,
+        leaderboard_metric,
+        affinity_prefix,
+        affinity_epochs,
+        assertions,
+        emit_metrics_stream,
+        metrics_stream_file,
+        #[cfg(feature = "prometheus")]
+        prometheus_address,
+        anomaly_threshold,
+        transcript_directory,
+    } = match args::parse_args().context("Unable to parse arguments")? {
+        Command::Run(args) => *args,
+        Command::CheckRegression(args) => return regression::check(&args),
+        Command::Schema(args) => return schema::print(&args),
+        Command::Replay(args) => {
+            tracing_subscriber::fmt().with_writer(io::stderr).init();
+            return replay::run(args);
+        }
+        Command::Experiment(args) => {
+            tracing_subscriber::fmt().with_writer(io::stderr).init();
+            return experiment::run(&args);
+        }
+    };
+
+    // the dashboard takes the place of everything below a warning, which
+    // would otherwise scroll it off of the terminal
+    let max_level = if tui {
+        LevelFilter::WARN
+    } else {
+        LevelFilter::INFO
+    };
+    anyhow::ensure!(
+        !tui || io::stderr().is_terminal(),
+        "The dashboard can only be shown when standard error is a terminal"
+    );
+
+    // when profiling, every span is additionally recorded in the chrome trace
+    // format, which can be loaded into a flamegraph viewer such as perfetto.
+    // the trace is written out when the guard is dropped at the end of main
+    let _chrome_guard = if let Some(profile) = profile {
+        let (chrome_layer, guard) = ChromeLayerBuilder::new()
+            .file(profile)
+            .include_args(true)
+            .build();
+        tracing_subscriber::registry()
+            .with(fmt::layer().with_writer(io::stderr).with_filter(max_level))
+            .with(chrome_layer)
+            .init();
+
+        Some(guard)
+    } else {
+        tracing_subscriber::fmt()
+            .with_writer(io::stderr)
+            .with_max_level(max_level)
+            .init();
+
+        None
+    };
+
+    let seed_graph = seed_graph_file
+        .map(|seed_graph_file| {
+            SimulationGraph::from_json(BufReader::new(
+                File::open(seed_graph_file)
+                    .context("Unable to open the seed graph file")?,
+            ))
+        })
+        .transpose()
+        .context("Unable to load the seed graph")?;
+
+    #[cfg(feature = "movielens")]
+    let seed_graph = if let Some(movielens_directory) = movielens_directory {
+        anyhow::ensure!(
+            seed_graph.is_none(),
+            "Only one of --graph and --movielens may be given"
+        );
+
+        Some(
+            import_movielens(&movielens_directory)
+                .context("Unable to import the MovieLens dataset")?,
+        )
+    } else {
+        seed_graph
+    };
+
+    let shepherd_names = RefCell::new(HashMap::default());
+    let epoch_metrics = RefCell::new(HashMap::default());
+    let custom_metrics = RefCell::new(metrics::MetricReports::default());
+
+    anyhow::ensure!(
+        [teaching, stress.is_some(), seed.is_some()]
+            .into_iter()
+            .filter(|given| *given)
+            .count()
+            <= 1,
+        "Only one of --teaching, --stress and --seed may be given"
+    );
+    anyhow::ensure!(
+        !teaching || n_epochs.is_none(),
+        "-n or --n-epochs may not be given along with --teaching"
+    );
+
+    if let Some(n_bots) = stress {
+        shepherds.extend(stress::shepherds(n_bots));
+    }
+
+    // teaching mode runs a tiny world from a fixed seed so that it plays out
+    // the same way every time, and stress mode does the same with a large
+    // world so that its throughput can be compared between runs. any other
+    // world is generated from the seed given, or from one drawn at random
+    // and logged, so that every run can be reproduced
+    let seed = if teaching {
+        teaching::SEED
+    } else if stress.is_some() {
+        stress::SEED
+    } else if let Some(seed) = seed {
+        seed
+    } else {
+        let seed = rand::random();
+        info!(seed, "generating the world from a random seed");
+        seed
+    };
+
+    // baselines draw from random number generators of their own, which are
+    // seeded along with the world
+    shepherds.extend(
+        baselines
+            .into_iter()
+            .map(|baseline| baseline.shepherd(seed)),
+    );
+
+    if let Some(directory) = transcript_directory {
+        record_transcripts(&mut shepherds, &directory)
+            .context("Unable to set up the recording of transcripts")?;
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let n_epochs = n_epochs.unwrap_or_default();
+    let (n_epochs, base_settings) = if teaching {
+        (teaching::N_EPOCHS, teaching::settings())
+    } else if stress.is_some() {
+        (n_epochs, stress::settings())
+    } else {
+        (n_epochs, Settings::default())
+    };
+
+    // the metrics stream is opened up front, so that a run isn't wasted on a
+    // stream which can't be written to
+    let mut metrics_stream = metrics_stream_file
+        .map(|path| File::create(path).map(BufWriter::new))
+        .transpose()
+        .context("Unable to open the metrics stream")?;
+    let stream_metrics = emit_metrics_stream || metrics_stream.is_some();
 
     let duckdb = if let Some(database_file) = database_file {
         Connection::open(database_file)
@@ -73,6 +469,10 @@ fn main() -> anyhow::Result<()> {
                 item UINTEGER NOT NULL,
                 response response NOT NULL,
                 hops UINTEGER,
+                item_age UINTEGER,
+                serendipitous BOOLEAN NOT NULL,
+                path VARCHAR,
+                distance UINTEGER,
                 epoch UINTEGER NOT NULL,
                 PRIMARY KEY (sheep, shepherd, item)
             );
@@ -80,62 +480,905 @@ fn main() -> anyhow::Result<()> {
                 id UINTEGER PRIMARY KEY,
                 name VARCHAR
             );
+            CREATE TABLE sheep (
+                id UINTEGER PRIMARY KEY,
+                archetype VARCHAR NOT NULL,
+                temperature DOUBLE NOT NULL
+            );
+            CREATE TABLE noisy_sheep (
+                sheep UINTEGER PRIMARY KEY
+            );
+            CREATE TABLE degradations (
+                epoch UINTEGER PRIMARY KEY,
+                drop_probability DOUBLE NOT NULL,
+                delay_probability DOUBLE NOT NULL
+            );
+            CREATE TABLE world_stats (
+                epoch UINTEGER PRIMARY KEY,
+                n_sheep UINTEGER NOT NULL,
+                n_tags UINTEGER NOT NULL,
+                n_items UINTEGER NOT NULL,
+                n_edges UINTEGER NOT NULL,
+                density DOUBLE NOT NULL,
+                tag_clustering DOUBLE NOT NULL,
+                mean_sheep_degree DOUBLE NOT NULL,
+                max_sheep_degree UINTEGER NOT NULL,
+                mean_item_degree DOUBLE NOT NULL,
+                max_item_degree UINTEGER NOT NULL
+            );
+            CREATE TABLE group_recovery (
+                n_communities UINTEGER NOT NULL,
+                nmi DOUBLE NOT NULL,
+                ari DOUBLE NOT NULL
+            );
+            CREATE TABLE metrics (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                name VARCHAR NOT NULL,
+                higher_is_better BOOLEAN NOT NULL,
+                value DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd, name)
+            );
+            CREATE TABLE hidden_tags (
+                sheep UINTEGER NOT NULL,
+                tag UINTEGER NOT NULL,
+                PRIMARY KEY (sheep, tag)
+            );
+            CREATE TABLE discoveries (
+                shepherd UINTEGER NOT NULL,
+                epoch UINTEGER NOT NULL,
+                fraction DOUBLE NOT NULL,
+                PRIMARY KEY (shepherd, epoch)
+            );
+            CREATE TABLE anomalies (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                metric VARCHAR NOT NULL,
+                value DOUBLE NOT NULL,
+                mean DOUBLE NOT NULL,
+                deviation DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd, metric)
+            );
+            CREATE TABLE justifications (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                sheep UINTEGER NOT NULL,
+                item UINTEGER NOT NULL,
+                kind VARCHAR NOT NULL,
+                cited UINTEGER NOT NULL,
+                faithful BOOLEAN NOT NULL,
+                PRIMARY KEY (epoch, shepherd, sheep, item)
+            );
+            CREATE TABLE throughput (
+                n_shepherds UINTEGER NOT NULL,
+                n_feeds UBIGINT NOT NULL,
+                seconds DOUBLE NOT NULL,
+                feeds_per_second DOUBLE NOT NULL,
+                peak_memory UBIGINT
+            );
+            CREATE TABLE under_deliveries (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                sheep UINTEGER NOT NULL,
+                n_missing UINTEGER NOT NULL,
+                PRIMARY KEY (epoch, shepherd, sheep)
+            );
+            CREATE TABLE retention (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                n_active UINTEGER NOT NULL,
+                n_retained UINTEGER NOT NULL,
+                PRIMARY KEY (epoch, shepherd)
+            );
+            CREATE TABLE catalog_coverage (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                n_items UINTEGER NOT NULL,
+                n_recommended UINTEGER NOT NULL,
+                coverage DOUBLE NOT NULL,
+                gini DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd)
+            );
+            CREATE TABLE novelty (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                novelty DOUBLE,
+                serendipity DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd)
+            );
+            CREATE TABLE regret (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                regret DOUBLE NOT NULL,
+                relative_regret DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd)
+            );
+            CREATE TABLE tag_entropy (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                entropy DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd)
+            );
+            CREATE TABLE drift (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                drift DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd)
+            );
+            CREATE TABLE group_exposure (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                tag_group UINTEGER NOT NULL,
+                exposure_share DOUBLE NOT NULL,
+                catalog_share DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd, tag_group)
+            );
+            CREATE TABLE disparity (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                disparity DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd)
+            );
+            CREATE TABLE cold_start (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                item_latency DOUBLE,
+                sheep_latency DOUBLE,
+                PRIMARY KEY (epoch, shepherd)
+            );
+            CREATE TABLE feed_latency (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                p50 DOUBLE NOT NULL,
+                p95 DOUBLE NOT NULL,
+                p99 DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd)
+            );
+            CREATE TABLE epoch_metrics (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                n_feeds UINTEGER NOT NULL,
+                n_timeouts UINTEGER NOT NULL,
+                positive_rate DOUBLE NOT NULL,
+                neutral_rate DOUBLE NOT NULL,
+                negative_rate DOUBLE NOT NULL,
+                mean_hops DOUBLE,
+                unreachable_fraction DOUBLE NOT NULL,
+                fill_rate DOUBLE NOT NULL,
+                ndcg DOUBLE,
+                map DOUBLE,
+                mrr DOUBLE,
+                regret DOUBLE,
+                diversity DOUBLE,
+                novelty DOUBLE,
+                serendipity DOUBLE NOT NULL,
+                tag_entropy DOUBLE,
+                drift DOUBLE,
+                disparity DOUBLE,
+                item_latency DOUBLE,
+                sheep_latency DOUBLE,
+                latency_p50 DOUBLE,
+                latency_p95 DOUBLE,
+                latency_p99 DOUBLE,
+                PRIMARY KEY (epoch, shepherd)
+            );
+            CREATE TABLE request_timeouts (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                sheep UINTEGER NOT NULL,
+                PRIMARY KEY (epoch, shepherd, sheep)
+            );
+            CREATE TABLE feed_violations (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                sheep UINTEGER NOT NULL,
+                item UINTEGER NOT NULL,
+                kind VARCHAR NOT NULL
+            );
+            CREATE TABLE stage_metrics (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                sheep UINTEGER NOT NULL,
+                n_retrieved UINTEGER NOT NULL,
+                n_candidates UINTEGER NOT NULL,
+                retrieval_recall DOUBLE NOT NULL,
+                ranking_precision DOUBLE NOT NULL,
+                PRIMARY KEY (epoch, shepherd, sheep)
+            );
+            CREATE TABLE item_budget_exceedances (
+                epoch UINTEGER NOT NULL,
+                shepherd UINTEGER NOT NULL,
+                sheep UINTEGER NOT NULL,
+                n_items UINTEGER NOT NULL,
+                PRIMARY KEY (epoch, shepherd, sheep)
+            );
+            CREATE TABLE budget_exhaustions (
+                shepherd UINTEGER PRIMARY KEY,
+                epoch UINTEGER NOT NULL,
+                compute_time DOUBLE NOT NULL
+            );
             ",
         )
         .context("Unable to initialize duckdb")?;
     let epoch = AtomicUsize::new(0);
+    let n_feeds = Cell::new(0usize);
+    let item_epochs = RefCell::new(HashMap::default());
+    // hooks can't return errors, so the first one is kept here and returned
+    // once the epoch it happened during has been simulated
+    let database_error = RefCell::new(None::<anyhow::Error>);
 
     let SimulationParts {
-        graph: SimulationGraph(graph),
+        graph,
+        settings:
+            Settings {
+                archetypes,
+                feed_size,
+                ..
+            },
+        tag_groups,
+        profiles,
+        noisy_sheep,
+        hidden_tags,
+        shepherd_ids,
+        stats,
+        cold_start,
+        latencies,
         ..
     } = {
-        let mut simulation = Simulation::new(
-            &mut rand::thread_rng(),
+        let mut archetypes = base_settings.archetypes;
+        if let Some(bounds) = temperature_bounds {
+            for archetype in &mut archetypes {
+                archetype.temperature_bounds = bounds;
+            }
+        }
+        // a seed graph stands in for the randomly generated world
+        let new_simulation = |rng, shepherds, settings| match seed_graph {
+            Some(graph) => {
+                Simulation::with_graph(rng, graph, shepherds, settings)
+            }
+            None => Simulation::new(rng, shepherds, settings),
+        };
+        let mut simulation = new_simulation(
+            &mut rng,
             shepherds,
             Settings {
-                new_epoch_hook: Some(Box::new(|i, _| {
+                feed_size: feed_size.unwrap_or(base_settings.feed_size),
+                archetypes,
+                n_epochs: Some(n_epochs),
+                noisy_sheep_fraction,
+                noise,
+                hidden_tag_fraction,
+                graph_generator,
+                edge_semantics,
+                parent_tag_probability,
+                group_merge_probability,
+                group_split_probability,
+                sheep_behavior: Box::new(DistanceBehavior {
+                    serendipity_probability,
+                    fatigue_rate,
+                    oracle: distance_oracle,
+                    decay_rate: interest_decay,
+                    ..Default::default()
+                }),
+                reinforcement_probability,
+                explain_responses: explain_responses
+                    || base_settings.explain_responses,
+                shepherd_compute_budget: compute_budget,
+                shepherd_output_quota: output_quota,
+                shepherd_request_timeout: request_timeout,
+                shepherd_heartbeat: heartbeat,
+                timeout_fallback,
+                feed_validation,
+                abandon_timed_out_shepherds: abandon_on_timeout,
+                n_candidates,
+                item_access_budget,
+                max_nodes,
+                max_edges,
+                epoch_summaries,
+                ranking_metrics,
+                oracle_regret,
+                diversity_metrics,
+                drift_metrics,
+                cold_start_metrics,
+                graph_diffs,
+                tag_group_snapshots,
+                under_delivery_penalty,
+                under_delivery_notices,
+                response_batch_interval,
+                degradations,
+                retention,
+                new_epoch_hook: Some(Box::new(|i, data| {
                     info!("starting epoch {:?}", i);
                     epoch.store(i.0, Ordering::Release);
+                    item_epochs.borrow_mut().extend(
+                        data.items
+                            .iter()
+                            .map(|(GraphId(item, _), _)| (*item, i.0)),
+                    );
+
+                    if teaching {
+                        teaching::narrate_epoch(i, data);
+                    }
                 })),
-                feed_generation_hook: Some(Box::new(|i, j, _| {
+                feed_generation_hook: Some(Box::new(|i, j, feed| {
                     info!("a feed has been generated by shepherd {:?} for sheep {:?}", i, j);
+                    n_feeds.set(n_feeds.get() + 1);
+
+                    if teaching {
+                        teaching::narrate_feed(i, j, feed);
+                    }
                 })),
-                feed_rated_hook: Some(Box::new(|ShepherdId(i), GraphId(j, _), responses| {
-                    info!("a feed generated by shepherd {:?} has been rated by sheep {:?}", i, j);
+                under_delivery_hook: Some(Box::new(
+                    |ShepherdId(i), GraphId(j, _), n_missing| {
+                        // the missing items are counted as negative responses
+                        // here as well, so that the metrics stream agrees with
+                        // the epoch summaries. the ratings in the database, which
+                        // assertions and regression checks read, are left as is
+                        if under_delivery_penalty
+                            && (stream_metrics || anomaly_threshold.is_some())
+                        {
+                            let mut epoch_metrics = epoch_metrics.borrow_mut();
+                            let (_, counts): &mut (usize, ResponseCounts) =
+                                epoch_metrics.entry(ShepherdId(i)).or_default();
+                            counts.negative += n_missing;
+                        }
 
-                    for (GraphId(k, _), response, hops) in &responses.0 {
                         duckdb
+                        .execute(
+                            "INSERT INTO under_deliveries (epoch, shepherd, sheep, n_missing) VALUES (?, ?, ?, ?)",
+                            params![epoch.load(Ordering::Acquire), i, j, n_missing]
+                        )
+                        .expect("Unable to record an under-delivered feed in the database");
+                    },
+                )),
+                feed_violation_hook: Some(Box::new(
+                    |ShepherdId(i), GraphId(j, _), violation| {
+                        duckdb
+                        .execute(
+                            "INSERT INTO feed_violations (epoch, shepherd, sheep, item, kind) VALUES (?, ?, ?, ?, ?)",
+                            params![epoch.load(Ordering::Acquire), i, j, violation.item().0, violation.kind()]
+                        )
+                        .expect("Unable to record a feed violation in the database");
+                    },
+                )),
+                stage_metrics_hook: Some(Box::new(
+                    |ShepherdId(i), GraphId(j, _), metrics| {
+                        duckdb
+                        .execute(
+                            "INSERT INTO stage_metrics (epoch, shepherd, sheep, n_retrieved, n_candidates, retrieval_recall, ranking_precision) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                            params![
+                                epoch.load(Ordering::Acquire),
+                                i,
+                                j,
+                                metrics.n_retrieved,
+                                metrics.n_candidates,
+                                metrics.retrieval_recall,
+                                metrics.ranking_precision,
+                            ]
+                        )
+                        .expect("Unable to record the metrics of a two-stage feed in the database");
+                    },
+                )),
+                item_budget_exceeded_hook: Some(Box::new(
+                    |ShepherdId(i), GraphId(j, _), n_items| {
+                        duckdb
+                        .execute(
+                            "INSERT INTO item_budget_exceedances (epoch, shepherd, sheep, n_items) VALUES (?, ?, ?, ?)",
+                            params![epoch.load(Ordering::Acquire), i, j, n_items]
+                        )
+                        .expect("Unable to record a feed beyond the item access budget in the database");
+                    },
+                )),
+                feed_rated_hook: Some(Box::new(
+                    |ShepherdId(i),
+                     sheep @ GraphId(j, _),
+                     responses,
+                     from_shepherd| {
+                        info!("a feed generated by shepherd {:?} has been rated by sheep {:?}", i, j);
+
+                        if teaching {
+                            teaching::narrate_responses(sheep, responses);
+                        }
+
+                        // fallback feeds weren't built by the shepherd, so their
+                        // ratings are kept out of everything it is measured by
+                        if !from_shepherd {
+                            return;
+                        }
+
+                        if stream_metrics || anomaly_threshold.is_some() {
+                            let mut epoch_metrics = epoch_metrics.borrow_mut();
+                            let (n_feeds, counts): &mut (usize, ResponseCounts) =
+                                epoch_metrics.entry(ShepherdId(i)).or_default();
+                            *n_feeds += 1;
+                            for (_, response, ..) in &responses.0 {
+                                counts.record(*response);
+                            }
+                        }
+
+                        let epoch = epoch.load(Ordering::Acquire);
+                        for (
+                            GraphId(k, _),
+                            response,
+                            hops,
+                            serendipitous,
+                            explanation,
+                        ) in &responses.0
+                        {
+                            let item_age = item_epochs
+                                .borrow()
+                                .get(k)
+                                .map(|introduced| epoch - introduced);
+                            let (path, distance) = explanation
+                                .as_ref()
+                                .map(|Explanation { path, distance }| {
+                                    (
+                                        path.iter()
+                                            .map(|GraphId(tag, _)| {
+                                                tag.to_string()
+                                            })
+                                            .collect::<Vec<_>>()
+                                            .join(" "),
+                                        *distance,
+                                    )
+                                })
+                                .unzip();
+                            let inserted = duckdb
                             .execute(
-                                "INSERT INTO ratings (sheep, shepherd, item, response, hops, epoch) VALUES (?, ?, ?, ?, ?, ?)",
+                                "INSERT INTO ratings (sheep, shepherd, item, response, hops, item_age, serendipitous, path, distance, epoch) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
                                 params![j, i, k, match response {
                                     Response::Positive => "positive",
                                     Response::Neutral => "neutral",
                                     Response::Negative => "negative",
-                                }, hops, epoch.load(Ordering::Acquire)]
+                                }, hops, item_age, serendipitous, path, distance, epoch]
                             )
-                            .expect("Unable to insert a rating into the database");
+                            .context("Unable to insert a rating into the database");
+                            if let Err(error) = inserted {
+                                database_error.borrow_mut().get_or_insert(error);
+                            }
+                        }
+                    },
+                )),
+                justification_hook: Some(Box::new(
+                    |ShepherdId(i),
+                     GraphId(j, _),
+                     GraphId(k, _),
+                     justification,
+                     faithful| {
+                        let (kind, cited) = match justification {
+                            Justification::Tag(GraphId(tag, _)) => ("tag", tag),
+                            Justification::SimilarItem(GraphId(item, _)) => {
+                                ("similar_item", item)
+                            }
+                        };
+
+                        duckdb
+                        .execute(
+                            "INSERT INTO justifications (epoch, shepherd, sheep, item, kind, cited, faithful) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                            params![epoch.load(Ordering::Acquire), i, j, k, kind, cited, faithful]
+                        )
+                        .expect("Unable to insert a justification into the database");
+                    },
+                )),
+                request_timeout_hook: Some(Box::new(
+                    |ShepherdId(i), GraphId(j, _)| {
+                        duckdb
+                        .execute(
+                            "INSERT INTO request_timeouts (epoch, shepherd, sheep) VALUES (?, ?, ?)",
+                            params![epoch.load(Ordering::Acquire), i, j]
+                        )
+                        .expect("Unable to record a request timeout in the database");
+                    },
+                )),
+                budget_exhausted_hook: Some(Box::new(
+                    |ShepherdId(i), EpochId(j), compute_time| {
+                        info!("shepherd {:?} has exhausted its compute budget during epoch {:?}", i, j);
+
+                        duckdb
+                        .execute(
+                            "INSERT INTO budget_exhaustions (shepherd, epoch, compute_time) VALUES (?, ?, ?)",
+                            params![i, j, compute_time.as_secs_f64()]
+                        )
+                        .expect("Unable to record a budget exhaustion in the database");
+                    },
+                )),
+                degradation_hook: Some(Box::new(|EpochId(i), degradation| {
+                    duckdb
+                        .execute(
+                            "INSERT INTO degradations (epoch, drop_probability, delay_probability) VALUES (?, ?, ?)",
+                            params![i, degradation.drop_probability, degradation.delay_probability]
+                        )
+                        .expect("Unable to record a degradation in the database");
+                })),
+                world_stats_hook: Some(Box::new(|EpochId(i), stats| {
+                    duckdb
+                        .execute(
+                            "INSERT INTO world_stats (epoch, n_sheep, n_tags, n_items, n_edges, density, tag_clustering, mean_sheep_degree, max_sheep_degree, mean_item_degree, max_item_degree) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                            params![
+                                i,
+                                stats.n_sheep,
+                                stats.n_tags,
+                                stats.n_items,
+                                stats.n_edges,
+                                stats.density,
+                                stats.tag_clustering,
+                                stats.mean_sheep_degree,
+                                stats.max_sheep_degree,
+                                stats.mean_item_degree,
+                                stats.max_item_degree
+                            ]
+                        )
+                        .expect("Unable to record the world statistics in the database");
+                })),
+                discovery_hook: Some(Box::new(
+                    |ShepherdId(i), EpochId(j), fraction| {
+                        duckdb
+                        .execute(
+                            "INSERT INTO discoveries (shepherd, epoch, fraction) VALUES (?, ?, ?)",
+                            params![i, j, fraction]
+                        )
+                        .expect("Unable to record a discovery fraction in the database");
+                    },
+                )),
+                retention_hook: Some(Box::new(
+                    |EpochId(i), ShepherdId(j), n_active, n_retained| {
+                        duckdb
+                        .execute(
+                            "INSERT INTO retention (epoch, shepherd, n_active, n_retained) VALUES (?, ?, ?, ?)",
+                            params![i, j, n_active, n_retained]
+                        )
+                        .expect("Unable to record retention in the database");
+                    },
+                )),
+                catalog_coverage_hook: Some(Box::new(
+                    |EpochId(i), ShepherdId(j), coverage| {
+                        duckdb
+                        .execute(
+                            "INSERT INTO catalog_coverage (epoch, shepherd, n_items, n_recommended, coverage, gini) VALUES (?, ?, ?, ?, ?, ?)",
+                            params![
+                                i,
+                                j,
+                                coverage.n_items,
+                                coverage.n_recommended,
+                                coverage.coverage(),
+                                coverage.gini
+                            ]
+                        )
+                        .expect("Unable to record catalog coverage in the database");
+                    },
+                )),
+                group_exposure_hook: Some(Box::new(
+                    |EpochId(i), ShepherdId(j), exposure| {
+                        for group in 0..exposure.catalog.len() {
+                            if exposure.exposures[group] == 0
+                                && exposure.catalog[group] == 0
+                            {
+                                continue;
+                            }
+
+                            duckdb
+                            .execute(
+                                "INSERT INTO group_exposure (epoch, shepherd, tag_group, exposure_share, catalog_share) VALUES (?, ?, ?, ?, ?)",
+                                params![
+                                    i,
+                                    j,
+                                    group,
+                                    exposure.exposure_share(group),
+                                    exposure.catalog_share(group)
+                                ]
+                            )
+                            .expect("Unable to record group exposure in the database");
+                        }
+                    },
+                )),
+                metric_hook: Some(Box::new(|EpochId(i), metric| {
+                    for (ShepherdId(j), value) in metric.report() {
+                        duckdb
+                            .execute(
+                                "INSERT INTO metrics (epoch, shepherd, name, higher_is_better, value) VALUES (?, ?, ?, ?, ?)",
+                                params![i, j, metric.name(), metric.higher_is_better(), value]
+                            )
+                            .expect("Unable to record a metric in the database");
+                        custom_metrics.borrow_mut().record(
+                            ShepherdId(j),
+                            EpochId(i),
+                            metric,
+                            value,
+                        );
                     }
                 })),
                 shepherd_assignment_hook: Some(Box::new(|new_shepherds| {
-                    shepherd_names = new_shepherds;
-                    for (id, name) in &shepherd_names {
+                    let mut shepherd_names = shepherd_names.borrow_mut();
+                    *shepherd_names = new_shepherds;
+                    if teaching {
+                        teaching::narrate_shepherds(&shepherd_names);
+                    }
+
+                    for (id, name) in shepherd_names.iter() {
                         duckdb
                             .execute(
                                 "INSERT INTO shepherds (id, name) VALUES (?, ?)",
-                                params![id.0, name]
+                                params![id.0, name],
                             )
-                            .expect("Unable to update the shepherd name mappings");
+                            .expect(
+                                "Unable to update the shepherd name mappings",
+                            );
                     }
                 })),
-                ..Default::default()
+                ..base_settings
             },
         )
         .context("Unable to initialize the simulation")?;
 
-        for _ in 0..n_epochs {
-            simulation
-                .simulate_epoch(&mut rand::thread_rng())
-                .context("Unable to simulate an epoch")?;
+        let mut anomaly_monitor = anomaly_threshold
+            .map(|threshold| AnomalyMonitor::new(ANOMALY_WINDOW, threshold));
+
+        #[cfg(feature = "prometheus")]
+        let exporter = prometheus_address
+            .as_deref()
+            .map(prometheus::Exporter::serve)
+            .transpose()
+            .context("Unable to start the Prometheus endpoint")?;
+
+        let mut dashboard = tui.then(|| tui::Dashboard::new(n_epochs));
+        let start = Instant::now();
+        for epoch in 0..=n_epochs {
+            if epoch > 0 {
+                simulation
+                    .simulate_epoch(&mut rng)
+                    .context("Unable to simulate an epoch")?;
+                if let Some(error) = database_error.take() {
+                    return Err(error);
+                }
+
+                let metrics = epoch_metrics.take();
+                for id in shepherd_names.borrow().keys() {
+                    // feeds which timed out still have a latency, so this is
+                    // recorded even if the shepherd built no feeds at all
+                    let latency =
+                        simulation.latencies().get(*id, EpochId(epoch));
+                    if let Some(latency) = latency {
+                        duckdb
+                            .execute(
+                                "INSERT INTO feed_latency (epoch, shepherd, p50, p95, p99) VALUES (?, ?, ?, ?, ?)",
+                                params![
+                                    epoch,
+                                    id.0,
+                                    latency.p50.as_secs_f64(),
+                                    latency.p95.as_secs_f64(),
+                                    latency.p99.as_secs_f64()
+                                ],
+                            )
+                            .context("Unable to record feed latency in the database")?;
+                    }
+
+                    let Some(stats) =
+                        simulation.stats().get(*id, EpochId(epoch))
+                    else {
+                        continue;
+                    };
+
+                    duckdb
+                        .execute(
+                            "INSERT INTO epoch_metrics VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                            params![
+                                epoch,
+                                id.0,
+                                stats.n_feeds,
+                                stats.n_timeouts,
+                                stats.positive_rate(),
+                                stats.neutral_rate(),
+                                stats.negative_rate(),
+                                stats.mean_hops(),
+                                stats.unreachable_fraction(),
+                                stats.fill_rate(),
+                                stats.ndcg(),
+                                stats.map(),
+                                stats.mrr(),
+                                stats.regret(),
+                                stats.diversity(),
+                                stats.novelty(),
+                                stats.serendipity(),
+                                stats.tag_entropy(),
+                                stats.drift(),
+                                stats.disparity(),
+                                stats.item_latency(),
+                                stats.sheep_latency(),
+                                latency.map(|l| l.p50.as_secs_f64()),
+                                latency.map(|l| l.p95.as_secs_f64()),
+                                latency.map(|l| l.p99.as_secs_f64())
+                            ],
+                        )
+                        .context("Unable to record an epoch's metrics in the database")?;
+
+                    duckdb
+                        .execute(
+                            "INSERT INTO novelty (epoch, shepherd, novelty, serendipity) VALUES (?, ?, ?, ?)",
+                            params![
+                                epoch,
+                                id.0,
+                                stats.novelty(),
+                                stats.serendipity()
+                            ],
+                        )
+                        .context("Unable to record novelty in the database")?;
+
+                    if let Some(regret) = stats.regret() {
+                        duckdb
+                            .execute(
+                                "INSERT INTO regret (epoch, shepherd, regret, relative_regret) VALUES (?, ?, ?, ?)",
+                                params![
+                                    epoch,
+                                    id.0,
+                                    regret,
+                                    stats.relative_regret()
+                                ],
+                            )
+                            .context("Unable to record regret in the database")?;
+                    }
+
+                    if let Some(entropy) = stats.tag_entropy() {
+                        duckdb
+                            .execute(
+                                "INSERT INTO tag_entropy (epoch, shepherd, entropy) VALUES (?, ?, ?)",
+                                params![epoch, id.0, entropy],
+                            )
+                            .context("Unable to record tag entropy in the database")?;
+                    }
+
+                    if let Some(drift) = stats.drift() {
+                        duckdb
+                            .execute(
+                                "INSERT INTO drift (epoch, shepherd, drift) VALUES (?, ?, ?)",
+                                params![epoch, id.0, drift],
+                            )
+                            .context("Unable to record drift in the database")?;
+                    }
+
+                    if let Some(disparity) = stats.disparity() {
+                        duckdb
+                            .execute(
+                                "INSERT INTO disparity (epoch, shepherd, disparity) VALUES (?, ?, ?)",
+                                params![epoch, id.0, disparity],
+                            )
+                            .context("Unable to record disparity in the database")?;
+                    }
+
+                    let item_latency = stats.item_latency();
+                    let sheep_latency = stats.sheep_latency();
+                    if item_latency.is_some() || sheep_latency.is_some() {
+                        duckdb
+                            .execute(
+                                "INSERT INTO cold_start (epoch, shepherd, item_latency, sheep_latency) VALUES (?, ?, ?, ?)",
+                                params![epoch, id.0, item_latency, sheep_latency],
+                            )
+                            .context("Unable to record cold-start latency in the database")?;
+                    }
+                }
+
+                if let Some(monitor) = &mut anomaly_monitor {
+                    let mut metrics = metrics.iter().collect::<Vec<_>>();
+                    metrics.sort_unstable_by_key(|(ShepherdId(id), _)| *id);
+                    for (id, (n_feeds, counts)) in metrics {
+                        let value = counts.positive as f64 / *n_feeds as f64;
+                        let Some(anomaly) = monitor.observe(*id, value)
+                        else {
+                            continue;
+                        };
+
+                        warn!(
+                            shepherd = id.0,
+                            epoch = epoch,
+                            value = anomaly.value,
+                            mean = anomaly.mean,
+                            deviation = anomaly.deviation,
+                            "a shepherd's positive responses per feed changed anomalously"
+                        );
+                        duckdb
+                            .execute(
+                                "INSERT INTO anomalies (epoch, shepherd, metric, value, mean, deviation) VALUES (?, ?, ?, ?, ?, ?)",
+                                params![
+                                    epoch,
+                                    id.0,
+                                    "positive_per_feed",
+                                    anomaly.value,
+                                    anomaly.mean,
+                                    anomaly.deviation
+                                ],
+                            )
+                            .context("Unable to record an anomaly in the database")?;
+                    }
+                }
+
+                // the same records are written to stdout and to the side
+                // channel, if both are enabled
+                let mut stdout =
+                    emit_metrics_stream.then(|| io::stdout().lock());
+                for stream in [
+                    stdout.as_mut().map(|s| s as &mut dyn Write),
+                    metrics_stream.as_mut().map(|s| s as &mut dyn Write),
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    emit_metrics(
+                        stream,
+                        epoch,
+                        &shepherd_names.borrow(),
+                        &metrics,
+                        simulation.stats(),
+                        simulation.latencies(),
+                        &custom_metrics.borrow(),
+                    )?;
+                }
+
+                if let Some(dashboard) = &mut dashboard {
+                    dashboard.draw(
+                        epoch,
+                        &shepherd_names.borrow(),
+                        simulation.stats(),
+                        simulation.latencies(),
+                    )?;
+                }
+
+                #[cfg(feature = "prometheus")]
+                if let Some(exporter) = &exporter {
+                    let shepherd_names = shepherd_names.borrow();
+                    let mut shepherds =
+                        shepherd_names.iter().collect::<Vec<_>>();
+                    shepherds.sort_unstable_by_key(|(ShepherdId(id), _)| *id);
+                    let shepherds = shepherds
+                        .into_iter()
+                        .map(|(id, name)| prometheus::ShepherdState {
+                            name,
+                            epoch: simulation
+                                .stats()
+                                .get(*id, EpochId(epoch)),
+                            total: simulation.stats().total(*id),
+                            latency: simulation
+                                .latencies()
+                                .get(*id, EpochId(epoch)),
+                        })
+                        .collect::<Vec<_>>();
+                    exporter.update(
+                        epoch,
+                        n_feeds.get() as f64 / start.elapsed().as_secs_f64(),
+                        &shepherds,
+                    );
+                }
+            }
+
+            if let Some(prefix) = &affinity_prefix {
+                if affinity_epochs.contains(&epoch) {
+                    export_affinities(&simulation, prefix, epoch)
+                        .context("Unable to export an affinity matrix")?;
+                }
+            }
+        }
+
+        if let Some(n_bots) = stress {
+            let seconds = start.elapsed().as_secs_f64();
+            let feeds_per_second = n_feeds.get() as f64 / seconds;
+            let peak_memory = stress::peak_memory();
+            info!(
+                n_feeds = n_feeds.get(),
+                seconds = seconds,
+                feeds_per_second = feeds_per_second,
+                peak_memory = peak_memory,
+                "the stress run has finished"
+            );
+            duckdb
+                .execute(
+                    "INSERT INTO throughput (n_shepherds, n_feeds, seconds, feeds_per_second, peak_memory) VALUES (?, ?, ?, ?, ?)",
+                    params![
+                        n_bots,
+                        n_feeds.get(),
+                        seconds,
+                        feeds_per_second,
+                        peak_memory
+                    ],
+                )
+                .context("Unable to record the throughput in the database")?;
         }
 
         simulation
@@ -143,7 +1386,241 @@ fn main() -> anyhow::Result<()> {
             .context("Unable to cleanly stop the simulation")?
     };
 
-    println!("{:?}", Dot::new(&graph));
+    let shepherd_names = shepherd_names.into_inner();
+    let custom_metrics = custom_metrics.into_inner();
+    for id in shepherd_ids {
+        let total = stats.total(id);
+
+        // a falling entropy means the shepherd is narrowing the sheep into
+        // fewer tags as the run goes on, and a rising drift means it is
+        // pulling them away from their original interests
+        let epochs = stats.epochs(id);
+        let change = |metric: fn(&metrics::EpochStats) -> Option<f64>| {
+            let values = epochs
+                .iter()
+                .filter_map(|(_, stats)| metric(stats))
+                .collect::<Vec<_>>();
+            values
+                .first()
+                .zip(values.last())
+                .map(|(first, last)| last - first)
+        };
+
+        info!(
+            shepherd = shepherd_names.get(&id).map_or("", String::as_str),
+            n_feeds = total.n_feeds,
+            n_timeouts = total.n_timeouts,
+            positive_rate = total.positive_rate(),
+            neutral_rate = total.neutral_rate(),
+            negative_rate = total.negative_rate(),
+            mean_hops = ?total.mean_hops(),
+            unreachable_fraction = total.unreachable_fraction(),
+            fill_rate = total.fill_rate(),
+            ndcg = ?total.ndcg(),
+            map = ?total.map(),
+            mrr = ?total.mrr(),
+            regret = ?total.regret(),
+            relative_regret = ?total.relative_regret(),
+            diversity = ?total.diversity(),
+            novelty = ?total.novelty(),
+            serendipity = total.serendipity(),
+            tag_entropy = ?total.tag_entropy(),
+            tag_entropy_change = ?change(metrics::EpochStats::tag_entropy),
+            drift = ?total.drift(),
+            drift_change = ?change(metrics::EpochStats::drift),
+            disparity = ?total.disparity(),
+            item_latency = ?total.item_latency(),
+            sheep_latency = ?total.sheep_latency(),
+            n_unexposed_items =
+                ?cold_start_metrics.then(|| cold_start.n_unexposed(id)),
+            n_unserved_sheep =
+                ?cold_start_metrics.then(|| cold_start.n_unserved(id)),
+            latency = ?latencies.total(id),
+            "a shepherd's feeds over the whole run have been summarized"
+        );
+    }
+
+    // the leaderboard goes to stderr, as stdout is reserved for the metrics
+    // stream and the graph, and is emitted as a record of its own at the end
+    // of the metrics stream
+    let leaderboard = leaderboard::Leaderboard::new(
+        leaderboard_metric,
+        &shepherd_names,
+        &stats,
+        &latencies,
+        &custom_metrics,
+    );
+    if !shepherd_names.is_empty() {
+        leaderboard
+            .print(io::stderr().lock())
+            .context("Unable to print the leaderboard")?;
+    }
+    let mut stdout = emit_metrics_stream.then(|| io::stdout().lock());
+    for stream in [
+        stdout.as_mut().map(|s| s as &mut dyn Write),
+        metrics_stream.as_mut().map(|s| s as &mut dyn Write),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        serde_json::to_writer(
+            &mut *stream,
+            &serde_json::json!({ "leaderboard": leaderboard.to_json() }),
+        )
+        .context("Unable to write the leaderboard to the metrics stream")?;
+        writeln!(stream).context(
+            "Unable to write the leaderboard to the metrics stream",
+        )?;
+        stream
+            .flush()
+            .context("Unable to flush the metrics stream")?;
+    }
+
+    let recovery = graph.verify_groups(&tag_groups);
+    info!(
+        n_groups = tag_groups.len(),
+        n_communities = recovery.n_communities,
+        nmi = recovery.nmi,
+        ari = recovery.ari,
+        "the tag groups have been compared against the graph's communities"
+    );
+    duckdb
+        .execute(
+            "INSERT INTO group_recovery (n_communities, nmi, ari) VALUES (?, ?, ?)",
+            params![recovery.n_communities, recovery.nmi, recovery.ari],
+        )
+        .context("Unable to record the group recovery in the database")?;
+
+    for (GraphId(sheep, _), profile) in profiles {
+        duckdb
+            .execute(
+                "INSERT INTO sheep (id, archetype, temperature) VALUES (?, ?, ?)",
+                params![
+                    sheep,
+                    archetypes[profile.archetype].name,
+                    profile.temperature
+                ],
+            )
+            .context("Unable to record a sheep in the database")?;
+    }
+
+    for GraphId(sheep, _) in noisy_sheep {
+        duckdb
+            .execute("INSERT INTO noisy_sheep (sheep) VALUES (?)", [sheep])
+            .context("Unable to record the noisy sheep in the database")?;
+    }
+
+    for (GraphId(sheep, _), tags) in hidden_tags {
+        for GraphId(tag, _) in tags {
+            duckdb
+                .execute(
+                    "INSERT INTO hidden_tags (sheep, tag) VALUES (?, ?)",
+                    [sheep, tag],
+                )
+                .context("Unable to record a hidden tag in the database")?;
+        }
+    }
+
+    let mut n_failures = 0;
+    for assertion in &assertions {
+        let value = assertion.evaluate(&duckdb)?;
+        if assertion.holds(value) {
+            info!(assertion = ?assertion, value = ?value, "an assertion passed");
+        } else {
+            warn!(assertion = ?assertion, value = ?value, "an assertion failed");
+            n_failures += 1;
+        }
+    }
+
+    // the metrics file was checked to be named as a CSV or Parquet file when
+    // the arguments were parsed
+    if let Some(metrics_file) = metrics_file {
+        let format = match metrics_file.extension().and_then(|e| e.to_str()) {
+            Some("parquet") => "FORMAT PARQUET",
+            _ => "FORMAT CSV, HEADER",
+        };
+        let path = metrics_file
+            .to_str()
+            .context("The metrics file's path is not valid UTF-8")?
+            .replace('\'', "''");
+
+        // custom metrics are kept in a table of their own, so each is added
+        // as a column of its own, prefixed so as not to clash with the rest
+        let custom_columns = custom_metrics
+            .metrics()
+            .iter()
+            .map(|(name, _)| {
+                format!(
+                    ", (SELECT value FROM metrics WHERE metrics.epoch = epoch_metrics.epoch AND metrics.shepherd = epoch_metrics.shepherd AND metrics.name = '{}') AS \"metric_{}\"",
+                    name.replace('\'', "''"),
+                    name.replace('"', "\"\"")
+                )
+            })
+            .collect::<String>();
+        duckdb
+            .execute_batch(&format!(
+                "COPY (SELECT epoch_metrics.epoch, epoch_metrics.shepherd, shepherds.name, epoch_metrics.* EXCLUDE (epoch, shepherd){custom_columns} FROM epoch_metrics JOIN shepherds ON shepherds.id = epoch_metrics.shepherd ORDER BY epoch_metrics.epoch, epoch_metrics.shepherd) TO '{path}' ({format})"
+            ))
+            .context("Unable to write out the metrics file")?;
+        info!(
+            path = %metrics_file.display(),
+            "the metrics have been written out"
+        );
+    }
+
+    if let Some(report_file) = report_file {
+        report::write(
+            BufWriter::new(
+                File::create(&report_file)
+                    .context("Unable to create the report file")?,
+            ),
+            &report::Report {
+                command_line: &env::args().collect::<Vec<_>>(),
+                seed,
+                n_epochs,
+                feed_size,
+                shepherds: &shepherd_names,
+                stats: &stats,
+                custom_metrics: &custom_metrics,
+                leaderboard: &leaderboard,
+            },
+        )
+        .context("Unable to write out the report")?;
+        info!(
+            path = %report_file.display(),
+            "the report has been written out"
+        );
+    }
+
+    // the graph is written out as GraphML unless the file is named as a DOT
+    // or JSON file
+    if let Some(graph_file) = graph_file {
+        let writer = BufWriter::new(
+            File::create(&graph_file)
+                .context("Unable to create the graph file")?,
+        );
+        match graph_file.extension().and_then(|e| e.to_str()) {
+            Some("dot" | "gv") => graph.export_dot(writer),
+            Some("json") => graph.export_json(writer),
+            _ => graph.export_graphml(writer),
+        }
+        .context("Unable to export the graph")?;
+    }
+
+    // stdout is reserved for the metrics stream when it is enabled, and the
+    // graph would only bury the dashboard
+    if !emit_metrics_stream && !tui {
+        graph
+            .export_dot(io::stdout().lock())
+            .context("Unable to print the graph")?;
+    }
+
+    anyhow::ensure!(
+        n_failures == 0,
+        "{} of {} assertions failed",
+        n_failures,
+        assertions.len()
+    );
 
     Ok(())
 }
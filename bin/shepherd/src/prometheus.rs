@@ -0,0 +1,230 @@
+use anyhow::Context;
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tracing::{info, warn};
+
+use shepherd_lib::metrics::{EpochStats, LatencyPercentiles};
+
+/// The state of a single shepherd as of the latest epoch, as served by an
+/// [`Exporter`]
+pub struct ShepherdState<'a> {
+    /// The name the shepherd is labelled with
+    pub name: &'a str,
+
+    /// The stats of the shepherd's feeds during the latest epoch
+    pub epoch: Option<&'a EpochStats>,
+
+    /// The stats of the shepherd's feeds over every epoch so far
+    pub total: EpochStats,
+
+    /// The time the shepherd took to build its feeds during the latest
+    /// epoch
+    pub latency: Option<LatencyPercentiles>,
+}
+
+/// A small HTTP endpoint serving gauges and counters describing a run in
+/// the Prometheus text format, so that long runs can be monitored while they
+/// are in progress
+///
+/// Every path other than `/metrics` is answered with a 404. The metrics are
+/// only replaced at the end of each epoch, through [`Exporter::update`]
+pub struct Exporter(Arc<Mutex<String>>);
+
+impl Exporter {
+    /// Start serving metrics on the given address, on a thread of its own
+    pub fn serve(address: &str) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(address)
+            .context("Unable to bind the Prometheus endpoint")?;
+        let metrics = Arc::new(Mutex::new(String::new()));
+        let served = Arc::clone(&metrics);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                // the metrics are copied out first, so that a slow scraper
+                // never holds up the simulation
+                let metrics = served
+                    .lock()
+                    .expect("Unable to read the served metrics")
+                    .clone();
+
+                // a scraper which goes away mid-request only affects itself,
+                // so the endpoint keeps serving any others
+                if let Err(error) =
+                    stream.and_then(|stream| respond(stream, &metrics))
+                {
+                    warn!(
+                        error = %error,
+                        "unable to answer a request to the prometheus endpoint"
+                    );
+                }
+            }
+        });
+
+        info!(address, "serving prometheus metrics");
+        Ok(Self(metrics))
+    }
+
+    /// Replace the metrics served with those as of the end of an epoch
+    pub fn update(
+        &self,
+        epoch: usize,
+        feeds_per_second: f64,
+        shepherds: &[ShepherdState],
+    ) {
+        let mut metrics = String::new();
+
+        // writing to a string never fails, so the results are ignored
+        let _ = writeln!(
+            metrics,
+            "# HELP shepherd_epoch The epoch most recently simulated\n\
+             # TYPE shepherd_epoch gauge\n\
+             shepherd_epoch {epoch}\n\
+             # HELP shepherd_feeds_per_second The feeds built per second of \
+             the run so far\n\
+             # TYPE shepherd_feeds_per_second gauge\n\
+             shepherd_feeds_per_second {feeds_per_second}"
+        );
+
+        let _ = writeln!(
+            metrics,
+            "# HELP shepherd_feeds_total The feeds built by each shepherd\n\
+             # TYPE shepherd_feeds_total counter"
+        );
+        for shepherd in shepherds {
+            let _ = writeln!(
+                metrics,
+                "shepherd_feeds_total{{shepherd=\"{}\"}} {}",
+                escape(shepherd.name),
+                shepherd.total.n_feeds
+            );
+        }
+
+        let _ = writeln!(
+            metrics,
+            "# HELP shepherd_timeouts_total The requests for feeds each \
+             shepherd didn't answer in time\n\
+             # TYPE shepherd_timeouts_total counter"
+        );
+        for shepherd in shepherds {
+            let _ = writeln!(
+                metrics,
+                "shepherd_timeouts_total{{shepherd=\"{}\"}} {}",
+                escape(shepherd.name),
+                shepherd.total.n_timeouts
+            );
+        }
+
+        let _ = writeln!(
+            metrics,
+            "# HELP shepherd_responses_total The responses to the items of \
+             each shepherd's feeds\n\
+             # TYPE shepherd_responses_total counter"
+        );
+        for shepherd in shepherds {
+            let responses = shepherd.total.responses;
+            for (response, count) in [
+                ("positive", responses.positive),
+                ("neutral", responses.neutral),
+                ("negative", responses.negative),
+            ] {
+                let _ = writeln!(
+                    metrics,
+                    "shepherd_responses_total{{shepherd=\"{}\",response=\"{}\"}} {}",
+                    escape(shepherd.name),
+                    response,
+                    count
+                );
+            }
+        }
+
+        let _ = writeln!(
+            metrics,
+            "# HELP shepherd_positive_rate The fraction of responses to each \
+             shepherd's feeds during the latest epoch which were positive\n\
+             # TYPE shepherd_positive_rate gauge"
+        );
+        for shepherd in shepherds {
+            if let Some(stats) = shepherd.epoch {
+                let _ = writeln!(
+                    metrics,
+                    "shepherd_positive_rate{{shepherd=\"{}\"}} {}",
+                    escape(shepherd.name),
+                    stats.positive_rate()
+                );
+            }
+        }
+
+        let _ = writeln!(
+            metrics,
+            "# HELP shepherd_feed_latency_seconds The time each shepherd took \
+             to build its feeds during the latest epoch\n\
+             # TYPE shepherd_feed_latency_seconds gauge"
+        );
+        for shepherd in shepherds {
+            if let Some(latency) = shepherd.latency {
+                for (quantile, value) in [
+                    ("0.5", latency.p50),
+                    ("0.95", latency.p95),
+                    ("0.99", latency.p99),
+                ] {
+                    let _ = writeln!(
+                        metrics,
+                        "shepherd_feed_latency_seconds{{shepherd=\"{}\",quantile=\"{}\"}} {}",
+                        escape(shepherd.name),
+                        quantile,
+                        value.as_secs_f64()
+                    );
+                }
+            }
+        }
+
+        *self.0.lock().expect("Unable to update the served metrics") =
+            metrics;
+    }
+}
+
+/// The time to wait on a scraper to send its request before giving up on
+/// it, so that one which never does can't stop others from being answered
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Escape a label value in the Prometheus text format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Answer a single HTTP request with the metrics, if it asked for them
+fn respond(mut stream: TcpStream, metrics: &str) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // the headers are read through and ignored
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let (status, body) = match request_line.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", metrics),
+        _ => ("404 Not Found", ""),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n\
+         {body}",
+        body.len()
+    )?;
+    stream.flush()
+}
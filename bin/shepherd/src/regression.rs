@@ -0,0 +1,290 @@
+use anyhow::Context;
+use duckdb::{params, AccessMode, Config, Connection};
+use std::path::Path;
+
+use crate::{args::RegressionArgs, assertions::Metric};
+use shepherd_lib::feed::Response;
+
+/// The metrics compared between runs, along with whether higher values of
+/// each are better
+const METRICS: [(Metric, bool); 3] = [
+    (Metric::ResponseRate(Response::Positive), true),
+    (Metric::ResponseRate(Response::Negative), false),
+    (Metric::UnderDeliveryRate, false),
+];
+
+/// Check whether a metric regressed between runs
+///
+/// A metric regresses if it worsens by more than the threshold relative to
+/// its baseline value plus the tolerance, or if it has no value in the
+/// candidate run. The tolerance keeps metrics whose baseline value is zero
+/// from regressing on any change at all
+fn regressed(
+    before: f64,
+    after: Option<f64>,
+    higher_is_better: bool,
+    args: &RegressionArgs,
+) -> bool {
+    after.is_none_or(|after| {
+        if higher_is_better {
+            after < before * (1.0 - args.threshold) - args.tolerance
+        } else {
+            after > before * (1.0 + args.threshold) + args.tolerance
+        }
+    })
+}
+
+/// Print the comparison of a metric between runs, returning whether it
+/// regressed
+fn report(
+    shepherd: &str,
+    metric: &str,
+    before: f64,
+    after: Option<f64>,
+    higher_is_better: bool,
+    args: &RegressionArgs,
+) -> bool {
+    let regressed = regressed(before, after, higher_is_better, args);
+
+    println!(
+        "{} {}: {} -> {}{}",
+        shepherd,
+        metric,
+        before,
+        after.map_or("none".to_string(), |after| after.to_string()),
+        if regressed { " (regressed)" } else { "" }
+    );
+
+    regressed
+}
+
+/// Open the database of a run, which must already exist, without being able
+/// to change it
+fn open(path: &Path) -> anyhow::Result<Connection> {
+    anyhow::ensure!(
+        path.is_file(),
+        "No database was found at {}",
+        path.display()
+    );
+
+    Connection::open_with_flags(
+        path,
+        Config::default()
+            .access_mode(AccessMode::ReadOnly)
+            .context("Unable to configure the database connection")?,
+    )
+    .context("Unable to open the database")
+}
+
+/// Compare the metrics of each shepherd in a candidate run against those in
+/// a baseline run, failing if any of them regress
+///
+/// See [`regressed`] for when a metric regresses. Shepherds
+/// without ratings in the baseline run are skipped. Custom metrics are
+/// compared by their last recorded values, the throughput of stress runs is
+/// compared if the baseline run recorded it, and so is the recovery of tag
+/// groups from the final graph
+pub fn check(args: &RegressionArgs) -> anyhow::Result<()> {
+    let baseline = open(&args.baseline)
+        .context("Unable to open the baseline run's database")?;
+    let candidate = open(&args.candidate)
+        .context("Unable to open the candidate run's database")?;
+
+    let shepherds = baseline
+        .prepare("SELECT DISTINCT name FROM shepherds ORDER BY name")
+        .context("Unable to prepare a statement")?
+        .query_map(params![], |row| row.get(0))
+        .context("Unable to retrieve the shepherds of the baseline run")?
+        .collect::<Result<Vec<String>, _>>()
+        .context("Unable to retrieve the shepherds of the baseline run")?;
+
+    let mut n_regressions = 0;
+    for shepherd in &shepherds {
+        for (metric, higher_is_better) in METRICS {
+            let Some(before) = metric.evaluate(&baseline, shepherd)? else {
+                continue;
+            };
+            let after = metric.evaluate(&candidate, shepherd)?;
+
+            if report(
+                shepherd,
+                &metric.to_string(),
+                before,
+                after,
+                higher_is_better,
+                args,
+            ) {
+                n_regressions += 1;
+            }
+        }
+    }
+
+    // custom metrics are compared by their values at the last epoch they were
+    // recorded in the baseline run
+    let custom_metrics = baseline
+        .prepare(
+            "
+            SELECT shepherds.name, metrics.name, metrics.higher_is_better, metrics.value
+            FROM metrics
+            JOIN shepherds ON metrics.shepherd = shepherds.id
+            WHERE metrics.epoch = (SELECT max(epoch) FROM metrics)
+            ORDER BY shepherds.name, metrics.name
+            ",
+        )
+        .context("Unable to prepare a statement")?
+        .query_map(params![], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })
+        .context("Unable to retrieve the metrics of the baseline run")?
+        .collect::<Result<Vec<(String, String, bool, f64)>, _>>()
+        .context("Unable to retrieve the metrics of the baseline run")?;
+
+    for (shepherd, metric, higher_is_better, before) in custom_metrics {
+        let after = candidate
+            .query_row(
+                "
+                SELECT arg_max(metrics.value, metrics.epoch)
+                FROM metrics
+                JOIN shepherds ON metrics.shepherd = shepherds.id
+                WHERE shepherds.name = ? AND metrics.name = ?
+                ",
+                params![shepherd, metric],
+                |row| row.get(0),
+            )
+            .context("Unable to retrieve a metric of the candidate run")?;
+
+        if report(&shepherd, &metric, before, after, higher_is_better, args) {
+            n_regressions += 1;
+        }
+    }
+
+    // the throughput of stress runs is compared as though it were a metric of
+    // the simulator itself
+    let throughput = |connection: &Connection| {
+        connection
+            .prepare("SELECT feeds_per_second, peak_memory FROM throughput")
+            .context("Unable to prepare a statement")?
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .context("Unable to retrieve the throughput of a run")?
+            .next()
+            .transpose()
+            .context("Unable to retrieve the throughput of a run")
+    };
+
+    let throughput_before: Option<(f64, Option<f64>)> =
+        throughput(&baseline)?;
+    if let Some((feeds_per_second, peak_memory)) = throughput_before {
+        let after: Option<(f64, Option<f64>)> = throughput(&candidate)?;
+        if report(
+            "simulator",
+            "feeds_per_second",
+            feeds_per_second,
+            after.map(|(after, _)| after),
+            true,
+            args,
+        ) {
+            n_regressions += 1;
+        }
+
+        if let Some(peak_memory) = peak_memory {
+            if report(
+                "simulator",
+                "peak_memory",
+                peak_memory,
+                after.and_then(|(_, after)| after),
+                false,
+                args,
+            ) {
+                n_regressions += 1;
+            }
+        }
+    }
+
+    // likewise, the recovery of tag groups guards the graph generator
+    let recovery = |connection: &Connection| {
+        connection
+            .prepare("SELECT nmi, ari FROM group_recovery")
+            .context("Unable to prepare a statement")?
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .context("Unable to retrieve the group recovery of a run")?
+            .next()
+            .transpose()
+            .context("Unable to retrieve the group recovery of a run")
+    };
+
+    let recovery_before: Option<(f64, f64)> = recovery(&baseline)?;
+    if let Some((nmi, ari)) = recovery_before {
+        let after: Option<(f64, f64)> = recovery(&candidate)?;
+        for (metric, before, after) in [
+            ("nmi", nmi, after.map(|(after, _)| after)),
+            ("ari", ari, after.map(|(_, after)| after)),
+        ] {
+            if report("generator", metric, before, after, true, args) {
+                n_regressions += 1;
+            }
+        }
+    }
+
+    anyhow::ensure!(
+        n_regressions == 0,
+        "The candidate run regressed on {} metrics",
+        n_regressions
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(threshold: f64, tolerance: f64) -> RegressionArgs {
+        RegressionArgs {
+            baseline: "baseline.db".into(),
+            candidate: "candidate.db".into(),
+            threshold,
+            tolerance,
+        }
+    }
+
+    #[test]
+    fn worsening_beyond_the_threshold_regresses() {
+        let args = args(0.1, 0.0);
+        assert!(!regressed(0.5, Some(0.46), true, &args));
+        assert!(regressed(0.5, Some(0.44), true, &args));
+        assert!(!regressed(0.5, Some(0.54), false, &args));
+        assert!(regressed(0.5, Some(0.56), false, &args));
+    }
+
+    #[test]
+    fn improvements_never_regress() {
+        let args = args(0.0, 0.0);
+        assert!(!regressed(0.5, Some(0.9), true, &args));
+        assert!(!regressed(0.5, Some(0.1), false, &args));
+    }
+
+    #[test]
+    fn missing_values_regress() {
+        assert!(regressed(0.5, None, true, &args(1.0, 1.0)));
+    }
+
+    #[test]
+    fn tolerance_covers_zero_baselines() {
+        assert!(regressed(0.0, Some(0.01), false, &args(0.5, 0.0)));
+        assert!(!regressed(0.0, Some(0.01), false, &args(0.5, 0.02)));
+        assert!(regressed(0.0, Some(0.03), false, &args(0.5, 0.02)));
+    }
+
+    #[test]
+    fn missing_databases_are_rejected() {
+        let directory = std::env::temp_dir();
+        let args = RegressionArgs {
+            baseline: directory.join("shepherd-missing-baseline.db"),
+            candidate: directory.join("shepherd-missing-candidate.db"),
+            threshold: 0.0,
+            tolerance: 0.0,
+        };
+        assert!(check(&args).is_err());
+        assert!(!args.baseline.exists());
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{bail, Context};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+};
+use tracing::{info, warn};
+
+use crate::args::ReplayArgs;
+use shepherd_lib::{
+    collections::HashMap,
+    ids::ItemId,
+    protocol::EventReader,
+    shepherd::{
+        Capability, Shepherd, ShepherdEvent, SimulationEvent, TranscriptEvent,
+    },
+};
+
+/// Get the items of every feed in a response to a request for feeds, leaving
+/// out any justifications, or the candidates retrieved for a feed
+fn feeds(event: &ShepherdEvent) -> Vec<Vec<ItemId>> {
+    match event {
+        ShepherdEvent::Feed { feed, .. } => vec![feed.0.clone()],
+        ShepherdEvent::JustifiedFeed { items, .. } => {
+            vec![items.iter().map(|(item, _)| *item).collect()]
+        }
+        ShepherdEvent::FeedBatch { feeds, .. } => {
+            feeds.iter().map(|feed| feed.0.clone()).collect()
+        }
+        ShepherdEvent::JustifiedFeedBatch { feeds, .. } => feeds
+            .iter()
+            .map(|items| items.iter().map(|(item, _)| *item).collect())
+            .collect(),
+        ShepherdEvent::Candidates { items, .. } => vec![items.clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// Replay the events sent to a shepherd in a transcript into another
+/// shepherd, writing out a transcript of the new exchange
+///
+/// Feeds which differ from those recorded in the transcript are reported,
+/// so a shepherd can be checked against an earlier run of itself without
+/// running the simulation again
+pub fn run(args: ReplayArgs) -> anyhow::Result<()> {
+    let mut transcript =
+        EventReader::<_, TranscriptEvent>::new(BufReader::new(
+            File::open(&args.transcript)
+                .context("Unable to open the transcript")?,
+        ));
+    let (mut sent, mut recorded) = (Vec::new(), HashMap::default());
+    while let Some(event) = transcript
+        .read_event()
+        .context("Unable to read an event from the transcript")?
+    {
+        match event {
+            TranscriptEvent::Sent(event) => sent.push(event),
+            TranscriptEvent::Received(event) => {
+                if let Some(request_id) = event.request_id() {
+                    recorded.insert(request_id, feeds(&event));
+                }
+            }
+        }
+    }
+
+    let mut shepherd = Shepherd::command(&args.program)
+        .args(&args.args)
+        .spawn()
+        .context("Unable to build a shepherd from the given command")?;
+    match &args.output {
+        Some(path) => shepherd.record_transcript(
+            File::create(path).context("Unable to create the output file")?,
+        ),
+        None => shepherd.record_transcript(io::stdout()),
+    }
+    shepherd.greet().context("Unable to greet the shepherd")?;
+
+    // batches are replayed exactly as they were sent, so a shepherd which
+    // doesn't accept them can't be given the transcript at all
+    if !shepherd.supports(Capability::FeedBatches)
+        && sent.iter().any(|event| {
+            matches!(event, SimulationEvent::FeedRequestBatch { .. })
+        })
+    {
+        bail!(
+            "The transcript holds batches of feed requests, which the \
+             shepherd does not accept"
+        );
+    }
+    if !shepherd.supports(Capability::ResponseBatches)
+        && sent.iter().any(|event| {
+            matches!(event, SimulationEvent::ResponsesBatch { .. })
+        })
+    {
+        bail!(
+            "The transcript holds batches of responses, which the shepherd \
+             does not accept"
+        );
+    }
+    if !shepherd.supports(Capability::TwoStageFeeds)
+        && sent.iter().any(|event| {
+            matches!(event, SimulationEvent::RetrieveCandidates { .. })
+        })
+    {
+        bail!(
+            "The transcript holds feeds built in two stages, which the \
+             shepherd does not build"
+        );
+    }
+
+    let (mut n_requests, mut n_differing, mut n_unrecorded) = (0, 0, 0);
+    for event in sent {
+        let request_id = match &event {
+            // the shepherd is greeted and shut down by the replay itself,
+            // and pings depend on the timing of the run being replayed
+            SimulationEvent::Hello { .. }
+            | SimulationEvent::Shutdown
+            | SimulationEvent::Ping => continue,
+            SimulationEvent::FeedRequest { request_id, .. }
+            | SimulationEvent::FeedRequestBatch { request_id, .. }
+            | SimulationEvent::RetrieveCandidates { request_id, .. }
+            | SimulationEvent::RankCandidates { request_id, .. } => {
+                Some(*request_id)
+            }
+            _ => None,
+        };
+
+        shepherd.write_event(&event);
+        let Some(request_id) = request_id else {
+            continue;
+        };
+
+        let response = shepherd
+            .read_event()
+            .context("Unable to read the response of the shepherd")?;
+        if response.request_id() != Some(request_id) {
+            bail!(
+                "The shepherd did not respond to request {} of the transcript",
+                request_id.0
+            );
+        }
+
+        n_requests += 1;
+        match recorded.get(&request_id) {
+            Some(recorded) if *recorded != feeds(&response) => {
+                n_differing += 1;
+                warn!(
+                    request = request_id.0,
+                    "the shepherd's response differs from the one recorded"
+                );
+            }
+            Some(_) => {}
+            None => n_unrecorded += 1,
+        }
+    }
+
+    shepherd.stop().context("Unable to stop the shepherd")?;
+
+    info!(
+        n_requests,
+        n_differing, n_unrecorded, "replayed the transcript"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,280 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+use crate::leaderboard::Leaderboard;
+use shepherd_lib::{
+    collections::HashMap,
+    ids::ShepherdId,
+    metrics::{EpochStats, MetricReports, Stats},
+};
+
+/// The width of each plot, in pixels
+const PLOT_WIDTH: f64 = 640.0;
+
+/// The height of each plot, in pixels
+const PLOT_HEIGHT: f64 = 240.0;
+
+/// The space left around each plot for its axis labels, in pixels
+const PLOT_MARGIN: f64 = 48.0;
+
+/// The colors each shepherd's line is drawn in, in order, wrapping around
+/// if there are more shepherds than colors
+const COLORS: &[&str] = &[
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b",
+    "#e377c2", "#7f7f7f", "#bcbd22", "#17becf",
+];
+
+/// A metric of a shepherd's feeds during an epoch, if it was measured
+type Metric = fn(&EpochStats) -> Option<f64>;
+
+/// The metrics plotted over the epochs of a run, each of which is left out
+/// if it was never measured
+const PLOTTED_METRICS: &[(&str, Metric)] = &[
+    ("positive rate", |stats| Some(stats.positive_rate())),
+    ("fill rate", |stats| Some(stats.fill_rate())),
+    ("nDCG", EpochStats::ndcg),
+    ("regret", EpochStats::regret),
+    ("diversity", EpochStats::diversity),
+    ("novelty", EpochStats::novelty),
+    ("tag entropy", EpochStats::tag_entropy),
+    ("drift", EpochStats::drift),
+    ("disparity", EpochStats::disparity),
+];
+
+/// Everything about a finished run which goes into its report
+pub struct Report<'a> {
+    /// The command line the run was started with
+    pub command_line: &'a [String],
+
+    /// The seed the world was generated from
+    pub seed: u64,
+
+    /// The number of epochs simulated
+    pub n_epochs: usize,
+
+    /// The number of items in each feed
+    pub feed_size: usize,
+
+    /// The name of each shepherd
+    pub shepherds: &'a HashMap<ShepherdId, String>,
+
+    /// The stats of each shepherd's feeds during each epoch
+    pub stats: &'a Stats,
+
+    /// The values each custom metric reported for each shepherd at the end
+    /// of each epoch
+    pub custom_metrics: &'a MetricReports,
+
+    /// The shepherds, ranked by the metric chosen for the run
+    pub leaderboard: &'a Leaderboard,
+}
+
+impl Report<'_> {
+    /// The shepherds in the order they were added, which is the order their
+    /// colors are picked in
+    fn shepherds(&self) -> Vec<(ShepherdId, &str)> {
+        let mut shepherds = self
+            .shepherds
+            .iter()
+            .map(|(id, name)| (*id, name.as_str()))
+            .collect::<Vec<_>>();
+        shepherds.sort_unstable_by_key(|(ShepherdId(id), _)| *id);
+        shepherds
+    }
+}
+
+/// Escape text for use in HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Draw a line plot of a metric over the epochs of each shepherd as SVG,
+/// given its value at each epoch for each shepherd in the order they were
+/// added, or [`None`] if no shepherd has a value for it
+fn plot(series: &[Vec<(f64, f64)>]) -> Option<String> {
+    let points = series.iter().flatten();
+    let (min_x, max_x) = points
+        .clone()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (x, _)| {
+            (min.min(*x), max.max(*x))
+        });
+    let (min_y, max_y) = points
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (_, y)| {
+            (min.min(*y), max.max(*y))
+        });
+    if !min_x.is_finite() {
+        return None;
+    }
+
+    // flat series are given some room, so that they are drawn across the
+    // middle of the plot instead of along an edge
+    let (min_y, max_y) = if max_y > min_y {
+        (min_y, max_y)
+    } else {
+        (min_y - 1.0, max_y + 1.0)
+    };
+    let x_span = (max_x - min_x).max(1.0);
+    let scale = |(x, y): (f64, f64)| {
+        (
+            PLOT_MARGIN
+                + (x - min_x) / x_span * (PLOT_WIDTH - 2.0 * PLOT_MARGIN),
+            PLOT_HEIGHT
+                - PLOT_MARGIN
+                - (y - min_y) / (max_y - min_y)
+                    * (PLOT_HEIGHT - 2.0 * PLOT_MARGIN),
+        )
+    };
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{PLOT_WIDTH}\" \
+         height=\"{PLOT_HEIGHT}\" font-size=\"11\">"
+    );
+    let (left, bottom) = scale((min_x, min_y));
+    let (right, top) = scale((max_x.max(min_x + 1.0), max_y));
+
+    // writing to a string never fails, so the results are ignored
+    let _ = write!(
+        svg,
+        "<polyline fill=\"none\" stroke=\"#999\" \
+         points=\"{left},{top} {left},{bottom} {right},{bottom}\"/>\
+         <text x=\"{}\" y=\"{top}\" text-anchor=\"end\">{max_y:.3}</text>\
+         <text x=\"{}\" y=\"{bottom}\" text-anchor=\"end\">{min_y:.3}</text>\
+         <text x=\"{left}\" y=\"{}\">epoch {min_x}</text>\
+         <text x=\"{right}\" y=\"{}\" text-anchor=\"end\">epoch {max_x}</text>",
+        left - 4.0,
+        left - 4.0,
+        bottom + 16.0,
+        bottom + 16.0,
+    );
+    for (i, points) in series.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let points = points
+            .iter()
+            .map(|point| {
+                let (x, y) = scale(*point);
+                format!("{x:.1},{y:.1}")
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = write!(
+            svg,
+            "<polyline fill=\"none\" stroke=\"{color}\" stroke-width=\"2\" \
+             points=\"{points}\"/>"
+        );
+    }
+    svg.push_str("</svg>");
+
+    Some(svg)
+}
+
+/// Write a report of a finished run as a self-contained HTML file, with
+/// plots of each shepherd's metrics over the epochs, a leaderboard of the
+/// shepherds, and the settings the run was started with
+pub fn write(mut writer: impl Write, report: &Report) -> io::Result<()> {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>shepherd report</title>\n<style>\
+         body { font-family: sans-serif; margin: 2em; }\
+         table { border-collapse: collapse; }\
+         th, td { padding: 0.25em 0.75em; text-align: right; }\
+         th:first-child, td:first-child { text-align: left; }\
+         tr:nth-child(even) { background: #f4f4f4; }\
+         .plots { display: flex; flex-wrap: wrap; gap: 1em; }\
+         </style>\n</head>\n<body>\n<h1>shepherd report</h1>\n",
+    );
+
+    let _ = write!(
+        html,
+        "<h2>settings</h2>\n<table>\n\
+         <tr><td>command line</td><td><code>{}</code></td></tr>\n\
+         <tr><td>seed</td><td>{}</td></tr>\n\
+         <tr><td>epochs</td><td>{}</td></tr>\n\
+         <tr><td>feed size</td><td>{}</td></tr>\n</table>\n",
+        escape(&report.command_line.join(" ")),
+        report.seed,
+        report.n_epochs,
+        report.feed_size,
+    );
+
+    let columns = report.leaderboard.columns();
+    html.push_str("<h2>leaderboard</h2>\n<table>\n<tr><th>shepherd</th>");
+    for (column, _) in &columns {
+        let _ = write!(html, "<th>{column}</th>");
+    }
+    html.push_str("</tr>\n");
+    for (rank, (name, values)) in report.leaderboard.rows.iter().enumerate() {
+        let _ = write!(html, "<tr><td>{}. {}</td>", rank + 1, escape(name));
+        for (column, i) in &columns {
+            let _ = write!(
+                html,
+                "<td>{}</td>",
+                values[*i].map_or_else(
+                    || "-".to_string(),
+                    |value| column.format(value)
+                )
+            );
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n<h2>metrics over time</h2>\n<p>");
+    for (i, (_, name)) in report.shepherds().into_iter().enumerate() {
+        let _ = write!(
+            html,
+            "<span style=\"color: {}\">&#9632;</span> {} ",
+            COLORS[i % COLORS.len()],
+            escape(name)
+        );
+    }
+    html.push_str("</p>\n<div class=\"plots\">\n");
+    let shepherds = report.shepherds();
+    for (title, metric) in PLOTTED_METRICS {
+        let series = shepherds
+            .iter()
+            .map(|(id, _)| {
+                report
+                    .stats
+                    .epochs(*id)
+                    .into_iter()
+                    .filter_map(|(epoch, stats)| {
+                        metric(&stats).map(|value| (epoch.0 as f64, value))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        if let Some(svg) = plot(&series) {
+            let _ = writeln!(
+                html,
+                "<figure><figcaption>{title}</figcaption>{svg}</figure>"
+            );
+        }
+    }
+    for (name, _) in report.custom_metrics.metrics() {
+        let series = shepherds
+            .iter()
+            .map(|(id, _)| {
+                report
+                    .custom_metrics
+                    .epochs(*id, name)
+                    .into_iter()
+                    .map(|(epoch, value)| (epoch.0 as f64, value))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        if let Some(svg) = plot(&series) {
+            let _ = writeln!(
+                html,
+                "<figure><figcaption>{}</figcaption>{svg}</figure>",
+                escape(name)
+            );
+        }
+    }
+    html.push_str("</div>\n</body>\n</html>\n");
+
+    writer.write_all(html.as_bytes())?;
+    writer.flush()
+}
@@ -0,0 +1,23 @@
+use anyhow::Context;
+use schemars::schema_for;
+use std::io;
+
+use crate::args::{Events, SchemaArgs};
+use shepherd_lib::shepherd::{
+    ShepherdEvent, SimulationEvent, TranscriptEvent,
+};
+
+/// Print the JSON Schema of the events sent in one direction (or of the
+/// events of a transcript), which describes them as they are encoded in
+/// JSON
+pub fn print(args: &SchemaArgs) -> anyhow::Result<()> {
+    let schema = match args.events {
+        Events::Simulation => schema_for!(SimulationEvent),
+        Events::Shepherd => schema_for!(ShepherdEvent),
+        Events::Transcript => schema_for!(TranscriptEvent),
+    };
+    serde_json::to_writer_pretty(io::stdout().lock(), &schema)
+        .context("Unable to write the schema")?;
+    println!();
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::fs;
+
+use shepherd_lib::{
+    baseline::Baseline, shepherd::Shepherd, simulation::Settings,
+};
+
+/// The seed used to generate the world in stress mode, so that runs are
+/// comparable with one another
+pub const SEED: u64 = 0x57e55;
+
+/// Create the given number of built-in shepherds to run in stress mode
+///
+/// Each runs [`Baseline::Random`] with a seed of its own, which does almost
+/// no work besides remembering what each sheep has been shown
+pub fn shepherds(n_shepherds: usize) -> Vec<Shepherd> {
+    (0..n_shepherds)
+        .map(|i| {
+            Baseline::Random
+                .shepherd(SEED + i as u64)
+                .named(format!("bot-{}", i))
+        })
+        .collect()
+}
+
+/// Settings describing the large world used in stress mode
+///
+/// This world starts with 1000 sheep and 5000 items, and gains another 500
+/// items every epoch
+pub fn settings<'a>() -> Settings<'a> {
+    Settings {
+        n_tags_bounds: (10, 10),
+        n_items_bounds: (500, 500),
+        initial_n_tags_bounds: (500, 500),
+        initial_n_items_bounds: (5000, 5000),
+        initial_n_sheep_bounds: (1000, 1000),
+        ..Default::default()
+    }
+}
+
+/// Get the peak resident memory of this process in bytes, if the platform
+/// reports it
+pub fn peak_memory() -> Option<u64> {
+    // this is only available on linux, through procfs
+    fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(|kibibytes| kibibytes * 1024)
+}
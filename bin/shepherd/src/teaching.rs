@@ -0,0 +1,138 @@
+use shepherd_lib::{
+    collections::HashMap,
+    feed::{Explanation, Feed, Response, Responses},
+    ids::{EpochId, GraphId, SheepId, ShepherdId},
+    sheep::Archetype,
+    simulation::{Epoch, Settings},
+};
+
+/// The seed used to generate the world in teaching mode
+pub const SEED: u64 = 0x5ee9;
+
+/// The number of epochs simulated in teaching mode
+pub const N_EPOCHS: usize = 3;
+
+/// Settings describing the tiny world used in teaching mode
+///
+/// Over the course of [`N_EPOCHS`] epochs, this world never holds more than
+/// 10 sheep or 30 items
+pub fn settings<'a>() -> Settings<'a> {
+    Settings {
+        feed_size: 3,
+        n_tags_bounds: (0, 1),
+        n_items_bounds: (0, 3),
+        n_item_tags_bounds: (1, 2),
+        initial_n_tags_bounds: (8, 8),
+        initial_n_items_bounds: (12, 12),
+        initial_n_sheep_bounds: (4, 4),
+        average_tags_per_group: 3,
+        orphaned_tag_threshold: 8,
+        archetypes: vec![Archetype {
+            n_tags_bounds: (2, 3),
+            ..Default::default()
+        }],
+        explain_responses: true,
+        ..Default::default()
+    }
+}
+
+/// Format a list of identifiers for narration
+fn list(ids: impl IntoIterator<Item = usize>) -> String {
+    ids.into_iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Narrate the assignment of identifiers to shepherds
+pub fn narrate_shepherds(shepherds: &HashMap<ShepherdId, String>) {
+    let mut shepherds = shepherds.iter().collect::<Vec<_>>();
+    shepherds.sort_unstable_by_key(|(ShepherdId(id), _)| *id);
+
+    for (ShepherdId(id), name) in shepherds {
+        eprintln!("shepherd {} is {}", id, name);
+    }
+}
+
+/// Narrate the start of an epoch
+pub fn narrate_epoch(EpochId(id): EpochId, epoch: &Epoch) {
+    eprintln!();
+    eprintln!("=== epoch {} ===", id);
+
+    if epoch.tags.is_empty() {
+        eprintln!("no new tags were introduced");
+    } else {
+        eprintln!(
+            "new tags were introduced: {}",
+            list(epoch.tags.iter().map(|GraphId(tag, _)| *tag))
+        );
+    }
+
+    for (GraphId(item, _), tags) in &epoch.items {
+        eprintln!(
+            "item {} was introduced, tagged with {}",
+            item,
+            list(tags.iter().map(|GraphId(tag, _)| *tag))
+        );
+    }
+}
+
+/// Narrate the generation of a feed by a shepherd
+pub fn narrate_feed(
+    ShepherdId(shepherd): ShepherdId,
+    GraphId(sheep, _): SheepId,
+    Feed(items): &Feed,
+) {
+    if items.is_empty() {
+        eprintln!("shepherd {} gave sheep {} an empty feed", shepherd, sheep);
+    } else {
+        eprintln!(
+            "shepherd {} recommended items {} to sheep {}",
+            shepherd,
+            list(items.iter().map(|GraphId(item, _)| *item)),
+            sheep
+        );
+    }
+}
+
+/// Narrate a sheep's responses to a feed
+pub fn narrate_responses(GraphId(sheep, _): SheepId, responses: &Responses) {
+    for (GraphId(item, _), response, hops, serendipitous, explanation) in
+        &responses.0
+    {
+        if *serendipitous {
+            eprintln!(
+                "  sheep {} rated item {} positively, by sheer serendipity",
+                sheep, item
+            );
+            continue;
+        }
+
+        let response = match response {
+            Response::Positive => "positively",
+            Response::Neutral => "neutrally",
+            Response::Negative => "negatively",
+        };
+
+        if let Some(Explanation { path, distance }) = explanation {
+            eprintln!(
+                "  sheep {} rated item {} {}, as it is {} away through tags {}",
+                sheep,
+                item,
+                response,
+                distance,
+                list(path.iter().map(|GraphId(tag, _)| *tag))
+            );
+        } else if let Some(hops) = hops {
+            eprintln!(
+                "  sheep {} rated item {} {}, as it is {} hops away",
+                sheep, item, response, hops
+            );
+        } else {
+            eprintln!(
+                "  sheep {} rated item {} {}, as no path leads to it",
+                sheep, item, response
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Context;
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use shepherd_lib::{
+    collections::HashMap,
+    ids::{EpochId, ShepherdId},
+    metrics::{EpochStats, Latencies, Stats},
+};
+
+/// The number of epochs the rates shown on the dashboard are averaged over
+const WINDOW: usize = 5;
+
+/// The width of the epoch progress bar, in characters
+const PROGRESS_WIDTH: usize = 40;
+
+/// The widest a shepherd's name is shown, in characters
+const NAME_WIDTH: usize = 16;
+
+/// A live table of every shepherd's recent feeds, redrawn in place on
+/// standard error at the end of every epoch
+pub struct Dashboard {
+    n_epochs: usize,
+    start: Instant,
+    n_lines: usize,
+}
+
+impl Dashboard {
+    /// Create a new [`Dashboard`] for a run of the given number of epochs
+    pub fn new(n_epochs: usize) -> Self {
+        Self {
+            n_epochs,
+            start: Instant::now(),
+            n_lines: 0,
+        }
+    }
+
+    /// Redraw the dashboard as of the end of an epoch, over the last time it
+    /// was drawn
+    pub fn draw(
+        &mut self,
+        epoch: usize,
+        shepherd_names: &HashMap<ShepherdId, String>,
+        stats: &Stats,
+        latencies: &Latencies,
+    ) -> anyhow::Result<()> {
+        let mut lines = Vec::new();
+
+        let fraction = epoch as f64 / self.n_epochs.max(1) as f64;
+        let filled = (fraction * PROGRESS_WIDTH as f64).round() as usize;
+        lines.push(format!(
+            "epoch {}/{} [{}{}] {:>3.0}% {:.1?}",
+            epoch,
+            self.n_epochs,
+            "#".repeat(filled.min(PROGRESS_WIDTH)),
+            ".".repeat(PROGRESS_WIDTH.saturating_sub(filled)),
+            fraction * 100.0,
+            Duration::from_secs(self.start.elapsed().as_secs()),
+        ));
+        lines.push(String::new());
+        lines.push(format!(
+            "{:<NAME_WIDTH$} {:>8} {:>9} {:>9} {:>10} {:>10}",
+            "shepherd", "feeds", "positive", "diversity", "p50", "p95"
+        ));
+
+        let mut shepherds = shepherd_names.iter().collect::<Vec<_>>();
+        shepherds.sort_unstable_by_key(|(ShepherdId(id), _)| *id);
+        for (id, name) in shepherds {
+            // the rates are averaged over the last few epochs, so that they
+            // don't jump around with every epoch
+            let mut recent = EpochStats::default();
+            for (_, epoch_stats) in stats
+                .epochs(*id)
+                .into_iter()
+                .filter(|(EpochId(i), _)| i + WINDOW > epoch)
+            {
+                recent.merge(&epoch_stats);
+            }
+            let latency = latencies.get(*id, EpochId(epoch));
+
+            lines.push(format!(
+                "{:<NAME_WIDTH$} {:>8} {:>8.1}% {:>9} {:>10} {:>10}",
+                name.chars().take(NAME_WIDTH).collect::<String>(),
+                stats.total(*id).n_feeds,
+                recent.positive_rate() * 100.0,
+                recent
+                    .diversity()
+                    .map_or_else(|| "-".to_string(), |d| format!("{d:.2}")),
+                latency.map_or_else(
+                    || "-".to_string(),
+                    |l| format!("{:.1?}", l.p50)
+                ),
+                latency.map_or_else(
+                    || "-".to_string(),
+                    |l| format!("{:.1?}", l.p95)
+                ),
+            ));
+        }
+
+        let mut stderr = io::stderr().lock();
+
+        // the cursor is moved back to the start of the last drawing, and
+        // everything after it is cleared
+        if self.n_lines > 0 {
+            write!(stderr, "\x1b[{}F\x1b[J", self.n_lines)
+                .context("Unable to draw the dashboard")?;
+        }
+        for line in &lines {
+            writeln!(stderr, "{line}")
+                .context("Unable to draw the dashboard")?;
+        }
+        self.n_lines = lines.len();
+
+        stderr.flush().context("Unable to draw the dashboard")
+    }
+}
@@ -29,58 +29,74 @@
 use anyhow::Context;
 use duckdb::{params, Connection};
 use rand::prelude::*;
-use std::{
-    collections::HashSet,
-    io::{self, prelude::*},
-};
+use std::collections::HashMap;
 
 use shepherd_lib::{
-    feed::Feed,
-    ids::GraphId,
-    shepherd::{ShepherdEvent, SimulationEvent},
-    simulation::Epoch,
+    feed::{Feed, Justification},
+    ids::{GraphId, ItemId, SheepId},
+    sdk::{self, Handler},
+    shepherd::{Capability, Payload, SimulationEvent},
+    simulation::{Epoch, RunConfig},
 };
 
-fn main() -> anyhow::Result<()> {
-    let mut stdout = io::stdout();
-
-    let duckdb = Connection::open_in_memory()
-        .context("Unable to open a duckdb database")?;
-    duckdb
-        .execute_batch(
-            "
-            CREATE TYPE kind AS ENUM ('sheep', 'item');
-            CREATE TABLE associations (
-                id UINTEGER NOT NULL,
-                kind kind NOT NULL,
-                tag UINTEGER NOT NULL,
-                PRIMARY KEY (id, kind, tag)
-            );
-            CREATE TABLE seen (
-                sheep_id UINTEGER NOT NULL,
-                item_id UINTEGER NOT NULL,
-                PRIMARY KEY (sheep_id, item_id)
-            );
-            ",
-        )
-        .context("Unable to initialize duckdb")?;
-
-    for event in serde_json::Deserializer::from_reader(io::stdin())
-        .into_iter::<SimulationEvent>()
-    {
-        let event = event
-            .context("Unable to retrieve an event from standard input")?;
+/// A shepherd which shows each sheep unseen items sharing a tag with it,
+/// citing the shared tag as the reason for each
+struct Tagged {
+    duckdb: Connection,
+    feed_size: Option<usize>,
+}
+
+impl Tagged {
+    fn new() -> anyhow::Result<Self> {
+        let duckdb = Connection::open_in_memory()
+            .context("Unable to open a duckdb database")?;
+        duckdb
+            .execute_batch(
+                "
+                CREATE TYPE kind AS ENUM ('sheep', 'item');
+                CREATE TABLE associations (
+                    id UINTEGER NOT NULL,
+                    kind kind NOT NULL,
+                    tag UINTEGER NOT NULL,
+                    PRIMARY KEY (id, kind, tag)
+                );
+                CREATE TABLE seen (
+                    sheep_id UINTEGER NOT NULL,
+                    item_id UINTEGER NOT NULL,
+                    PRIMARY KEY (sheep_id, item_id)
+                );
+                ",
+            )
+            .context("Unable to initialize duckdb")?;
+
+        Ok(Self {
+            duckdb,
+            feed_size: None,
+        })
+    }
+}
+
+impl Handler for Tagged {
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::JustifiedFeeds, Capability::ResponseBatches]
+    }
+
+    fn payloads(&self) -> Vec<Payload> {
+        vec![Payload::ItemTags]
+    }
+
+    fn handle(&mut self, event: SimulationEvent) -> anyhow::Result<()> {
         match event {
+            SimulationEvent::RunConfig(RunConfig { feed_size, .. }) => {
+                self.feed_size = Some(feed_size);
+            }
             SimulationEvent::BeginEpoch {
-                data:
-                    Epoch {
-                        items: new_items, ..
-                    },
+                data: Epoch { items, .. },
                 ..
             } => {
-                for (GraphId(id, _), tags) in new_items {
+                for (GraphId(id, _), tags) in items {
                     for GraphId(tag, _) in tags {
-                        duckdb.execute(
+                        self.duckdb.execute(
                             "INSERT INTO associations (id, kind, tag) VALUES (?, 'item', ?)",
                             params![id, tag]
                         )
@@ -94,76 +110,97 @@ fn main() -> anyhow::Result<()> {
             } => {
                 // TODO: there was an error with conflicts here ? what was that
                 for GraphId(tag, _) in associated_tags {
-                    duckdb.execute(
+                    self.duckdb.execute(
                         "INSERT INTO associations (id, kind, tag) VALUES (?, 'sheep', ?) ON CONFLICT DO NOTHING",
                         params![id, tag]
                     )
                     .context("Unable to insert a sheep association into the database")?;
                 }
             }
-            SimulationEvent::FeedRequest {
-                sheep: GraphId(id, _),
-            } => {
-                let mut candidates = HashSet::new();
-                let mut tag_query = duckdb
-                    .prepare("SELECT tag FROM associations WHERE id = ? AND kind = 'sheep'")
-                    .context("Unable to prepare a statement")?;
-                let tags = tag_query
-                    .query_map([id], |row| row.get("tag"))
-                    .context(
-                        "Unable to retrieve tags associated with a sheep",
-                    )?
-                    .collect::<Result<Vec<usize>, _>>()?;
-
-                let mut item_query = duckdb
-                    .prepare(
-                        "
-                        SELECT id FROM associations
-                        WHERE NOT EXISTS (
-                            SELECT 1
-                              FROM seen
-                             WHERE seen.sheep_id = ?
-                               AND seen.item_id = associations.id
-                        )
-                        AND tag = ? AND kind = 'item'
-                        ",
-                    )
-                    .context("Unable to prepare a statement")?;
-
-                for tag in tags {
-                    candidates.extend(
-                        item_query
-                            .query_map([id, tag], |row| row.get("id"))
-                            .context("Unable to retrieve unseen items")?
-                            .collect::<Result<Vec<usize>, _>>()?,
-                    );
-                }
+            _ => (),
+        }
 
-                let chosen = candidates
-                    .into_iter()
-                    .choose_multiple(&mut rand::thread_rng(), 10);
+        Ok(())
+    }
 
-                for item in &chosen {
-                    duckdb
-                        .execute(
-                            "INSERT INTO seen (sheep_id, item_id) VALUES (?, ?)",
-                            [id, *item]
-                        )
-                        .context("Unable to mark items as seen")?;
-                }
+    fn build_feed(&mut self, sheep: SheepId) -> anyhow::Result<Feed> {
+        Ok(Feed(
+            self.build_justified_feed(sheep)?
+                .into_iter()
+                .map(|(item, _)| item)
+                .collect(),
+        ))
+    }
+
+    fn build_justified_feed(
+        &mut self,
+        GraphId(id, _): SheepId,
+    ) -> anyhow::Result<Vec<(ItemId, Option<Justification>)>> {
+        // each candidate is kept alongside the tag that found it, so that it
+        // can be cited as the reason for showing the item
+        let mut candidates = HashMap::new();
+        let mut tag_query = self
+            .duckdb
+            .prepare("SELECT tag FROM associations WHERE id = ? AND kind = 'sheep'")
+            .context("Unable to prepare a statement")?;
+        let tags = tag_query
+            .query_map([id], |row| row.get("tag"))
+            .context("Unable to retrieve tags associated with a sheep")?
+            .collect::<Result<Vec<usize>, _>>()?;
 
-                serde_json::to_writer(
-                    &mut stdout,
-                    &ShepherdEvent::Feed(Feed(
-                        chosen.into_iter().map(GraphId::new).collect(),
-                    )),
+        let mut item_query = self
+            .duckdb
+            .prepare(
+                "
+                SELECT id FROM associations
+                WHERE NOT EXISTS (
+                    SELECT 1
+                      FROM seen
+                     WHERE seen.sheep_id = ?
+                       AND seen.item_id = associations.id
                 )
-                .context("Unable to write an event to stdout")?;
-                stdout.flush().context("Unable to flush stdout")?;
+                AND tag = ? AND kind = 'item'
+                ",
+            )
+            .context("Unable to prepare a statement")?;
+
+        for tag in tags {
+            for item in item_query
+                .query_map([id, tag], |row| row.get("id"))
+                .context("Unable to retrieve unseen items")?
+            {
+                candidates.entry(item?).or_insert(tag);
             }
-            _ => (),
         }
+
+        let chosen = candidates.into_iter().choose_multiple(
+            &mut rand::thread_rng(),
+            self.feed_size.context(
+                "A feed was requested before the run configuration was received",
+            )?,
+        );
+
+        for (item, _) in &chosen {
+            self.duckdb
+                .execute(
+                    "INSERT INTO seen (sheep_id, item_id) VALUES (?, ?)",
+                    [id, *item],
+                )
+                .context("Unable to mark items as seen")?;
+        }
+
+        Ok(chosen
+            .into_iter()
+            .map(|(item, tag)| {
+                (
+                    GraphId::new(item),
+                    Some(Justification::Tag(GraphId::new(tag))),
+                )
+            })
+            .collect())
     }
+}
 
-    Ok(())
+fn main() -> anyhow::Result<()> {
+    sdk::run(Tagged::new()?)
 }
@@ -0,0 +1,157 @@
+use anyhow::Context;
+use rand::prelude::*;
+use std::cmp::Reverse;
+
+use crate::{
+    collections::{HashMap, HashSet},
+    feed::{Feed, Response},
+    ids::{EpochId, ItemId, SheepId},
+    sdk::Handler,
+    shepherd::{Capability, LimitedResponses, Shepherd, SimulationEvent},
+    simulation::{Epoch, RunConfig},
+};
+
+/// A simple feed algorithm built into the library, which can be run
+/// alongside other shepherds to give them a reference to be compared against
+///
+/// Every baseline only ever shows a sheep items it has not been shown yet,
+/// and forgets items it is told are beyond its item access budget until it
+/// is told about them again
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Baseline {
+    /// Shows each sheep items chosen uniformly at random
+    Random,
+
+    /// Shows each sheep the items which have been responded to positively
+    /// most often, with ties broken by identifier
+    Popular,
+
+    /// Shows each sheep the items which were introduced most recently, with
+    /// ties broken by identifier
+    Recent,
+}
+
+impl Baseline {
+    /// Get the name shepherds running this baseline are given
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Random => "random",
+            Self::Popular => "popular",
+            Self::Recent => "recent",
+        }
+    }
+
+    /// Create a [`Shepherd`] running this baseline in the same process as the
+    /// simulation (see [`Shepherd::from_handler`])
+    ///
+    /// The seed is only used by [`Baseline::Random`], which draws from its
+    /// own random number generator so that it never disturbs the simulation
+    pub fn shepherd(self, seed: u64) -> Shepherd {
+        Shepherd::from_handler(
+            self.name(),
+            BaselineHandler {
+                baseline: self,
+                rng: StdRng::seed_from_u64(seed),
+                feed_size: None,
+                items: HashMap::default(),
+                n_positive: HashMap::default(),
+                sheep_seen: HashMap::default(),
+            },
+        )
+    }
+}
+
+/// The [`Handler`] behind a [`Shepherd`] created by [`Baseline::shepherd`]
+struct BaselineHandler {
+    baseline: Baseline,
+    rng: StdRng,
+    feed_size: Option<usize>,
+    items: HashMap<ItemId, EpochId>,
+    n_positive: HashMap<ItemId, usize>,
+    sheep_seen: HashMap<SheepId, HashSet<ItemId>>,
+}
+
+impl BaselineHandler {
+    /// Count the positive responses to the items of a feed
+    fn count_responses(&mut self, responses: LimitedResponses) {
+        for (item, response, ..) in responses.0 {
+            if response == Response::Positive {
+                *self.n_positive.entry(item).or_default() += 1;
+            }
+        }
+    }
+}
+
+impl Handler for BaselineHandler {
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::ResponseBatches, Capability::FeedBatches]
+    }
+
+    fn handle(&mut self, event: SimulationEvent) -> anyhow::Result<()> {
+        match event {
+            SimulationEvent::RunConfig(RunConfig { feed_size, .. }) => {
+                self.feed_size = Some(feed_size);
+            }
+            SimulationEvent::BeginEpoch {
+                id,
+                data: Epoch { items, .. },
+            } => self
+                .items
+                .extend(items.into_iter().map(|(item, _)| (item, id))),
+            SimulationEvent::FeedResponses { responses, .. } => {
+                self.count_responses(responses);
+            }
+            SimulationEvent::ResponsesBatch { responses } => {
+                for (_, _, responses) in responses {
+                    self.count_responses(responses);
+                }
+            }
+            SimulationEvent::BudgetExceeded { items, .. } => {
+                for item in items {
+                    self.items.remove(&item);
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn build_feed(&mut self, sheep: SheepId) -> anyhow::Result<Feed> {
+        let feed_size = self.feed_size.context(
+            "A feed was requested before the run configuration was received",
+        )?;
+        let seen = self.sheep_seen.entry(sheep).or_default();
+        let mut unseen = self
+            .items
+            .keys()
+            .filter(|item| !seen.contains(*item))
+            .copied()
+            .collect::<Vec<_>>();
+
+        // the unseen items are gathered from a map, so they are put in a
+        // fixed order before being chosen from
+        unseen.sort_unstable_by_key(|item| item.0);
+        let chosen = match self.baseline {
+            Baseline::Random => unseen
+                .choose_multiple(&mut self.rng, feed_size)
+                .copied()
+                .collect(),
+            Baseline::Popular => {
+                unseen.sort_by_key(|item| {
+                    Reverse(self.n_positive.get(item).copied().unwrap_or(0))
+                });
+                unseen.truncate(feed_size);
+                unseen
+            }
+            Baseline::Recent => {
+                unseen.sort_by_key(|item| Reverse(self.items[item].0));
+                unseen.truncate(feed_size);
+                unseen
+            }
+        };
+
+        seen.extend(chosen.iter().copied());
+        Ok(Feed(chosen))
+    }
+}
@@ -0,0 +1,18 @@
+use std::{
+    collections,
+    hash::{BuildHasherDefault, DefaultHasher},
+};
+
+/// A [`collections::HashMap`] using a hasher with fixed keys
+///
+/// Iteration order over the simulation's collections determines the order in
+/// which random numbers are drawn, so a fixed hasher is needed for a seeded
+/// simulation to be reproducible
+pub type HashMap<K, V> =
+    collections::HashMap<K, V, BuildHasherDefault<DefaultHasher>>;
+
+/// A [`collections::HashSet`] using a hasher with fixed keys
+///
+/// See [`HashMap`] for why this is used
+pub type HashSet<T> =
+    collections::HashSet<T, BuildHasherDefault<DefaultHasher>>;
@@ -0,0 +1,33 @@
+use std::{
+    io::{self, Write},
+    iter,
+};
+
+/// Write a dense, row-major matrix of `f64`s in the `.npy` format used by
+/// `numpy`
+pub fn write_npy(
+    mut writer: impl Write,
+    (rows, columns): (usize, usize),
+    data: impl IntoIterator<Item = f64>,
+) -> io::Result<()> {
+    let mut header = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}",
+        rows, columns
+    );
+
+    // the magic string, version, and header length take up ten bytes, and
+    // the header (terminated by a newline) is padded out so the data starts
+    // on a multiple of 64 bytes
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.extend(iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in data {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    writer.flush()
+}
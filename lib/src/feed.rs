@@ -1,9 +1,14 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-use crate::ids::ItemId;
+use crate::{
+    graph::Simulation,
+    ids::{ItemId, TagId},
+};
 
 /// An enum indicating a Sheep's response to a [`Feed`] item
-#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Response {
     /// A positive response to a feed item
     Positive,
@@ -15,14 +20,169 @@ pub enum Response {
     Negative,
 }
 
-/// The [`Response`]s returned from a Sheep after evaluating a [`Feed`]
+impl Response {
+    /// Get the opposite of this [`Response`], leaving neutral responses as
+    /// they are
+    pub fn inverted(self) -> Self {
+        match self {
+            Self::Positive => Self::Negative,
+            Self::Neutral => Self::Neutral,
+            Self::Negative => Self::Positive,
+        }
+    }
+}
+
+/// Counts of each kind of [`Response`] given by a Sheep
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ResponseCounts {
+    /// The number of positive responses
+    pub positive: usize,
+
+    /// The number of neutral responses
+    pub neutral: usize,
+
+    /// The number of negative responses
+    pub negative: usize,
+}
+
+impl ResponseCounts {
+    /// Count a single [`Response`]
+    pub fn record(&mut self, response: Response) {
+        match response {
+            Response::Positive => self.positive += 1,
+            Response::Neutral => self.neutral += 1,
+            Response::Negative => self.negative += 1,
+        }
+    }
+}
+
+/// The shortest path from a Sheep to a feed item which drove its [`Response`]
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Explanation {
+    /// The tags along the path, in order from the Sheep to the item
+    pub path: Vec<TagId>,
+
+    /// The sum of the weights along the path
+    pub distance: u32,
+}
+
+/// A single Sheep's [`Response`] to a feed item
 ///
 /// The first two values are self-explanatory, the third is a count of how many
 /// hops are required to get from the Sheep to the feed item (if it is
-/// reachable)
+/// reachable), the fourth indicates whether the response was a serendipitous
+/// positive one which the Sheep would not otherwise have given, and the fifth
+/// is an [`Explanation`] of the response (if one was requested and the item
+/// is reachable)
+pub type Rating = (ItemId, Response, Option<u32>, bool, Option<Explanation>);
+
+/// The [`Response`]s returned from a Sheep after evaluating a [`Feed`]
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
-pub struct Responses(pub Vec<(ItemId, Response, Option<u32>)>);
+pub struct Responses(pub Vec<Rating>);
 
 /// The feed returned from a [`Shepherd`], prepared for a specific Sheep
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Feed(pub Vec<ItemId>);
+
+/// A way in which an item of a [`Feed`] built by a [`Shepherd`] breaks the
+/// rules of the simulation
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FeedViolation {
+    /// The item appeared earlier in the same feed
+    DuplicateItem(ItemId),
+
+    /// The item has not been introduced to shepherds
+    UnknownItem(ItemId),
+
+    /// The item has already been shown to the sheep by the same shepherd
+    AlreadyShown(ItemId),
+
+    /// The item came after the feed was already full
+    Overlong(ItemId),
+
+    /// The item was not among the candidates the feed was ranked from
+    NotCandidate(ItemId),
+}
+
+impl FeedViolation {
+    /// Get the item which broke the rules
+    pub fn item(self) -> ItemId {
+        match self {
+            Self::DuplicateItem(item)
+            | Self::UnknownItem(item)
+            | Self::AlreadyShown(item)
+            | Self::Overlong(item)
+            | Self::NotCandidate(item) => item,
+        }
+    }
+
+    /// Get a short name for the kind of violation, for use in reports
+    pub fn kind(self) -> &'static str {
+        match self {
+            Self::DuplicateItem(_) => "duplicate_item",
+            Self::UnknownItem(_) => "unknown_item",
+            Self::AlreadyShown(_) => "already_shown",
+            Self::Overlong(_) => "overlong",
+            Self::NotCandidate(_) => "not_candidate",
+        }
+    }
+}
+
+impl fmt::Display for FeedViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateItem(item) => {
+                write!(f, "Item {} appears more than once", item.0)
+            }
+            Self::UnknownItem(item) => {
+                write!(f, "Item {} does not exist", item.0)
+            }
+            Self::AlreadyShown(item) => {
+                write!(f, "Item {} has already been shown", item.0)
+            }
+            Self::Overlong(item) => {
+                write!(f, "Item {} does not fit in the feed", item.0)
+            }
+            Self::NotCandidate(item) => {
+                write!(f, "Item {} was not among the candidates", item.0)
+            }
+        }
+    }
+}
+
+/// A reason given by a [`Shepherd`] for including an item in a [`Feed`]
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "kind", content = "data")]
+pub enum Justification {
+    /// The item was included because of a tag
+    Tag(TagId),
+
+    /// The item was included because it is similar to another item
+    SimilarItem(ItemId),
+}
+
+impl Justification {
+    /// Check whether this justification agrees with the tags along the
+    /// shortest path from a Sheep to the justified item, in order from the
+    /// Sheep to the item
+    ///
+    /// A cited tag is faithful if it lies on the path, and a cited item is
+    /// faithful if it shares a tag with the path
+    pub fn is_faithful(&self, graph: &Simulation, path: &[TagId]) -> bool {
+        match self {
+            Self::Tag(tag) => path.contains(tag),
+            Self::SimilarItem(item) => {
+                graph.associated_tags(*item).any(|tag| path.contains(&tag))
+            }
+        }
+    }
+}
@@ -1,20 +1,622 @@
+use anyhow::{ensure, Context};
 use itertools::Itertools;
-use petgraph::{prelude::*, Graph};
+use petgraph::{dot::Dot, prelude::*, unionfind::UnionFind, Graph};
 use rand::{distributions::uniform::SampleRange, prelude::*};
+use serde::{Deserialize, Serialize};
 use statrs::distribution::{Poisson, PoissonError};
-use std::collections::HashSet;
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, VecDeque},
+    io::{self, Read, Write},
+};
+use tracing::instrument;
 
-use crate::ids::{self, GraphId, NodeType, TagId};
+use crate::{
+    collections::{HashMap, HashSet},
+    ids::{self, EpochId, GraphId, ItemId, NodeType, SheepId, TagId},
+};
 
 /// A container type holding the graph organizing the simulation data
 ///
 /// Wraps a [`Graph`] with methods for working with the graph in the manner
 /// laid out in the tag graph Jupyter notebook, with some extensions to
 /// support gradually building it up across many epochs
+///
+/// Alongside the graph is the current epoch, which every edge added or
+/// reinforced is marked with
 #[derive(Default)]
-pub struct Simulation(pub Graph<NodeType, u32, Directed, usize>);
+pub struct Simulation(
+    pub Graph<NodeType, EdgeWeight, Directed, usize>,
+    pub EpochId,
+);
+
+/// The weight of an edge in the graph
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize,
+)]
+pub struct EdgeWeight {
+    /// The distance the edge adds to paths along it, where lower values
+    /// indicate a closer relationship
+    pub affinity: u32,
+
+    /// The epoch in which the edge was added or last reinforced
+    pub last_touched: EpochId,
+}
+
+impl EdgeWeight {
+    /// Get the affinity of the edge as of the given epoch, faded by the given
+    /// fraction of itself for every epoch since it was last touched
+    ///
+    /// A rate of zero leaves the affinity as is
+    pub fn decayed(&self, epoch: EpochId, rate: f64) -> u32 {
+        if rate <= 0.0 {
+            return self.affinity;
+        }
+
+        let age = epoch.0.saturating_sub(self.last_touched.0);
+        (f64::from(self.affinity) * (1.0 + rate).powf(age as f64))
+            .round()
+            .min(f64::from(u32::MAX)) as u32
+    }
+}
+
+/// A simple description of a graph, used to import and export graphs as JSON
+///
+/// Nodes are identified by their index in `nodes`, and edges are given as
+/// source, target, and affinity triples. Edges must run from sheep to tags,
+/// between tags, and from tags to items, or in the opposite direction as well
+/// in undirected graphs (see [`EdgeSemantics::Undirected`])
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct GraphDescription {
+    /// The type of each node in the graph
+    pub nodes: Vec<NodeType>,
+
+    /// The edges of the graph
+    pub edges: Vec<(usize, usize, u32)>,
+}
+
+/// The model used to connect tags to one another as the graph grows
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum GraphGenerator {
+    /// Tags are split into groups with Poisson-distributed sizes, with every
+    /// pair of tags within a group connected and pairs across groups rarely
+    /// connected
+    #[default]
+    PoissonGroups,
+
+    /// As with [`GraphGenerator::PoissonGroups`], but pairs of tags within
+    /// and across groups are connected with the given probabilities, giving
+    /// a stochastic block model
+    StochasticBlock {
+        /// The probability that two tags in the same group are connected
+        within_probability: f64,
+
+        /// The probability that two tags in different groups are connected
+        between_probability: f64,
+    },
+
+    /// Each new tag is connected to up to the given number of existing tags,
+    /// chosen with probability proportional to their degree, as done by
+    /// [`Simulation::attach_preferentially`]
+    ///
+    /// This does not form tag groups, so every tag is placed in one group
+    PreferentialAttachment {
+        /// The number of existing tags each new tag is connected to
+        n_edges: usize,
+    },
+}
+
+/// How the edges of the graph relate the nodes they connect
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum EdgeSemantics {
+    /// Tags are connected by a pair of edges, one in each direction, with
+    /// separately sampled weights, while sheep only have edges to their tags
+    /// and items only have edges from theirs
+    #[default]
+    DirectedAsymmetric,
+
+    /// As with [`EdgeSemantics::DirectedAsymmetric`], but both edges between
+    /// a pair of tags share the same weight
+    DirectedSymmetric,
+
+    /// Every edge is mirrored by one with the same weight in the opposite
+    /// direction, including those between tags and sheep or items, so paths
+    /// may pass through sheep and items as well as tags
+    Undirected,
+}
+
+impl GraphGenerator {
+    /// Get the probabilities of tags being connected within and across tag
+    /// groups, or [`None`] if the generator does not form tag groups
+    pub fn group_probabilities(&self) -> Option<(f64, f64)> {
+        match *self {
+            Self::PoissonGroups => Some((1.0, 1e-3)),
+            Self::StochasticBlock {
+                within_probability,
+                between_probability,
+            } => Some((within_probability, between_probability)),
+            Self::PreferentialAttachment { .. } => None,
+        }
+    }
+}
+
+/// Structural statistics of the graph at a point in time
+#[derive(Copy, Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct WorldStats {
+    /// The number of sheep in the graph
+    pub n_sheep: usize,
+
+    /// The number of tags in the graph
+    pub n_tags: usize,
+
+    /// The number of items in the graph
+    pub n_items: usize,
+
+    /// The number of edges in the graph
+    pub n_edges: usize,
+
+    /// The fraction of possible directed edges present in the graph
+    pub density: f64,
+
+    /// The average local clustering coefficient of the subgraph formed by
+    /// tags, ignoring the direction of edges
+    pub tag_clustering: f64,
+
+    /// The average number of tags connected to a sheep
+    pub mean_sheep_degree: f64,
+
+    /// The largest number of tags connected to a sheep
+    pub max_sheep_degree: usize,
+
+    /// The average number of tags connected to an item
+    pub mean_item_degree: f64,
+
+    /// The largest number of tags connected to an item
+    pub max_item_degree: usize,
+}
+
+/// How well the communities found in the edges between tags agree with the
+/// tag groups they were generated from, as measured by
+/// [`Simulation::verify_groups`]
+#[derive(Copy, Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct GroupRecovery {
+    /// The number of communities found
+    pub n_communities: usize,
+
+    /// The normalized mutual information between the communities and the
+    /// groups, from zero (independent) to one (identical)
+    pub nmi: f64,
+
+    /// The adjusted Rand index between the communities and the groups, which
+    /// is one when they are identical and around zero when they agree no
+    /// more than chance
+    pub ari: f64,
+}
+
+/// The maximum number of passes made over the tags by label propagation in
+/// [`Simulation::verify_groups`]
+const MAX_PROPAGATION_PASSES: usize = 100;
 
 impl Simulation {
+    /// Computes structural statistics of the graph
+    ///
+    /// See [`WorldStats`] for details
+    pub fn stats(&self) -> WorldStats {
+        let mut stats = WorldStats {
+            n_edges: self.0.edge_count(),
+            ..Default::default()
+        };
+
+        let mut tag_neighbors =
+            HashMap::<NodeIndex<usize>, HashSet<_>>::default();
+        let (mut sheep_degrees, mut item_degrees) = (0, 0);
+        for node in self.0.node_indices() {
+            let degree = self
+                .0
+                .neighbors_undirected(node)
+                .filter(|neighbor| self.0[*neighbor] == NodeType::Tag)
+                .collect::<HashSet<_>>();
+            match self.0[node] {
+                NodeType::Sheep => {
+                    stats.n_sheep += 1;
+                    sheep_degrees += degree.len();
+                    stats.max_sheep_degree =
+                        stats.max_sheep_degree.max(degree.len());
+                }
+                NodeType::Tag => {
+                    stats.n_tags += 1;
+                    tag_neighbors.insert(node, degree);
+                }
+                NodeType::Item => {
+                    stats.n_items += 1;
+                    item_degrees += degree.len();
+                    stats.max_item_degree =
+                        stats.max_item_degree.max(degree.len());
+                }
+                NodeType::Removed => {}
+            }
+        }
+
+        // removed nodes are left out of the density, as no edges can be
+        // formed with them
+        let n_nodes = stats.n_sheep + stats.n_tags + stats.n_items;
+        stats.density = stats.n_edges as f64
+            / (n_nodes as f64 * n_nodes.saturating_sub(1) as f64).max(1.0);
+        stats.mean_sheep_degree =
+            sheep_degrees as f64 / stats.n_sheep.max(1) as f64;
+        stats.mean_item_degree =
+            item_degrees as f64 / stats.n_items.max(1) as f64;
+        stats.tag_clustering = tag_neighbors
+            .values()
+            .map(|neighbors| {
+                if neighbors.len() < 2 {
+                    return 0.0;
+                }
+
+                let n_links = neighbors
+                    .iter()
+                    .tuple_combinations()
+                    .filter(|(a, b)| tag_neighbors[*a].contains(*b))
+                    .count();
+                (2 * n_links) as f64
+                    / (neighbors.len() * (neighbors.len() - 1)) as f64
+            })
+            .sum::<f64>()
+            / stats.n_tags.max(1) as f64;
+
+        stats
+    }
+
+    /// Measures how well the communities formed by the edges between the
+    /// given tag groups recover the groups themselves
+    ///
+    /// Communities are found by label propagation, ignoring the direction and
+    /// weight of edges. Each tag starts with its own label and repeatedly
+    /// takes on the label most common among its neighbors, visiting tags in
+    /// order and breaking ties towards the smallest label, until no label
+    /// changes. Only edges between tags in the groups are considered, so
+    /// orphaned tags are left out entirely
+    pub fn verify_groups(
+        &self,
+        tag_groups: &[HashSet<TagId>],
+    ) -> GroupRecovery {
+        let mut groups = tag_groups
+            .iter()
+            .enumerate()
+            .flat_map(|(i, group)| {
+                group.iter().map(move |GraphId(tag, _)| (*tag, i))
+            })
+            .collect::<Vec<_>>();
+        groups.sort_unstable();
+
+        let indices = groups
+            .iter()
+            .enumerate()
+            .map(|(i, (tag, _))| (*tag, i))
+            .collect::<HashMap<_, _>>();
+        let neighbors = groups
+            .iter()
+            .map(|(tag, _)| {
+                self.0
+                    .neighbors_undirected((*tag).into())
+                    .filter_map(|neighbor| indices.get(&neighbor.index()))
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut labels = (0..groups.len()).collect::<Vec<_>>();
+        for _ in 0..MAX_PROPAGATION_PASSES {
+            let mut changed = false;
+            for (i, neighbors) in neighbors.iter().enumerate() {
+                let mut counts = HashMap::<usize, usize>::default();
+                for neighbor in neighbors {
+                    *counts.entry(labels[*neighbor]).or_default() += 1;
+                }
+
+                let Some((label, _)) = counts
+                    .into_iter()
+                    .max_by_key(|(label, count)| (*count, Reverse(*label)))
+                else {
+                    continue;
+                };
+                if label != labels[i] {
+                    labels[i] = label;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        let mut contingency = HashMap::<(usize, usize), usize>::default();
+        let mut community_sizes = HashMap::<usize, usize>::default();
+        let mut group_sizes = HashMap::<usize, usize>::default();
+        for ((_, group), label) in groups.iter().zip(&labels) {
+            *contingency.entry((*label, *group)).or_default() += 1;
+            *community_sizes.entry(*label).or_default() += 1;
+            *group_sizes.entry(*group).or_default() += 1;
+        }
+
+        let n = groups.len() as f64;
+        let entropy = |sizes: &HashMap<usize, usize>| {
+            -sizes
+                .values()
+                .map(|size| {
+                    let p = *size as f64 / n;
+                    p * p.ln()
+                })
+                .sum::<f64>()
+        };
+        let mutual_information = contingency
+            .iter()
+            .map(|((label, group), size)| {
+                let p = *size as f64 / n;
+                p * (p * n * n
+                    / (community_sizes[label] * group_sizes[group]) as f64)
+                    .ln()
+            })
+            .sum::<f64>();
+        let entropies = entropy(&community_sizes) + entropy(&group_sizes);
+
+        let pairs = |size: usize| (size * size.saturating_sub(1) / 2) as f64;
+        let index = contingency.values().copied().map(pairs).sum::<f64>();
+        let community_pairs =
+            community_sizes.values().copied().map(pairs).sum::<f64>();
+        let group_pairs =
+            group_sizes.values().copied().map(pairs).sum::<f64>();
+        let expected_index =
+            community_pairs * group_pairs / pairs(groups.len()).max(1.0);
+        let max_index = (community_pairs + group_pairs) / 2.0;
+
+        // both measures are taken to be perfect in degenerate cases, such as
+        // when every tag is in the same group and community
+        GroupRecovery {
+            n_communities: community_sizes.len(),
+            nmi: if entropies > 0.0 {
+                2.0 * mutual_information / entropies
+            } else {
+                1.0
+            },
+            ari: if max_index > expected_index {
+                (index - expected_index) / (max_index - expected_index)
+            } else {
+                1.0
+            },
+        }
+    }
+
+    /// Builds a graph from a JSON [`GraphDescription`]
+    pub fn from_json(reader: impl Read) -> anyhow::Result<Self> {
+        let description: GraphDescription =
+            serde_json::from_reader(reader)
+                .context("Unable to parse the graph description")?;
+
+        let mut graph = Graph::with_capacity(
+            description.nodes.len(),
+            description.edges.len(),
+        );
+        for node_type in description.nodes {
+            graph.add_node(node_type);
+        }
+        for (source, target, weight) in description.edges {
+            ensure!(
+                source < graph.node_count() && target < graph.node_count(),
+                "An edge of the graph description refers to a missing node"
+            );
+            let (source_type, target_type) = (
+                graph[NodeIndex::new(source)],
+                graph[NodeIndex::new(target)],
+            );
+            ensure!(
+                matches!(
+                    (source_type, target_type),
+                    (NodeType::Sheep, NodeType::Tag)
+                        | (NodeType::Tag, NodeType::Sheep)
+                        | (NodeType::Tag, NodeType::Tag)
+                        | (NodeType::Tag, NodeType::Item)
+                        | (NodeType::Item, NodeType::Tag)
+                ),
+                "An edge of the graph description connects a {:?} to a {:?}",
+                source_type,
+                target_type
+            );
+            graph.add_edge(
+                source.into(),
+                target.into(),
+                EdgeWeight {
+                    affinity: weight,
+                    last_touched: EpochId::default(),
+                },
+            );
+        }
+
+        Ok(Self(graph, EpochId::default()))
+    }
+
+    /// Writes the graph out as a JSON [`GraphDescription`]
+    pub fn export_json(&self, mut writer: impl Write) -> io::Result<()> {
+        serde_json::to_writer(
+            &mut writer,
+            &GraphDescription {
+                nodes: self.0.node_weights().copied().collect(),
+                edges: self
+                    .0
+                    .edge_references()
+                    .map(|edge| {
+                        (
+                            edge.source().index(),
+                            edge.target().index(),
+                            edge.weight().affinity,
+                        )
+                    })
+                    .collect(),
+            },
+        )?;
+        writer.flush()
+    }
+
+    /// Groups the provided tags by the components formed by the edges
+    /// between them, returning the groups alongside the tags which are not
+    /// connected to any other tag
+    pub fn tag_components(
+        &self,
+        tags: &[TagId],
+    ) -> (Vec<HashSet<TagId>>, HashSet<TagId>) {
+        let tag_indices = tags
+            .iter()
+            .enumerate()
+            .map(|(i, tag)| (*tag, i))
+            .collect::<HashMap<_, _>>();
+        let mut components = UnionFind::new(tags.len());
+        for edge in self.0.edge_references() {
+            let source = GraphId::new(edge.source().index());
+            let target = GraphId::new(edge.target().index());
+            if let (Some(a), Some(b)) =
+                (tag_indices.get(&source), tag_indices.get(&target))
+            {
+                components.union(*a, *b);
+            }
+        }
+
+        let mut groups = HashMap::<_, HashSet<TagId>>::default();
+        for (i, tag) in tags.iter().enumerate() {
+            groups.entry(components.find(i)).or_default().insert(*tag);
+        }
+
+        let mut groups = groups.into_values().collect::<Vec<_>>();
+        groups.sort_unstable_by_key(|group| {
+            group.iter().map(|GraphId(tag, _)| *tag).min()
+        });
+
+        let (groups, orphans): (Vec<_>, Vec<_>) =
+            groups.into_iter().partition(|group| group.len() > 1);
+        (groups, orphans.into_iter().flatten().collect())
+    }
+
+    /// Projects the tags shared between items onto the items themselves,
+    /// giving the `k` items most similar to each item
+    ///
+    /// The similarity of two items is the Jaccard index of their tags, which
+    /// is the number of tags they share divided by the number of distinct
+    /// tags between them. Only items sharing at least one tag are considered
+    /// similar, and items without any tags are left out. Neighbors are
+    /// ordered from most to least similar, with ties broken by identifier
+    pub fn item_similarity(
+        &self,
+        k: usize,
+    ) -> HashMap<ItemId, Vec<(ItemId, f64)>> {
+        let mut n_tags = HashMap::<ItemId, usize>::default();
+        let mut tag_items = HashMap::<TagId, Vec<ItemId>>::default();
+        for node in self.0.node_indices() {
+            if self.0[node] != NodeType::Item {
+                continue;
+            }
+
+            let item = GraphId::new(node.index());
+            for tag in self.associated_tags(item) {
+                *n_tags.entry(item).or_default() += 1;
+                tag_items.entry(tag).or_default().push(item);
+            }
+        }
+
+        n_tags
+            .iter()
+            .map(|(item, n_item_tags)| {
+                let mut n_shared = HashMap::<ItemId, usize>::default();
+                for tag in self.associated_tags(*item) {
+                    for other in &tag_items[&tag] {
+                        if other != item {
+                            *n_shared.entry(*other).or_default() += 1;
+                        }
+                    }
+                }
+
+                let mut neighbors = n_shared
+                    .into_iter()
+                    .map(|(other, n_shared)| {
+                        let n_distinct =
+                            n_item_tags + n_tags[&other] - n_shared;
+                        (other, n_shared as f64 / n_distinct as f64)
+                    })
+                    .collect::<Vec<_>>();
+                neighbors.sort_unstable_by(|(a, x), (b, y)| {
+                    y.total_cmp(x).then(a.0.cmp(&b.0))
+                });
+                neighbors.truncate(k);
+
+                (*item, neighbors)
+            })
+            .collect()
+    }
+
+    /// Get the number of hops from the given tags, such as those of an item
+    /// or a sheep, to every tag reachable from them, following only the
+    /// edges between tags
+    ///
+    /// The given tags are zero hops away. Edges are followed in either
+    /// direction, so the distances are the same whatever the edge semantics
+    pub fn tag_distances(
+        &self,
+        tags: impl IntoIterator<Item = TagId>,
+    ) -> HashMap<TagId, u32> {
+        let mut distances = HashMap::default();
+        let mut queue = VecDeque::new();
+        for tag in tags {
+            if distances.insert(tag, 0).is_none() {
+                queue.push_back(tag);
+            }
+        }
+
+        while let Some(tag) = queue.pop_front() {
+            let hops = distances[&tag] + 1;
+            for neighbor in self.0.neighbors_undirected(tag.0.into()) {
+                if self.0[neighbor] != NodeType::Tag {
+                    continue;
+                }
+
+                let neighbor = GraphId::new(neighbor.index());
+                if let Entry::Vacant(entry) = distances.entry(neighbor) {
+                    entry.insert(hops);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        distances
+    }
+
+    /// Connects two nodes by a pair of edges, one in each direction, sampling
+    /// the weight of each from `weight` unless the edge semantics call for
+    /// them to share one
+    fn connect_both_ways(
+        &mut self,
+        a: usize,
+        b: usize,
+        semantics: EdgeSemantics,
+        mut weight: impl FnMut() -> u32,
+    ) {
+        let forward = self.weight(weight());
+        let backward = if semantics == EdgeSemantics::DirectedAsymmetric {
+            self.weight(weight())
+        } else {
+            forward
+        };
+        self.0.add_edge(a.into(), b.into(), forward);
+        self.0.add_edge(b.into(), a.into(), backward);
+    }
+
+    /// Get the weight of an edge with the given affinity, touched in the
+    /// current epoch
+    fn weight(&self, affinity: u32) -> EdgeWeight {
+        EdgeWeight {
+            affinity,
+            last_touched: self.1,
+        }
+    }
+
     /// Adds several nodes to the simulation
     #[inline(always)]
     pub fn create_nodes<K>(
@@ -40,16 +642,112 @@ impl Simulation {
     where
         K: ids::IsItemOrSheep,
     {
+        // only the edges running from sheep to tags and from tags to items
+        // are followed, as with undirected edge semantics each of them is
+        // mirrored by another, which would have each tag found twice
+        let direction = if K::NODE_TYPE == NodeType::Item {
+            Incoming
+        } else {
+            Outgoing
+        };
         self.0
-            .neighbors_undirected(id.into())
+            .neighbors_directed(id.into(), direction)
             .map(|id| GraphId::new(id.index()))
     }
 
+    /// Removes a node from the graph, returning whether it was present
+    ///
+    /// Every edge to and from the node is removed, and the node itself is
+    /// left behind as a [`NodeType::Removed`] tombstone so that the
+    /// identifiers of other nodes stay stable. As a tombstone has no edges,
+    /// it is never found by [`Simulation::associated_tags`] or reached by
+    /// shortest path searches
+    pub fn remove_node<K>(&mut self, GraphId(id, _): GraphId<K>) -> bool
+    where
+        K: ids::GraphIdKind,
+    {
+        if self.0.node_weight(id.into()) != Some(&K::NODE_TYPE) {
+            return false;
+        }
+
+        while let Some(edge) = self
+            .0
+            .first_edge(id.into(), Outgoing)
+            .or_else(|| self.0.first_edge(id.into(), Incoming))
+        {
+            self.0.remove_edge(edge);
+        }
+        self.0[NodeIndex::from(id)] = NodeType::Removed;
+
+        true
+    }
+
+    /// Writes the graph out in the DOT format, labelling nodes with their
+    /// types and edges with their affinities
+    pub fn export_dot(&self, mut writer: impl Write) -> io::Result<()> {
+        let affinities =
+            self.0.map(|_, node| *node, |_, weight| weight.affinity);
+        writeln!(writer, "{:?}", Dot::new(&affinities))?;
+        writer.flush()
+    }
+
+    /// Writes the graph out in the `GraphML` format, with the type of each
+    /// node and the weight of each edge stored as attributes
+    pub fn export_graphml(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <key id="type" for="node" attr.name="type" attr.type="string"/>"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <key id="weight" for="edge" attr.name="weight" attr.type="int"/>"#
+        )?;
+        writeln!(writer, r#"  <graph edgedefault="directed">"#)?;
+
+        for node in self.0.node_indices() {
+            let node_type = match self.0[node] {
+                NodeType::Sheep => "sheep",
+                NodeType::Tag => "tag",
+                NodeType::Item => "item",
+                NodeType::Removed => "removed",
+            };
+            writeln!(
+                writer,
+                r#"    <node id="n{}"><data key="type">{}</data></node>"#,
+                node.index(),
+                node_type
+            )?;
+        }
+
+        for edge in self.0.edge_references() {
+            writeln!(
+                writer,
+                r#"    <edge source="n{}" target="n{}"><data key="weight">{}</data></edge>"#,
+                edge.source().index(),
+                edge.target().index(),
+                edge.weight().affinity
+            )?;
+        }
+
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</graphml>")?;
+        writer.flush()
+    }
+
     /// Forms up to `max_groups` tag groups from the provided tags
     ///
-    /// This method builds groups of tags (which are all connected to one
-    /// another by edges with weights in the range `5..=10`) and forms edges
-    /// across groups (with weights in the range `1..=5`)
+    /// This method builds groups of tags (where each pair is connected with
+    /// probability `within_probability` by edges with weights in the range
+    /// `5..=10`) and forms edges across groups (where each pair is connected
+    /// with probability `between_probability` by edges with weights in the
+    /// range `1..=5`)
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip_all)]
     pub fn add_new_tag_groups(
         &mut self,
         rng: &mut (impl Rng + ?Sized),
@@ -57,6 +755,8 @@ impl Simulation {
         orphans: &mut HashSet<TagId>,
         max_groups: usize,
         tags: impl IntoIterator<Item = TagId>,
+        (within_probability, between_probability): (f64, f64),
+        semantics: EdgeSemantics,
     ) -> Result<(), PoissonError> {
         groups.reserve(max_groups);
         let mut tags = tags.into_iter().collect::<Vec<TagId>>();
@@ -87,16 +787,15 @@ impl Simulation {
             for (GraphId(a, _), GraphId(b, _)) in
                 group.iter().tuple_combinations()
             {
-                self.0.add_edge(
-                    (*a).into(),
-                    (*b).into(),
-                    rng.gen_range(5..=10),
-                );
-                self.0.add_edge(
-                    (*b).into(),
-                    (*a).into(),
-                    rng.gen_range(5..=10),
-                );
+                if within_probability < 1.0
+                    && rng.gen::<f64>() > within_probability
+                {
+                    continue;
+                }
+
+                self.connect_both_ways(*a, *b, semantics, || {
+                    rng.gen_range(5..=10)
+                });
             }
         }
 
@@ -104,17 +803,10 @@ impl Simulation {
             for (GraphId(a, _), GraphId(b, _)) in
                 group_a.iter().cartesian_product(group_b)
             {
-                if rng.gen::<f64>() <= 1e-3 {
-                    self.0.add_edge(
-                        (*a).into(),
-                        (*b).into(),
-                        rng.gen_range(1..=5),
-                    );
-                    self.0.add_edge(
-                        (*b).into(),
-                        (*a).into(),
-                        rng.gen_range(1..=5),
-                    );
+                if rng.gen::<f64>() <= between_probability {
+                    self.connect_both_ways(*a, *b, semantics, || {
+                        rng.gen_range(1..=5)
+                    });
                 }
             }
         }
@@ -127,12 +819,15 @@ impl Simulation {
     /// This method adds on tags from the provided tags to the provided groups
     /// and adds any orphans to the provided set. Weights of edges follow the
     /// same rules outlined in the description of `add_new_tag_groups`
+    #[instrument(skip_all)]
     pub fn add_to_tag_groups(
         &mut self,
         rng: &mut (impl Rng + ?Sized),
         groups: &mut [HashSet<TagId>],
         orphans: &mut HashSet<TagId>,
         tags: impl IntoIterator<Item = TagId>,
+        (within_probability, between_probability): (f64, f64),
+        semantics: EdgeSemantics,
     ) -> Result<(), PoissonError> {
         let mut new_members: Vec<HashSet<TagId>> =
             Vec::with_capacity(groups.len());
@@ -168,31 +863,29 @@ impl Simulation {
             for (GraphId(a, _), GraphId(b, _)) in
                 members.iter().tuple_combinations()
             {
-                self.0.add_edge(
-                    (*a).into(),
-                    (*b).into(),
-                    rng.gen_range(5..=10),
-                );
-                self.0.add_edge(
-                    (*b).into(),
-                    (*a).into(),
-                    rng.gen_range(5..=10),
-                );
+                if within_probability < 1.0
+                    && rng.gen::<f64>() > within_probability
+                {
+                    continue;
+                }
+
+                self.connect_both_ways(*a, *b, semantics, || {
+                    rng.gen_range(5..=10)
+                });
             }
 
             for (GraphId(a, _), GraphId(b, _)) in
                 members.iter().cartesian_product(groups[i].iter())
             {
-                self.0.add_edge(
-                    (*a).into(),
-                    (*b).into(),
-                    rng.gen_range(5..=10),
-                );
-                self.0.add_edge(
-                    (*b).into(),
-                    (*a).into(),
-                    rng.gen_range(5..=10),
-                );
+                if within_probability < 1.0
+                    && rng.gen::<f64>() > within_probability
+                {
+                    continue;
+                }
+
+                self.connect_both_ways(*a, *b, semantics, || {
+                    rng.gen_range(5..=10)
+                });
             }
         }
 
@@ -200,17 +893,10 @@ impl Simulation {
             for (GraphId(a, _), GraphId(b, _)) in
                 new_members[i].iter().cartesian_product(groups[j].iter())
             {
-                if rng.gen::<f64>() <= 1e-3 {
-                    self.0.add_edge(
-                        (*a).into(),
-                        (*b).into(),
-                        rng.gen_range(1..=5),
-                    );
-                    self.0.add_edge(
-                        (*b).into(),
-                        (*a).into(),
-                        rng.gen_range(1..=5),
-                    );
+                if rng.gen::<f64>() <= between_probability {
+                    self.connect_both_ways(*a, *b, semantics, || {
+                        rng.gen_range(1..=5)
+                    });
                 }
             }
         }
@@ -222,13 +908,162 @@ impl Simulation {
         Ok(())
     }
 
+    /// Connects each of the provided tags to up to `n_edges` distinct tags
+    /// which precede it, either in `existing` or earlier in `tags`
+    ///
+    /// Tags are chosen with probability proportional to one more than the
+    /// number of tags they are connected to, and are connected in both
+    /// directions by edges with weights in the range `1..=10`
+    #[instrument(skip_all)]
+    pub fn attach_preferentially(
+        &mut self,
+        rng: &mut (impl Rng + ?Sized),
+        existing: &[TagId],
+        tags: impl IntoIterator<Item = TagId>,
+        n_edges: usize,
+        semantics: EdgeSemantics,
+    ) {
+        // each tag appears once for itself and once for each tag it is
+        // connected to, so sampling uniformly from this is weighted by degree
+        let mut targets = Vec::new();
+        for tag in existing.iter().copied() {
+            targets.push(tag);
+            targets.extend(
+                self.0
+                    .neighbors(tag.0.into())
+                    .filter(|node| self.0[*node] == NodeType::Tag)
+                    .map(|_| tag),
+            );
+        }
+
+        for (n_targets, tag) in (existing.len()..).zip(tags) {
+            let mut chosen = Vec::with_capacity(n_edges);
+            while chosen.len() < n_edges.min(n_targets) {
+                let target = *targets
+                    .choose(&mut *rng)
+                    .expect("There is always a target to choose");
+                if !chosen.contains(&target) {
+                    chosen.push(target);
+                }
+            }
+
+            for target in chosen.iter().copied() {
+                self.connect_both_ways(tag.0, target.0, semantics, || {
+                    rng.gen_range(1..=10)
+                });
+                targets.push(target);
+            }
+            targets.push(tag);
+            targets.extend(chosen.iter().map(|_| tag));
+        }
+    }
+
+    /// Strengthens the connections between two sets of tags, as is done when
+    /// tag groups merge
+    ///
+    /// Each tag of `b` is paired with up to `n_links` tags of `a`, chosen at
+    /// random, so that at most `2 * n_links` edges are added for each tag of
+    /// `b` however large `a` is. Each pair is connected in both directions by
+    /// an edge with a weight in the range `5..=10`, as within tag groups.
+    /// Pairs which are already connected have the weights of their edges
+    /// halved instead (to a minimum of one)
+    pub fn join_tags(
+        &mut self,
+        rng: &mut (impl Rng + ?Sized),
+        a: &HashSet<TagId>,
+        b: &HashSet<TagId>,
+        n_links: usize,
+        semantics: EdgeSemantics,
+    ) {
+        let mut a = a.iter().copied().collect::<Vec<_>>();
+        a.sort_unstable_by_key(|tag| tag.0);
+        let mut b = b.iter().copied().collect::<Vec<_>>();
+        b.sort_unstable_by_key(|tag| tag.0);
+
+        for GraphId(b, _) in b {
+            let linked = a
+                .choose_multiple(&mut *rng, n_links)
+                .copied()
+                .collect::<Vec<_>>();
+            for GraphId(a, _) in linked {
+                self.strengthen_both_ways(&mut *rng, a, b, semantics);
+            }
+        }
+    }
+
+    /// Connects two tags in both directions as [`Simulation::join_tags`]
+    /// does, or halves the weights of the edges between them if they are
+    /// already connected
+    fn strengthen_both_ways(
+        &mut self,
+        rng: &mut (impl Rng + ?Sized),
+        a: usize,
+        b: usize,
+        semantics: EdgeSemantics,
+    ) {
+        let mut shared_weight = None;
+        for (from, to) in [(a, b), (b, a)] {
+            if let Some(edge) = self.0.find_edge(from.into(), to.into()) {
+                self.0[edge] =
+                    self.weight((self.0[edge].affinity / 2).max(1));
+                continue;
+            }
+
+            let weight = if semantics == EdgeSemantics::DirectedAsymmetric {
+                rng.gen_range(5..=10)
+            } else {
+                *shared_weight.get_or_insert_with(|| rng.gen_range(5..=10))
+            };
+            self.0.add_edge(from.into(), to.into(), self.weight(weight));
+        }
+    }
+
+    /// Removes every edge between two sets of tags, as is done when a tag
+    /// group splits
+    pub fn separate_tags(&mut self, a: &HashSet<TagId>, b: &HashSet<TagId>) {
+        for (GraphId(a, _), GraphId(b, _)) in a.iter().cartesian_product(b) {
+            for (from, to) in [(*a, *b), (*b, *a)] {
+                while let Some(edge) =
+                    self.0.find_edge(from.into(), to.into())
+                {
+                    self.0.remove_edge(edge);
+                }
+            }
+        }
+    }
+
+    /// Connects tags to a parent tag above their tag group
+    ///
+    /// Each tag is connected to the parent in both directions. Weights are
+    /// sampled from the range `5..=10` as within tag groups, then multiplied
+    /// by `discount` (and rounded, to a minimum of one), so that distances
+    /// through the parent are shorter than those across the group itself
+    pub fn connect_to_parent(
+        &mut self,
+        rng: &mut (impl Rng + ?Sized),
+        parent: TagId,
+        tags: impl IntoIterator<Item = TagId>,
+        discount: f64,
+        semantics: EdgeSemantics,
+    ) {
+        for tag in tags {
+            self.connect_both_ways(tag.0, parent.0, semantics, || {
+                (f64::from(rng.gen_range(5..=10u32)) * discount)
+                    .round()
+                    .max(1.0) as u32
+            });
+        }
+    }
+
     /// Adds singular edges between nodes specified in the `source_nodes` and
     /// `target_nodes` lists
     ///
     /// A number of edges within the range specified by `edge_bounds` will be
     /// added from a source node to distinct target nodes. A weight in the
     /// range `1..=10` is assigned to the edge, sampled from a discrete
-    /// uniform distribution
+    /// uniform distribution. With [`EdgeSemantics::Undirected`], each edge is
+    /// mirrored by one with the same weight in the opposite direction
+    #[instrument(skip_all)]
     pub fn connect_extremities<K>(
         &mut self,
         rng: &mut (impl Rng + ?Sized),
@@ -236,16 +1071,10 @@ impl Simulation {
         target_nodes: impl IntoIterator<Item = TagId> + Clone,
         edge_bounds: impl SampleRange<usize> + Clone,
         reverse_direction: bool,
+        semantics: EdgeSemantics,
     ) where
         K: ids::IsItemOrSheep,
     {
-        // TODO: add some behavior here where we "magically" connect new tags
-        //       to source nodes. the chance of this happening will be
-        //       computed based on the weight associated with the edge between
-        //       the source node and target node combined with the weight
-        //       associated with the edge between the candidate tag and the
-        //       tag already connected to the source node
-
         for GraphId(source, _) in source_nodes {
             let n_edges = rng.gen_range(edge_bounds.clone());
             for GraphId(tag, _) in target_nodes
@@ -253,20 +1082,81 @@ impl Simulation {
                 .into_iter()
                 .choose_multiple(rng, n_edges)
             {
-                if reverse_direction {
-                    self.0.add_edge(
-                        tag.into(),
-                        source.into(),
-                        rng.gen_range(1..=10),
-                    );
+                let (from, to) = if reverse_direction {
+                    (tag, source)
                 } else {
-                    self.0.add_edge(
-                        source.into(),
-                        tag.into(),
-                        rng.gen_range(1..=10),
-                    );
+                    (source, tag)
+                };
+                let weight = self.weight(rng.gen_range(1..=10));
+                self.0.add_edge(from.into(), to.into(), weight);
+                if semantics == EdgeSemantics::Undirected {
+                    self.0.add_edge(to.into(), from.into(), weight);
                 }
             }
         }
     }
+
+    /// Reinforces a sheep's preference for the tags of an item it responded
+    /// positively to
+    ///
+    /// Each of the item's tags is considered with the given probability. If
+    /// the sheep is already connected to a tag, the weight of the edge is
+    /// decremented (down to a minimum of one), otherwise a new edge is added
+    /// with a weight in the range `1..=10`. Either way, the edge is marked as
+    /// touched in the current epoch. With [`EdgeSemantics::Undirected`], the
+    /// edge from the tag back to the sheep is given the same weight
+    #[instrument(skip_all, fields(sheep = sheep.0, item = item.0))]
+    pub fn reinforce_preference(
+        &mut self,
+        rng: &mut (impl Rng + ?Sized),
+        sheep: SheepId,
+        item: ItemId,
+        probability: f64,
+        semantics: EdgeSemantics,
+    ) {
+        for GraphId(tag, _) in self.associated_tags(item).collect::<Vec<_>>()
+        {
+            if !rng.gen_bool(probability) {
+                continue;
+            }
+
+            let weight = if let Some(edge) =
+                self.0.find_edge(sheep.0.into(), tag.into())
+            {
+                let affinity = self.0[edge].affinity.saturating_sub(1).max(1);
+                self.0[edge] = self.weight(affinity);
+                self.0[edge]
+            } else {
+                let weight = self.weight(rng.gen_range(1..=10));
+                self.0.add_edge(sheep.0.into(), tag.into(), weight);
+                weight
+            };
+
+            if semantics == EdgeSemantics::Undirected {
+                self.0.update_edge(tag.into(), sheep.0.into(), weight);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_json_rejects_edges_between_unrelated_nodes() {
+        let graph = Simulation::from_json(
+            br#"{"nodes": ["sheep", "tag", "item"], "edges": [[0, 1, 5], [1, 2, 5]]}"#
+                .as_slice(),
+        )
+        .expect("a valid graph description was rejected");
+        assert_eq!(graph.0.edge_count(), 2);
+
+        for edges in ["[[0, 2, 5]]", "[[2, 2, 5]]", "[[0, 3, 5]]"] {
+            let description = format!(
+                r#"{{"nodes": ["sheep", "tag", "item", "removed"], "edges": {edges}}}"#
+            );
+            assert!(Simulation::from_json(description.as_bytes()).is_err());
+        }
+    }
 }
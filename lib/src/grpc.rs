@@ -0,0 +1,988 @@
+use anyhow::{anyhow, Context};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    rc::Rc,
+};
+use tokio::{
+    runtime::{self, Runtime},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{
+    client::Grpc, codegen::http::uri::PathAndQuery, transport::Endpoint,
+    Streaming,
+};
+use tonic_prost::ProstCodec;
+
+use crate::{
+    feed::{Feed, Justification, Response, ResponseCounts},
+    ids::{EpochId, GraphId, GraphIdKind, ItemId, RequestId},
+    protocol::Encoding,
+    shepherd::{
+        Capability, LimitedResponses, Payload, ShepherdEvent, SimulationEvent,
+    },
+    simulation::{Epoch, GraphDiff, RunConfig},
+};
+
+/// The path of the method called to exchange events with a shepherd
+const EXCHANGE_PATH: &str = "/shepherd.Shepherd/Exchange";
+
+/// The number of events which may be waiting to be sent to a shepherd before
+/// sending another blocks
+const CHANNEL_CAPACITY: usize = 64;
+
+/// The messages of the gRPC protocol, which mirror those defined in
+/// `proto/shepherd.proto`
+///
+/// These are kept by hand rather than generated, so that building the crate
+/// does not require `protoc`, and must be updated alongside the schema
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SimulationEvent {
+        #[prost(
+            oneof = "simulation_event::Event",
+            tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19"
+        )]
+        pub event: Option<simulation_event::Event>,
+    }
+
+    pub mod simulation_event {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Event {
+            #[prost(message, tag = "1")]
+            Hello(super::Hello),
+            #[prost(message, tag = "2")]
+            RunConfig(super::RunConfig),
+            #[prost(message, tag = "3")]
+            BeginEpoch(super::BeginEpoch),
+            #[prost(message, tag = "4")]
+            SheepIntroduction(super::SheepIntroduction),
+            #[prost(message, tag = "5")]
+            FeedRequest(super::FeedRequest),
+            #[prost(message, tag = "6")]
+            FeedResponses(super::FeedResponses),
+            #[prost(message, tag = "7")]
+            ResponsesBatch(super::ResponsesBatch),
+            #[prost(message, tag = "8")]
+            UnderDelivered(super::UnderDelivered),
+            #[prost(message, tag = "9")]
+            TagGroupsMerged(super::TagGroups),
+            #[prost(message, tag = "10")]
+            TagGroupSplit(super::TagGroups),
+            #[prost(message, tag = "11")]
+            EpochSummary(super::EpochSummary),
+            #[prost(message, tag = "12")]
+            EndEpoch(super::EndEpoch),
+            #[prost(message, tag = "13")]
+            Shutdown(super::Shutdown),
+            #[prost(message, tag = "14")]
+            TagGroups(super::TagGroupSnapshot),
+            #[prost(message, tag = "15")]
+            FeedRequestBatch(super::FeedRequestBatch),
+            #[prost(message, tag = "16")]
+            Ping(super::Ping),
+            #[prost(message, tag = "17")]
+            RetrieveCandidates(super::RetrieveCandidates),
+            #[prost(message, tag = "18")]
+            RankCandidates(super::RankCandidates),
+            #[prost(message, tag = "19")]
+            BudgetExceeded(super::BudgetExceeded),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ShepherdEvent {
+        #[prost(
+            oneof = "shepherd_event::Event",
+            tags = "1, 2, 3, 4, 5, 7, 8"
+        )]
+        pub event: Option<shepherd_event::Event>,
+        #[prost(uint64, tag = "6")]
+        pub request_id: u64,
+    }
+
+    pub mod shepherd_event {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Event {
+            #[prost(message, tag = "1")]
+            Hello(super::Hello),
+            #[prost(message, tag = "2")]
+            Feed(super::Feed),
+            #[prost(message, tag = "3")]
+            JustifiedFeed(super::JustifiedFeed),
+            #[prost(message, tag = "4")]
+            FeedBatch(super::FeedBatch),
+            #[prost(message, tag = "5")]
+            JustifiedFeedBatch(super::JustifiedFeedBatch),
+            #[prost(message, tag = "7")]
+            Pong(super::Pong),
+            #[prost(message, tag = "8")]
+            Candidates(super::Candidates),
+        }
+    }
+
+    #[derive(
+        Copy,
+        Clone,
+        Eq,
+        PartialEq,
+        Hash,
+        PartialOrd,
+        Ord,
+        Debug,
+        prost::Enumeration,
+    )]
+    #[repr(i32)]
+    pub enum Capability {
+        Unspecified = 0,
+        JustifiedFeeds = 1,
+        ResponseBatches = 2,
+        FeedBatches = 3,
+        Heartbeats = 4,
+        TwoStageFeeds = 5,
+    }
+
+    #[derive(
+        Copy,
+        Clone,
+        Eq,
+        PartialEq,
+        Hash,
+        PartialOrd,
+        Ord,
+        Debug,
+        prost::Enumeration,
+    )]
+    #[repr(i32)]
+    pub enum Payload {
+        Unspecified = 0,
+        ItemTags = 1,
+        TagGroupChanges = 2,
+        GraphDiffs = 3,
+        TagGroups = 4,
+    }
+
+    #[derive(
+        Copy,
+        Clone,
+        Eq,
+        PartialEq,
+        Hash,
+        PartialOrd,
+        Ord,
+        Debug,
+        prost::Enumeration,
+    )]
+    #[repr(i32)]
+    pub enum Response {
+        Unspecified = 0,
+        Positive = 1,
+        Neutral = 2,
+        Negative = 3,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Hello {
+        #[prost(uint32, tag = "1")]
+        pub protocol_version: u32,
+        #[prost(enumeration = "Capability", repeated, tag = "2")]
+        pub capabilities: Vec<i32>,
+        #[prost(enumeration = "Payload", repeated, tag = "3")]
+        pub payloads: Vec<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RunConfig {
+        #[prost(uint64, tag = "1")]
+        pub feed_size: u64,
+        #[prost(uint64, optional, tag = "2")]
+        pub n_epochs: Option<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BeginEpoch {
+        #[prost(uint64, tag = "1")]
+        pub id: u64,
+        #[prost(uint64, repeated, tag = "2")]
+        pub tags: Vec<u64>,
+        #[prost(message, repeated, tag = "3")]
+        pub items: Vec<Item>,
+        #[prost(message, optional, tag = "4")]
+        pub diff: Option<GraphDiff>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Item {
+        #[prost(uint64, tag = "1")]
+        pub id: u64,
+        #[prost(uint64, repeated, tag = "2")]
+        pub tags: Vec<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GraphDiff {
+        #[prost(message, repeated, tag = "1")]
+        pub tag_edges: Vec<TagEdge>,
+        #[prost(message, repeated, tag = "2")]
+        pub removed_tag_edges: Vec<TagEdge>,
+        #[prost(message, repeated, tag = "3")]
+        pub tag_groups: Vec<TagGroupChange>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TagEdge {
+        #[prost(uint64, tag = "1")]
+        pub source: u64,
+        #[prost(uint64, tag = "2")]
+        pub target: u64,
+        #[prost(uint32, tag = "3")]
+        pub weight: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TagGroupChange {
+        #[prost(uint64, tag = "1")]
+        pub tag: u64,
+        #[prost(uint64, optional, tag = "2")]
+        pub group: Option<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SheepIntroduction {
+        #[prost(uint64, tag = "1")]
+        pub sheep: u64,
+        #[prost(uint64, repeated, tag = "2")]
+        pub associated_tags: Vec<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FeedRequest {
+        #[prost(uint64, tag = "1")]
+        pub sheep: u64,
+        #[prost(uint64, tag = "2")]
+        pub request_id: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FeedRequestBatch {
+        #[prost(uint64, repeated, tag = "1")]
+        pub sheep: Vec<u64>,
+        #[prost(uint64, tag = "2")]
+        pub request_id: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RetrieveCandidates {
+        #[prost(uint64, tag = "1")]
+        pub sheep: u64,
+        #[prost(uint64, tag = "2")]
+        pub request_id: u64,
+        #[prost(uint64, tag = "3")]
+        pub n_candidates: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RankCandidates {
+        #[prost(uint64, tag = "1")]
+        pub sheep: u64,
+        #[prost(uint64, tag = "2")]
+        pub request_id: u64,
+        #[prost(uint64, repeated, tag = "3")]
+        pub candidates: Vec<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FeedResponses {
+        #[prost(uint64, tag = "1")]
+        pub sheep: u64,
+        #[prost(message, repeated, tag = "2")]
+        pub responses: Vec<Rating>,
+        #[prost(uint64, tag = "3")]
+        pub epoch: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Rating {
+        #[prost(uint64, tag = "1")]
+        pub item: u64,
+        #[prost(enumeration = "Response", tag = "2")]
+        pub response: i32,
+        #[prost(uint64, optional, tag = "3")]
+        pub age: Option<u64>,
+        #[prost(uint32, optional, tag = "4")]
+        pub hops: Option<u32>,
+        #[prost(uint64, tag = "5")]
+        pub position: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ResponsesBatch {
+        #[prost(message, repeated, tag = "1")]
+        pub responses: Vec<FeedResponses>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct UnderDelivered {
+        #[prost(uint64, tag = "1")]
+        pub sheep: u64,
+        #[prost(uint64, tag = "2")]
+        pub n_missing: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BudgetExceeded {
+        #[prost(uint64, tag = "1")]
+        pub sheep: u64,
+        #[prost(uint64, repeated, tag = "2")]
+        pub items: Vec<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TagGroups {
+        #[prost(uint64, repeated, tag = "1")]
+        pub first: Vec<u64>,
+        #[prost(uint64, repeated, tag = "2")]
+        pub second: Vec<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TagGroupSnapshot {
+        #[prost(message, repeated, tag = "1")]
+        pub groups: Vec<TagGroup>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TagGroup {
+        #[prost(uint64, repeated, tag = "1")]
+        pub tags: Vec<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct EpochSummary {
+        #[prost(uint64, tag = "1")]
+        pub id: u64,
+        #[prost(message, repeated, tag = "2")]
+        pub sheep_stats: Vec<SheepStats>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SheepStats {
+        #[prost(uint64, tag = "1")]
+        pub sheep: u64,
+        #[prost(uint64, tag = "2")]
+        pub positive: u64,
+        #[prost(uint64, tag = "3")]
+        pub neutral: u64,
+        #[prost(uint64, tag = "4")]
+        pub negative: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct EndEpoch {
+        #[prost(uint64, tag = "1")]
+        pub id: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Shutdown {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Ping {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Pong {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Candidates {
+        #[prost(uint64, repeated, tag = "1")]
+        pub items: Vec<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Feed {
+        #[prost(uint64, repeated, tag = "1")]
+        pub items: Vec<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct JustifiedFeed {
+        #[prost(message, repeated, tag = "1")]
+        pub items: Vec<JustifiedItem>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FeedBatch {
+        #[prost(message, repeated, tag = "1")]
+        pub feeds: Vec<Feed>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct JustifiedFeedBatch {
+        #[prost(message, repeated, tag = "1")]
+        pub feeds: Vec<JustifiedFeed>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct JustifiedItem {
+        #[prost(uint64, tag = "1")]
+        pub item: u64,
+        #[prost(oneof = "justified_item::Justification", tags = "2, 3")]
+        pub justification: Option<justified_item::Justification>,
+    }
+
+    pub mod justified_item {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Justification {
+            #[prost(uint64, tag = "2")]
+            Tag(u64),
+            #[prost(uint64, tag = "3")]
+            SimilarItem(u64),
+        }
+    }
+}
+
+/// Convert a list of identifiers into the integers they are sent as
+fn ids<K: GraphIdKind>(ids: Vec<GraphId<K>>) -> Vec<u64> {
+    ids.into_iter().map(|id| id.0 as u64).collect()
+}
+
+/// Convert a greeting into the message it is sent as
+fn hello(
+    protocol_version: u32,
+    capabilities: Vec<Capability>,
+    payloads: Vec<Payload>,
+) -> proto::Hello {
+    proto::Hello {
+        protocol_version,
+        capabilities: capabilities
+            .into_iter()
+            .map(|capability| {
+                match capability {
+                    Capability::JustifiedFeeds => {
+                        proto::Capability::JustifiedFeeds
+                    }
+                    Capability::ResponseBatches => {
+                        proto::Capability::ResponseBatches
+                    }
+                    Capability::FeedBatches => proto::Capability::FeedBatches,
+                    Capability::Heartbeats => proto::Capability::Heartbeats,
+                    Capability::TwoStageFeeds => {
+                        proto::Capability::TwoStageFeeds
+                    }
+                }
+                .into()
+            })
+            .collect(),
+        payloads: payloads
+            .into_iter()
+            .map(|payload| {
+                match payload {
+                    Payload::ItemTags => proto::Payload::ItemTags,
+                    Payload::TagGroupChanges => {
+                        proto::Payload::TagGroupChanges
+                    }
+                    Payload::GraphDiffs => proto::Payload::GraphDiffs,
+                    Payload::TagGroups => proto::Payload::TagGroups,
+                }
+                .into()
+            })
+            .collect(),
+    }
+}
+
+/// Convert the responses to a feed into the message they are sent as
+fn feed_responses(
+    sheep: u64,
+    epoch: u64,
+    LimitedResponses(responses): LimitedResponses,
+) -> proto::FeedResponses {
+    proto::FeedResponses {
+        sheep,
+        epoch,
+        responses: responses
+            .into_iter()
+            .map(|(item, response, age, hops, position)| proto::Rating {
+                item: item.0 as u64,
+                response: match response {
+                    Response::Positive => proto::Response::Positive,
+                    Response::Neutral => proto::Response::Neutral,
+                    Response::Negative => proto::Response::Negative,
+                }
+                .into(),
+                age: age.map(|age| age as u64),
+                hops,
+                position: position as u64,
+            })
+            .collect(),
+    }
+}
+
+/// Convert a [`GraphDiff`] into the message it is sent as
+fn graph_diff(diff: GraphDiff) -> proto::GraphDiff {
+    proto::GraphDiff {
+        tag_edges: diff
+            .tag_edges
+            .into_iter()
+            .map(|(source, target, weight)| proto::TagEdge {
+                source: source.0 as u64,
+                target: target.0 as u64,
+                weight,
+            })
+            .collect(),
+        removed_tag_edges: diff
+            .removed_tag_edges
+            .into_iter()
+            .map(|(source, target)| proto::TagEdge {
+                source: source.0 as u64,
+                target: target.0 as u64,
+                weight: 0,
+            })
+            .collect(),
+        tag_groups: diff
+            .tag_groups
+            .into_iter()
+            .map(|(tag, group)| proto::TagGroupChange {
+                tag: tag.0 as u64,
+                group: group.map(|group| group as u64),
+            })
+            .collect(),
+    }
+}
+
+impl From<SimulationEvent> for proto::SimulationEvent {
+    fn from(event: SimulationEvent) -> Self {
+        use proto::simulation_event::Event;
+
+        let event = match event {
+            SimulationEvent::Hello {
+                protocol_version,
+                capabilities,
+                payloads,
+                ..
+            } => {
+                Event::Hello(hello(protocol_version, capabilities, payloads))
+            }
+            SimulationEvent::RunConfig(RunConfig {
+                feed_size,
+                n_epochs,
+            }) => Event::RunConfig(proto::RunConfig {
+                feed_size: feed_size as u64,
+                n_epochs: n_epochs.map(|n_epochs| n_epochs as u64),
+            }),
+            SimulationEvent::BeginEpoch {
+                id: EpochId(id),
+                data: Epoch { tags, items, diff },
+            } => Event::BeginEpoch(proto::BeginEpoch {
+                id: id as u64,
+                tags: ids(tags),
+                items: items
+                    .into_iter()
+                    .map(|(id, tags)| proto::Item {
+                        id: id.0 as u64,
+                        tags: ids(tags),
+                    })
+                    .collect(),
+                diff: diff.map(graph_diff),
+            }),
+            SimulationEvent::SheepIntroduction {
+                sheep,
+                associated_tags,
+            } => Event::SheepIntroduction(proto::SheepIntroduction {
+                sheep: sheep.0 as u64,
+                associated_tags: ids(associated_tags),
+            }),
+            SimulationEvent::FeedRequest { request_id, sheep } => {
+                Event::FeedRequest(proto::FeedRequest {
+                    sheep: sheep.0 as u64,
+                    request_id: request_id.0 as u64,
+                })
+            }
+            SimulationEvent::FeedRequestBatch { request_id, sheep } => {
+                Event::FeedRequestBatch(proto::FeedRequestBatch {
+                    sheep: ids(sheep),
+                    request_id: request_id.0 as u64,
+                })
+            }
+            SimulationEvent::RetrieveCandidates {
+                request_id,
+                sheep,
+                n_candidates,
+            } => Event::RetrieveCandidates(proto::RetrieveCandidates {
+                sheep: sheep.0 as u64,
+                request_id: request_id.0 as u64,
+                n_candidates: n_candidates as u64,
+            }),
+            SimulationEvent::RankCandidates {
+                request_id,
+                sheep,
+                candidates,
+            } => Event::RankCandidates(proto::RankCandidates {
+                sheep: sheep.0 as u64,
+                request_id: request_id.0 as u64,
+                candidates: ids(candidates),
+            }),
+            SimulationEvent::FeedResponses {
+                sheep,
+                epoch,
+                responses,
+            } => Event::FeedResponses(feed_responses(
+                sheep.0 as u64,
+                epoch.0 as u64,
+                responses,
+            )),
+            SimulationEvent::ResponsesBatch { responses } => {
+                Event::ResponsesBatch(proto::ResponsesBatch {
+                    responses: responses
+                        .into_iter()
+                        .map(|(sheep, epoch, responses)| {
+                            feed_responses(
+                                sheep.0 as u64,
+                                epoch.0 as u64,
+                                responses,
+                            )
+                        })
+                        .collect(),
+                })
+            }
+            SimulationEvent::UnderDelivered { sheep, n_missing } => {
+                Event::UnderDelivered(proto::UnderDelivered {
+                    sheep: sheep.0 as u64,
+                    n_missing: n_missing as u64,
+                })
+            }
+            SimulationEvent::BudgetExceeded { sheep, items } => {
+                Event::BudgetExceeded(proto::BudgetExceeded {
+                    sheep: sheep.0 as u64,
+                    items: ids(items),
+                })
+            }
+            SimulationEvent::TagGroupsMerged {
+                tags: (first, second),
+            } => Event::TagGroupsMerged(proto::TagGroups {
+                first: ids(first),
+                second: ids(second),
+            }),
+            SimulationEvent::TagGroupSplit {
+                tags: (first, second),
+            } => Event::TagGroupSplit(proto::TagGroups {
+                first: ids(first),
+                second: ids(second),
+            }),
+            SimulationEvent::TagGroups { groups } => {
+                Event::TagGroups(proto::TagGroupSnapshot {
+                    groups: groups
+                        .into_iter()
+                        .map(|tags| proto::TagGroup { tags: ids(tags) })
+                        .collect(),
+                })
+            }
+            SimulationEvent::EpochSummary {
+                id: EpochId(id),
+                sheep_stats,
+            } => Event::EpochSummary(proto::EpochSummary {
+                id: id as u64,
+                sheep_stats: sheep_stats
+                    .into_iter()
+                    .map(
+                        |(
+                            sheep,
+                            ResponseCounts {
+                                positive,
+                                neutral,
+                                negative,
+                            },
+                        )| proto::SheepStats {
+                            sheep: sheep.0 as u64,
+                            positive: positive as u64,
+                            neutral: neutral as u64,
+                            negative: negative as u64,
+                        },
+                    )
+                    .collect(),
+            }),
+            SimulationEvent::EndEpoch { id: EpochId(id) } => {
+                Event::EndEpoch(proto::EndEpoch { id: id as u64 })
+            }
+            SimulationEvent::Shutdown => Event::Shutdown(proto::Shutdown {}),
+            SimulationEvent::Ping => Event::Ping(proto::Ping {}),
+        };
+
+        Self { event: Some(event) }
+    }
+}
+
+impl TryFrom<proto::ShepherdEvent> for ShepherdEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(event: proto::ShepherdEvent) -> anyhow::Result<Self> {
+        use proto::shepherd_event::Event;
+
+        let request_id = RequestId(event.request_id as usize);
+        Ok(
+            match event.event.context("The shepherd sent an empty event")? {
+                Event::Hello(proto::Hello {
+                    protocol_version,
+                    capabilities,
+                    payloads,
+                }) => Self::Hello {
+                    protocol_version,
+                    capabilities: capabilities
+                        .into_iter()
+                        .map(|capability| {
+                            match proto::Capability::try_from(capability) {
+                                Ok(proto::Capability::JustifiedFeeds) => {
+                                    Ok(Capability::JustifiedFeeds)
+                                }
+                                Ok(proto::Capability::ResponseBatches) => {
+                                    Ok(Capability::ResponseBatches)
+                                }
+                                Ok(proto::Capability::FeedBatches) => {
+                                    Ok(Capability::FeedBatches)
+                                }
+                                Ok(proto::Capability::Heartbeats) => {
+                                    Ok(Capability::Heartbeats)
+                                }
+                                Ok(proto::Capability::TwoStageFeeds) => {
+                                    Ok(Capability::TwoStageFeeds)
+                                }
+                                _ => Err(anyhow!(
+                            "The shepherd declared an unknown capability"
+                        )),
+                            }
+                        })
+                        .collect::<anyhow::Result<_>>()?,
+                    payloads: payloads
+                        .into_iter()
+                        .map(|payload| {
+                            match proto::Payload::try_from(payload) {
+                                Ok(proto::Payload::ItemTags) => {
+                                    Ok(Payload::ItemTags)
+                                }
+                                Ok(proto::Payload::TagGroupChanges) => {
+                                    Ok(Payload::TagGroupChanges)
+                                }
+                                Ok(proto::Payload::GraphDiffs) => {
+                                    Ok(Payload::GraphDiffs)
+                                }
+                                Ok(proto::Payload::TagGroups) => {
+                                    Ok(Payload::TagGroups)
+                                }
+                                _ => Err(anyhow!(
+                                "The shepherd requested an unknown payload"
+                            )),
+                            }
+                        })
+                        .collect::<anyhow::Result<_>>()?,
+                    // events are passed through the shepherd's streams as json,
+                    // regardless of how they are sent over the network
+                    encoding: Encoding::Json,
+                },
+                Event::Feed(items) => Self::Feed {
+                    request_id,
+                    feed: feed(items),
+                },
+                Event::JustifiedFeed(items) => Self::JustifiedFeed {
+                    request_id,
+                    items: justified_feed(items),
+                },
+                Event::FeedBatch(proto::FeedBatch { feeds }) => {
+                    Self::FeedBatch {
+                        request_id,
+                        feeds: feeds.into_iter().map(feed).collect(),
+                    }
+                }
+                Event::JustifiedFeedBatch(proto::JustifiedFeedBatch {
+                    feeds,
+                }) => Self::JustifiedFeedBatch {
+                    request_id,
+                    feeds: feeds.into_iter().map(justified_feed).collect(),
+                },
+                Event::Candidates(proto::Candidates { items }) => {
+                    Self::Candidates {
+                        request_id,
+                        items: items
+                            .into_iter()
+                            .map(|item| GraphId::new(item as usize))
+                            .collect(),
+                    }
+                }
+                Event::Pong(proto::Pong {}) => Self::Pong,
+            },
+        )
+    }
+}
+
+/// Convert a feed from the message it is sent as
+fn feed(proto::Feed { items }: proto::Feed) -> Feed {
+    Feed(
+        items
+            .into_iter()
+            .map(|item| GraphId::new(item as usize))
+            .collect(),
+    )
+}
+
+/// Convert a justified feed from the message it is sent as
+fn justified_feed(
+    proto::JustifiedFeed { items }: proto::JustifiedFeed,
+) -> Vec<(ItemId, Option<Justification>)> {
+    use proto::justified_item::Justification as Reason;
+
+    items
+        .into_iter()
+        .map(
+            |proto::JustifiedItem {
+                 item,
+                 justification,
+             }| {
+                (
+                    GraphId::new(item as usize),
+                    justification.map(|justification| match justification {
+                        Reason::Tag(tag) => {
+                            Justification::Tag(GraphId::new(tag as usize))
+                        }
+                        Reason::SimilarItem(item) => {
+                            Justification::SimilarItem(GraphId::new(
+                                item as usize,
+                            ))
+                        }
+                    }),
+                )
+            },
+        )
+        .collect()
+}
+
+/// The input half of a shepherd created by [`Shepherd::connect_grpc`]
+///
+/// Each event is sent to the shepherd once it is flushed
+///
+/// [`Shepherd::connect_grpc`]: crate::shepherd::Shepherd::connect_grpc
+pub(crate) struct GrpcInput {
+    runtime: Rc<Runtime>,
+    sender: mpsc::Sender<proto::SimulationEvent>,
+    buffer: Vec<u8>,
+}
+
+impl Write for GrpcInput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let event: SimulationEvent = serde_json::from_slice(&self.buffer)?;
+        self.buffer.clear();
+
+        self.runtime
+            .block_on(self.sender.send(event.into()))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+/// The output half of a shepherd created by [`Shepherd::connect_grpc`]
+///
+/// The call is only waited on once the first event is read, as some servers
+/// do not respond to it until they have received an event
+///
+/// [`Shepherd::connect_grpc`]: crate::shepherd::Shepherd::connect_grpc
+pub(crate) struct GrpcOutput {
+    runtime: Rc<Runtime>,
+    call: Option<
+        JoinHandle<Result<Streaming<proto::ShepherdEvent>, tonic::Status>>,
+    >,
+    events: Option<Streaming<proto::ShepherdEvent>>,
+    buffer: VecDeque<u8>,
+}
+
+impl Read for GrpcOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            if let Some(call) = self.call.take() {
+                self.events = Some(
+                    self.runtime
+                        .block_on(call)
+                        .map_err(io::Error::other)?
+                        .map_err(io::Error::other)?,
+                );
+            }
+            let Some(events) = self.events.as_mut() else {
+                return Ok(0);
+            };
+            let Some(event) = self
+                .runtime
+                .block_on(events.message())
+                .map_err(io::Error::other)?
+            else {
+                return Ok(0);
+            };
+
+            let event =
+                ShepherdEvent::try_from(event).map_err(io::Error::other)?;
+            serde_json::to_writer(&mut self.buffer, &event)?;
+            self.buffer.push_back(b'\n');
+        }
+
+        self.buffer.read(buf)
+    }
+}
+
+/// Connect to a shepherd serving the `Shepherd` service at the given
+/// endpoint, and start exchanging events with it
+pub(crate) fn connect(
+    endpoint: String,
+) -> anyhow::Result<(GrpcInput, GrpcOutput)> {
+    // a single worker keeps the connection alive while the simulation is
+    // busy with something else
+    let runtime = Rc::new(
+        runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .context("Unable to start the runtime for the gRPC transport")?,
+    );
+    let channel = runtime
+        .block_on(
+            Endpoint::from_shared(endpoint)
+                .context("Invalid gRPC endpoint")?
+                .connect(),
+        )
+        .context("Unable to connect to the shepherd")?;
+
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let call = runtime.spawn(async move {
+        let mut client = Grpc::new(channel);
+        client
+            .ready()
+            .await
+            .map_err(|error| tonic::Status::unavailable(error.to_string()))?;
+        client
+            .streaming(
+                tonic::Request::new(ReceiverStream::new(receiver)),
+                PathAndQuery::from_static(EXCHANGE_PATH),
+                ProstCodec::default(),
+            )
+            .await
+            .map(tonic::Response::into_inner)
+    });
+
+    Ok((
+        GrpcInput {
+            runtime: Rc::clone(&runtime),
+            sender,
+            buffer: Vec::new(),
+        },
+        GrpcOutput {
+            runtime,
+            call: Some(call),
+            events: None,
+            buffer: VecDeque::new(),
+        },
+    ))
+}
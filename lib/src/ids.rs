@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "schema")]
+use std::borrow::Cow;
 use std::marker::PhantomData;
 
 /// An identifier for an epoch within the simulation
@@ -6,6 +8,7 @@ use std::marker::PhantomData;
 #[derive(
     Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Serialize, Deserialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(transparent)]
 pub struct EpochId(pub usize);
 
@@ -17,6 +20,16 @@ pub struct EpochId(pub usize);
 #[serde(transparent)]
 pub struct ShepherdId(pub usize);
 
+/// An identifier for a request sent to a shepherd, which it repeats in its
+/// response so that the two can be matched up
+#[repr(transparent)]
+#[derive(
+    Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Serialize, Deserialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(transparent)]
+pub struct RequestId(pub usize);
+
 /// An identifier that represents a tag within the simulation
 pub type TagId = GraphId<Tag>;
 
@@ -42,6 +55,28 @@ where
     }
 }
 
+// the kind of a graph identifier isn't part of its encoding, so the schema
+// of every kind is that of the number it is encoded as
+#[cfg(feature = "schema")]
+impl<K> schemars::JsonSchema for GraphId<K>
+where
+    K: GraphIdKind,
+{
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        usize::schema_name()
+    }
+
+    fn json_schema(
+        generator: &mut schemars::SchemaGenerator,
+    ) -> schemars::Schema {
+        usize::json_schema(generator)
+    }
+}
+
 /// A trait represeting the kinds of identifiers that relate to the
 /// simulation graph
 #[allow(private_bounds)]
@@ -93,7 +128,8 @@ impl IsItemOrSheep for Item {}
 impl IsItemOrSheep for Sheep {}
 
 /// An enumeration over the kinds of nodes in the tag graph
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum NodeType {
     /// A sheep (user) in the simulation
     Sheep,
@@ -103,4 +139,10 @@ pub enum NodeType {
 
     /// An item (content) in the simulation
     Item,
+
+    /// A node which has been removed from the simulation
+    ///
+    /// Removed nodes are kept as tombstones without any edges so that the
+    /// identifiers of the remaining nodes stay stable
+    Removed,
 }
@@ -26,9 +26,21 @@
 #![deny(clippy::option_option)]
 #![deny(clippy::mut_mut)]
 
+pub mod baseline;
+pub mod collections;
+pub mod export;
 pub mod feed;
 pub mod graph;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod ids;
+#[cfg(feature = "process")]
+pub mod limits;
+pub mod metrics;
+#[cfg(feature = "movielens")]
+pub mod movielens;
+pub mod protocol;
+pub mod sdk;
 pub mod sheep;
 pub mod shepherd;
 pub mod simulation;
@@ -0,0 +1,184 @@
+#[cfg(all(windows, feature = "job-objects"))]
+use anyhow::Context;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+#[cfg(all(windows, feature = "job-objects"))]
+use std::{io, mem, os::windows::io::RawHandle, ptr};
+use std::{process::Command, time::Duration};
+#[cfg(all(windows, feature = "job-objects"))]
+use windows_sys::Win32::{
+    Foundation::CloseHandle,
+    System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW,
+        JobObjectExtendedLimitInformation, SetInformationJobObject,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_PROCESS_MEMORY, JOB_OBJECT_LIMIT_PROCESS_TIME,
+    },
+};
+
+/// Limits on the resources a shepherd running in a process of its own may
+/// use, which are placed on it as it is spawned
+///
+/// On unix, these are enforced as resource limits (see `setrlimit(2)`), so a
+/// shepherd exceeding its CPU time is killed and one exceeding its memory
+/// has its allocations fail. On windows, they are enforced by assigning the
+/// shepherd to a job object, which requires the `job-objects` feature, and
+/// only the CPU time spent in user mode is counted. They are unsupported
+/// anywhere else
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// The CPU time the shepherd may use over its whole lifetime, which is
+    /// rounded up to whole seconds on unix
+    pub cpu_time: Option<Duration>,
+
+    /// The memory the shepherd may use, in bytes
+    pub memory: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Check whether no limits are set at all
+    pub fn is_unlimited(&self) -> bool {
+        self.cpu_time.is_none() && self.memory.is_none()
+    }
+
+    /// Arrange for these limits to be placed on the process spawned by the
+    /// given command
+    #[cfg(unix)]
+    pub(crate) fn prepare(self, command: &mut Command) -> anyhow::Result<()> {
+        if self.is_unlimited() {
+            return Ok(());
+        }
+
+        // the limit on cpu time is counted in whole seconds, so it is
+        // rounded up rather than ever cutting a shepherd short
+        let cpu_time = self.cpu_time.map(|limit| {
+            let seconds =
+                limit.as_secs() + u64::from(limit.subsec_nanos() > 0);
+            libc::rlim_t::try_from(seconds).unwrap_or(libc::RLIM_INFINITY)
+        });
+        let memory = self.memory.map(|limit| {
+            libc::rlim_t::try_from(limit).unwrap_or(libc::RLIM_INFINITY)
+        });
+
+        // SAFETY: nothing but setrlimit, which is async-signal-safe, is
+        // called between forking and executing the shepherd
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(limit) = cpu_time {
+                    let limit = libc::rlimit {
+                        rlim_cur: limit,
+                        rlim_max: limit,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_CPU, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+
+                if let Some(limit) = memory {
+                    let limit = libc::rlimit {
+                        rlim_cur: limit,
+                        rlim_max: limit,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Arrange for these limits to be placed on the process spawned by the
+    /// given command
+    ///
+    /// The limits themselves are placed on the process by
+    /// [`ResourceLimits::enforce`] once it has been spawned
+    #[cfg(windows)]
+    pub(crate) fn prepare(self, command: &mut Command) -> anyhow::Result<()> {
+        let _ = command;
+        if !cfg!(feature = "job-objects") && !self.is_unlimited() {
+            anyhow::bail!(
+                "Limiting the resources of a shepherd requires the \
+                 job-objects feature on windows"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Arrange for these limits to be placed on the process spawned by the
+    /// given command
+    #[cfg(not(any(unix, windows)))]
+    pub(crate) fn prepare(self, command: &mut Command) -> anyhow::Result<()> {
+        let _ = command;
+        if !self.is_unlimited() {
+            anyhow::bail!(
+                "Limiting the resources of a shepherd is unsupported on this \
+                 platform"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Place these limits on a process which has just been spawned, by
+    /// assigning it to a job object of its own
+    #[cfg(all(windows, feature = "job-objects"))]
+    pub(crate) fn enforce(self, process: RawHandle) -> anyhow::Result<()> {
+        if self.is_unlimited() {
+            return Ok(());
+        }
+
+        // SAFETY: this is plain data, in which all zeroes means no limits
+        let mut information: JOBOBJECT_EXTENDED_LIMIT_INFORMATION =
+            unsafe { mem::zeroed() };
+        let basic = &mut information.BasicLimitInformation;
+        if let Some(limit) = self.cpu_time {
+            // job objects count time in units of 100 nanoseconds
+            basic.PerProcessUserTimeLimit =
+                i64::try_from(limit.as_nanos() / 100).unwrap_or(i64::MAX);
+            basic.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+        }
+        if let Some(limit) = self.memory {
+            information.ProcessMemoryLimit =
+                usize::try_from(limit).unwrap_or(usize::MAX);
+            basic.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+        }
+
+        // SAFETY: the job object is only used while its handle is open, and
+        // the process handle is owned by the caller for the whole call
+        unsafe {
+            let job = CreateJobObjectW(ptr::null(), ptr::null());
+            if job.is_null() {
+                return Err(io::Error::last_os_error()).context(
+                    "Unable to create a job object for the shepherd process",
+                );
+            }
+
+            let result = if SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                ptr::from_ref(&information).cast(),
+                mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            ) == 0
+            {
+                Err(io::Error::last_os_error())
+                    .context("Unable to set the limits of a job object")
+            } else if AssignProcessToJobObject(job, process) == 0 {
+                Err(io::Error::last_os_error()).context(
+                    "Unable to assign the shepherd process to a job object",
+                )
+            } else {
+                Ok(())
+            };
+
+            // the job object lives on for as long as the process is assigned
+            // to it, so its handle isn't needed past this point
+            CloseHandle(job);
+            result
+        }
+    }
+}
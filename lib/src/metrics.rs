@@ -0,0 +1,1747 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    collections::{HashMap, HashSet},
+    feed::{Response, ResponseCounts, Responses},
+    graph,
+    ids::{EpochId, ItemId, SheepId, ShepherdId},
+};
+
+/// A custom metric computed over the responses each [`Shepherd`] receives
+///
+/// Metrics registered in [`Settings::metrics`] are updated with every rated
+/// feed and finalized at the end of every epoch, after which their values are
+/// reported through [`Settings::metric_hook`]
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+/// [`Settings::metrics`]: crate::simulation::Settings::metrics
+/// [`Settings::metric_hook`]: crate::simulation::Settings::metric_hook
+pub trait Metric {
+    /// The name the metric is reported under, which should be unique among
+    /// the registered metrics
+    fn name(&self) -> &str;
+
+    /// Whether higher values of the metric are better, which is used when
+    /// comparing runs
+    fn higher_is_better(&self) -> bool {
+        true
+    }
+
+    /// Update the metric with the responses of a sheep to a feed built by a
+    /// [`Shepherd`]
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    fn update(
+        &mut self,
+        shepherd: ShepherdId,
+        sheep: SheepId,
+        responses: &Responses,
+    );
+
+    /// Finalize the metric at the end of an epoch, such as by folding the
+    /// epoch's updates into a running value
+    fn finalize_epoch(&mut self, _epoch: EpochId) {}
+
+    /// Report the current value of the metric for each [`Shepherd`] it has
+    /// a value for
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    fn report(&self) -> Vec<(ShepherdId, f64)>;
+}
+
+/// A collection of [`Metric`]s to compute over the course of a simulation
+#[derive(Default)]
+pub struct Registry<'a>(Vec<Box<dyn Metric + 'a>>);
+
+impl<'a> Registry<'a> {
+    /// Register a [`Metric`] to be computed
+    pub fn register(&mut self, metric: impl Metric + 'a) {
+        self.0.push(Box::new(metric));
+    }
+
+    /// Check whether no metrics are registered
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterate over the registered metrics
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Metric> + '_ {
+        self.0.iter().map(|metric| &**metric as &dyn Metric)
+    }
+
+    /// Update every registered metric with the responses of a sheep to a
+    /// feed
+    pub fn update(
+        &mut self,
+        shepherd: ShepherdId,
+        sheep: SheepId,
+        responses: &Responses,
+    ) {
+        for metric in &mut self.0 {
+            metric.update(shepherd, sheep, responses);
+        }
+    }
+
+    /// Finalize every registered metric at the end of an epoch
+    pub fn finalize_epoch(&mut self, epoch: EpochId) {
+        for metric in &mut self.0 {
+            metric.finalize_epoch(epoch);
+        }
+    }
+}
+
+/// The values each [`Metric`] has reported for each [`Shepherd`] at the end
+/// of each epoch of a simulation, as passed to [`Settings::metric_hook`]
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+/// [`Settings::metric_hook`]: crate::simulation::Settings::metric_hook
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct MetricReports {
+    /// The name of each metric and whether higher values of it are better,
+    /// in the order they first reported a value in
+    metrics: Vec<(String, bool)>,
+
+    /// The index of each metric and the value it reported, for each
+    /// shepherd during each epoch
+    values: HashMap<(ShepherdId, EpochId), Vec<(usize, f64)>>,
+}
+
+impl MetricReports {
+    /// Count a value a [`Metric`] reported for a [`Shepherd`] at the end of
+    /// an epoch
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        metric: &dyn Metric,
+        value: f64,
+    ) {
+        let index = self
+            .metrics
+            .iter()
+            .position(|(name, _)| name == metric.name())
+            .unwrap_or_else(|| {
+                self.metrics.push((
+                    metric.name().to_string(),
+                    metric.higher_is_better(),
+                ));
+                self.metrics.len() - 1
+            });
+        self.values
+            .entry((shepherd, epoch))
+            .or_default()
+            .push((index, value));
+    }
+
+    /// Get the name of each [`Metric`] which has reported a value and
+    /// whether higher values of it are better, in the order they first
+    /// reported one in
+    pub fn metrics(&self) -> &[(String, bool)] {
+        &self.metrics
+    }
+
+    /// Get the name of each [`Metric`] and the value it reported for a
+    /// [`Shepherd`] at the end of an epoch
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn get(
+        &self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+    ) -> impl Iterator<Item = (&str, f64)> + '_ {
+        self.values
+            .get(&(shepherd, epoch))
+            .into_iter()
+            .flatten()
+            .map(|(index, value)| (self.metrics[*index].0.as_str(), *value))
+    }
+
+    /// Get the value a [`Metric`] reported for a [`Shepherd`] at the end of
+    /// each epoch it reported one for it during, in order
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn epochs(
+        &self,
+        shepherd: ShepherdId,
+        metric: &str,
+    ) -> Vec<(EpochId, f64)> {
+        let Some(index) =
+            self.metrics.iter().position(|(name, _)| name == metric)
+        else {
+            return Vec::new();
+        };
+        let mut epochs = self
+            .values
+            .iter()
+            .filter(|((id, _), _)| *id == shepherd)
+            .filter_map(|((_, epoch), values)| {
+                values
+                    .iter()
+                    .find(|(i, _)| *i == index)
+                    .map(|(_, value)| (*epoch, *value))
+            })
+            .collect::<Vec<_>>();
+        epochs.sort_unstable_by_key(|(epoch, _)| epoch.0);
+        epochs
+    }
+
+    /// Get the value a [`Metric`] reported for a [`Shepherd`] at the end of
+    /// the latest epoch it reported one for it during, if any
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn latest(&self, shepherd: ShepherdId, metric: &str) -> Option<f64> {
+        self.epochs(shepherd, metric)
+            .last()
+            .map(|(_, value)| *value)
+    }
+}
+
+/// The relevance an item must have for it to count as relevant when
+/// computing [`RankingMetrics`] which only distinguish relevant items from
+/// irrelevant ones
+///
+/// Relevance is the probability of a sheep responding positively to an item,
+/// so this counts items the sheep is more likely than not to like
+pub const RELEVANCE_THRESHOLD: f64 = 0.5;
+
+/// The number of hops from a sheep within which items are close to it, which
+/// covers exactly the items of its own tags
+///
+/// Items further away than this, or not reachable at all, are counted as
+/// serendipitous when the sheep responds positively to them (see
+/// [`is_serendipitous`])
+pub const SERENDIPITY_HOPS: u32 = 2;
+
+/// Check whether a sheep's response to an item was serendipitous, which is a
+/// positive response to an item far from its tags (see [`SERENDIPITY_HOPS`])
+///
+/// This is independent of whether the response was made positive by the
+/// serendipity of the sheep's behavior, as it only looks at where the item
+/// sits in the graph
+pub fn is_serendipitous(response: Response, hops: Option<u32>) -> bool {
+    response == Response::Positive
+        && hops.is_none_or(|hops| hops > SERENDIPITY_HOPS)
+}
+
+/// How well a feed was ordered, judged against the graded relevance of each
+/// item to the sheep it was built for
+///
+/// Each metric is computed over the first `k` items of the feed, where `k` is
+/// the feed size. A feed is never penalized for what could not have been
+/// shown, so each metric is one if no item which could have been shown was
+/// relevant at all
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RankingMetrics {
+    /// The normalized discounted cumulative gain of the feed, which compares
+    /// the graded relevance of its items against that of the best feed which
+    /// could have been built
+    pub ndcg: f64,
+
+    /// The average precision of the feed, taken at each relevant item (see
+    /// [`RELEVANCE_THRESHOLD`])
+    pub average_precision: f64,
+
+    /// The reciprocal of the position of the first relevant item in the
+    /// feed, counted from one, or zero if there was none
+    pub reciprocal_rank: f64,
+}
+
+impl RankingMetrics {
+    /// Measure a feed given the relevance of each of its items in order, the
+    /// relevance of every item which could have been shown, and the feed
+    /// size
+    pub fn new(
+        relevances: &[f64],
+        eligible: impl IntoIterator<Item = f64>,
+        k: usize,
+    ) -> Self {
+        let relevances = &relevances[..relevances.len().min(k)];
+        let dcg = |relevances: &mut dyn Iterator<Item = f64>| {
+            relevances
+                .enumerate()
+                .map(|(i, relevance)| relevance / (i as f64 + 2.0).log2())
+                .sum::<f64>()
+        };
+
+        let mut ideal = eligible.into_iter().collect::<Vec<_>>();
+        ideal.sort_unstable_by(|a, b| b.total_cmp(a));
+        // at most `k` relevant items could have been shown
+        let n_relevant = ideal
+            .iter()
+            .filter(|relevance| **relevance >= RELEVANCE_THRESHOLD)
+            .count()
+            .min(k);
+        let ideal_dcg = dcg(&mut ideal.into_iter().take(k));
+
+        let (mut n_found, mut precision_sum, mut first) = (0, 0.0, None);
+        for (i, relevance) in relevances.iter().enumerate() {
+            if *relevance >= RELEVANCE_THRESHOLD {
+                n_found += 1;
+                precision_sum += f64::from(n_found) / (i as f64 + 1.0);
+                first.get_or_insert(i);
+            }
+        }
+
+        Self {
+            ndcg: if ideal_dcg > 0.0 {
+                dcg(&mut relevances.iter().copied()) / ideal_dcg
+            } else {
+                1.0
+            },
+            average_precision: if n_relevant > 0 {
+                precision_sum / n_relevant as f64
+            } else {
+                1.0
+            },
+            reciprocal_rank: if n_relevant > 0 {
+                first.map_or(0.0, |i| 1.0 / (i as f64 + 1.0))
+            } else {
+                1.0
+            },
+        }
+    }
+}
+
+/// How far a feed fell short of the one an oracle with full access to the
+/// graph would have built for the same sheep
+///
+/// The gain of a feed is the expected number of positive responses to its
+/// first `k` items, where `k` is the feed size, going by the relevance of
+/// each item to the sheep as with [`RankingMetrics`]. The oracle is never
+/// held to the item access budget of a [`Shepherd`], so it may pick items
+/// the shepherd could not have
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Regret {
+    /// The gain of the feed built by the oracle (see [`Regret::oracle_feed`])
+    pub oracle_gain: f64,
+
+    /// The gain of the feed
+    pub gain: f64,
+}
+
+impl Regret {
+    /// Measure a feed given the relevance of each of its items in order, the
+    /// relevance of every item which could have been shown, and the feed
+    /// size
+    pub fn new(
+        relevances: &[f64],
+        eligible: &HashMap<ItemId, f64>,
+        k: usize,
+    ) -> Self {
+        Self {
+            oracle_gain: Self::oracle_feed(eligible, k)
+                .iter()
+                .map(|item| eligible[item])
+                .sum(),
+            gain: relevances.iter().take(k).sum(),
+        }
+    }
+
+    /// Build the feed of the oracle out of the items which could have been
+    /// shown, given their relevance to the sheep, and the feed size
+    ///
+    /// As relevance only falls with the distance from the sheep to an item,
+    /// these are the `k` items closest to the sheep, ordered from closest to
+    /// furthest with ties broken by identifier
+    pub fn oracle_feed(
+        eligible: &HashMap<ItemId, f64>,
+        k: usize,
+    ) -> Vec<ItemId> {
+        let mut items = eligible.iter().collect::<Vec<_>>();
+        items.sort_unstable_by(|(a, x), (b, y)| {
+            y.total_cmp(x).then(a.0.cmp(&b.0))
+        });
+        items.into_iter().take(k).map(|(item, _)| *item).collect()
+    }
+
+    /// Get the expected number of positive responses lost by not building
+    /// the feed of the oracle
+    pub fn regret(&self) -> f64 {
+        self.oracle_gain - self.gain
+    }
+}
+
+/// Measure the intra-list diversity of a feed, which is the mean number of
+/// hops between the tags of each pair of distinct items in it (see
+/// [`graph::Simulation::tag_distances`])
+///
+/// Items sharing a tag are zero hops apart, so a feed drawn from a single
+/// narrow topic scores close to zero. Pairs of items whose tags aren't
+/// connected at all are left out, as are items without any tags, giving
+/// [`None`] if no pair of items is left
+pub fn intra_list_diversity(
+    graph: &graph::Simulation,
+    feed: &[ItemId],
+) -> Option<f64> {
+    let mut included = HashSet::default();
+    let items = feed
+        .iter()
+        .copied()
+        .filter(|item| included.insert(*item))
+        .collect::<Vec<_>>();
+
+    let (mut n_pairs, mut total_hops) = (0_usize, 0);
+    for (i, item) in items.iter().enumerate() {
+        let distances = graph.tag_distances(graph.associated_tags(*item));
+        for other in &items[i + 1..] {
+            if let Some(hops) = graph
+                .associated_tags(*other)
+                .filter_map(|tag| distances.get(&tag))
+                .min()
+            {
+                n_pairs += 1;
+                total_hops += u64::from(*hops);
+            }
+        }
+    }
+
+    (n_pairs > 0).then(|| total_hops as f64 / n_pairs as f64)
+}
+
+/// Get the Shannon entropy of a distribution given the count of each of its
+/// outcomes, in bits, or zero if nothing was counted
+///
+/// Used over the tags of the items a sheep was shown during an epoch, this
+/// falls as its feeds narrow into fewer tags
+pub fn entropy(counts: impl IntoIterator<Item = usize>) -> f64 {
+    let counts = counts.into_iter().collect::<Vec<_>>();
+    let total = counts.iter().sum::<usize>() as f64;
+    counts
+        .into_iter()
+        .filter(|count| *count > 0)
+        .map(|count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Counts accumulated over the feeds built by a [`Shepherd`] during an
+/// epoch, or over any number of epochs
+///
+/// Only feeds built by the shepherd itself are counted, so fallback feeds
+/// given in place of them are left out. Requests the shepherd didn't answer
+/// in time are counted as timeouts instead, and their slots as undelivered
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct EpochStats {
+    /// The number of feeds rated
+    pub n_feeds: usize,
+
+    /// The number of items rated across every feed
+    pub n_items: usize,
+
+    /// The number of items which could have been shown across every feed,
+    /// going by the feed size, including those of the feeds which timed out
+    pub n_slots: usize,
+
+    /// The number of requests for feeds which the shepherd didn't answer in
+    /// time, including those skipped once it was abandoned
+    pub n_timeouts: usize,
+
+    /// The responses given to the items
+    pub responses: ResponseCounts,
+
+    /// The number of items which were reachable from the sheep they were
+    /// shown to
+    pub n_reachable: usize,
+
+    /// The sum of the hops from each sheep to the reachable items it was
+    /// shown
+    pub total_hops: u64,
+
+    /// The number of feeds whose [`RankingMetrics`] were computed
+    pub n_ranked: usize,
+
+    /// The sum of the nDCG of each feed whose [`RankingMetrics`] were
+    /// computed
+    pub total_ndcg: f64,
+
+    /// The sum of the average precision of each feed whose
+    /// [`RankingMetrics`] were computed
+    pub total_average_precision: f64,
+
+    /// The sum of the reciprocal rank of each feed whose [`RankingMetrics`]
+    /// were computed
+    pub total_reciprocal_rank: f64,
+
+    /// The number of feeds whose [`Regret`] was computed
+    pub n_compared: usize,
+
+    /// The sum of the gain of each feed whose [`Regret`] was computed
+    pub total_gain: f64,
+
+    /// The sum of the gain of the oracle's feed in place of each feed whose
+    /// [`Regret`] was computed
+    pub total_oracle_gain: f64,
+
+    /// The number of feeds whose intra-list diversity was computed (see
+    /// [`intra_list_diversity`])
+    pub n_diversified: usize,
+
+    /// The sum of the intra-list diversity of each feed it was computed for
+    pub total_diversity: f64,
+
+    /// The number of responses which were serendipitous (see
+    /// [`is_serendipitous`])
+    pub n_serendipitous: usize,
+
+    /// The number of feeds whose novelty was measured (see
+    /// [`Exposures::novelty`])
+    pub n_novel: usize,
+
+    /// The sum of the novelty of each feed it was measured for
+    pub total_novelty: f64,
+
+    /// The number of sheep whose tag entropy was measured, which is each
+    /// sheep shown any items with tags, once for every epoch
+    pub n_sheep: usize,
+
+    /// The sum of the [`entropy`] of the tags of the items each sheep was
+    /// shown during an epoch
+    pub total_tag_entropy: f64,
+
+    /// The number of sheep whose drift from their original tags was
+    /// measured, once for every epoch
+    pub n_anchored: usize,
+
+    /// The sum of the mean number of hops from the original tags of each
+    /// sheep to the tags of the items it was shown during an epoch
+    pub total_drift: f64,
+
+    /// The number of epochs whose [`GroupExposure::disparity`] was measured
+    pub n_disparities: usize,
+
+    /// The sum of the [`GroupExposure::disparity`] of each epoch it was
+    /// measured for
+    pub total_disparity: f64,
+
+    /// The number of items shown for the first time (see
+    /// [`ColdStart::record_exposure`])
+    pub n_exposed: usize,
+
+    /// The sum of the epochs each item shown for the first time waited for
+    /// it since being introduced
+    pub total_item_latency: usize,
+
+    /// The number of sheep shown a relevant item for the first time (see
+    /// [`ColdStart::record_relevant_feed`])
+    pub n_served: usize,
+
+    /// The sum of the epochs each sheep shown a relevant item for the first
+    /// time waited for it since being introduced
+    pub total_sheep_latency: usize,
+}
+
+impl EpochStats {
+    /// Count the responses of a sheep to a feed
+    pub fn record(&mut self, feed_size: usize, responses: &Responses) {
+        self.n_feeds += 1;
+        self.n_items += responses.0.len();
+        self.n_slots += feed_size;
+        for (_, response, hops, ..) in &responses.0 {
+            self.responses.record(*response);
+            if is_serendipitous(*response, *hops) {
+                self.n_serendipitous += 1;
+            }
+            if let Some(hops) = hops {
+                self.n_reachable += 1;
+                self.total_hops += u64::from(*hops);
+            }
+        }
+    }
+
+    /// Count a request for a feed which timed out, leaving every one of its
+    /// slots undelivered
+    pub fn record_timeout(&mut self, feed_size: usize) {
+        self.n_timeouts += 1;
+        self.n_slots += feed_size;
+    }
+
+    /// Count the [`RankingMetrics`] of a feed
+    pub fn record_ranking(&mut self, ranking: RankingMetrics) {
+        self.n_ranked += 1;
+        self.total_ndcg += ranking.ndcg;
+        self.total_average_precision += ranking.average_precision;
+        self.total_reciprocal_rank += ranking.reciprocal_rank;
+    }
+
+    /// Count the [`Regret`] of a feed
+    pub fn record_regret(&mut self, regret: Regret) {
+        self.n_compared += 1;
+        self.total_gain += regret.gain;
+        self.total_oracle_gain += regret.oracle_gain;
+    }
+
+    /// Count the intra-list diversity of a feed (see
+    /// [`intra_list_diversity`])
+    pub fn record_diversity(&mut self, diversity: f64) {
+        self.n_diversified += 1;
+        self.total_diversity += diversity;
+    }
+
+    /// Count the novelty of a feed (see [`Exposures::novelty`])
+    pub fn record_novelty(&mut self, novelty: f64) {
+        self.n_novel += 1;
+        self.total_novelty += novelty;
+    }
+
+    /// Count the [`entropy`] of the tags of the items a sheep was shown
+    /// during an epoch
+    pub fn record_tag_entropy(&mut self, entropy: f64) {
+        self.n_sheep += 1;
+        self.total_tag_entropy += entropy;
+    }
+
+    /// Count the mean number of hops from the original tags of a sheep to
+    /// the tags of the items it was shown during an epoch
+    pub fn record_drift(&mut self, drift: f64) {
+        self.n_anchored += 1;
+        self.total_drift += drift;
+    }
+
+    /// Count the [`GroupExposure::disparity`] of an epoch
+    pub fn record_disparity(&mut self, disparity: f64) {
+        self.n_disparities += 1;
+        self.total_disparity += disparity;
+    }
+
+    /// Count the epochs an item waited to be shown since being introduced
+    pub fn record_item_latency(&mut self, latency: usize) {
+        self.n_exposed += 1;
+        self.total_item_latency += latency;
+    }
+
+    /// Count the epochs a sheep waited to be shown a relevant item since
+    /// being introduced
+    pub fn record_sheep_latency(&mut self, latency: usize) {
+        self.n_served += 1;
+        self.total_sheep_latency += latency;
+    }
+
+    /// Add the counts of another [`EpochStats`] to these
+    pub fn merge(&mut self, other: &Self) {
+        self.n_feeds += other.n_feeds;
+        self.n_items += other.n_items;
+        self.n_slots += other.n_slots;
+        self.n_timeouts += other.n_timeouts;
+        self.responses.positive += other.responses.positive;
+        self.responses.neutral += other.responses.neutral;
+        self.responses.negative += other.responses.negative;
+        self.n_reachable += other.n_reachable;
+        self.total_hops += other.total_hops;
+        self.n_ranked += other.n_ranked;
+        self.total_ndcg += other.total_ndcg;
+        self.total_average_precision += other.total_average_precision;
+        self.total_reciprocal_rank += other.total_reciprocal_rank;
+        self.n_compared += other.n_compared;
+        self.total_gain += other.total_gain;
+        self.total_oracle_gain += other.total_oracle_gain;
+        self.n_diversified += other.n_diversified;
+        self.total_diversity += other.total_diversity;
+        self.n_serendipitous += other.n_serendipitous;
+        self.n_novel += other.n_novel;
+        self.total_novelty += other.total_novelty;
+        self.n_sheep += other.n_sheep;
+        self.total_tag_entropy += other.total_tag_entropy;
+        self.n_anchored += other.n_anchored;
+        self.total_drift += other.total_drift;
+        self.n_disparities += other.n_disparities;
+        self.total_disparity += other.total_disparity;
+        self.n_exposed += other.n_exposed;
+        self.total_item_latency += other.total_item_latency;
+        self.n_served += other.n_served;
+        self.total_sheep_latency += other.total_sheep_latency;
+    }
+
+    /// Get the fraction of responses which were positive, or zero if no
+    /// items were rated
+    pub fn positive_rate(&self) -> f64 {
+        fraction(self.responses.positive, self.n_items)
+    }
+
+    /// Get the fraction of responses which were neutral, or zero if no items
+    /// were rated
+    pub fn neutral_rate(&self) -> f64 {
+        fraction(self.responses.neutral, self.n_items)
+    }
+
+    /// Get the fraction of responses which were negative, or zero if no
+    /// items were rated
+    pub fn negative_rate(&self) -> f64 {
+        fraction(self.responses.negative, self.n_items)
+    }
+
+    /// Get the mean number of hops from each sheep to the reachable items it
+    /// was shown, if any were reachable
+    pub fn mean_hops(&self) -> Option<f64> {
+        (self.n_reachable > 0)
+            .then(|| self.total_hops as f64 / self.n_reachable as f64)
+    }
+
+    /// Get the fraction of items which were unreachable from the sheep they
+    /// were shown to, or zero if no items were rated
+    pub fn unreachable_fraction(&self) -> f64 {
+        fraction(self.n_items - self.n_reachable, self.n_items)
+    }
+
+    /// Get the fraction of the feed size filled by the feeds, or zero if no
+    /// items could have been shown
+    ///
+    /// This may exceed one if feeds longer than the feed size were rated
+    pub fn fill_rate(&self) -> f64 {
+        fraction(self.n_items, self.n_slots)
+    }
+
+    /// Get the mean nDCG of the feeds, if their [`RankingMetrics`] were
+    /// computed
+    pub fn ndcg(&self) -> Option<f64> {
+        (self.n_ranked > 0).then(|| self.total_ndcg / self.n_ranked as f64)
+    }
+
+    /// Get the mean average precision (MAP) of the feeds, if their
+    /// [`RankingMetrics`] were computed
+    pub fn map(&self) -> Option<f64> {
+        (self.n_ranked > 0)
+            .then(|| self.total_average_precision / self.n_ranked as f64)
+    }
+
+    /// Get the mean reciprocal rank (MRR) of the feeds, if their
+    /// [`RankingMetrics`] were computed
+    pub fn mrr(&self) -> Option<f64> {
+        (self.n_ranked > 0)
+            .then(|| self.total_reciprocal_rank / self.n_ranked as f64)
+    }
+
+    /// Get the mean [`Regret`] of the feeds, if it was computed
+    pub fn regret(&self) -> Option<f64> {
+        (self.n_compared > 0).then(|| {
+            (self.total_oracle_gain - self.total_gain)
+                / self.n_compared as f64
+        })
+    }
+
+    /// Get the [`Regret`] of the feeds as a fraction of the gain of the
+    /// oracle, or zero if the oracle gained nothing, if it was computed
+    pub fn relative_regret(&self) -> Option<f64> {
+        (self.n_compared > 0).then(|| {
+            if self.total_oracle_gain > 0.0 {
+                1.0 - self.total_gain / self.total_oracle_gain
+            } else {
+                0.0
+            }
+        })
+    }
+
+    /// Get the mean intra-list diversity of the feeds, if it was computed for
+    /// any of them
+    pub fn diversity(&self) -> Option<f64> {
+        (self.n_diversified > 0)
+            .then(|| self.total_diversity / self.n_diversified as f64)
+    }
+
+    /// Get the fraction of responses which were serendipitous (see
+    /// [`is_serendipitous`]), or zero if no items were rated
+    pub fn serendipity(&self) -> f64 {
+        fraction(self.n_serendipitous, self.n_items)
+    }
+
+    /// Get the mean novelty of the feeds, if it was measured for any of them
+    pub fn novelty(&self) -> Option<f64> {
+        (self.n_novel > 0).then(|| self.total_novelty / self.n_novel as f64)
+    }
+
+    /// Get the mean [`entropy`] of the tags of the items each sheep was shown
+    /// during an epoch, if any sheep were shown items with tags
+    pub fn tag_entropy(&self) -> Option<f64> {
+        (self.n_sheep > 0)
+            .then(|| self.total_tag_entropy / self.n_sheep as f64)
+    }
+
+    /// Get the mean number of hops from the original tags of each sheep to
+    /// the tags of the items it was shown during an epoch, if it was measured
+    /// for any sheep
+    pub fn drift(&self) -> Option<f64> {
+        (self.n_anchored > 0)
+            .then(|| self.total_drift / self.n_anchored as f64)
+    }
+
+    /// Get the mean [`GroupExposure::disparity`] of the epochs, if it was
+    /// measured for any of them
+    pub fn disparity(&self) -> Option<f64> {
+        (self.n_disparities > 0)
+            .then(|| self.total_disparity / self.n_disparities as f64)
+    }
+
+    /// Get the mean number of epochs the items shown for the first time
+    /// waited for it since being introduced, if any were
+    pub fn item_latency(&self) -> Option<f64> {
+        (self.n_exposed > 0)
+            .then(|| self.total_item_latency as f64 / self.n_exposed as f64)
+    }
+
+    /// Get the mean number of epochs the sheep shown a relevant item for the
+    /// first time waited for it since being introduced, if any were
+    pub fn sheep_latency(&self) -> Option<f64> {
+        (self.n_served > 0)
+            .then(|| self.total_sheep_latency as f64 / self.n_served as f64)
+    }
+}
+
+/// Get a count as a fraction of a total, or zero if the total is zero
+fn fraction(count: usize, total: usize) -> f64 {
+    if total > 0 {
+        count as f64 / total as f64
+    } else {
+        0.0
+    }
+}
+
+/// The [`EpochStats`] of each [`Shepherd`] during each epoch of a
+/// simulation
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Stats(HashMap<(ShepherdId, EpochId), EpochStats>);
+
+impl Stats {
+    /// Count the responses of a sheep to a feed built by a [`Shepherd`]
+    /// during an epoch
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        feed_size: usize,
+        responses: &Responses,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record(feed_size, responses);
+    }
+
+    /// Count a request for a feed which a [`Shepherd`] didn't answer in time
+    /// during an epoch (see [`EpochStats::record_timeout`])
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_timeout(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        feed_size: usize,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record_timeout(feed_size);
+    }
+
+    /// Count the [`RankingMetrics`] of a feed built by a [`Shepherd`] during
+    /// an epoch
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_ranking(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        ranking: RankingMetrics,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record_ranking(ranking);
+    }
+
+    /// Count the [`Regret`] of a feed built by a [`Shepherd`] during an
+    /// epoch
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_regret(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        regret: Regret,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record_regret(regret);
+    }
+
+    /// Count the intra-list diversity of a feed built by a [`Shepherd`]
+    /// during an epoch (see [`intra_list_diversity`])
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_diversity(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        diversity: f64,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record_diversity(diversity);
+    }
+
+    /// Count the [`entropy`] of the tags of the items a [`Shepherd`] showed a
+    /// sheep during an epoch
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_tag_entropy(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        entropy: f64,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record_tag_entropy(entropy);
+    }
+
+    /// Count the mean number of hops from the original tags of a sheep to
+    /// the tags of the items a [`Shepherd`] showed it during an epoch
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_drift(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        drift: f64,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record_drift(drift);
+    }
+
+    /// Count the [`GroupExposure::disparity`] of the items a [`Shepherd`]
+    /// showed during an epoch
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_disparity(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        disparity: f64,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record_disparity(disparity);
+    }
+
+    /// Count the novelty of a feed built by a [`Shepherd`] during an epoch
+    /// (see [`Exposures::novelty`])
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_novelty(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        novelty: f64,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record_novelty(novelty);
+    }
+
+    /// Count the epochs an item shown by a [`Shepherd`] for the first time
+    /// during an epoch waited for it (see [`ColdStart::record_exposure`])
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_item_latency(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        latency: usize,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record_item_latency(latency);
+    }
+
+    /// Count the epochs a sheep shown a relevant item by a [`Shepherd`] for
+    /// the first time during an epoch waited for it (see
+    /// [`ColdStart::record_relevant_feed`])
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_sheep_latency(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        latency: usize,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .record_sheep_latency(latency);
+    }
+
+    /// Get the [`EpochStats`] of a [`Shepherd`] during an epoch, if it built
+    /// any feeds during it
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn get(
+        &self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+    ) -> Option<&EpochStats> {
+        self.0.get(&(shepherd, epoch))
+    }
+
+    /// Get the [`EpochStats`] of a [`Shepherd`] during each epoch it built
+    /// any feeds during, in order
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn epochs(&self, shepherd: ShepherdId) -> Vec<(EpochId, EpochStats)> {
+        let mut epochs = self
+            .0
+            .iter()
+            .filter(|((id, _), _)| *id == shepherd)
+            .map(|((_, epoch), stats)| (*epoch, *stats))
+            .collect::<Vec<_>>();
+        epochs.sort_unstable_by_key(|(epoch, _)| epoch.0);
+        epochs
+    }
+
+    /// Get the [`EpochStats`] of a [`Shepherd`] over every epoch
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn total(&self, shepherd: ShepherdId) -> EpochStats {
+        let mut total = EpochStats::default();
+        for ((id, _), stats) in &self.0 {
+            if *id == shepherd {
+                total.merge(stats);
+            }
+        }
+        total
+    }
+}
+
+/// Percentiles of the time a [`Shepherd`] took to build its feeds (see
+/// [`Shepherd::take_feed_latencies`])
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+/// [`Shepherd::take_feed_latencies`]:
+///     crate::shepherd::Shepherd::take_feed_latencies
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct LatencyPercentiles {
+    /// The median time taken to build a feed
+    pub p50: Duration,
+
+    /// The time within which 95% of feeds were built
+    pub p95: Duration,
+
+    /// The time within which 99% of feeds were built
+    pub p99: Duration,
+}
+
+impl LatencyPercentiles {
+    /// Get the percentiles of the given latencies by the nearest-rank
+    /// method, or [`None`] if there are none
+    pub fn new(
+        latencies: impl IntoIterator<Item = Duration>,
+    ) -> Option<Self> {
+        let mut latencies = latencies.into_iter().collect::<Vec<_>>();
+        latencies.sort_unstable();
+        let percentile = |p: f64| {
+            let rank = (p * latencies.len() as f64).ceil() as usize;
+            latencies.get(rank.saturating_sub(1)).copied()
+        };
+
+        Some(Self {
+            p50: percentile(0.5)?,
+            p95: percentile(0.95)?,
+            p99: percentile(0.99)?,
+        })
+    }
+}
+
+/// The time each [`Shepherd`] took to build each of its feeds during each
+/// epoch of a simulation
+///
+/// Unlike [`Stats`], every feed requested from a shepherd is counted,
+/// including those which timed out
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Latencies(HashMap<(ShepherdId, EpochId), Vec<Duration>>);
+
+impl Latencies {
+    /// Count the time a [`Shepherd`] took to build each of its feeds during
+    /// an epoch
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record(
+        &mut self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+        latencies: impl IntoIterator<Item = Duration>,
+    ) {
+        self.0
+            .entry((shepherd, epoch))
+            .or_default()
+            .extend(latencies);
+    }
+
+    /// Get the [`LatencyPercentiles`] of a [`Shepherd`] during an epoch, if
+    /// any feeds were requested from it during it
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn get(
+        &self,
+        shepherd: ShepherdId,
+        epoch: EpochId,
+    ) -> Option<LatencyPercentiles> {
+        LatencyPercentiles::new(
+            self.0
+                .get(&(shepherd, epoch))
+                .into_iter()
+                .flatten()
+                .copied(),
+        )
+    }
+
+    /// Get the [`LatencyPercentiles`] of a [`Shepherd`] over every epoch, if
+    /// any feeds were requested from it
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn total(&self, shepherd: ShepherdId) -> Option<LatencyPercentiles> {
+        LatencyPercentiles::new(
+            self.0
+                .iter()
+                .filter(|((id, _), _)| *id == shepherd)
+                .flat_map(|(_, latencies)| latencies.iter().copied()),
+        )
+    }
+}
+
+/// How widely the items recommended by a [`Shepherd`] have been spread over
+/// the catalog, as measured by [`Exposures::coverage`]
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct CatalogCoverage {
+    /// The number of items in the catalog
+    pub n_items: usize,
+
+    /// The number of items in the catalog which have ever been recommended
+    pub n_recommended: usize,
+
+    /// The Gini coefficient of the number of times each item in the catalog
+    /// has been recommended, from zero when every item has been recommended
+    /// equally often to nearly one when a single item has taken up every
+    /// recommendation
+    pub gini: f64,
+}
+
+impl CatalogCoverage {
+    /// Get the fraction of the catalog which has ever been recommended, or
+    /// zero if the catalog is empty
+    pub fn coverage(&self) -> f64 {
+        fraction(self.n_recommended, self.n_items)
+    }
+}
+
+/// How the items shown by a [`Shepherd`] during an epoch were spread over
+/// the tag groups, against how the items of the catalog are
+///
+/// Each item is counted once for every tag it has in each group, so items
+/// spanning several groups count towards each of them. Tags belonging to no
+/// group are left out
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct GroupExposure {
+    /// The number of times items with the tags of each group, by index, were
+    /// shown
+    pub exposures: Vec<usize>,
+
+    /// The number of items with the tags of each group, by index, in the
+    /// catalog
+    pub catalog: Vec<usize>,
+}
+
+impl GroupExposure {
+    /// Get the fraction of exposures which went to a tag group, or zero if
+    /// nothing was shown
+    pub fn exposure_share(&self, group: usize) -> f64 {
+        fraction(self.exposures[group], self.exposures.iter().sum())
+    }
+
+    /// Get the fraction of the catalog which a tag group makes up, or zero if
+    /// the catalog is empty
+    pub fn catalog_share(&self, group: usize) -> f64 {
+        fraction(self.catalog[group], self.catalog.iter().sum())
+    }
+
+    /// Get the total variation distance between the exposure shares and the
+    /// catalog shares of the tag groups, if anything was shown out of a
+    /// catalog which isn't empty
+    ///
+    /// This is zero when every group is shown in proportion to its share of
+    /// the catalog, and approaches one as exposure concentrates on groups
+    /// which make up little of the catalog
+    pub fn disparity(&self) -> Option<f64> {
+        let n_exposures = self.exposures.iter().sum::<usize>();
+        let n_catalog = self.catalog.iter().sum::<usize>();
+        (n_exposures > 0 && n_catalog > 0).then(|| {
+            (0..self.exposures.len())
+                .map(|group| {
+                    (self.exposure_share(group) - self.catalog_share(group))
+                        .abs()
+                })
+                .sum::<f64>()
+                / 2.0
+        })
+    }
+}
+
+/// The number of feeds each [`Shepherd`] has built over a simulation, and
+/// the number of times it has recommended each item in them
+///
+/// As with [`EpochStats`], only feeds built by the shepherd itself are
+/// counted
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Exposures(HashMap<ShepherdId, (usize, HashMap<ItemId, usize>)>);
+
+impl Exposures {
+    /// Count the items of a feed built by a [`Shepherd`]
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record(
+        &mut self,
+        shepherd: ShepherdId,
+        items: impl IntoIterator<Item = ItemId>,
+    ) {
+        let (n_feeds, exposures) = self.0.entry(shepherd).or_default();
+        *n_feeds += 1;
+        for item in items {
+            *exposures.entry(item).or_default() += 1;
+        }
+    }
+
+    /// Get the number of times a [`Shepherd`] has recommended an item
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn get(&self, shepherd: ShepherdId, item: ItemId) -> usize {
+        self.0
+            .get(&shepherd)
+            .and_then(|(_, exposures)| exposures.get(&item))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Measure the novelty of a feed about to be built by a [`Shepherd`],
+    /// which is the mean self-information of its items going by how often
+    /// the shepherd has recommended them before, or [`None`] if the feed is
+    /// empty
+    ///
+    /// An item recommended in `n` of the `m` feeds built so far has a
+    /// novelty of `-log2((n + 1) / (m + 1))` bits, so items in every feed
+    /// are worth nothing and items never recommended before are worth the
+    /// most
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn novelty(
+        &self,
+        shepherd: ShepherdId,
+        items: impl IntoIterator<Item = ItemId>,
+    ) -> Option<f64> {
+        let n_feeds =
+            self.0.get(&shepherd).map_or(0, |(n_feeds, _)| *n_feeds);
+        let (mut n_items, mut total) = (0_usize, 0.0);
+        for item in items {
+            let frequency =
+                (self.get(shepherd, item) + 1) as f64 / (n_feeds + 1) as f64;
+            n_items += 1;
+            total -= frequency.log2();
+        }
+
+        (n_items > 0).then(|| total / n_items as f64)
+    }
+
+    /// Measure how widely the items recommended by a [`Shepherd`] have been
+    /// spread over the given catalog
+    ///
+    /// Recommendations of items no longer in the catalog are left out
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn coverage(
+        &self,
+        shepherd: ShepherdId,
+        catalog: impl IntoIterator<Item = ItemId>,
+    ) -> CatalogCoverage {
+        let mut counts = catalog
+            .into_iter()
+            .map(|item| self.get(shepherd, item))
+            .collect::<Vec<_>>();
+        counts.sort_unstable();
+
+        let n_items = counts.len();
+        let n_recommended = counts.iter().filter(|count| **count > 0).count();
+        let total = counts.iter().sum::<usize>();
+
+        // with the counts in ascending order, this is the mean absolute
+        // difference between every pair of counts relative to their mean
+        let gini = if total > 0 {
+            let weighted = counts
+                .iter()
+                .enumerate()
+                .map(|(i, count)| (i + 1) as f64 * *count as f64)
+                .sum::<f64>();
+            2.0 * weighted / (n_items as f64 * total as f64)
+                - (n_items as f64 + 1.0) / n_items as f64
+        } else {
+            0.0
+        };
+
+        CatalogCoverage {
+            n_items,
+            n_recommended,
+            gini,
+        }
+    }
+}
+
+/// The epoch each item and sheep was introduced in, and whether each
+/// [`Shepherd`] has reached it since, which measures how long new items wait
+/// to be shown and new sheep wait to be shown anything relevant to them
+///
+/// As with [`EpochStats`], only feeds built by the shepherd itself are
+/// counted. Items and sheep are only ever reached once by each shepherd, so
+/// the latencies recorded in the stats of each epoch are those of the items
+/// and sheep first reached during it
+///
+/// [`Shepherd`]: crate::shepherd::Shepherd
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ColdStart {
+    items: HashMap<ItemId, EpochId>,
+    sheep: HashMap<SheepId, EpochId>,
+    exposed: HashSet<(ShepherdId, ItemId)>,
+    served: HashSet<(ShepherdId, SheepId)>,
+}
+
+impl ColdStart {
+    /// Record the epoch an item was introduced in
+    pub fn introduce_item(&mut self, item: ItemId, epoch: EpochId) {
+        self.items.insert(item, epoch);
+    }
+
+    /// Record the epoch a sheep was introduced in
+    pub fn introduce_sheep(&mut self, sheep: SheepId, epoch: EpochId) {
+        self.sheep.insert(sheep, epoch);
+    }
+
+    /// Record a [`Shepherd`] showing an item during an epoch, getting the
+    /// number of epochs since it was introduced if this is the first time
+    /// the shepherd has shown it
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_exposure(
+        &mut self,
+        shepherd: ShepherdId,
+        item: ItemId,
+        epoch: EpochId,
+    ) -> Option<usize> {
+        let introduced = self.items.get(&item)?;
+        self.exposed
+            .insert((shepherd, item))
+            .then(|| epoch.0.saturating_sub(introduced.0))
+    }
+
+    /// Check whether a [`Shepherd`] has shown a sheep a relevant item yet
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn is_served(&self, shepherd: ShepherdId, sheep: SheepId) -> bool {
+        self.served.contains(&(shepherd, sheep))
+    }
+
+    /// Record a [`Shepherd`] showing a sheep a feed containing a relevant
+    /// item during an epoch, getting the number of epochs since the sheep was
+    /// introduced if this is the first such feed the shepherd has shown it
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn record_relevant_feed(
+        &mut self,
+        shepherd: ShepherdId,
+        sheep: SheepId,
+        epoch: EpochId,
+    ) -> Option<usize> {
+        let introduced = self.sheep.get(&sheep)?;
+        self.served
+            .insert((shepherd, sheep))
+            .then(|| epoch.0.saturating_sub(introduced.0))
+    }
+
+    /// Get the number of items a [`Shepherd`] has never shown
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn n_unexposed(&self, shepherd: ShepherdId) -> usize {
+        self.items
+            .keys()
+            .filter(|item| !self.exposed.contains(&(shepherd, **item)))
+            .count()
+    }
+
+    /// Get the number of sheep a [`Shepherd`] has never shown a relevant
+    /// item
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn n_unserved(&self, shepherd: ShepherdId) -> usize {
+        self.sheep
+            .keys()
+            .filter(|sheep| !self.is_served(shepherd, **sheep))
+            .count()
+    }
+}
+
+/// A value of a metric flagged as anomalous by an [`AnomalyMonitor`]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Anomaly {
+    /// The anomalous value
+    pub value: f64,
+
+    /// The mean of the values in the trailing window
+    pub mean: f64,
+
+    /// The number of standard deviations the value lies from the mean,
+    /// which is negative if the value is below it
+    pub deviation: f64,
+}
+
+/// A monitor which flags values of a per-shepherd metric that deviate from
+/// the values preceding them
+///
+/// Each value is compared against the mean and standard deviation of a
+/// trailing window of previous values. This catches shepherds which have
+/// stopped working without erroring, or sudden shocks to the simulated world,
+/// while a run is still in progress
+#[derive(Clone, PartialEq, Debug)]
+pub struct AnomalyMonitor {
+    window: usize,
+    threshold: f64,
+    history: HashMap<ShepherdId, VecDeque<f64>>,
+}
+
+impl AnomalyMonitor {
+    /// Create a new [`AnomalyMonitor`] which flags values more than
+    /// `threshold` standard deviations from the mean of the previous `window`
+    /// values
+    pub fn new(window: usize, threshold: f64) -> Self {
+        Self {
+            window,
+            threshold,
+            history: HashMap::default(),
+        }
+    }
+
+    /// Record the next value of the metric for a [`Shepherd`], returning an
+    /// [`Anomaly`] if it is anomalous
+    ///
+    /// Values are never flagged until the window has filled up. If every
+    /// value in the window is the same, any other value is anomalous
+    ///
+    /// [`Shepherd`]: crate::shepherd::Shepherd
+    pub fn observe(
+        &mut self,
+        shepherd: ShepherdId,
+        value: f64,
+    ) -> Option<Anomaly> {
+        let history = self.history.entry(shepherd).or_default();
+
+        let anomaly = (history.len() >= self.window.max(1))
+            .then(|| {
+                let n = history.len() as f64;
+                let mean = history.iter().sum::<f64>() / n;
+                let sigma = (history
+                    .iter()
+                    .map(|value| (value - mean).powi(2))
+                    .sum::<f64>()
+                    / n)
+                    .sqrt();
+                let deviation = if sigma > 0.0 {
+                    (value - mean) / sigma
+                } else if value == mean {
+                    0.0
+                } else {
+                    (value - mean).signum() * f64::INFINITY
+                };
+
+                Anomaly {
+                    value,
+                    mean,
+                    deviation,
+                }
+            })
+            .filter(|anomaly| anomaly.deviation.abs() > self.threshold);
+
+        history.push_back(value);
+        if history.len() > self.window.max(1) {
+            history.pop_front();
+        }
+
+        anomaly
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::GraphId;
+
+    struct Constant(&'static str, bool);
+
+    impl Metric for Constant {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn higher_is_better(&self) -> bool {
+            self.1
+        }
+
+        fn update(&mut self, _: ShepherdId, _: SheepId, _: &Responses) {}
+
+        fn report(&self) -> Vec<(ShepherdId, f64)> {
+            Vec::new()
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn ranking_metrics_match_hand_computed_values() {
+        let ranking =
+            RankingMetrics::new(&[0.0, 1.0, 0.5], [0.25, 1.0, 0.0, 0.5], 3);
+
+        // the feed gains 1 / log2(3) + 0.5 / log2(4), against the
+        // 1 / log2(2) + 0.5 / log2(3) + 0.25 / log2(4) of the best feed
+        let log3 = 3.0_f64.log2();
+        assert_close(
+            ranking.ndcg,
+            (1.0 / log3 + 0.25) / (1.0 + 0.5 / log3 + 0.125),
+        );
+        // relevant items sit second and third, out of two which could have
+        // been shown
+        assert_close(
+            ranking.average_precision,
+            (1.0 / 2.0 + 2.0 / 3.0) / 2.0,
+        );
+        assert_close(ranking.reciprocal_rank, 0.5);
+    }
+
+    #[test]
+    fn ranking_metrics_ignore_items_past_the_feed_size() {
+        let ranking = RankingMetrics::new(&[0.0, 1.0], [1.0, 0.0], 1);
+
+        assert_close(ranking.ndcg, 0.0);
+        assert_close(ranking.average_precision, 0.0);
+        assert_close(ranking.reciprocal_rank, 0.0);
+    }
+
+    #[test]
+    fn ranking_metrics_only_count_relevant_items_which_fit() {
+        // three relevant items could have been shown, but only two fit
+        let ranking = RankingMetrics::new(&[1.0, 1.0], [1.0, 1.0, 1.0], 2);
+
+        assert_close(ranking.ndcg, 1.0);
+        assert_close(ranking.average_precision, 1.0);
+        assert_close(ranking.reciprocal_rank, 1.0);
+    }
+
+    #[test]
+    fn ranking_metrics_are_perfect_without_relevant_items() {
+        let ranking = RankingMetrics::new(&[0.0, 0.25], [0.0, 0.25], 2);
+
+        assert_close(ranking.average_precision, 1.0);
+        assert_close(ranking.reciprocal_rank, 1.0);
+
+        let ranking = RankingMetrics::new(&[], [], 2);
+
+        assert_close(ranking.ndcg, 1.0);
+        assert_close(ranking.average_precision, 1.0);
+        assert_close(ranking.reciprocal_rank, 1.0);
+    }
+
+    fn exposures(feeds: &[&[usize]]) -> Exposures {
+        let mut exposures = Exposures::default();
+        for feed in feeds {
+            exposures.record(
+                ShepherdId(0),
+                feed.iter().copied().map(GraphId::new),
+            );
+        }
+        exposures
+    }
+
+    fn catalog(ids: &[usize]) -> Vec<ItemId> {
+        ids.iter().copied().map(GraphId::new).collect()
+    }
+
+    #[test]
+    fn coverage_matches_hand_computed_values() {
+        // items 2 and 3 are recommended once and thrice, while items 0 and 1
+        // never are, and item 4 is no longer in the catalog
+        let coverage = exposures(&[&[3, 2], &[3, 4], &[3, 4]])
+            .coverage(ShepherdId(0), catalog(&[0, 1, 2, 3]));
+
+        assert_eq!(coverage.n_items, 4);
+        assert_eq!(coverage.n_recommended, 2);
+        assert_close(coverage.coverage(), 0.5);
+        // the counts 0, 0, 1 and 3 differ by 20 over every ordered pair,
+        // against a mean count of 1
+        assert_close(coverage.gini, 20.0 / (2.0 * 4.0 * 4.0 * 1.0));
+    }
+
+    #[test]
+    fn gini_is_zero_when_recommendations_are_even() {
+        let coverage = exposures(&[&[0, 1], &[2, 0], &[1, 2]])
+            .coverage(ShepherdId(0), catalog(&[0, 1, 2]));
+
+        assert_close(coverage.coverage(), 1.0);
+        assert_close(coverage.gini, 0.0);
+    }
+
+    #[test]
+    fn gini_approaches_one_when_a_single_item_is_recommended() {
+        let coverage = exposures(&[&[0], &[0]])
+            .coverage(ShepherdId(0), catalog(&[0, 1, 2, 3]));
+
+        assert_close(coverage.coverage(), 0.25);
+        // with n items, the most unequal spread has a Gini of (n - 1) / n
+        assert_close(coverage.gini, 0.75);
+    }
+
+    #[test]
+    fn coverage_is_zero_without_recommendations_or_items() {
+        let coverage =
+            exposures(&[&[0]]).coverage(ShepherdId(1), catalog(&[0, 1]));
+
+        assert_eq!(coverage.n_recommended, 0);
+        assert_close(coverage.coverage(), 0.0);
+        assert_close(coverage.gini, 0.0);
+
+        let coverage = exposures(&[&[0]]).coverage(ShepherdId(0), []);
+
+        assert_close(coverage.coverage(), 0.0);
+        assert_close(coverage.gini, 0.0);
+    }
+
+    fn millis(values: impl IntoIterator<Item = u64>) -> Vec<Duration> {
+        values.into_iter().map(Duration::from_millis).collect()
+    }
+
+    #[test]
+    fn percentiles_take_the_nearest_rank() {
+        // in reverse, so that the latencies have to be sorted first
+        let percentiles =
+            LatencyPercentiles::new(millis((1..=100).rev())).unwrap();
+        assert_eq!(
+            percentiles,
+            LatencyPercentiles {
+                p50: Duration::from_millis(50),
+                p95: Duration::from_millis(95),
+                p99: Duration::from_millis(99),
+            }
+        );
+
+        // ranks between two latencies round up to the higher of them
+        let percentiles = LatencyPercentiles::new(millis(1..=10)).unwrap();
+        assert_eq!(
+            percentiles,
+            LatencyPercentiles {
+                p50: Duration::from_millis(5),
+                p95: Duration::from_millis(10),
+                p99: Duration::from_millis(10),
+            }
+        );
+    }
+
+    #[test]
+    fn percentiles_of_a_single_latency_are_that_latency() {
+        let percentiles = LatencyPercentiles::new(millis([7])).unwrap();
+        assert_eq!(percentiles.p50, Duration::from_millis(7));
+        assert_eq!(percentiles.p95, Duration::from_millis(7));
+        assert_eq!(percentiles.p99, Duration::from_millis(7));
+    }
+
+    #[test]
+    fn percentiles_need_a_latency() {
+        assert_eq!(LatencyPercentiles::new([]), None);
+    }
+
+    #[test]
+    fn latencies_are_pooled_over_every_epoch() {
+        let mut latencies = Latencies::default();
+        latencies.record(ShepherdId(0), EpochId(0), millis([1, 2]));
+        latencies.record(ShepherdId(0), EpochId(1), millis([3, 4]));
+        latencies.record(ShepherdId(1), EpochId(0), millis([100]));
+
+        assert_eq!(
+            latencies.get(ShepherdId(0), EpochId(1)).unwrap().p50,
+            Duration::from_millis(3)
+        );
+        assert_eq!(
+            latencies.total(ShepherdId(0)).unwrap().p50,
+            Duration::from_millis(2)
+        );
+        assert_eq!(
+            latencies.total(ShepherdId(0)).unwrap().p99,
+            Duration::from_millis(4)
+        );
+        assert_eq!(latencies.get(ShepherdId(1), EpochId(1)), None);
+    }
+
+    #[test]
+    fn metric_reports_are_kept_per_shepherd_and_epoch() {
+        let (clicks, cost) =
+            (Constant("clicks", true), Constant("cost", false));
+        let mut reports = MetricReports::default();
+        reports.record(ShepherdId(0), EpochId(1), &cost, 2.0);
+        reports.record(ShepherdId(0), EpochId(1), &clicks, 3.0);
+        reports.record(ShepherdId(1), EpochId(1), &clicks, 4.0);
+        reports.record(ShepherdId(0), EpochId(2), &cost, 5.0);
+
+        assert_eq!(
+            reports.metrics(),
+            [("cost".to_string(), false), ("clicks".to_string(), true)]
+        );
+        assert_eq!(
+            reports.get(ShepherdId(0), EpochId(1)).collect::<Vec<_>>(),
+            [("cost", 2.0), ("clicks", 3.0)]
+        );
+        assert_eq!(
+            reports.get(ShepherdId(0), EpochId(2)).collect::<Vec<_>>(),
+            [("cost", 5.0)]
+        );
+        assert_eq!(reports.get(ShepherdId(1), EpochId(2)).count(), 0);
+
+        assert_eq!(
+            reports.epochs(ShepherdId(0), "cost"),
+            [(EpochId(1), 2.0), (EpochId(2), 5.0)]
+        );
+        assert_eq!(reports.latest(ShepherdId(0), "cost"), Some(5.0));
+        assert_eq!(reports.latest(ShepherdId(0), "clicks"), Some(3.0));
+        assert_eq!(reports.latest(ShepherdId(1), "cost"), None);
+        assert_eq!(reports.latest(ShepherdId(0), "views"), None);
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::{ensure, Context};
+use itertools::Itertools;
+use petgraph::Graph;
+use std::io::Read;
+
+use crate::{
+    collections::HashMap,
+    graph::{EdgeWeight, Simulation},
+    ids::{EpochId, GraphId, ItemId, NodeType, SheepId, TagId},
+};
+
+/// A tag graph imported from a ratings dataset, along with the identifiers
+/// the dataset used for each node
+#[derive(Default)]
+pub struct Dataset {
+    /// The imported graph
+    pub graph: Simulation,
+
+    /// The label of each tag
+    pub tags: HashMap<TagId, String>,
+
+    /// The dataset's identifier of each item
+    pub items: HashMap<ItemId, String>,
+
+    /// The dataset's identifier of each sheep
+    pub sheep: HashMap<SheepId, String>,
+}
+
+/// The label `MovieLens` gives items without any genres, which is not a tag
+const NO_GENRES: &str = "(no genres listed)";
+
+/// Map a value within `0.0..=1.0` onto an edge weight, with higher values
+/// giving lighter (closer) edges
+fn weight(closeness: f64) -> EdgeWeight {
+    EdgeWeight {
+        affinity: (10.0 - 9.0 * closeness.clamp(0.0, 1.0)).round() as u32,
+        last_touched: EpochId::default(),
+    }
+}
+
+/// Import a dataset in the format used by `MovieLens`
+///
+/// `items` is a CSV file with a header whose rows hold an item identifier,
+/// a title (which is ignored), and a `|`-separated list of tags, as in
+/// `MovieLens`' `movies.csv`. `ratings` is a CSV file with a header whose
+/// rows hold a user identifier, an item identifier, and a rating out of
+/// `max_rating`, as in `MovieLens`' `ratings.csv`. Any further columns are
+/// ignored, so tag datasets such as `LastFM`'s can be used once flattened
+/// into this shape
+///
+/// Item identifiers must be unique. A tag listed more than once on the same
+/// item is only counted once, and `MovieLens`' placeholder for items without
+/// any genres is skipped
+///
+/// Each tag is connected to each of its items with an edge of weight one.
+/// Tags which appear on the same items are connected in both directions, with
+/// lighter edges for tags which co-occur more often. A user is connected to
+/// a tag if they rated at least `min_ratings` of its items, with lighter
+/// edges for higher average ratings
+pub fn import(
+    items: impl Read,
+    ratings: impl Read,
+    max_rating: f64,
+    min_ratings: usize,
+) -> anyhow::Result<Dataset> {
+    let mut dataset = Dataset::default();
+    let mut graph = Graph::default();
+    let mut tag_ids = HashMap::<String, TagId>::default();
+    let mut item_ids = HashMap::<String, ItemId>::default();
+    let mut item_tags = HashMap::<ItemId, Vec<TagId>>::default();
+
+    for record in csv::Reader::from_reader(items).into_records() {
+        let record = record.context("Unable to read an item record")?;
+        let id = record.get(0).context("An item record has no identifier")?;
+        ensure!(
+            !item_ids.contains_key(id),
+            "The item {:?} is listed more than once",
+            id
+        );
+        let item = GraphId::new(graph.add_node(NodeType::Item).index());
+        item_ids.insert(id.to_string(), item);
+        dataset.items.insert(item, id.to_string());
+
+        let mut tags = Vec::new();
+        for label in record
+            .get(2)
+            .unwrap_or_default()
+            .split('|')
+            .map(str::trim)
+            .filter(|label| !label.is_empty() && *label != NO_GENRES)
+            .unique()
+        {
+            let tag =
+                *tag_ids.entry(label.to_string()).or_insert_with(|| {
+                    let tag =
+                        GraphId::new(graph.add_node(NodeType::Tag).index());
+                    dataset.tags.insert(tag, label.to_string());
+                    tag
+                });
+            graph.add_edge(tag.0.into(), item.0.into(), weight(1.0));
+            tags.push(tag);
+        }
+        item_tags.insert(item, tags);
+    }
+
+    // tags are connected by the jaccard similarity of the items they are on
+    let mut tag_counts = HashMap::<TagId, usize>::default();
+    let mut pair_counts = HashMap::<(TagId, TagId), usize>::default();
+    for tags in item_tags.values() {
+        for (i, a) in tags.iter().enumerate() {
+            *tag_counts.entry(*a).or_default() += 1;
+            for b in &tags[i + 1..] {
+                let pair = if a.0 < b.0 { (*a, *b) } else { (*b, *a) };
+                *pair_counts.entry(pair).or_default() += 1;
+            }
+        }
+    }
+    let mut pairs = pair_counts.into_iter().collect::<Vec<_>>();
+    pairs.sort_unstable_by_key(|((a, b), _)| (a.0, b.0));
+    for ((a, b), count) in pairs {
+        let similarity =
+            count as f64 / (tag_counts[&a] + tag_counts[&b] - count) as f64;
+        graph.add_edge(a.0.into(), b.0.into(), weight(similarity));
+        graph.add_edge(b.0.into(), a.0.into(), weight(similarity));
+    }
+
+    // the ratings of each user are totalled per tag, in the order users first
+    // appear in the dataset
+    let mut user_order = Vec::new();
+    let mut user_ratings =
+        HashMap::<String, HashMap<TagId, (f64, usize)>>::default();
+    for record in csv::Reader::from_reader(ratings).into_records() {
+        let record = record.context("Unable to read a rating record")?;
+        let user = record.get(0).context("A rating record has no user")?;
+        let item = record.get(1).context("A rating record has no item")?;
+        let rating = record
+            .get(2)
+            .context("A rating record has no rating")?
+            .parse::<f64>()
+            .context("A rating record has an invalid rating")?;
+        let Some(item) = item_ids.get(item) else {
+            continue;
+        };
+
+        let totals =
+            user_ratings.entry(user.to_string()).or_insert_with(|| {
+                user_order.push(user.to_string());
+                HashMap::default()
+            });
+        for tag in &item_tags[item] {
+            let (sum, count) = totals.entry(*tag).or_default();
+            *sum += rating;
+            *count += 1;
+        }
+    }
+
+    for user in user_order {
+        let sheep = GraphId::new(graph.add_node(NodeType::Sheep).index());
+        let mut totals = user_ratings
+            .remove(&user)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, (_, count))| *count >= min_ratings)
+            .collect::<Vec<_>>();
+        totals.sort_unstable_by_key(|(tag, _)| tag.0);
+        for (tag, (sum, count)) in totals {
+            graph.add_edge(
+                sheep.0.into(),
+                tag.0.into(),
+                weight(sum / count as f64 / max_rating),
+            );
+        }
+        dataset.sheep.insert(sheep, user);
+    }
+
+    dataset.graph = Simulation(graph, EpochId::default());
+    Ok(dataset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use petgraph::Direction;
+
+    const MOVIES: &str = "\
+movieId,title,genres
+1,Toy Story (1995),Adventure|Animation|Children
+2,Jumanji (1995),Adventure|Children|Adventure
+3,Unknown (1995),(no genres listed)
+";
+
+    const RATINGS: &str = "\
+userId,movieId,rating,timestamp
+1,1,5.0,0
+1,2,4.0,0
+2,2,1.0,0
+2,3,3.0,0
+2,4,5.0,0
+";
+
+    fn tag(dataset: &Dataset, label: &str) -> TagId {
+        *dataset
+            .tags
+            .iter()
+            .find(|(_, tag)| *tag == label)
+            .expect("a tag is missing from the dataset")
+            .0
+    }
+
+    #[test]
+    fn import_builds_the_tag_graph() {
+        let dataset = import(MOVIES.as_bytes(), RATINGS.as_bytes(), 5.0, 1)
+            .expect("the dataset was rejected");
+        let graph = &dataset.graph.0;
+
+        let mut labels = dataset.tags.values().cloned().collect::<Vec<_>>();
+        labels.sort_unstable();
+        assert_eq!(labels, ["Adventure", "Animation", "Children"]);
+        assert_eq!(dataset.items.len(), 3);
+        assert_eq!(dataset.sheep.len(), 2);
+
+        // adventure and children are on both items, and animation on one
+        let (adventure, animation, children) = (
+            tag(&dataset, "Adventure"),
+            tag(&dataset, "Animation"),
+            tag(&dataset, "Children"),
+        );
+        let affinity = |a: TagId, b: TagId| {
+            graph[graph
+                .find_edge(a.0.into(), b.0.into())
+                .expect("two tags are not connected")]
+            .affinity
+        };
+        assert_eq!(affinity(adventure, children), 1);
+        assert_eq!(affinity(children, adventure), 1);
+        assert_eq!(affinity(adventure, animation), 6);
+
+        // the repeated tag is counted once, and the untagged item has no
+        // edges at all
+        let item = |id: &str| {
+            *dataset
+                .items
+                .iter()
+                .find(|(_, item)| *item == id)
+                .expect("an item is missing from the dataset")
+                .0
+        };
+        assert_eq!(
+            graph
+                .neighbors_directed(item("2").0.into(), Direction::Incoming)
+                .count(),
+            2
+        );
+        assert_eq!(graph.neighbors_undirected(item("3").0.into()).count(), 0);
+    }
+
+    #[test]
+    fn import_rejects_duplicate_items() {
+        let movies = "movieId,title,genres\n1,A,Drama\n1,B,Comedy\n";
+        assert!(
+            import(movies.as_bytes(), RATINGS.as_bytes(), 5.0, 1).is_err()
+        );
+    }
+}
@@ -0,0 +1,462 @@
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    io::{BufRead, Write},
+    marker::PhantomData,
+};
+
+/// The encoding of the events exchanged with a shepherd, which it chooses
+/// during the handshake
+///
+/// The handshake itself is always encoded as JSON
+#[non_exhaustive]
+#[derive(
+    Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Serialize, Deserialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Encoding {
+    /// Newline-delimited JSON values, which are the easiest to produce and
+    /// inspect by hand
+    ///
+    /// Each event is written on a line of its own, so it must not contain
+    /// any unescaped newlines (which compact JSON never does)
+    #[default]
+    Json,
+
+    /// Concatenated `MessagePack` values, with structs encoded as maps keyed
+    /// by their field names
+    MessagePack,
+
+    /// Concatenated CBOR values
+    Cbor,
+}
+
+impl Encoding {
+    /// Every encoding the simulation supports
+    pub const ALL: [Self; 3] = [Self::Json, Self::MessagePack, Self::Cbor];
+}
+
+/// A reader of the events of type `T` sent over a stream in the framing
+/// described by [`Encoding`]
+///
+/// This is used by the simulation to read from shepherds, and can be used by
+/// shepherds written in Rust to read from the simulation. Events are read in
+/// JSON until [`EventReader::set_encoding`] is called
+pub struct EventReader<R, T> {
+    reader: R,
+    encoding: Encoding,
+    line: String,
+    broken: bool,
+    event: PhantomData<fn() -> T>,
+}
+
+impl<R, T> EventReader<R, T>
+where
+    R: BufRead,
+    T: DeserializeOwned,
+{
+    /// Create a new [`EventReader`] reading from the given reader
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            encoding: Encoding::default(),
+            line: String::new(),
+            broken: false,
+            event: PhantomData,
+        }
+    }
+
+    /// Get the [`Encoding`] events are currently read in
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Set the [`Encoding`] every following event is read in
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Check whether an event in an encoding other than JSON was cut short
+    /// by an error while reading it
+    ///
+    /// The rest of such an event can't be told apart from the start of the
+    /// next, so nothing more can be read from the stream once this happens
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Read the next event, or [`None`] if the stream has ended
+    ///
+    /// Blank lines between JSON events are skipped, and a JSON event cut
+    /// short by an error while reading it is picked up where it left off by
+    /// the next read. Events in any other encoding can't be, and leave the
+    /// reader broken instead (see [`EventReader::is_broken`])
+    pub fn read_event(&mut self) -> anyhow::Result<Option<T>> {
+        match self.encoding {
+            Encoding::Json => loop {
+                let n_read = self
+                    .reader
+                    .read_line(&mut self.line)
+                    .context("Unable to read an event")?;
+                if n_read == 0 {
+                    self.line.clear();
+                    return Ok(None);
+                }
+
+                let event = (!self.line.trim().is_empty())
+                    .then(|| serde_json::from_str(&self.line));
+                self.line.clear();
+                if let Some(event) = event {
+                    return event
+                        .map(Some)
+                        .context("Unable to parse an event");
+                }
+            },
+            Encoding::MessagePack => {
+                if self.at_end()? {
+                    return Ok(None);
+                }
+
+                let event = rmp_serde::from_read(&mut self.reader);
+                self.broken |= event.is_err();
+                event.map(Some).context("Unable to parse an event")
+            }
+            Encoding::Cbor => {
+                if self.at_end()? {
+                    return Ok(None);
+                }
+
+                let event = ciborium::from_reader(&mut self.reader);
+                self.broken |= event.is_err();
+                event.map(Some).context("Unable to parse an event")
+            }
+        }
+    }
+
+    /// Check whether the stream has ended, waiting for more of it if needed
+    fn at_end(&mut self) -> anyhow::Result<bool> {
+        Ok(self
+            .reader
+            .fill_buf()
+            .context("Unable to read an event")?
+            .is_empty())
+    }
+}
+
+impl<R, T> Iterator for EventReader<R, T>
+where
+    R: BufRead,
+    T: DeserializeOwned,
+{
+    type Item = anyhow::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_event().transpose()
+    }
+}
+
+/// A writer of events sent over a stream in the framing described by
+/// [`Encoding`]
+///
+/// This is used by the simulation to write to shepherds, and can be used by
+/// shepherds written in Rust to write to the simulation. Events are written
+/// in JSON until [`EventWriter::set_encoding`] is called
+pub struct EventWriter<W> {
+    writer: W,
+    encoding: Encoding,
+}
+
+impl<W> EventWriter<W>
+where
+    W: Write,
+{
+    /// Create a new [`EventWriter`] writing to the given writer
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            encoding: Encoding::default(),
+        }
+    }
+
+    /// Get the [`Encoding`] events are currently written in
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Set the [`Encoding`] every following event is written in
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Write an event, flushing the writer so that it is sent immediately
+    pub fn write_event(
+        &mut self,
+        event: &impl Serialize,
+    ) -> anyhow::Result<()> {
+        self.buffer_event(event)?;
+        self.flush()
+    }
+
+    /// Write an event without flushing the writer, so that it is only sent
+    /// once [`EventWriter::flush`] is called (or the writer sends it on its
+    /// own)
+    pub fn buffer_event(
+        &mut self,
+        event: &impl Serialize,
+    ) -> anyhow::Result<()> {
+        match self.encoding {
+            Encoding::Json => {
+                serde_json::to_writer(&mut self.writer, event)
+                    .context("Unable to write an event")?;
+                self.writer
+                    .write_all(b"\n")
+                    .context("Unable to write an event")?;
+            }
+            Encoding::MessagePack => {
+                rmp_serde::encode::write_named(&mut self.writer, event)
+                    .context("Unable to write an event")?;
+            }
+            Encoding::Cbor => {
+                ciborium::into_writer(event, &mut self.writer)
+                    .context("Unable to write an event")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flush the writer, sending every event written to it so far
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush().context("Unable to flush an event")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use super::*;
+    use crate::{
+        feed::{Feed, Justification, Response, ResponseCounts},
+        ids::{EpochId, GraphId, RequestId},
+        shepherd::{
+            Capability, LimitedResponses, Payload, ShepherdEvent,
+            SimulationEvent, PROTOCOL_VERSION,
+        },
+        simulation::{Epoch, GraphDiff, RunConfig},
+    };
+
+    /// One of every event sent from the simulation to a shepherd
+    fn simulation_events() -> Vec<SimulationEvent> {
+        let tag = GraphId::new;
+        let item = GraphId::new;
+        let sheep = GraphId::new;
+        let responses = LimitedResponses(vec![
+            (item(3), Response::Positive, Some(0), Some(2), 0),
+            (item(4), Response::Neutral, None, None, 1),
+            (item(5), Response::Negative, Some(7), Some(5), 2),
+        ]);
+
+        vec![
+            SimulationEvent::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                capabilities: Capability::ALL.to_vec(),
+                payloads: Payload::ALL.to_vec(),
+                encodings: Encoding::ALL.to_vec(),
+            },
+            SimulationEvent::RunConfig(RunConfig {
+                feed_size: 10,
+                n_epochs: Some(5),
+            }),
+            SimulationEvent::RunConfig(RunConfig {
+                feed_size: 10,
+                n_epochs: None,
+            }),
+            SimulationEvent::BeginEpoch {
+                id: EpochId(1),
+                data: Epoch {
+                    tags: vec![tag(0), tag(1)],
+                    items: vec![(item(3), vec![tag(0)]), (item(4), vec![])],
+                    diff: None,
+                },
+            },
+            SimulationEvent::BeginEpoch {
+                id: EpochId(2),
+                data: Epoch {
+                    tags: Vec::new(),
+                    items: vec![(item(5), vec![tag(0), tag(1)])],
+                    diff: Some(GraphDiff {
+                        tag_edges: vec![(tag(0), tag(1), 4)],
+                        removed_tag_edges: vec![(tag(1), tag(0))],
+                        tag_groups: vec![(tag(0), Some(1)), (tag(1), None)],
+                    }),
+                },
+            },
+            SimulationEvent::SheepIntroduction {
+                sheep: sheep(2),
+                associated_tags: vec![tag(0), tag(1)],
+            },
+            SimulationEvent::FeedRequest {
+                request_id: RequestId(0),
+                sheep: sheep(2),
+            },
+            SimulationEvent::FeedRequestBatch {
+                request_id: RequestId(1),
+                sheep: vec![sheep(2), sheep(6)],
+            },
+            SimulationEvent::RetrieveCandidates {
+                request_id: RequestId(2),
+                sheep: sheep(2),
+                n_candidates: 20,
+            },
+            SimulationEvent::RankCandidates {
+                request_id: RequestId(3),
+                sheep: sheep(2),
+                candidates: vec![item(3), item(5)],
+            },
+            SimulationEvent::FeedResponses {
+                sheep: sheep(2),
+                epoch: EpochId(1),
+                responses: responses.clone(),
+            },
+            SimulationEvent::ResponsesBatch {
+                responses: vec![
+                    (sheep(2), EpochId(1), responses),
+                    (sheep(6), EpochId(2), LimitedResponses(Vec::new())),
+                ],
+            },
+            SimulationEvent::UnderDelivered {
+                sheep: sheep(2),
+                n_missing: 3,
+            },
+            SimulationEvent::BudgetExceeded {
+                sheep: sheep(2),
+                items: vec![item(4)],
+            },
+            SimulationEvent::TagGroupsMerged {
+                tags: (vec![tag(0)], vec![tag(1)]),
+            },
+            SimulationEvent::TagGroupSplit {
+                tags: (vec![tag(0)], vec![tag(1)]),
+            },
+            SimulationEvent::TagGroups {
+                groups: vec![vec![tag(0), tag(1)], Vec::new()],
+            },
+            SimulationEvent::EpochSummary {
+                id: EpochId(1),
+                sheep_stats: vec![(
+                    sheep(2),
+                    ResponseCounts {
+                        positive: 1,
+                        neutral: 1,
+                        negative: 1,
+                    },
+                )],
+            },
+            SimulationEvent::EndEpoch { id: EpochId(1) },
+            SimulationEvent::Shutdown,
+            SimulationEvent::Ping,
+        ]
+    }
+
+    /// One of every event sent from a shepherd to the simulation
+    fn shepherd_events() -> Vec<ShepherdEvent> {
+        let item = GraphId::new;
+        let justified = vec![
+            (item(3), Some(Justification::Tag(GraphId::new(0)))),
+            (item(4), Some(Justification::SimilarItem(item(3)))),
+            (item(5), None),
+        ];
+
+        vec![
+            ShepherdEvent::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                capabilities: Capability::ALL.to_vec(),
+                payloads: Payload::ALL.to_vec(),
+                encoding: Encoding::Cbor,
+            },
+            ShepherdEvent::Feed {
+                request_id: RequestId(0),
+                feed: Feed(vec![item(3), item(4)]),
+            },
+            ShepherdEvent::JustifiedFeed {
+                request_id: RequestId(1),
+                items: justified.clone(),
+            },
+            ShepherdEvent::FeedBatch {
+                request_id: RequestId(2),
+                feeds: vec![Feed(vec![item(3)]), Feed(Vec::new())],
+            },
+            ShepherdEvent::JustifiedFeedBatch {
+                request_id: RequestId(3),
+                feeds: vec![justified, Vec::new()],
+            },
+            ShepherdEvent::Candidates {
+                request_id: RequestId(4),
+                items: vec![item(5), item(3)],
+            },
+            ShepherdEvent::Pong,
+        ]
+    }
+
+    /// Write every event to a stream in each encoding, then check that they
+    /// are read back unchanged, in order, and followed by the end of the
+    /// stream
+    fn round_trip<T>(events: &[T])
+    where
+        T: Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        for encoding in Encoding::ALL {
+            let mut stream = Vec::new();
+            let mut writer = EventWriter::new(&mut stream);
+            writer.set_encoding(encoding);
+            for event in events {
+                writer.write_event(event).unwrap();
+            }
+
+            let mut reader = EventReader::<_, T>::new(stream.as_slice());
+            reader.set_encoding(encoding);
+            for event in events {
+                assert_eq!(
+                    reader.read_event().unwrap().as_ref(),
+                    Some(event),
+                    "an event was changed by a round trip in {encoding:?}"
+                );
+            }
+            assert!(reader.read_event().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn simulation_events_round_trip() {
+        round_trip(&simulation_events());
+    }
+
+    #[test]
+    fn shepherd_events_round_trip() {
+        round_trip(&shepherd_events());
+    }
+
+    #[test]
+    fn events_buffered_together_round_trip() {
+        for encoding in Encoding::ALL {
+            let events = simulation_events();
+            let mut stream = Vec::new();
+            let mut writer = EventWriter::new(&mut stream);
+            writer.set_encoding(encoding);
+            for event in &events {
+                writer.buffer_event(event).unwrap();
+            }
+            writer.flush().unwrap();
+
+            let mut reader =
+                EventReader::<_, SimulationEvent>::new(stream.as_slice());
+            reader.set_encoding(encoding);
+            assert_eq!(
+                reader.collect::<anyhow::Result<Vec<_>>>().unwrap(),
+                events,
+                "events buffered together in {encoding:?} were changed"
+            );
+        }
+    }
+}
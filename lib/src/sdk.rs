@@ -0,0 +1,314 @@
+use anyhow::Context;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    thread,
+};
+
+use crate::{
+    feed::{Feed, Justification},
+    ids::{ItemId, SheepId},
+    protocol::{Encoding, EventReader, EventWriter},
+    shepherd::{
+        Capability, Payload, ShepherdEvent, SimulationEvent, PROTOCOL_VERSION,
+    },
+};
+
+/// A feed algorithm which is run as a shepherd by [`run`]
+///
+/// The handshake, the framing of events and requests for feeds (whether
+/// one at a time or in batches) are taken care of by [`run`], so that only
+/// the algorithm itself needs to be written
+pub trait Handler {
+    /// Get the capabilities declared during the handshake
+    ///
+    /// Batches of feed requests are answered one feed at a time through
+    /// [`Handler::build_feed`], so declaring [`Capability::FeedBatches`]
+    /// never requires any more work. If [`Capability::JustifiedFeeds`] is
+    /// declared, feeds are built through [`Handler::build_justified_feed`]
+    /// instead. If [`Capability::TwoStageFeeds`] is declared,
+    /// [`Handler::retrieve_candidates`] and [`Handler::rank_candidates`] must
+    /// be implemented as well
+    fn capabilities(&self) -> Vec<Capability> {
+        Vec::new()
+    }
+
+    /// Get the payloads requested during the handshake
+    fn payloads(&self) -> Vec<Payload> {
+        Vec::new()
+    }
+
+    /// Get the preferred [`Encoding`] of events, which is used whenever the
+    /// simulation offers it and JSON is used otherwise
+    fn encoding(&self) -> Encoding {
+        Encoding::Json
+    }
+
+    /// Handle an event which does not call for a response
+    ///
+    /// Every event other than the handshake and requests for feeds is
+    /// passed on here, including [`SimulationEvent::Shutdown`], after which
+    /// no more events are handled
+    fn handle(&mut self, event: SimulationEvent) -> anyhow::Result<()> {
+        let _ = event;
+        Ok(())
+    }
+
+    /// Build a feed for the specified sheep
+    fn build_feed(&mut self, sheep: SheepId) -> anyhow::Result<Feed>;
+
+    /// Build a feed for the specified sheep, along with the justification
+    /// for any of its items
+    ///
+    /// This is only used if [`Capability::JustifiedFeeds`] is declared, and
+    /// builds a feed without any justifications by default
+    fn build_justified_feed(
+        &mut self,
+        sheep: SheepId,
+    ) -> anyhow::Result<Vec<(ItemId, Option<Justification>)>> {
+        Ok(self
+            .build_feed(sheep)?
+            .0
+            .into_iter()
+            .map(|item| (item, None))
+            .collect())
+    }
+
+    /// Retrieve up to the given number of candidates for the feed of the
+    /// specified sheep, which is the first stage of building a feed in two
+    /// stages
+    ///
+    /// This is only used if [`Capability::TwoStageFeeds`] is declared
+    fn retrieve_candidates(
+        &mut self,
+        sheep: SheepId,
+        n_candidates: usize,
+    ) -> anyhow::Result<Vec<ItemId>> {
+        let _ = (sheep, n_candidates);
+        anyhow::bail!("Retrieving candidates is not implemented")
+    }
+
+    /// Build the feed of the specified sheep out of the given candidates,
+    /// which is the second stage of building a feed in two stages
+    ///
+    /// The candidates are those retrieved by
+    /// [`Handler::retrieve_candidates`], less any the sheep can't be shown.
+    /// This is only used if [`Capability::TwoStageFeeds`] is declared
+    fn rank_candidates(
+        &mut self,
+        sheep: SheepId,
+        candidates: Vec<ItemId>,
+    ) -> anyhow::Result<Feed> {
+        let _ = (sheep, candidates);
+        anyhow::bail!("Ranking candidates is not implemented")
+    }
+}
+
+/// Run a [`Handler`] as a shepherd over the standard input and output of the
+/// process, until the simulation shuts it down or its input runs out
+pub fn run(handler: impl Handler) -> anyhow::Result<()> {
+    serve(handler, BufReader::new(io::stdin()), io::stdout())
+}
+
+/// Run a [`Handler`] as a shepherd over the given streams, until the
+/// simulation shuts it down or the input runs out
+///
+/// This is the same as [`run`], but allows for shepherds which do not
+/// communicate over the standard input and output of the process. Events
+/// are read on a thread of their own, which answers every
+/// [`SimulationEvent::Ping`] as soon as it arrives, so declaring
+/// [`Capability::Heartbeats`] never requires any more work either
+pub fn serve<W>(
+    mut handler: impl Handler,
+    input: impl BufRead + Send + 'static,
+    output: W,
+) -> anyhow::Result<()>
+where
+    W: Write + Send + 'static,
+{
+    let output = Arc::new(Mutex::new(EventWriter::new(output)));
+    let (chosen_encoding, encodings) = mpsc::channel();
+    let events = read_events(input, Arc::clone(&output), encodings);
+    let mut justified = false;
+
+    for event in events {
+        match event? {
+            SimulationEvent::Hello { encodings, .. } => {
+                let capabilities = handler.capabilities();
+                justified =
+                    capabilities.contains(&Capability::JustifiedFeeds);
+                let encoding = Some(handler.encoding())
+                    .filter(|encoding| encodings.contains(encoding))
+                    .unwrap_or_default();
+                let mut output =
+                    output.lock().unwrap_or_else(PoisonError::into_inner);
+                output
+                    .write_event(&ShepherdEvent::Hello {
+                        protocol_version: PROTOCOL_VERSION,
+                        capabilities,
+                        payloads: handler.payloads(),
+                        encoding,
+                    })
+                    .context("Unable to greet the simulation")?;
+                output.set_encoding(encoding);
+                // the reader stops once this is dropped, so there is no
+                // error to handle if it has stopped already
+                let _ = chosen_encoding.send(encoding);
+            }
+            SimulationEvent::Shutdown => {
+                return handler.handle(SimulationEvent::Shutdown);
+            }
+            event => {
+                if let Some(response) =
+                    respond(&mut handler, event, justified)?
+                {
+                    output
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .write_event(&response)
+                        .context("Unable to respond to the simulation")?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Pass an event other than the handshake on to a [`Handler`], returning
+/// the response to it if it was a request
+///
+/// Feeds are built through [`Handler::build_justified_feed`] if `justified`
+/// is set, which is the case when [`Capability::JustifiedFeeds`] was
+/// declared
+pub(crate) fn respond(
+    handler: &mut impl Handler,
+    event: SimulationEvent,
+    justified: bool,
+) -> anyhow::Result<Option<ShepherdEvent>> {
+    let response = match event {
+        SimulationEvent::FeedRequest { request_id, sheep } => {
+            if justified {
+                ShepherdEvent::JustifiedFeed {
+                    request_id,
+                    items: handler.build_justified_feed(sheep)?,
+                }
+            } else {
+                ShepherdEvent::Feed {
+                    request_id,
+                    feed: handler.build_feed(sheep)?,
+                }
+            }
+        }
+        SimulationEvent::FeedRequestBatch { request_id, sheep } => {
+            if justified {
+                ShepherdEvent::JustifiedFeedBatch {
+                    request_id,
+                    feeds: sheep
+                        .into_iter()
+                        .map(|sheep| handler.build_justified_feed(sheep))
+                        .collect::<anyhow::Result<_>>()?,
+                }
+            } else {
+                ShepherdEvent::FeedBatch {
+                    request_id,
+                    feeds: sheep
+                        .into_iter()
+                        .map(|sheep| handler.build_feed(sheep))
+                        .collect::<anyhow::Result<_>>()?,
+                }
+            }
+        }
+        SimulationEvent::RetrieveCandidates {
+            request_id,
+            sheep,
+            n_candidates,
+        } => ShepherdEvent::Candidates {
+            request_id,
+            items: handler.retrieve_candidates(sheep, n_candidates)?,
+        },
+        SimulationEvent::RankCandidates {
+            request_id,
+            sheep,
+            candidates,
+        } => {
+            let feed = handler.rank_candidates(sheep, candidates)?;
+            if justified {
+                ShepherdEvent::JustifiedFeed {
+                    request_id,
+                    items: feed
+                        .0
+                        .into_iter()
+                        .map(|item| (item, None))
+                        .collect(),
+                }
+            } else {
+                ShepherdEvent::Feed { request_id, feed }
+            }
+        }
+        event => {
+            handler.handle(event)?;
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(response))
+}
+
+/// Start reading events from the simulation on a thread of their own,
+/// answering pings there and passing every other event on
+///
+/// Once the simulation has been greeted, the reader waits for the
+/// [`Encoding`] chosen in response before reading anything else
+fn read_events<W>(
+    input: impl BufRead + Send + 'static,
+    output: Arc<Mutex<EventWriter<W>>>,
+    encodings: mpsc::Receiver<Encoding>,
+) -> mpsc::Receiver<anyhow::Result<SimulationEvent>>
+where
+    W: Write + Send + 'static,
+{
+    let (sender, events) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = EventReader::<_, SimulationEvent>::new(input);
+        loop {
+            let event = match input
+                .read_event()
+                .context("Unable to read an event from the simulation")
+            {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                Err(error) => {
+                    let _ = sender.send(Err(error));
+                    break;
+                }
+            };
+
+            if event == SimulationEvent::Ping {
+                let pong = output
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .write_event(&ShepherdEvent::Pong)
+                    .context("Unable to answer a ping from the simulation");
+                if let Err(error) = pong {
+                    let _ = sender.send(Err(error));
+                    break;
+                }
+                continue;
+            }
+
+            let greeting = matches!(event, SimulationEvent::Hello { .. });
+            if sender.send(Ok(event)).is_err() {
+                break;
+            }
+            if greeting {
+                let Ok(encoding) = encodings.recv() else {
+                    break;
+                };
+                input.set_encoding(encoding);
+            }
+        }
+    });
+
+    events
+}
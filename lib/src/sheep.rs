@@ -1,91 +1,820 @@
-use petgraph::algo;
+use petgraph::{
+    algo,
+    graph::{EdgeReference, NodeIndex},
+    visit::EdgeRef,
+    Direction::{self, Incoming, Outgoing},
+};
 use rand::prelude::*;
-use std::ops::Add;
+use std::{cmp::Reverse, collections::BinaryHeap, ops::Add};
 use tracing::info;
 
 use crate::{
-    feed::{Feed, Response, Responses},
-    graph::Simulation,
-    ids::SheepId,
+    collections::{HashMap, HashSet},
+    feed::{Explanation, Feed, Response, Responses},
+    graph::{EdgeWeight, Simulation},
+    ids::{EpochId, GraphId, ItemId, NodeType, SheepId, TagId},
 };
 
 /// Calculate the probability of a positive rating given the input sum of
 /// weights along the shortest path
+///
+/// This is one at a distance of zero and decays towards zero as the distance
+/// grows. Negative and NaN distances are treated as zero
 pub fn p_positive(distance: f64) -> f64 {
-    3f64.powf(distance) / 4f64.powf(distance)
+    // the base is computed before exponentiating, as raising the numerator
+    // and denominator separately overflows to `inf / inf` for large distances
+    (3.0f64 / 4.0).powf(distance.max(0.0))
 }
 
-/// Calculate the probability of a neutral rating given the input sum of
-/// weights along the shortest path
+/// Calculate the probability of a rating being at least neutral (that is,
+/// either positive or neutral) given the input sum of weights along the
+/// shortest path
+///
+/// This is cumulative, so it is never less than [`p_positive`] at the same
+/// distance, and the probability of a neutral rating alone is the difference
+/// between the two. Negative and NaN distances are treated as zero
 pub fn p_neutral(distance: f64) -> f64 {
-    19f64.powf(distance) / 20f64.powf(distance)
+    (19.0f64 / 20.0).powf(distance.max(0.0))
+}
+
+/// The kind of label noise a noisy sheep applies to its ratings
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum Noise {
+    /// The sheep responds uniformly at random, ignoring the tag graph
+    #[default]
+    Uniform,
+
+    /// The sheep rates as usual, but positive and negative responses are
+    /// swapped
+    Inverted,
+}
+
+/// A named kind of sheep with its own behavioral parameters
+#[derive(Clone, PartialEq, Debug)]
+pub struct Archetype {
+    /// The name of the archetype, used when reporting results
+    pub name: String,
+
+    /// The share of the sheep population belonging to this archetype
+    ///
+    /// Proportions are relative to the sum of the proportions of every
+    /// archetype in the simulation, so they need not add up to one
+    pub proportion: f64,
+
+    /// Bounds on the number of tags a sheep of this archetype has
+    pub n_tags_bounds: (usize, usize),
+
+    /// The probability that a sheep of this archetype is active (and
+    /// therefore requests feeds) during an epoch
+    pub activity_probability: f64,
+
+    /// Bounds on the response temperature of sheep of this archetype
+    ///
+    /// Each sheep's temperature is sampled uniformly from these bounds when
+    /// it is created. Distances are divided by the temperature before
+    /// computing response probabilities, so temperatures above one flatten
+    /// the response curves and temperatures below one sharpen them
+    pub temperature_bounds: (f64, f64),
+}
+
+impl Default for Archetype {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            proportion: 1.0,
+            n_tags_bounds: (10, 15),
+            activity_probability: 1.0,
+            temperature_bounds: (1.0, 1.0),
+        }
+    }
+}
+
+/// The parameters of an individual sheep
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Profile {
+    /// The index of the sheep's [`Archetype`] within the simulation settings
+    pub archetype: usize,
+
+    /// The sheep's response temperature, sampled from the bounds given by its
+    /// [`Archetype`]
+    pub temperature: f64,
+
+    /// The kind of noise the sheep applies to its ratings, if it is noisy
+    pub noise: Option<Noise>,
+}
+
+/// A sheep's fatigue towards each tag group, keyed by the index of the group
+///
+/// Fatigue levels range from zero to one, and reduce the probability of a
+/// positive response to items in the group proportionally. The index of a
+/// group never changes or gets reused, as groups merged into others are left
+/// empty and groups split off of others are given new ones, so fatigue is
+/// carried over through [`Fatigue::merge`] and [`Fatigue::split`]
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Fatigue(pub HashMap<usize, f64>);
+
+impl Fatigue {
+    /// Get the fatigue towards an item, which is the highest fatigue towards
+    /// any of the tag groups it belongs to
+    pub fn level(&self, groups: &[usize]) -> f64 {
+        groups
+            .iter()
+            .filter_map(|group| self.0.get(group))
+            .copied()
+            .fold(0.0, f64::max)
+    }
+
+    /// Carry the fatigue towards a tag group over to the group it was merged
+    /// into, keeping the higher of the two levels
+    pub fn merge(&mut self, absorbed: usize, group: usize) {
+        if let Some(absorbed) = self.0.remove(&absorbed) {
+            let level = self.0.entry(group).or_default();
+            *level = level.max(absorbed);
+        }
+    }
+
+    /// Carry the fatigue towards a tag group over to a group split off of it
+    pub fn split(&mut self, group: usize, split: usize) {
+        if let Some(level) = self.0.get(&group).copied() {
+            self.0.insert(split, level);
+        }
+    }
+
+    /// Recover from fatigue by the given fraction
+    pub fn recover(&mut self, rate: f64) {
+        self.0.retain(|_, level| {
+            *level *= 1.0 - rate;
+            *level > f64::EPSILON
+        });
+    }
+}
+
+/// Get the indices of the tag groups an item belongs to through its tags
+pub fn item_tag_groups(
+    graph: &Simulation,
+    tag_groups: &[HashSet<TagId>],
+    item: ItemId,
+) -> Vec<usize> {
+    let mut groups = graph
+        .associated_tags(item)
+        .filter_map(|tag| tag_groups.iter().position(|g| g.contains(&tag)))
+        .collect::<Vec<_>>();
+    groups.sort_unstable();
+    groups.dedup();
+    groups
+}
+
+/// A model of how sheep respond to the feeds they are given
+///
+/// Implementing this allows for plugging alternative user models into the
+/// simulation through [`Settings::sheep_behavior`]
+///
+/// [`Settings::sheep_behavior`]: crate::simulation::Settings::sheep_behavior
+pub trait SheepBehavior {
+    /// Respond to a feed given to a sheep
+    ///
+    /// The sheep's fatigue is the one it has built up towards the feeds of
+    /// the shepherd which built this one. If `explain` is set, responses
+    /// should be accompanied by an [`Explanation`] where possible
+    #[allow(clippy::too_many_arguments)]
+    fn respond(
+        &mut self,
+        rng: &mut dyn RngCore,
+        graph: &Simulation,
+        tag_groups: &[HashSet<TagId>],
+        sheep: SheepId,
+        profile: &Profile,
+        fatigue: &mut Fatigue,
+        explain: bool,
+        feed: Feed,
+    ) -> Responses;
+
+    /// Notify the behavior that the graph has changed, so that anything it
+    /// has computed from the graph is recomputed before it is next used
+    ///
+    /// If only the edges leaving a single sheep have changed, that sheep is
+    /// given, as no other sheep's paths pass through it. Edges mirrored back
+    /// into the sheep (as with [`EdgeSemantics::Undirected`]) let other
+    /// sheep's paths pass through it, so [`None`] is given for those. This
+    /// may be called any number of times within an epoch
+    ///
+    /// [`EdgeSemantics::Undirected`]: crate::graph::EdgeSemantics::Undirected
+    fn graph_changed(&mut self, _sheep: Option<SheepId>) {}
+
+    /// Check that the behavior's settings are valid, before the simulation
+    /// it is used in starts
+    fn validate(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Distances from a sheep to the items it may be shown, as used by
+/// [`process_feed`]
+pub trait Distances {
+    /// Get the measure of the path to an item, or [`None`] if it is
+    /// unreachable
+    fn measure(&self, item: ItemId) -> Option<PathMeasure>;
+
+    /// Get the nodes along the path to an item, from the sheep to the item,
+    /// or [`None`] if it is unreachable
+    fn path(&self, item: ItemId) -> Option<Vec<NodeIndex<usize>>>;
+}
+
+/// The best known measure of the path to each node found by [`search`],
+/// along with the node before it on that path
+type SearchTree =
+    HashMap<NodeIndex<usize>, (PathMeasure, Option<NodeIndex<usize>>)>;
+
+/// Get the affinity of an edge as seen by a sheep in the graph's current
+/// epoch
+///
+/// Edges leaving sheep describe their interests, so those fade by the given
+/// rate for every epoch since they were last touched, as described by
+/// [`EdgeWeight::decayed`]. Every other edge is taken as is
+fn affinity(
+    graph: &Simulation,
+    edge: EdgeReference<'_, EdgeWeight, usize>,
+    decay_rate: f64,
+) -> u32 {
+    if graph.0[edge.source()] == NodeType::Sheep {
+        edge.weight().decayed(graph.1, decay_rate)
+    } else {
+        edge.weight().affinity
+    }
+}
+
+/// Find the shortest paths from a node to every node reachable from it,
+/// following edges in the given direction and only entering nodes accepted
+/// by the filter, with interests fading by the given rate
+fn search(
+    graph: &Simulation,
+    start: NodeIndex<usize>,
+    direction: Direction,
+    filter: impl Fn(NodeIndex<usize>) -> bool,
+    decay_rate: f64,
+) -> SearchTree {
+    let mut paths = HashMap::default();
+    let mut frontier = BinaryHeap::new();
+    paths.insert(start, (PathMeasure::default(), None));
+    frontier.push(Reverse((PathMeasure::default(), start)));
+
+    while let Some(Reverse((measure, node))) = frontier.pop() {
+        if paths.get(&node).is_some_and(|(best, _)| *best < measure) {
+            continue;
+        }
+
+        for edge in graph.0.edges_directed(node, direction) {
+            let target = match direction {
+                Outgoing => edge.target(),
+                Incoming => edge.source(),
+            };
+            if !filter(target) {
+                continue;
+            }
+
+            let next =
+                measure + PathMeasure::new(affinity(graph, edge, decay_rate));
+            if paths.get(&target).is_none_or(|(best, _)| next < *best) {
+                paths.insert(target, (next, Some(node)));
+                frontier.push(Reverse((next, target)));
+            }
+        }
+    }
+
+    paths
+}
+
+/// The shortest paths from a sheep to every node reachable from it
+///
+/// Paths are ordered by their [`PathMeasure`], so ties in distance are broken
+/// by the number of hops
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ShortestPaths(SearchTree);
+
+impl ShortestPaths {
+    /// Find the shortest paths from a sheep with a single-source search,
+    /// with interests fading by the given rate (see [`EdgeWeight::decayed`])
+    pub fn new(graph: &Simulation, sheep: SheepId, decay_rate: f64) -> Self {
+        Self(search(
+            graph,
+            NodeIndex::new(sheep.0),
+            Outgoing,
+            |_| true,
+            decay_rate,
+        ))
+    }
+}
+
+impl Distances for ShortestPaths {
+    fn measure(&self, item: ItemId) -> Option<PathMeasure> {
+        self.0
+            .get(&NodeIndex::new(item.0))
+            .map(|(measure, _)| *measure)
+    }
+
+    fn path(&self, item: ItemId) -> Option<Vec<NodeIndex<usize>>> {
+        let mut node = NodeIndex::new(item.0);
+        let mut path = vec![node];
+        while let Some(previous) = self.0.get(&node)?.1 {
+            path.push(previous);
+            node = previous;
+        }
+        path.reverse();
+
+        Some(path)
+    }
+}
+
+/// An embedding of the tags of a graph by their shortest paths to and from a
+/// few landmark tags, used to approximate the distance between a sheep and
+/// an item without searching the graph from every sheep
+///
+/// The distance to an item is estimated as the length of the shortest path
+/// from the sheep to a landmark followed by the shortest path from the
+/// landmark to the item, through whichever landmark gives the shortest
+/// total. As this is the length of a real path, the estimate is never
+/// shorter than the true distance, and it is exact whenever a landmark lies
+/// on a shortest path. More generally, if a tag `x` lies on a shortest path,
+/// the estimate exceeds the true distance by at most `d(x, l) + d(l, x)` for
+/// any landmark `l`, which is the round trip between `x` and the landmark
+/// closest to it. The number of hops is not bounded in the same way, as the
+/// path through a landmark may double back on itself
+///
+/// Only the paths between tags are embedded. The edges leaving sheep and
+/// entering items are read from the graph whenever a distance is requested,
+/// so those are always current, but changes to the edges between tags are
+/// not seen until the embedding is recomputed, and the bound above only
+/// holds until then. Items which cannot be reached through any landmark are
+/// treated as unreachable
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Landmarks {
+    /// The shortest paths from each tag to each landmark, with the next tag
+    /// along each path
+    to: Vec<SearchTree>,
+
+    /// The shortest paths from each landmark to each tag, with the previous
+    /// tag along each path
+    from: Vec<SearchTree>,
+}
+
+impl Landmarks {
+    /// Choose up to the given number of landmarks and find the shortest
+    /// paths to and from each of them
+    ///
+    /// Landmarks are chosen from the tags connected to the most other tags,
+    /// preferring tags which cannot yet be reached from any landmark so that
+    /// every part of the tag graph is covered where possible
+    pub fn new(graph: &Simulation, n_landmarks: usize) -> Self {
+        let is_tag = |node: NodeIndex<usize>| graph.0[node] == NodeType::Tag;
+        let mut tags = graph
+            .0
+            .node_indices()
+            .filter(|node| is_tag(*node))
+            .collect::<Vec<_>>();
+        // the sort is stable, so ties are left in index order
+        tags.sort_by_key(|tag| {
+            Reverse(
+                graph
+                    .0
+                    .neighbors_undirected(*tag)
+                    .filter(|node| is_tag(*node))
+                    .count(),
+            )
+        });
+
+        let mut landmarks = Self::default();
+        let mut chosen = HashSet::default();
+        for covering in [true, false] {
+            for tag in &tags {
+                if landmarks.to.len() >= n_landmarks {
+                    break;
+                }
+
+                if chosen.contains(tag)
+                    || (covering
+                        && landmarks
+                            .from
+                            .iter()
+                            .any(|from| from.contains_key(tag)))
+                {
+                    continue;
+                }
+
+                chosen.insert(*tag);
+                // only tags are entered, so no interests are on these paths
+                landmarks
+                    .to
+                    .push(search(graph, *tag, Incoming, is_tag, 0.0));
+                landmarks
+                    .from
+                    .push(search(graph, *tag, Outgoing, is_tag, 0.0));
+            }
+        }
+
+        landmarks
+    }
+
+    /// Get the approximate distances from a sheep to every item, with its
+    /// interests fading by the given rate (see [`EdgeWeight::decayed`])
+    pub fn distances<'a>(
+        &'a self,
+        graph: &'a Simulation,
+        sheep: SheepId,
+        decay_rate: f64,
+    ) -> LandmarkDistances<'a> {
+        let start = NodeIndex::new(sheep.0);
+        let legs = self
+            .to
+            .iter()
+            .map(|to| {
+                graph
+                    .0
+                    .edges(start)
+                    .filter_map(|edge| {
+                        to.get(&edge.target()).map(|(measure, _)| {
+                            (
+                                PathMeasure::new(affinity(
+                                    graph, edge, decay_rate,
+                                )) + *measure,
+                                edge.target(),
+                            )
+                        })
+                    })
+                    .min()
+            })
+            .collect();
+
+        LandmarkDistances {
+            landmarks: self,
+            graph,
+            start,
+            legs,
+        }
+    }
+}
+
+/// The approximate distances from a sheep to every item, found through a set
+/// of [`Landmarks`]
+#[derive(Clone)]
+pub struct LandmarkDistances<'a> {
+    landmarks: &'a Landmarks,
+    graph: &'a Simulation,
+    start: NodeIndex<usize>,
+
+    /// For each landmark, the measure of the shortest path from the sheep to
+    /// it along with the first tag on that path, if it is reachable
+    legs: Vec<Option<(PathMeasure, NodeIndex<usize>)>>,
+}
+
+impl LandmarkDistances<'_> {
+    /// Find the shortest path to an item through a landmark, giving its
+    /// measure, the index of the landmark, the first tag after the sheep and
+    /// the last tag before the item
+    fn route(
+        &self,
+        item: ItemId,
+    ) -> Option<(PathMeasure, usize, NodeIndex<usize>, NodeIndex<usize>)>
+    {
+        let end = NodeIndex::new(item.0);
+        self.legs
+            .iter()
+            .zip(&self.landmarks.from)
+            .enumerate()
+            .filter_map(|(landmark, (leg, from))| {
+                let (to_landmark, first) = (*leg)?;
+                self.graph
+                    .0
+                    .edges_directed(end, Incoming)
+                    .filter_map(|edge| {
+                        from.get(&edge.source()).map(|(measure, _)| {
+                            (
+                                to_landmark
+                                    + *measure
+                                    + PathMeasure::new(
+                                        edge.weight().affinity,
+                                    ),
+                                landmark,
+                                first,
+                                edge.source(),
+                            )
+                        })
+                    })
+                    .min()
+            })
+            .min()
+    }
+}
+
+impl Distances for LandmarkDistances<'_> {
+    fn measure(&self, item: ItemId) -> Option<PathMeasure> {
+        self.route(item).map(|(measure, ..)| measure)
+    }
+
+    fn path(&self, item: ItemId) -> Option<Vec<NodeIndex<usize>>> {
+        let (_, landmark, first, last) = self.route(item)?;
+
+        let mut path = vec![self.start, first];
+        let mut node = first;
+        while let Some((_, Some(next))) =
+            self.landmarks.to[landmark].get(&node)
+        {
+            path.push(*next);
+            node = *next;
+        }
+
+        let mut tail = vec![NodeIndex::new(item.0), last];
+        let mut node = last;
+        while let Some((_, Some(previous))) =
+            self.landmarks.from[landmark].get(&node)
+        {
+            tail.push(*previous);
+            node = *previous;
+        }
+
+        // both halves of the path include the landmark itself
+        tail.pop();
+        path.extend(tail.into_iter().rev());
+
+        Some(path)
+    }
+}
+
+/// The method used by [`DistanceBehavior`] to find the distances between
+/// sheep and items
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum DistanceOracle {
+    /// Exact shortest paths, found with a search from each sheep which is
+    /// reused until the graph changes
+    #[default]
+    Exact,
+
+    /// Approximate distances through a number of landmark tags, which are
+    /// reused for the given number of epochs before being recomputed
+    ///
+    /// See [`Landmarks`] for the error bound. An interval of zero is treated
+    /// as an interval of one
+    Landmarks {
+        /// The number of landmark tags to embed the graph with
+        n_landmarks: usize,
+
+        /// The number of epochs between recomputations of the embedding
+        refresh_interval: usize,
+    },
+}
+
+/// The default [`SheepBehavior`], which responds to items based on the
+/// weighted distance between them and the sheep as done by [`process_feed`]
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct DistanceBehavior {
+    /// The probability that a sheep responds positively to an item regardless
+    /// of its distance from it
+    pub serendipity_probability: f64,
+
+    /// The fatigue a sheep gains towards each of the tag groups of an item
+    /// every time it is shown the item
+    pub fatigue_rate: f64,
+
+    /// The method used to find the distances between sheep and items
+    pub oracle: DistanceOracle,
+
+    /// The fraction by which the affinity of a sheep's interest in a tag
+    /// fades for every epoch since it was last reinforced, if at all
+    ///
+    /// See [`EdgeWeight::decayed`] for details. This lets sheep lose
+    /// interest in tags without the graph being rebuilt, and has no effect
+    /// when set to zero
+    pub decay_rate: f64,
+
+    /// The shortest paths from each sheep computed so far, which are reused
+    /// until the graph changes
+    pub paths: HashMap<SheepId, ShortestPaths>,
+
+    /// The landmark embedding in use, if any, along with the epoch it was
+    /// computed in
+    pub landmarks: Option<(Landmarks, EpochId)>,
+}
+
+impl SheepBehavior for DistanceBehavior {
+    fn respond(
+        &mut self,
+        rng: &mut dyn RngCore,
+        graph: &Simulation,
+        tag_groups: &[HashSet<TagId>],
+        sheep: SheepId,
+        profile: &Profile,
+        fatigue: &mut Fatigue,
+        explain: bool,
+        feed: Feed,
+    ) -> Responses {
+        let rates = (self.serendipity_probability, self.fatigue_rate);
+        match self.oracle {
+            DistanceOracle::Exact => {
+                let paths = self.paths.entry(sheep).or_insert_with(|| {
+                    ShortestPaths::new(graph, sheep, self.decay_rate)
+                });
+                process_feed(
+                    rng,
+                    graph,
+                    tag_groups,
+                    (sheep, paths),
+                    profile,
+                    fatigue,
+                    rates,
+                    explain,
+                    feed,
+                )
+            }
+            DistanceOracle::Landmarks {
+                n_landmarks,
+                refresh_interval,
+            } => {
+                let EpochId(epoch) = graph.1;
+                if self.landmarks.as_ref().is_some_and(
+                    |(_, EpochId(computed))| {
+                        epoch.saturating_sub(*computed)
+                            >= refresh_interval.max(1)
+                    },
+                ) {
+                    self.landmarks = None;
+                }
+
+                let (landmarks, _) =
+                    self.landmarks.get_or_insert_with(|| {
+                        (Landmarks::new(graph, n_landmarks), graph.1)
+                    });
+                process_feed(
+                    rng,
+                    graph,
+                    tag_groups,
+                    (
+                        sheep,
+                        &landmarks.distances(graph, sheep, self.decay_rate),
+                    ),
+                    profile,
+                    fatigue,
+                    rates,
+                    explain,
+                    feed,
+                )
+            }
+        }
+    }
+
+    fn graph_changed(&mut self, sheep: Option<SheepId>) {
+        // the landmark embedding is left alone, as it is only an
+        // approximation to begin with, and is recomputed on an interval of
+        // its own
+        match sheep {
+            Some(sheep) => {
+                self.paths.remove(&sheep);
+            }
+            None => self.paths.clear(),
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&self.serendipity_probability),
+            "The serendipity probability is not within [0, 1]"
+        );
+        anyhow::ensure!(
+            self.fatigue_rate >= 0.0,
+            "The fatigue rate is negative"
+        );
+
+        Ok(())
+    }
 }
 
 /// Wrapper around a pair to count both the number of vertices visited and
 /// the sum of weights
-#[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Copy, Clone)]
-pub struct PathMeasure(pub u32, pub u32);
+///
+/// Paths are ordered by their distance, with ties broken by their number of
+/// hops
+#[derive(Debug, Default, Eq, PartialEq, PartialOrd, Ord, Copy, Clone)]
+pub struct PathMeasure {
+    /// The sum of the weights of the edges along the path
+    pub distance: u32,
 
-impl PathMeasure {
-    pub fn new(weight: u32) -> Self {
-        Self(weight, 1)
-    }
+    /// The number of edges along the path
+    pub hops: u32,
 }
 
-impl Default for PathMeasure {
-    fn default() -> Self {
-        Self(0, 0)
+impl PathMeasure {
+    pub fn new(weight: u32) -> Self {
+        Self {
+            distance: weight,
+            hops: 1,
+        }
     }
 }
 
 impl Add for PathMeasure {
     type Output = Self;
 
+    // paths long enough to overflow are effectively unreachable, so they
+    // saturate rather than wrapping around to look short
     fn add(self, other: Self) -> Self::Output {
-        Self(self.0 + other.0, self.1 + other.1)
+        Self {
+            distance: self.distance.saturating_add(other.distance),
+            hops: self.hops.saturating_add(other.hops),
+        }
     }
 }
 
-/// Process a feed given the tag graph, sheep id, and feed
+/// Calculate the probability of a positive response from a sheep to each
+/// item reachable from it
+///
+/// This accounts for the sheep's temperature, but not for any noise it
+/// applies to its ratings or any fatigue it has built up. Items missing from
+/// the returned map are unreachable, and are always responded to negatively
+pub fn affinities(
+    graph: &Simulation,
+    sheep: SheepId,
+    profile: &Profile,
+) -> HashMap<ItemId, f64> {
+    algo::dijkstra(&graph.0, sheep.0.into(), None, |e| e.weight().affinity)
+        .into_iter()
+        .filter(|(node, _)| graph.0[*node] == NodeType::Item)
+        .map(|(node, distance)| {
+            (
+                GraphId::new(node.index()),
+                p_positive(f64::from(distance) / profile.temperature),
+            )
+        })
+        .collect()
+}
+
+/// Process a feed given the tag graph and its tag groups, sheep id and its
+/// distances to items, profile and fatigue, serendipity probability and fatigue
+/// rate, whether to explain responses, and feed
+///
+/// Explanations give the shortest path from the sheep to each item along
+/// with its distance, and are only attached to responses to reachable items
+///
+/// With the given probability, an item which would not otherwise have been
+/// responded to positively is responded to positively anyways. These
+/// serendipitous responses are flagged as such in the returned [`Responses`]
+///
+/// Every item shown to the sheep increases its fatigue towards the item's tag
+/// groups by the fatigue rate, including items later in the same feed
+///
+/// Distances are taken as given, so any fading of the sheep's interests (see
+/// [`DistanceBehavior::decay_rate`]) is applied when they are found
+#[allow(clippy::too_many_arguments)]
 pub fn process_feed(
     rng: &mut (impl Rng + ?Sized),
     graph: &Simulation,
-    sheep: SheepId,
+    tag_groups: &[HashSet<TagId>],
+    (sheep, paths): (SheepId, &impl Distances),
+    profile: &Profile,
+    fatigue: &mut Fatigue,
+    (serendipity_probability, fatigue_rate): (f64, f64),
+    explain: bool,
     feed: Feed,
 ) -> Responses {
     let mut responses = Vec::with_capacity(feed.0.len());
 
     for item in feed.0 {
-        responses.push(
-            if let Some(PathMeasure(distance, hops)) = algo::dijkstra(
-                &graph.0,
-                sheep.0.into(),
-                Some(item.0.into()),
-                |e| PathMeasure::new(*e.weight()),
-            )
-            .get(&item.0.into())
+        // without any fatigue to apply or build up, the groups don't matter,
+        // and finding them takes a scan over every group for each tag
+        let groups = if fatigue_rate > 0.0 || !fatigue.0.is_empty() {
+            item_tag_groups(graph, tag_groups, item)
+        } else {
+            Vec::new()
+        };
+        let freshness = 1.0 - fatigue.level(&groups);
+
+        let (item, response, hops, explanation) =
+            if let Some(PathMeasure { distance, hops }) = paths.measure(item)
             {
+                let scaled_distance =
+                    f64::from(distance) / profile.temperature;
                 (
                     item,
                     match rng.gen::<f64>() {
-                        c if c <= p_positive(f64::from(*distance)) => {
+                        c if c <= p_positive(scaled_distance) * freshness => {
                             info!(
                                 sheep = sheep.0,
                                 item = item.0,
                                 distance = distance,
+                                temperature = profile.temperature,
+                                freshness = freshness,
                                 probability = c,
-                                threshold = p_positive(f64::from(*distance)),
+                                threshold =
+                                    p_positive(scaled_distance) * freshness,
                                 rating = "positive"
                             );
                             Response::Positive
                         }
-                        c if c <= p_neutral(f64::from(*distance)) => {
+                        c if c <= p_neutral(scaled_distance) => {
                             info!(
                                 sheep = sheep.0,
                                 item = item.0,
                                 distance = distance,
+                                temperature = profile.temperature,
                                 probability = c,
-                                threshold = p_neutral(f64::from(*distance)),
+                                threshold = p_neutral(scaled_distance),
                                 rating = "neutral"
                             );
                             Response::Neutral
@@ -101,7 +830,17 @@ pub fn process_feed(
                             Response::Negative
                         }
                     },
-                    Some(*hops),
+                    Some(hops),
+                    explain.then(|| Explanation {
+                        path: paths
+                            .path(item)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .filter(|node| graph.0[*node] == NodeType::Tag)
+                            .map(|node| GraphId::new(node.index()))
+                            .collect(),
+                        distance,
+                    }),
                 )
             } else {
                 // to keep the model simple, we always respond negatively to
@@ -117,10 +856,128 @@ pub fn process_feed(
                     distance = "unconnected",
                     rating = "negative"
                 );
-                (item, Response::Negative, None)
-            },
-        );
+                (item, Response::Negative, None, None)
+            };
+
+        let response = match profile.noise {
+            Some(Noise::Uniform) => {
+                *[Response::Positive, Response::Neutral, Response::Negative]
+                    .choose(rng)
+                    .expect("The list of possible responses is never empty")
+            }
+            Some(Noise::Inverted) => response.inverted(),
+            None => response,
+        };
+
+        if let Some(noise) = profile.noise {
+            info!(
+                sheep = sheep.0,
+                item = item.0,
+                noise = ?noise,
+                rating = ?response,
+                "the rating was replaced by a noisy one"
+            );
+        }
+
+        // the probability is checked first so that no randomness is consumed
+        // when serendipity is disabled
+        let serendipitous = serendipity_probability > 0.0
+            && response != Response::Positive
+            && rng.gen_bool(serendipity_probability);
+        let response = if serendipitous {
+            info!(
+                sheep = sheep.0,
+                item = item.0,
+                rating = "positive",
+                "the rating was replaced by a serendipitous one"
+            );
+
+            Response::Positive
+        } else {
+            response
+        };
+
+        for group in groups {
+            let level = fatigue.0.entry(group).or_default();
+            *level = (*level + fatigue_rate).min(1.0);
+        }
+
+        responses.push((item, response, hops, serendipitous, explanation));
     }
 
     Responses(responses)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Distances spanning the whole domain of the response curves, from
+    /// those which are clamped to zero to those which once overflowed
+    fn distances() -> impl Iterator<Item = f64> {
+        [f64::NEG_INFINITY, f64::MIN, -1.0, -0.0]
+            .into_iter()
+            .chain((0..=4000).map(|i| f64::from(i) / 4.0))
+            .chain((3..=308).map(|exponent| 10f64.powi(exponent)))
+            .chain([f64::MAX, f64::INFINITY])
+    }
+
+    #[test]
+    fn curves_are_probabilities() {
+        for distance in distances().chain([f64::NAN]) {
+            for p in [p_positive(distance), p_neutral(distance)] {
+                assert!(
+                    (0.0..=1.0).contains(&p),
+                    "{p} at a distance of {distance} is not within [0, 1]"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn curves_are_non_increasing() {
+        let mut last = (1.0, 1.0);
+        for distance in distances() {
+            let p = (p_positive(distance), p_neutral(distance));
+            assert!(
+                p.0 <= last.0 && p.1 <= last.1,
+                "the curves increase at a distance of {distance}"
+            );
+            last = p;
+        }
+    }
+
+    #[test]
+    fn responses_are_exclusive() {
+        // `p_neutral` is cumulative, so the probability of a neutral rating
+        // alone is its difference from `p_positive`
+        for distance in distances() {
+            let positive = p_positive(distance);
+            let neutral = p_neutral(distance) - positive;
+            assert!(
+                neutral >= 0.0 && positive + neutral <= 1.0,
+                "the responses overlap at a distance of {distance}"
+            );
+        }
+    }
+
+    #[test]
+    fn fatigue_follows_merged_and_split_groups() {
+        let mut fatigue = Fatigue::default();
+        fatigue.0.extend([(0, 0.25), (1, 0.5)]);
+
+        fatigue.merge(1, 0);
+        assert_eq!(fatigue.level(&[0]), 0.5);
+        assert_eq!(fatigue.level(&[1]), 0.0);
+
+        fatigue.split(0, 2);
+        assert_eq!(fatigue.level(&[0]), 0.5);
+        assert_eq!(fatigue.level(&[2]), 0.5);
+
+        // groups without any fatigue are left without any
+        fatigue.merge(3, 2);
+        fatigue.split(4, 5);
+        assert_eq!(fatigue.level(&[2]), 0.5);
+        assert_eq!(fatigue.level(&[5]), 0.0);
+    }
+}
@@ -1,147 +1,1862 @@
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use serde::{Deserialize, Serialize};
+#[cfg(all(feature = "process", unix))]
+use std::os::unix::net::UnixStream;
+#[cfg(all(feature = "process", windows, feature = "job-objects"))]
+use std::os::windows::io::AsRawHandle;
+#[cfg(any(feature = "process", feature = "wasm"))]
+use std::path::Path;
+#[cfg(any(feature = "process", not(target_arch = "wasm32")))]
+use std::time::Instant;
 use std::{
-    ffi::OsStr,
-    path::Path,
-    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    io::{self, BufReader, Read, Write},
+    iter, mem,
+    rc::Rc,
+    time::Duration,
 };
+#[cfg(feature = "process")]
+use std::{
+    ffi::{OsStr, OsString},
+    io::{BufRead, BufWriter},
+    net::TcpStream,
+    process::{Child, Command, Stdio},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+};
+#[cfg(feature = "process")]
+use tracing::info;
+use tracing::{instrument, warn};
 
+#[cfg(feature = "grpc")]
+use crate::grpc;
+#[cfg(feature = "process")]
+use crate::limits::ResourceLimits;
 use crate::{
-    feed::{Feed, Response, Responses},
-    graph::Simulation,
-    ids::{EpochId, ItemId, SheepId, TagId},
-    simulation::Epoch,
+    collections::{HashMap, HashSet},
+    feed::{Feed, Justification, Response, ResponseCounts, Responses},
+    ids::{EpochId, ItemId, RequestId, SheepId, TagId},
+    protocol::{Encoding, EventReader, EventWriter},
+    sdk::{self, Handler},
+    simulation::{Epoch, RunConfig},
 };
 
-/// A wrapper around a child process which implements a feed algorithm
-pub struct Shepherd<'de> {
+/// The version of the protocol spoken between the simulation and shepherds,
+/// which covers [`SimulationEvent`] and [`ShepherdEvent`]
+///
+/// This is incremented whenever either of them changes in a way which
+/// existing shepherds could misread, and shepherds speaking any other version
+/// are rejected during the handshake (see [`Shepherd::greet`]). Events are
+/// framed as described by [`Encoding`]. The Python helper in `python/`
+/// mirrors both, and must be kept in step with this
+pub const PROTOCOL_VERSION: u32 = 12;
+
+/// How long a shepherd process is given to exit on its own after being sent
+/// [`SimulationEvent::Shutdown`], before it is killed
+#[cfg(feature = "process")]
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a [`Shepherd`] is pinged while the simulation waits on it, and
+/// how many pings in a row it may miss before it is taken to have hung (see
+/// [`Shepherd::set_heartbeat`])
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Heartbeat {
+    /// The time between pings, which is also the time the shepherd is given
+    /// to answer each of them
+    pub interval: Duration,
+
+    /// The number of pings in a row which, once missed, mark the shepherd as
+    /// hung
+    pub miss_threshold: u32,
+}
+
+/// A reader which fails once more bytes have been read from it than allowed
+///
+/// This sits directly on top of the shepherd's output, beneath any
+/// buffering, so every byte the shepherd sends is counted as it is read. The
+/// remaining quota is shared with the owning [`Shepherd`], which resets it
+/// before each request. A quota of [`None`] is unlimited
+struct QuotaReader {
+    inner: Box<dyn Read>,
+    remaining: Rc<Cell<Option<usize>>>,
+}
+
+impl Read for QuotaReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(remaining) = self.remaining.get() else {
+            return self.inner.read(buf);
+        };
+        if remaining == 0 {
+            return Err(io::Error::other(
+                "The shepherd exceeded its output quota",
+            ));
+        }
+
+        let length = buf.len().min(remaining);
+        let n_read = self.inner.read(&mut buf[..length])?;
+        self.remaining.set(Some(remaining - n_read));
+        Ok(n_read)
+    }
+}
+
+/// The number of chunks a [`DeadlineReader`] reads ahead of what has been
+/// taken from it, after which its thread waits before reading any more
+#[cfg(feature = "process")]
+const READ_AHEAD: usize = 16;
+
+/// How often the epoch of the engine running a WebAssembly shepherd is
+/// advanced, which bounds how far past its request timeout it may run
+#[cfg(feature = "wasm")]
+const WASM_TICK: Duration = Duration::from_millis(10);
+
+/// The memory a WebAssembly shepherd may grow to when it is given no limit
+/// of its own, in bytes (see [`Shepherd::wasm`])
+#[cfg(feature = "wasm")]
+pub const DEFAULT_WASM_MEMORY_LIMIT: u64 = 1 << 30;
+
+/// A reader which reads from another on a thread of its own, so that reading
+/// can give up once a deadline has passed
+///
+/// The thread only reads up to [`READ_AHEAD`] chunks ahead, so a shepherd
+/// flooding its output fills the pipe between them rather than the
+/// simulation's memory, and is stopped by its output quota (see
+/// [`QuotaReader`]) once the flood is read. The deadline is shared with the
+/// owning [`Shepherd`], which sets it before each request. A deadline of
+/// [`None`] never passes
+#[cfg(feature = "process")]
+struct DeadlineReader {
+    chunks: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: VecDeque<u8>,
+    deadline: Rc<Cell<Option<Instant>>>,
+}
+
+#[cfg(feature = "process")]
+impl DeadlineReader {
+    /// Start reading from the given reader on a new thread
+    fn spawn(
+        mut inner: impl Read + Send + 'static,
+        deadline: Rc<Cell<Option<Instant>>>,
+    ) -> Self {
+        let (sender, chunks) = mpsc::sync_channel(READ_AHEAD);
+        thread::spawn(move || {
+            let mut buffer = [0; 8192];
+            loop {
+                let chunk = inner
+                    .read(&mut buffer)
+                    .map(|n_read| buffer[..n_read].to_vec());
+                // an empty chunk marks the end of the stream
+                let done = !matches!(&chunk, Ok(chunk) if !chunk.is_empty());
+                if sender.send(chunk).is_err() || done {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            chunks,
+            chunk: VecDeque::new(),
+            deadline,
+        }
+    }
+}
+
+#[cfg(feature = "process")]
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.chunk.is_empty() {
+            let chunk = match self.deadline.get() {
+                Some(deadline) => match self.chunks.recv_timeout(
+                    deadline.saturating_duration_since(Instant::now()),
+                ) {
+                    Ok(chunk) => chunk,
+                    Err(RecvTimeoutError::Timeout) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "The shepherd did not respond in time",
+                        ))
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return Ok(0),
+                },
+                None => match self.chunks.recv() {
+                    Ok(chunk) => chunk,
+                    Err(_) => return Ok(0),
+                },
+            };
+            self.chunk.extend(chunk?);
+        }
+
+        self.chunk.read(buf)
+    }
+}
+
+/// The input half of a shepherd created by [`Shepherd::from_handler`]
+///
+/// Each event is passed to the handler once it is flushed, and any response
+/// is queued for [`QueueOutput`] to read
+struct HandlerInput<H> {
+    handler: H,
+    justified: bool,
+    buffer: Vec<u8>,
+    output: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl<H: Handler> Write for HandlerInput<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let events = serde_json::Deserializer::from_slice(&self.buffer)
+            .into_iter::<SimulationEvent>()
+            .collect::<Result<Vec<_>, _>>()?;
+        self.buffer.clear();
+
+        let mut queue = self.output.borrow_mut();
+        let mut output = EventWriter::new(&mut *queue);
+        for event in events {
+            let response = match event {
+                SimulationEvent::Hello { .. } => {
+                    let capabilities = self.handler.capabilities();
+                    self.justified =
+                        capabilities.contains(&Capability::JustifiedFeeds);
+                    Some(ShepherdEvent::Hello {
+                        protocol_version: PROTOCOL_VERSION,
+                        capabilities,
+                        payloads: self.handler.payloads(),
+                        encoding: Encoding::Json,
+                    })
+                }
+                SimulationEvent::Ping => Some(ShepherdEvent::Pong),
+                event => {
+                    sdk::respond(&mut self.handler, event, self.justified)
+                        .map_err(io::Error::other)?
+                }
+            };
+
+            if let Some(response) = response {
+                output.write_event(&response).map_err(io::Error::other)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The input half of a shepherd created by [`Shepherd::wasm`]
+///
+/// Each event is passed to the module once it is flushed, and any response is
+/// queued for [`QueueOutput`] to read
+///
+/// A call into the module is interrupted once the deadline shared with the
+/// owning [`Shepherd`] has passed, which it sets before each request
+#[cfg(feature = "wasm")]
+struct WasmInput {
+    store: wasmtime::Store<WasmState>,
+    memory: wasmtime::Memory,
+    alloc: wasmtime::TypedFunc<u32, u32>,
+    handle: wasmtime::TypedFunc<(u32, u32), u64>,
+    buffer: Vec<u8>,
+    output: Rc<RefCell<VecDeque<u8>>>,
+    deadline: Rc<Cell<Option<Instant>>>,
+}
+
+/// The state kept in the store of a WebAssembly shepherd
+#[cfg(feature = "wasm")]
+struct WasmState {
+    limits: wasmtime::StoreLimits,
+    deadline: Option<Instant>,
+}
+
+/// Convert an error raised by a call into a WebAssembly shepherd into an
+/// [`io::Error`], telling a call interrupted by its deadline apart
+#[cfg(feature = "wasm")]
+fn wasm_error(error: wasmtime::Error) -> io::Error {
+    if error.downcast_ref::<wasmtime::Trap>()
+        == Some(&wasmtime::Trap::Interrupt)
+    {
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            "The shepherd module ran past its request timeout",
+        )
+    } else {
+        io::Error::other(error)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl Write for WasmInput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        self.store.data_mut().deadline = self.deadline.get();
+        self.store.set_epoch_deadline(1);
+
+        let length =
+            u32::try_from(self.buffer.len()).map_err(io::Error::other)?;
+        let event = self
+            .alloc
+            .call(&mut self.store, length)
+            .map_err(wasm_error)?;
+        self.memory
+            .write(&mut self.store, event as usize, &self.buffer)
+            .map_err(io::Error::other)?;
+        self.buffer.clear();
+
+        let response = self
+            .handle
+            .call(&mut self.store, (event, length))
+            .map_err(wasm_error)?;
+        let start = (response >> 32) as usize;
+        let end = start + (response & u64::from(u32::MAX)) as usize;
+        let response =
+            self.memory.data(&self.store).get(start..end).ok_or_else(|| {
+                io::Error::other(
+                    "The shepherd module responded from outside of its memory",
+                )
+            })?;
+        self.output.borrow_mut().extend(response);
+
+        Ok(())
+    }
+}
+
+/// The output half of the shepherds created by [`Shepherd::from_handler`]
+/// and [`Shepherd::wasm`], which reads the responses they have queued
+struct QueueOutput(Rc<RefCell<VecDeque<u8>>>);
+
+impl Read for QueueOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+/// A wrapper around a feed algorithm which communicates over a pair of
+/// streams, usually the standard input and output of a child process
+pub struct Shepherd {
     name: String,
-    process: Child,
-    stdin: ChildStdin,
-    stdout: serde_json::StreamDeserializer<
-        'de,
-        serde_json::de::IoRead<ChildStdout>,
-        ShepherdEvent,
-    >,
+    capabilities: HashSet<Capability>,
+    payloads: HashSet<Payload>,
+    encodings: &'static [Encoding],
+    compute_time: Duration,
+    feed_latencies: Vec<Duration>,
+    #[cfg(feature = "process")]
+    process: Option<Child>,
+    #[cfg(feature = "process")]
+    label: Option<Arc<Mutex<String>>>,
+    input: EventWriter<Box<dyn Write>>,
+    buffered: bool,
+    output_quota: Option<usize>,
+    remaining_output: Rc<Cell<Option<usize>>>,
+    request_timeout: Option<Duration>,
+    heartbeat: Option<Heartbeat>,
+    #[cfg(feature = "process")]
+    deadline: Rc<Cell<Option<Instant>>>,
+    #[cfg(feature = "wasm")]
+    wasm_deadline: Rc<Cell<Option<Instant>>>,
+    next_request_id: RequestId,
+    unanswered_requests: HashSet<RequestId>,
+    #[allow(clippy::type_complexity)]
+    prefetched_feeds:
+        HashMap<SheepId, Option<(Feed, Vec<(ItemId, Justification)>)>>,
+    abandoned: bool,
+    transcript: Option<EventWriter<Box<dyn Write>>>,
+    output: EventReader<BufReader<QuotaReader>, ShepherdEvent>,
 }
 
-impl Shepherd<'_> {
-    /// Create a new [`Shepherd`] from a command name or path
-    pub fn new(program: impl AsRef<OsStr>) -> anyhow::Result<Self> {
-        let name = Path::new(&program)
+impl Shepherd {
+    /// Create a new [`Shepherd`] which is sent events through `input` and
+    /// reads its responses from `output`
+    ///
+    /// This allows for shepherds which run in the same process as the
+    /// simulation, such as on targets where child processes are unavailable
+    pub fn from_streams(
+        name: impl Into<String>,
+        input: impl Write + 'static,
+        output: impl Read + 'static,
+    ) -> Self {
+        let remaining_output = Rc::new(Cell::new(None));
+        Self {
+            name: name.into(),
+            capabilities: HashSet::default(),
+            payloads: HashSet::default(),
+            encodings: &Encoding::ALL,
+            compute_time: Duration::ZERO,
+            feed_latencies: Vec::new(),
+            #[cfg(feature = "process")]
+            process: None,
+            #[cfg(feature = "process")]
+            label: None,
+            input: EventWriter::new(Box::new(input)),
+            buffered: false,
+            output_quota: None,
+            remaining_output: Rc::clone(&remaining_output),
+            request_timeout: None,
+            heartbeat: None,
+            #[cfg(feature = "process")]
+            deadline: Rc::new(Cell::new(None)),
+            #[cfg(feature = "wasm")]
+            wasm_deadline: Rc::new(Cell::new(None)),
+            next_request_id: RequestId::default(),
+            unanswered_requests: HashSet::default(),
+            prefetched_feeds: HashMap::default(),
+            abandoned: false,
+            transcript: None,
+            output: EventReader::new(BufReader::new(QuotaReader {
+                inner: Box::new(output),
+                remaining: remaining_output,
+            })),
+        }
+    }
+
+    /// Create a new [`Shepherd`] like [`Shepherd::from_streams`], but reading
+    /// from `output` on a thread of its own so that requests can time out
+    ///
+    /// Events written to `input` are buffered, and are only sent once the
+    /// shepherd is flushed or waited on (see [`Shepherd::flush`])
+    #[cfg(feature = "process")]
+    fn from_threaded_streams(
+        name: impl Into<String>,
+        input: impl Write + 'static,
+        output: impl Read + Send + 'static,
+    ) -> Self {
+        let deadline = Rc::new(Cell::new(None));
+        Self {
+            buffered: true,
+            deadline: Rc::clone(&deadline),
+            ..Self::from_streams(
+                name,
+                BufWriter::new(input),
+                DeadlineReader::spawn(output, deadline),
+            )
+        }
+    }
+
+    /// Create a new [`Shepherd`] running a [`Handler`] in the same process as
+    /// the simulation
+    ///
+    /// The handler is driven just as [`sdk::run`] would drive it, except that
+    /// events are always encoded as JSON and are handled as soon as they are
+    /// sent, so any error it returns is taken as the shepherd failing to
+    /// receive the event
+    pub fn from_handler(
+        name: impl Into<String>,
+        handler: impl Handler + 'static,
+    ) -> Self {
+        let output = Rc::new(RefCell::new(VecDeque::new()));
+        Self::from_streams(
+            name,
+            HandlerInput {
+                handler,
+                justified: false,
+                buffer: Vec::new(),
+                output: Rc::clone(&output),
+            },
+            QueueOutput(output),
+        )
+    }
+
+    /// Create a new [`Shepherd`] from a WebAssembly module, which runs in the
+    /// same process as the simulation without access to anything outside of
+    /// its own memory
+    ///
+    /// The module must export its `memory`, an `alloc` function which takes a
+    /// length in bytes and returns a pointer to that much free memory, and a
+    /// `handle` function which takes the pointer and length of an event. The
+    /// result of `handle` holds the pointer to the module's response in its
+    /// upper 32 bits and the response's length in its lower 32 bits, with a
+    /// length of zero if there is no response. Events and responses are
+    /// framed as described by [`Encoding`]. As the module is given no
+    /// imports, it has no clock or source of randomness, so it runs
+    /// deterministically
+    ///
+    /// The module's memory may not grow past `memory_limit` bytes, or
+    /// [`DEFAULT_WASM_MEMORY_LIMIT`] if it is given no limit. A module which
+    /// runs past its request timeout (see [`Shepherd::set_request_timeout`])
+    /// is interrupted and abandoned, as it may have been stopped partway
+    /// through changing its memory
+    #[cfg(feature = "wasm")]
+    pub fn wasm(
+        path: impl AsRef<Path>,
+        memory_limit: Option<u64>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let name = path
             .file_name()
-            .ok_or(anyhow!(
-                "No file name was present in the shepherd's executable path"
-            ))?
+            .context(
+                "No file name was present in the shepherd module's path",
+            )?
             .to_string_lossy()
             .into_owned();
-        let mut process = Command::new(program)
+
+        let engine = wasmtime::Engine::new(
+            wasmtime::Config::new().epoch_interruption(true),
+        )
+        .context("Unable to set up the WebAssembly engine")?;
+        let module = wasmtime::Module::from_file(&engine, path)
+            .context("Unable to load the shepherd module")?;
+
+        // the engine's epoch is advanced on a thread of its own for as long
+        // as the shepherd's store holds onto the engine
+        let ticker = engine.weak();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(WASM_TICK);
+            match ticker.upgrade() {
+                Some(engine) => engine.increment_epoch(),
+                None => break,
+            }
+        });
+
+        let memory_limit = memory_limit.unwrap_or(DEFAULT_WASM_MEMORY_LIMIT);
+        let mut store = wasmtime::Store::new(
+            &engine,
+            WasmState {
+                limits: wasmtime::StoreLimitsBuilder::new()
+                    .memory_size(
+                        usize::try_from(memory_limit).unwrap_or(usize::MAX),
+                    )
+                    .build(),
+                deadline: None,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        // the deadline is checked on every tick, and is only extended until
+        // it has passed
+        store.epoch_deadline_callback(|store| {
+            Ok(match store.data().deadline {
+                Some(deadline) if Instant::now() >= deadline => {
+                    wasmtime::UpdateDeadline::Interrupt
+                }
+                _ => wasmtime::UpdateDeadline::Continue(1),
+            })
+        });
+        store.set_epoch_deadline(1);
+        let instance = wasmtime::Instance::new(&mut store, &module, &[])
+            .context("Unable to instantiate the shepherd module")?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .context("The shepherd module does not export its memory")?;
+        let alloc = instance
+            .get_typed_func(&mut store, "alloc")
+            .context("The shepherd module does not export alloc")?;
+        let handle = instance
+            .get_typed_func(&mut store, "handle")
+            .context("The shepherd module does not export handle")?;
+
+        let output = Rc::new(RefCell::new(VecDeque::new()));
+        let deadline = Rc::new(Cell::new(None));
+        Ok(Self {
+            wasm_deadline: Rc::clone(&deadline),
+            ..Self::from_streams(
+                name,
+                WasmInput {
+                    store,
+                    memory,
+                    alloc,
+                    handle,
+                    buffer: Vec::new(),
+                    output: Rc::clone(&output),
+                    deadline,
+                },
+                QueueOutput(output),
+            )
+        })
+    }
+
+    /// Create a new [`Shepherd`] connected to a service running elsewhere
+    /// over gRPC, at an endpoint such as `http://HOST:PORT`
+    ///
+    /// The service must implement the `Shepherd` service defined in
+    /// `proto/shepherd.proto`, which carries the same events as any other
+    /// transport as strongly typed messages. As these have a fixed encoding,
+    /// JSON is the only [`Encoding`] offered to the shepherd, which is what
+    /// the events are converted from and to on the simulation's side
+    #[cfg(feature = "grpc")]
+    pub fn connect_grpc(endpoint: impl Into<String>) -> anyhow::Result<Self> {
+        let endpoint = endpoint.into();
+        let (input, output) = grpc::connect(endpoint.clone())?;
+        Ok(Self {
+            encodings: &[Encoding::Json],
+            ..Self::from_streams(endpoint, input, output)
+        })
+    }
+
+    /// Create a new [`Shepherd`] from a command name or path
+    ///
+    /// Instead of a command, this may be given the address of a shepherd
+    /// which is already running as a service, either as `tcp://HOST:PORT` or
+    /// (on unix) as `unix:///PATH`, in which case events are exchanged over a
+    /// socket connected to it rather than with a new process
+    #[cfg(feature = "process")]
+    pub fn new(program: impl AsRef<OsStr>) -> anyhow::Result<Self> {
+        if let Some(address) = program.as_ref().to_str() {
+            if let Some(host) = address.strip_prefix("tcp://") {
+                let stream = TcpStream::connect(host)
+                    .context("Unable to connect to the shepherd")?;
+                stream
+                    .set_nodelay(true)
+                    .context("Unable to configure the shepherd's socket")?;
+                let output = stream
+                    .try_clone()
+                    .context("Unable to split the shepherd's socket")?;
+                return Ok(Self::from_threaded_streams(
+                    address, stream, output,
+                ));
+            }
+
+            #[cfg(unix)]
+            if let Some(path) = address.strip_prefix("unix://") {
+                let stream = UnixStream::connect(path)
+                    .context("Unable to connect to the shepherd")?;
+                let output = stream
+                    .try_clone()
+                    .context("Unable to split the shepherd's socket")?;
+                return Ok(Self::from_threaded_streams(
+                    address, stream, output,
+                ));
+            }
+        }
+
+        Self::with_args(program, iter::empty::<&OsStr>())
+    }
+
+    /// Create a new [`Shepherd`] from a command name or path, which is run
+    /// with the given arguments
+    #[cfg(feature = "process")]
+    pub fn with_args(
+        program: impl AsRef<OsStr>,
+        args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    ) -> anyhow::Result<Self> {
+        Self::command(program).args(args).spawn()
+    }
+
+    /// Start building a [`Shepherd`] from a command name or path, whose
+    /// arguments, environment and working directory can be configured
+    /// before it is spawned with [`ShepherdCommand::spawn`]
+    #[cfg(feature = "process")]
+    pub fn command(program: impl AsRef<OsStr>) -> ShepherdCommand {
+        ShepherdCommand {
+            program: program.as_ref().to_os_string(),
+            command: Command::new(program),
+            limits: ResourceLimits::default(),
+        }
+    }
+
+    /// Spawn a configured command as a [`Shepherd`] with the given name,
+    /// placing the given limits on its resources
+    #[cfg(feature = "process")]
+    fn spawn(
+        name: String,
+        mut command: Command,
+        limits: ResourceLimits,
+    ) -> anyhow::Result<Self> {
+        limits.prepare(&mut command)?;
+        let mut process = command
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
             .spawn()
             .context("Unable to spawn a shepherd process")?;
 
+        #[cfg(all(windows, feature = "job-objects"))]
+        if let Err(error) = limits.enforce(process.as_raw_handle()) {
+            let _ = process.kill();
+            return Err(error);
+        }
         let stdin = process.stdin.take().context(
             "Unable to extract the stdin handle from the shepherd process",
         )?;
         let stdout = process.stdout.take().context(
             "Unable to extract the stdout handle from the shepherd process",
         )?;
+        let stderr = process.stderr.take().context(
+            "Unable to extract the stderr handle from the shepherd process",
+        )?;
+
+        // the shepherd's diagnostics are passed on line by line, so that
+        // they can be told apart from those of the simulation and of any
+        // other shepherds
+        let label = Arc::new(Mutex::new(name.clone()));
+        let shepherd = Arc::clone(&label);
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).split(b'\n') {
+                let Ok(line) = line else {
+                    break;
+                };
+                let name =
+                    shepherd.lock().unwrap_or_else(PoisonError::into_inner);
+                info!(
+                    shepherd = %name,
+                    "{}",
+                    String::from_utf8_lossy(&line).trim_end()
+                );
+            }
+        });
 
         Ok(Self {
-            name,
-            process,
-            stdin,
-            stdout: serde_json::Deserializer::from_reader(stdout).into_iter(),
+            process: Some(process),
+            label: Some(label),
+            ..Self::from_threaded_streams(name, stdin, stdout)
         })
     }
 
+    /// Give this [`Shepherd`] a name of its own in place of the one it was
+    /// assigned when it was created
+    ///
+    /// This is useful for telling apart several instances of the same
+    /// shepherd, as the name is used everywhere the shepherd is reported on
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        #[cfg(feature = "process")]
+        if let Some(label) = &self.label {
+            *label.lock().unwrap_or_else(PoisonError::into_inner) =
+                self.name.clone();
+        }
+
+        self
+    }
+
     /// Get the [`Shepherd`]'s assigned name
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    /// Stop the [`Shepherd`]'s underlying process
+    /// Check whether this [`Shepherd`] declared support for the given
+    /// [`Capability`] during the handshake
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Check whether this [`Shepherd`] requested the given [`Payload`]
+    /// during the handshake
+    pub fn wants(&self, payload: Payload) -> bool {
+        self.payloads.contains(&payload)
+    }
+
+    /// Get the [`Encoding`] of the events exchanged with this [`Shepherd`]
+    pub fn encoding(&self) -> Encoding {
+        self.input.encoding()
+    }
+
+    /// Exchange greetings with this [`Shepherd`], which must happen before
+    /// any other event is sent to it
+    ///
+    /// The simulation announces the [`PROTOCOL_VERSION`] it speaks, every
+    /// [`Capability`] it supports, every [`Payload`] it can include and
+    /// every [`Encoding`] it can use, and the shepherd is expected to respond
+    /// with a greeting of its own. Shepherds which respond with anything
+    /// else, close their output, or speak another version of the protocol
+    /// are rejected, as are those which choose an encoding which was not
+    /// offered to them. The capabilities the shepherd declares and the
+    /// payloads it requests are recorded, and can be checked with
+    /// [`Shepherd::supports`] and [`Shepherd::wants`], and every later event
+    /// is exchanged in the encoding it chooses
+    #[instrument(skip_all, fields(shepherd = %self.name))]
+    pub fn greet(&mut self) -> anyhow::Result<()> {
+        self.remaining_output.set(self.output_quota);
+        // the handshake is always in json, as no encoding has been chosen
+        let hello = SimulationEvent::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: Capability::ALL.to_vec(),
+            payloads: Payload::ALL.to_vec(),
+            encodings: self.encodings.to_vec(),
+        };
+        self.record(RecordedEvent::Sent(&hello));
+        self.input
+            .write_event(&hello)
+            .context("Unable to greet the shepherd")?;
+
+        let greeting = self
+            .receive()
+            .context("Unable to read the greeting of the shepherd")?
+            .context("The shepherd stopped before greeting the simulation")?;
+        let (capabilities, payloads, encoding) = accept_greeting(greeting)?;
+        if !self.encodings.contains(&encoding) {
+            bail!(
+                "The shepherd chose an encoding which was not offered to it"
+            );
+        }
+
+        self.capabilities = capabilities.into_iter().collect();
+        self.payloads = payloads.into_iter().collect();
+        self.input.set_encoding(encoding);
+        self.output.set_encoding(encoding);
+        Ok(())
+    }
+
+    /// Get the total time this [`Shepherd`] has spent building feeds
+    ///
+    /// This is measured as the wall-clock time between requesting a feed and
+    /// receiving it, summed over every feed requested so far. As there is no
+    /// clock to measure it with on `wasm32` targets, it is always zero there
+    pub fn compute_time(&self) -> Duration {
+        self.compute_time
+    }
+
+    /// Take the time this [`Shepherd`] spent building each feed requested
+    /// since this was last called, in the order they were requested
+    ///
+    /// As with [`Shepherd::compute_time`], this is wall-clock time, and
+    /// requests which timed out are counted up to their timeout. Feeds
+    /// fetched together by [`Shepherd::prefetch_feeds`] are each counted as
+    /// an equal share of the time the batch took, and a feed built in two
+    /// stages is counted as the time both stages took
+    pub fn take_feed_latencies(&mut self) -> Vec<Duration> {
+        mem::take(&mut self.feed_latencies)
+    }
+
+    /// Set the maximum number of bytes read from this [`Shepherd`] in
+    /// response to a single request
+    ///
+    /// Reading past the quota is treated as a protocol violation, which
+    /// protects the simulator from a shepherd flooding its output, and the
+    /// shepherd is abandoned. A quota of [`None`] is unlimited
+    pub fn set_output_quota(&mut self, quota: Option<usize>) {
+        self.output_quota = quota;
+    }
+
+    /// Set the maximum time to wait for this [`Shepherd`] to respond to a
+    /// request for a feed
+    ///
+    /// Only shepherds running in a process of their own, connected to over a
+    /// socket, or created from a WebAssembly module can time out, as the
+    /// others run in the simulation's own thread. A timeout of [`None`] is
+    /// unlimited. See [`Shepherd::build_feed`] for what happens when a
+    /// request times out, and [`Shepherd::wasm`] for modules which do.
+    /// Shepherds exchanging events in any [`Encoding`] other than JSON which
+    /// time out partway through sending an event are abandoned, as the rest
+    /// of the event can't be told apart from whatever they send next
+    pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.request_timeout = timeout;
+    }
+
+    /// Set how often this [`Shepherd`] is pinged while a request to it is
+    /// outstanding, and how many pings it may miss
+    ///
+    /// This lets a shepherd which is busy be told apart from one which has
+    /// hung without waiting out its request timeout. A shepherd which misses
+    /// too many pings is treated as though the request timed out. Only
+    /// shepherds which support [`Capability::Heartbeats`], exchange events in
+    /// JSON, and run in a process of their own or are connected to over a
+    /// socket are pinged. A heartbeat of [`None`] disables pings
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.heartbeat = heartbeat;
+    }
+
+    /// Record every event exchanged with this [`Shepherd`] from now on in a
+    /// transcript written to the given writer
+    ///
+    /// Transcripts are always written in JSON, as described by
+    /// [`TranscriptEvent`], and hold events exactly as they were sent
+    /// (without any [`Payload`] the shepherd did not request)
+    pub fn record_transcript(&mut self, writer: impl Write + 'static) {
+        self.transcript = Some(EventWriter::new(Box::new(writer)));
+    }
+
+    /// Write an event to this [`Shepherd`]'s transcript, if it has one
+    fn record(&mut self, event: RecordedEvent<'_>) {
+        if let Some(transcript) = &mut self.transcript {
+            transcript
+                .write_event(&event)
+                .expect("Unable to record an event in the transcript");
+        }
+    }
+
+    /// Read the next event from this [`Shepherd`]'s output, recording it in
+    /// its transcript
+    fn receive(&mut self) -> anyhow::Result<Option<ShepherdEvent>> {
+        let event = self.output.read_event()?;
+        if let Some(event) = &event {
+            self.record(RecordedEvent::Received(event));
+        }
+
+        Ok(event)
+    }
+
+    /// Give up on this [`Shepherd`], killing its underlying process if it has
+    /// one
+    ///
+    /// No more events are sent to an abandoned shepherd, and it is no longer
+    /// asked for feeds. This is intended for shepherds which have stopped
+    /// responding
+    pub fn abandon(&mut self) {
+        #[cfg(feature = "process")]
+        if let Some(process) = &mut self.process {
+            // the process may well have exited already, which is fine
+            let _ = process.kill();
+        }
+
+        self.abandoned = true;
+    }
+
+    /// Abandon this [`Shepherd`] after it failed to keep to the protocol,
+    /// such as by closing its output or sending something unreadable
+    fn fail(&mut self, error: &anyhow::Error) {
+        warn!(
+            shepherd = %self.name,
+            error = %format!("{error:#}"),
+            "the shepherd failed, and has been abandoned"
+        );
+        self.abandon();
+    }
+
+    /// Check whether this [`Shepherd`] has been abandoned
+    pub fn is_abandoned(&self) -> bool {
+        self.abandoned
+    }
+
+    /// Stop the [`Shepherd`], sending it [`SimulationEvent::Shutdown`] so
+    /// that it can save any state it keeps
+    ///
+    /// An underlying process is given up to [`SHUTDOWN_TIMEOUT`] to exit
+    /// before it is killed. Shepherds connected to over a socket are only
+    /// disconnected from, and are left running
     pub fn stop(mut self) -> anyhow::Result<()> {
-        self.process
-            .kill()
-            .context("Unable to stop the shepherd process")
+        if !self.abandoned {
+            // the shepherd may have exited already, in which case there is
+            // nobody left to tell
+            self.record(RecordedEvent::Sent(&SimulationEvent::Shutdown));
+            let _ = self.input.write_event(&SimulationEvent::Shutdown);
+        }
+
+        #[cfg(feature = "process")]
+        if let Some(mut process) = self.process {
+            // closing its standard input also lets a shepherd which reads
+            // events until they run out know that it is done
+            drop(self.input);
+
+            let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+            while Instant::now() < deadline {
+                if process
+                    .try_wait()
+                    .context("Unable to wait for the shepherd process")?
+                    .is_some()
+                {
+                    return Ok(());
+                }
+
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            warn!(
+                shepherd = %self.name,
+                "the shepherd did not exit after being shut down"
+            );
+            process
+                .kill()
+                .context("Unable to stop the shepherd process")?;
+        }
+
+        Ok(())
     }
 
     /// Write an arbitrary [`SimulationEvent`] to this [`Shepherd`]'s
     /// standard input
+    ///
+    /// Any [`Payload`] the shepherd did not request is left out of the
+    /// event, and events made up entirely of one are not sent at all.
+    /// Shepherds running in a process of their own or connected to over a
+    /// socket buffer the events written to them, which are sent once
+    /// [`Shepherd::flush`] is called or a feed is requested. A shepherd
+    /// which can't be passed the event is abandoned
+    #[instrument(level = "debug", skip_all, fields(shepherd = %self.name))]
     pub fn write_event(&mut self, event: &SimulationEvent) {
-        serde_json::to_writer(&self.stdin, event)
-            .expect("Unable to pass an event to the shepherd process")
+        if self.abandoned {
+            return;
+        }
+
+        let Some(event) = requested_part(&self.payloads, event) else {
+            return;
+        };
+
+        self.record(RecordedEvent::Sent(&event));
+        let written = if self.buffered {
+            self.input.buffer_event(&event)
+        } else {
+            self.input.write_event(&event)
+        };
+        if let Err(error) = written {
+            self.fail(
+                &error.context("Unable to pass an event to the shepherd"),
+            );
+        }
+    }
+
+    /// Write several [`SimulationEvent`]s to this [`Shepherd`]'s standard
+    /// input at once, flushing them afterwards
+    ///
+    /// This is equivalent to calling [`Shepherd::write_event`] for each of
+    /// the events followed by [`Shepherd::flush`]
+    pub fn write_events(&mut self, events: &[SimulationEvent]) {
+        for event in events {
+            self.write_event(event);
+        }
+
+        self.flush();
+    }
+
+    /// Send every event written to this [`Shepherd`] that is still buffered
+    ///
+    /// This happens on its own before waiting on the shepherd for anything,
+    /// so it only needs to be called to let the shepherd get on with events
+    /// which don't call for a response. A shepherd which can't be passed the
+    /// events is abandoned
+    pub fn flush(&mut self) {
+        if self.abandoned {
+            return;
+        }
+
+        if let Err(error) = self.input.flush() {
+            self.fail(
+                &error.context("Unable to pass an event to the shepherd"),
+            );
+        }
     }
 
     /// Read the next [`ShepherdEvent`] from this [`Shepherd`]'s
-    /// standard output
-    pub fn read_event(&mut self) -> ShepherdEvent {
-        self.stdout
-            .next()
-            .expect("Unable to read an event from the shepherd process")
-            .expect("Unable to read an event from the shepherd process")
+    /// standard output, flushing any events written to it beforehand
+    ///
+    /// A shepherd which closes its output or sends something which can't be
+    /// read is abandoned, and an error is returned, as it is for a shepherd
+    /// which has already been abandoned
+    #[instrument(level = "debug", skip_all, fields(shepherd = %self.name))]
+    pub fn read_event(&mut self) -> anyhow::Result<ShepherdEvent> {
+        self.flush();
+        if self.abandoned {
+            bail!("The shepherd has been abandoned");
+        }
+
+        let event = self.receive().and_then(|event| {
+            event.context("The shepherd stopped before sending an event")
+        });
+        if let Err(error) = &event {
+            self.fail(error);
+        }
+
+        event
     }
 
     /// Request that this [`Shepherd`] build a feed for the specified sheep
-    /// and wait for it to return the feed
-    pub fn build_feed(&mut self, sheep: SheepId) -> Feed {
-        self.write_event(&SimulationEvent::FeedRequest { sheep });
-        match self.read_event() {
-            ShepherdEvent::Feed(feed) => feed,
+    /// and wait for it to return the feed, along with the justifications it
+    /// gave for any of its items
+    ///
+    /// If the shepherd has been abandoned, or does not respond within its
+    /// request timeout, [`None`] is returned instead. The response to a
+    /// request which timed out is discarded whenever it arrives, so it is
+    /// never mistaken for the response to a later one. A shepherd which
+    /// closes its output or sends something which can't be read in place of
+    /// a response is abandoned, and [`None`] is returned for it as well
+    ///
+    /// Feeds fetched ahead of time by [`Shepherd::prefetch_feeds`] are
+    /// returned without waiting on the shepherd again
+    #[instrument(skip_all, fields(shepherd = %self.name, sheep = sheep.0))]
+    pub fn build_feed(
+        &mut self,
+        sheep: SheepId,
+    ) -> Option<(Feed, Vec<(ItemId, Justification)>)> {
+        if self.abandoned {
+            return None;
         }
+
+        if let Some(feed) = self.prefetched_feeds.remove(&sheep) {
+            return feed;
+        }
+
+        let request_id = self.new_request_id();
+        let start = self.compute_time;
+        let event = self.request(
+            request_id,
+            &SimulationEvent::FeedRequest { request_id, sheep },
+            1,
+        );
+        self.feed_latencies.push(self.compute_time - start);
+        self.expect_feed(event?)
+    }
+
+    /// Request that this [`Shepherd`] build feeds for every one of the
+    /// specified sheep at once, to be returned by [`Shepherd::build_feed`]
+    ///
+    /// This saves a round trip per sheep, and does nothing for shepherds
+    /// which have been abandoned or do not support
+    /// [`Capability::FeedBatches`]. The request timeout and output quota of
+    /// the shepherd are multiplied by the number of sheep, and every feed in
+    /// a batch which is not returned in time is considered to have timed out
+    #[instrument(skip_all, fields(shepherd = %self.name))]
+    pub fn prefetch_feeds(&mut self, sheep: Vec<SheepId>) {
+        if self.abandoned
+            || sheep.is_empty()
+            || !self.supports(Capability::FeedBatches)
+        {
+            return;
+        }
+
+        let n_sheep = sheep.len();
+        let request_id = self.new_request_id();
+        let event = SimulationEvent::FeedRequestBatch {
+            request_id,
+            sheep: sheep.clone(),
+        };
+        let start = self.compute_time;
+        let response = self.request(request_id, &event, n_sheep);
+        let latency = (self.compute_time - start)
+            / u32::try_from(n_sheep).unwrap_or(u32::MAX);
+        self.feed_latencies.extend(iter::repeat_n(latency, n_sheep));
+        let feeds = match response {
+            Some(ShepherdEvent::FeedBatch { feeds, .. }) => feeds
+                .into_iter()
+                .map(|feed| Some((feed, Vec::new())))
+                .collect::<Vec<_>>(),
+            Some(ShepherdEvent::JustifiedFeedBatch { feeds, .. }) => feeds
+                .into_iter()
+                .map(|items| Some(split_justifications(items)))
+                .collect(),
+            Some(_) => {
+                self.fail(&anyhow!(
+                    "The shepherd sent something other than a batch of feeds"
+                ));
+                return;
+            }
+            None => iter::repeat_with(|| None).take(n_sheep).collect(),
+        };
+        if feeds.len() != n_sheep {
+            self.fail(&anyhow!(
+                "The shepherd built {} feeds in response to a request for {}",
+                feeds.len(),
+                n_sheep
+            ));
+            return;
+        }
+
+        self.prefetched_feeds.extend(sheep.into_iter().zip(feeds));
+    }
+
+    /// Check whether a feed has been fetched ahead of time for the specified
+    /// sheep by [`Shepherd::prefetch_feeds`], and not yet been built
+    pub fn has_prefetched_feed(&self, sheep: SheepId) -> bool {
+        self.prefetched_feeds.contains_key(&sheep)
+    }
+
+    /// Request that this [`Shepherd`] retrieve up to `n_candidates`
+    /// candidate items for the feed of the specified sheep, which is the
+    /// first stage of building a feed in two stages
+    ///
+    /// This is only meaningful for shepherds which support
+    /// [`Capability::TwoStageFeeds`], and the candidates are expected to be
+    /// ranked by [`Shepherd::rank_candidates`] afterwards. If the shepherd
+    /// has been abandoned, or does not respond within its request timeout,
+    /// [`None`] is returned instead
+    #[instrument(skip_all, fields(shepherd = %self.name, sheep = sheep.0))]
+    pub fn retrieve_candidates(
+        &mut self,
+        sheep: SheepId,
+        n_candidates: usize,
+    ) -> Option<Vec<ItemId>> {
+        if self.abandoned {
+            return None;
+        }
+
+        let request_id = self.new_request_id();
+        let start = self.compute_time;
+        let event = self.request(
+            request_id,
+            &SimulationEvent::RetrieveCandidates {
+                request_id,
+                sheep,
+                n_candidates,
+            },
+            1,
+        );
+        self.feed_latencies.push(self.compute_time - start);
+        if let ShepherdEvent::Candidates { items, .. } = event? {
+            return Some(items);
+        }
+
+        self.fail(&anyhow!(
+            "The shepherd sent something other than candidates"
+        ));
+        None
+    }
+
+    /// Get the feed in a response from this [`Shepherd`], abandoning it if
+    /// the response is anything else
+    fn expect_feed(
+        &mut self,
+        event: ShepherdEvent,
+    ) -> Option<(Feed, Vec<(ItemId, Justification)>)> {
+        into_feed(event).map_err(|error| self.fail(&error)).ok()
+    }
+
+    /// Request that this [`Shepherd`] build the feed of the specified sheep
+    /// out of the given candidates, which is the second stage of building a
+    /// feed in two stages, and wait for it to return the feed along with the
+    /// justifications it gave for any of its items
+    ///
+    /// This behaves like [`Shepherd::build_feed`] otherwise, except that it
+    /// never returns a feed fetched ahead of time
+    #[instrument(skip_all, fields(shepherd = %self.name, sheep = sheep.0))]
+    pub fn rank_candidates(
+        &mut self,
+        sheep: SheepId,
+        candidates: Vec<ItemId>,
+    ) -> Option<(Feed, Vec<(ItemId, Justification)>)> {
+        if self.abandoned {
+            return None;
+        }
+
+        let request_id = self.new_request_id();
+        let start = self.compute_time;
+        let event = self.request(
+            request_id,
+            &SimulationEvent::RankCandidates {
+                request_id,
+                sheep,
+                candidates,
+            },
+            1,
+        );
+
+        // ranking finishes the feed whose candidates were retrieved last, so
+        // the time it took is added onto the time retrieving them took
+        let latency = self.compute_time - start;
+        match self.feed_latencies.last_mut() {
+            Some(retrieval) => *retrieval += latency,
+            None => self.feed_latencies.push(latency),
+        }
+        self.expect_feed(event?)
+    }
+
+    /// Allocate the [`RequestId`] of the next request sent to this
+    /// [`Shepherd`]
+    fn new_request_id(&mut self) -> RequestId {
+        let id = self.next_request_id;
+        self.next_request_id.0 += 1;
+        id
+    }
+
+    /// Send a request for `n_feeds` feeds to this [`Shepherd`] and wait for
+    /// its response, or [`None`] if it does not respond in time or is
+    /// abandoned while it is waited on
+    ///
+    /// Responses to earlier requests which timed out are skipped over
+    /// whenever they arrive, in any order, and a response to a request which
+    /// was never made or has already been answered is a protocol violation
+    fn request(
+        &mut self,
+        request_id: RequestId,
+        event: &SimulationEvent,
+        n_feeds: usize,
+    ) -> Option<ShepherdEvent> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let start = Instant::now();
+        self.remaining_output.set(
+            self.output_quota.map(|quota| quota.saturating_mul(n_feeds)),
+        );
+        #[cfg(any(feature = "process", feature = "wasm"))]
+        let deadline = self.request_timeout.map(|timeout| {
+            Instant::now()
+                + timeout.saturating_mul(
+                    u32::try_from(n_feeds).unwrap_or(u32::MAX),
+                )
+        });
+        // an event cut short by a ping is only picked up where it left off
+        // in json, so shepherds using any other encoding are never pinged
+        #[cfg(feature = "process")]
+        let heartbeat = self.heartbeat.filter(|_| {
+            self.supports(Capability::Heartbeats)
+                && self.encoding() == Encoding::Json
+        });
+        #[cfg(feature = "process")]
+        let (mut next_ping, mut awaiting_pong, mut n_missed) = (
+            heartbeat.map(|heartbeat| Instant::now() + heartbeat.interval),
+            false,
+            0,
+        );
+        #[cfg(feature = "process")]
+        self.deadline
+            .set([deadline, next_ping].into_iter().flatten().min());
+        #[cfg(feature = "wasm")]
+        self.wasm_deadline.set(deadline);
+        self.unanswered_requests.insert(request_id);
+        self.write_event(event);
+        self.flush();
+        let event = loop {
+            if self.abandoned {
+                break None;
+            }
+
+            let event = self.receive();
+            #[cfg(feature = "process")]
+            if event.is_err() {
+                let now = Instant::now();
+                // the request is left unanswered, so that its response is
+                // skipped over whenever it arrives
+                if deadline.is_some_and(|deadline| now >= deadline) {
+                    // unless the response was cut short partway through, in
+                    // which case nothing more can be read from the shepherd
+                    if self.output.is_broken() {
+                        warn!(
+                            shepherd = %self.name,
+                            "the shepherd timed out partway through an event, \
+                             and has been abandoned"
+                        );
+                        self.abandon();
+                    }
+                    break None;
+                }
+
+                // anything but the read timing out is the shepherd's own
+                // doing, and is dealt with below
+                if let Some(heartbeat) = heartbeat.filter(|_| {
+                    next_ping.is_some_and(|ping| now >= ping)
+                        && event.as_ref().is_err_and(is_timeout)
+                }) {
+                    if awaiting_pong {
+                        n_missed += 1;
+                    }
+                    if n_missed >= heartbeat.miss_threshold {
+                        warn!(
+                            shepherd = %self.name,
+                            n_missed,
+                            "the shepherd stopped answering pings"
+                        );
+                        break None;
+                    }
+
+                    self.write_event(&SimulationEvent::Ping);
+                    self.flush();
+                    awaiting_pong = true;
+                    next_ping = Some(now + heartbeat.interval);
+                    self.deadline.set(
+                        [deadline, next_ping].into_iter().flatten().min(),
+                    );
+                    continue;
+                }
+            }
+
+            let event = match event.and_then(|event| {
+                event.context(
+                    "The shepherd stopped before responding to a request",
+                )
+            }) {
+                Ok(event) => event,
+                Err(error) => {
+                    self.fail(&error);
+                    break None;
+                }
+            };
+            // a pong may also arrive after the request it was sent during
+            // has been answered, in which case it is skipped over
+            if event == ShepherdEvent::Pong {
+                #[cfg(feature = "process")]
+                {
+                    awaiting_pong = false;
+                    n_missed = 0;
+                }
+                continue;
+            }
+
+            match event.request_id() {
+                Some(id) if id != request_id => {
+                    if !self.unanswered_requests.remove(&id) {
+                        self.fail(&anyhow!(
+                            "The shepherd responded to request {}, which was not awaiting a response",
+                            id.0
+                        ));
+                        break None;
+                    }
+                }
+                _ => {
+                    self.unanswered_requests.remove(&request_id);
+                    break Some(event);
+                }
+            }
+        };
+        #[cfg(feature = "process")]
+        self.deadline.set(None);
+        #[cfg(feature = "wasm")]
+        self.wasm_deadline.set(None);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.compute_time += start.elapsed();
+        }
+
+        event
     }
 
     /// Give this [`Shepherd`] responses to a feed generated by it for the
-    /// specified sheep
+    /// specified sheep, which was rated during the given epoch
     pub fn incorporate_responses(
         &mut self,
         sheep: SheepId,
-        responses: Responses,
+        epoch: EpochId,
+        responses: LimitedResponses,
     ) {
         self.write_event(&SimulationEvent::FeedResponses {
             sheep,
-            responses: responses.into(),
+            epoch,
+            responses,
+        })
+    }
+
+    /// Give this [`Shepherd`] a batch of responses to feeds generated by it
+    /// for any number of sheep, in the order they were rated, along with the
+    /// epoch each feed was rated during
+    pub fn incorporate_batch(
+        &mut self,
+        responses: Vec<(SheepId, EpochId, LimitedResponses)>,
+    ) {
+        self.write_event(&SimulationEvent::ResponsesBatch { responses })
+    }
+
+    /// Notify this [`Shepherd`] that the last feed it built for the specified
+    /// sheep was short by the given number of items
+    pub fn notify_under_delivery(
+        &mut self,
+        sheep: SheepId,
+        n_missing: usize,
+    ) {
+        self.write_event(&SimulationEvent::UnderDelivered {
+            sheep,
+            n_missing,
         })
     }
 
+    /// Notify this [`Shepherd`] that the given items were dropped from the
+    /// last feed it built for the specified sheep, as they were beyond its
+    /// item access budget
+    pub fn notify_budget_exceeded(
+        &mut self,
+        sheep: SheepId,
+        items: Vec<ItemId>,
+    ) {
+        self.write_event(&SimulationEvent::BudgetExceeded { sheep, items })
+    }
+
     /// Notify this [`Shepherd`] of the start of a new epoch
     pub fn begin(&mut self, id: EpochId, data: Epoch) {
         self.write_event(&SimulationEvent::BeginEpoch { id, data })
     }
 
-    /// Introduce this [`Shepherd`] to a sheep
-    pub fn introduce_to(&mut self, graph: &Simulation, sheep: SheepId) {
+    /// Give this [`Shepherd`] a summary of the responses it received during
+    /// an epoch
+    pub fn summarize_epoch(
+        &mut self,
+        id: EpochId,
+        sheep_stats: Vec<(SheepId, ResponseCounts)>,
+    ) {
+        self.write_event(&SimulationEvent::EpochSummary { id, sheep_stats })
+    }
+
+    /// Notify this [`Shepherd`] that every feed it built during an epoch has
+    /// been rated, and that the epoch has ended
+    pub fn end(&mut self, id: EpochId) {
+        self.write_event(&SimulationEvent::EndEpoch { id })
+    }
+
+    /// Introduce this [`Shepherd`] to a sheep with the given tags
+    pub fn introduce_to(
+        &mut self,
+        sheep: SheepId,
+        associated_tags: Vec<TagId>,
+    ) {
         self.write_event(&SimulationEvent::SheepIntroduction {
             sheep,
-            associated_tags: graph.associated_tags(sheep).collect(),
+            associated_tags,
         })
     }
 }
 
-/// A subset of the data provided in the full [`Responses`] structure
+/// A builder for a [`Shepherd`] running in a process of its own, created by
+/// [`Shepherd::command`]
+///
+/// This allows for shepherds which need model files, temporary directories
+/// or API keys to be given them through their environment or working
+/// directory, independently of any other shepherd in the same run
+#[cfg(feature = "process")]
+pub struct ShepherdCommand {
+    program: OsString,
+    command: Command,
+    limits: ResourceLimits,
+}
+
+#[cfg(feature = "process")]
+impl ShepherdCommand {
+    /// Add an argument to pass to the shepherd
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.command.arg(arg);
+        self
+    }
+
+    /// Add several arguments to pass to the shepherd
+    pub fn args(
+        mut self,
+        args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    ) -> Self {
+        self.command.args(args);
+        self
+    }
+
+    /// Set an environment variable for the shepherd, in addition to those
+    /// it inherits from the simulation
+    pub fn env(
+        mut self,
+        key: impl AsRef<OsStr>,
+        value: impl AsRef<OsStr>,
+    ) -> Self {
+        self.command.env(key, value);
+        self
+    }
+
+    /// Set several environment variables for the shepherd, in addition to
+    /// those it inherits from the simulation
+    pub fn envs(
+        mut self,
+        vars: impl IntoIterator<Item = (impl AsRef<OsStr>, impl AsRef<OsStr>)>,
+    ) -> Self {
+        self.command.envs(vars);
+        self
+    }
+
+    /// Set the working directory of the shepherd, which is otherwise that of
+    /// the simulation
+    pub fn current_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.command.current_dir(dir);
+        self
+    }
+
+    /// Limit the CPU time the shepherd may use over its whole lifetime
+    ///
+    /// See [`ResourceLimits`] for how this is enforced on each platform
+    pub fn cpu_time_limit(mut self, limit: Duration) -> Self {
+        self.limits.cpu_time = Some(limit);
+        self
+    }
+
+    /// Limit the memory the shepherd may use, in bytes
+    ///
+    /// See [`ResourceLimits`] for how this is enforced on each platform
+    pub fn memory_limit(mut self, limit: u64) -> Self {
+        self.limits.memory = Some(limit);
+        self
+    }
+
+    /// Get the name the shepherd is assigned, which is the file name of its
+    /// executable
+    fn name(&self) -> anyhow::Result<String> {
+        Ok(Path::new(&self.program)
+            .file_name()
+            .ok_or(anyhow!(
+                "No file name was present in the shepherd's executable path"
+            ))?
+            .to_string_lossy()
+            .into_owned())
+    }
+
+    /// Spawn the shepherd, creating a new [`Shepherd`]
+    pub fn spawn(self) -> anyhow::Result<Shepherd> {
+        Shepherd::spawn(self.name()?, self.command, self.limits)
+    }
+}
+
+/// Check whether an error reading from a shepherd was its read timing out,
+/// rather than anything the shepherd did
+#[cfg(feature = "process")]
+fn is_timeout(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|error| error.kind() == io::ErrorKind::TimedOut)
+    })
+}
+
+/// Check that a shepherd's response to the handshake is a greeting in the
+/// [`PROTOCOL_VERSION`] spoken by the simulation, returning the
+/// capabilities, payloads and encoding it chose
+fn accept_greeting(
+    greeting: ShepherdEvent,
+) -> anyhow::Result<(Vec<Capability>, Vec<Payload>, Encoding)> {
+    let ShepherdEvent::Hello {
+        protocol_version,
+        capabilities,
+        payloads,
+        encoding,
+    } = greeting
+    else {
+        bail!(
+            "The shepherd responded to the handshake without greeting the simulation"
+        );
+    };
+    if protocol_version != PROTOCOL_VERSION {
+        bail!(
+            "The shepherd speaks version {} of the protocol, but version {} is required",
+            protocol_version,
+            PROTOCOL_VERSION
+        );
+    }
+
+    Ok((capabilities, payloads, encoding))
+}
+
+/// Get the feed a shepherd built in response to a
+/// [`SimulationEvent::FeedRequest`], along with the justifications it gave
+/// for any of its items
+fn into_feed(
+    event: ShepherdEvent,
+) -> anyhow::Result<(Feed, Vec<(ItemId, Justification)>)> {
+    match event {
+        ShepherdEvent::Feed { feed, .. } => Ok((feed, Vec::new())),
+        ShepherdEvent::JustifiedFeed { items, .. } => {
+            Ok(split_justifications(items))
+        }
+        ShepherdEvent::Hello { .. } => {
+            bail!("The shepherd greeted the simulation in place of a feed")
+        }
+        ShepherdEvent::Pong => {
+            bail!("The shepherd answered a ping in place of a feed")
+        }
+        ShepherdEvent::Candidates { .. } => {
+            bail!("The shepherd retrieved candidates in place of a feed")
+        }
+        ShepherdEvent::FeedBatch { .. }
+        | ShepherdEvent::JustifiedFeedBatch { .. } => {
+            bail!("The shepherd sent a batch of feeds in place of a feed")
+        }
+    }
+}
+
+/// Strip every [`Payload`] not among the requested `payloads` from an
+/// event, or [`None`] if nothing requested is left
+///
+/// Events are only copied when something has to be left out of them
+fn requested_part<'e>(
+    payloads: &HashSet<Payload>,
+    event: &'e SimulationEvent,
+) -> Option<Cow<'e, SimulationEvent>> {
+    match event {
+        SimulationEvent::TagGroupsMerged { .. }
+        | SimulationEvent::TagGroupSplit { .. }
+            if !payloads.contains(&Payload::TagGroupChanges) =>
+        {
+            None
+        }
+        SimulationEvent::TagGroups { .. }
+            if !payloads.contains(&Payload::TagGroups) =>
+        {
+            None
+        }
+        SimulationEvent::BeginEpoch { id, data }
+            if !payloads.contains(&Payload::ItemTags)
+                || (data.diff.is_some()
+                    && !payloads.contains(&Payload::GraphDiffs)) =>
+        {
+            let items = if payloads.contains(&Payload::ItemTags) {
+                data.items.clone()
+            } else {
+                data.items.iter().map(|(id, _)| (*id, Vec::new())).collect()
+            };
+            let diff = data
+                .diff
+                .clone()
+                .filter(|_| payloads.contains(&Payload::GraphDiffs));
+
+            Some(Cow::Owned(SimulationEvent::BeginEpoch {
+                id: *id,
+                data: Epoch {
+                    tags: data.tags.clone(),
+                    items,
+                    diff,
+                },
+            }))
+        }
+        _ => Some(Cow::Borrowed(event)),
+    }
+}
+
+/// Split the items of a justified feed from the justifications given for
+/// them
+fn split_justifications(
+    items: Vec<(ItemId, Option<Justification>)>,
+) -> (Feed, Vec<(ItemId, Justification)>) {
+    let feed = Feed(items.iter().map(|(item, _)| *item).collect());
+    let justifications = items
+        .into_iter()
+        .filter_map(|(item, justification)| {
+            justification.map(|justification| (item, justification))
+        })
+        .collect();
+    (feed, justifications)
+}
+
+/// A single sheep's [`Response`] to a feed item, as given to shepherds
+///
+/// The first two values are the item and the response, the third is the age
+/// of the item (the number of epochs since it was introduced, if it is
+/// present in the simulation), the fourth is a count of how many hops are
+/// required to get from the sheep to the item (if it is reachable), and the
+/// fifth is the position of the item within the feed, counted from zero
+pub type LimitedRating =
+    (ItemId, Response, Option<usize>, Option<u32>, usize);
+
+/// A subset of the data provided in the full [`Responses`] structure, along
+/// with the age and position of each item
 ///
 /// This exists to avoid revealing information about the underlying tag graph
-/// to shepherds
+/// to shepherds, so neither the explanation of a response nor whether it was
+/// serendipitous is passed on (see [`LimitedRating`])
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
-pub struct LimitedResponses(pub Vec<(ItemId, Response)>);
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LimitedResponses(pub Vec<LimitedRating>);
 
-impl From<Responses> for LimitedResponses {
-    fn from(Responses(inner): Responses) -> Self {
+impl LimitedResponses {
+    /// Limit the given [`Responses`], using the provided function to look up
+    /// the age of each item
+    ///
+    /// The responses are taken to be in the order the items appeared in the
+    /// feed
+    pub fn new(
+        Responses(inner): Responses,
+        age: impl Fn(ItemId) -> Option<usize>,
+    ) -> Self {
         Self(
             inner
                 .into_iter()
-                .map(|(id, response, _)| (id, response))
+                .enumerate()
+                .map(|(position, (id, response, hops, ..))| {
+                    (id, response, age(id), hops, position)
+                })
                 .collect(),
         )
     }
 }
 
+/// An optional part of the protocol, which shepherds declare support for
+/// during the handshake
+#[non_exhaustive]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Capability {
+    /// Sending [`ShepherdEvent::JustifiedFeed`] in place of plain feeds
+    JustifiedFeeds,
+
+    /// Receiving responses in batches through
+    /// [`SimulationEvent::ResponsesBatch`], rather than after every feed
+    ResponseBatches,
+
+    /// Receiving requests for the feeds of many sheep at once through
+    /// [`SimulationEvent::FeedRequestBatch`], which are answered with a
+    /// [`ShepherdEvent::FeedBatch`] or [`ShepherdEvent::JustifiedFeedBatch`]
+    /// holding a feed for each sheep in the order they were requested
+    FeedBatches,
+
+    /// Answering each [`SimulationEvent::Ping`] with a
+    /// [`ShepherdEvent::Pong`] as soon as it is read, which lets the
+    /// simulation tell a shepherd which is busy apart from one which has
+    /// hung (see [`Shepherd::set_heartbeat`])
+    ///
+    /// Pings are only sent while a request is outstanding, so a shepherd
+    /// which declares this must keep reading its input while it builds
+    /// feeds, or it will be taken to have hung
+    Heartbeats,
+
+    /// Building feeds in two stages when the simulation asks for it, first
+    /// retrieving candidates in response to
+    /// [`SimulationEvent::RetrieveCandidates`] with a
+    /// [`ShepherdEvent::Candidates`], then ranking those the simulation
+    /// keeps in response to [`SimulationEvent::RankCandidates`] with a
+    /// [`ShepherdEvent::Feed`] or [`ShepherdEvent::JustifiedFeed`] made up
+    /// of them
+    ///
+    /// Shepherds which declare this are asked for every feed this way, one
+    /// sheep at a time, in place of [`SimulationEvent::FeedRequest`] and
+    /// [`SimulationEvent::FeedRequestBatch`]
+    TwoStageFeeds,
+}
+
+impl Capability {
+    /// Every capability the simulation supports
+    pub const ALL: [Self; 5] = [
+        Self::JustifiedFeeds,
+        Self::ResponseBatches,
+        Self::FeedBatches,
+        Self::Heartbeats,
+        Self::TwoStageFeeds,
+    ];
+}
+
+/// An optional part of the events sent to shepherds, which shepherds request
+/// during the handshake
+///
+/// Payloads which are not requested are left out of the events sent to a
+/// shepherd, so that shepherds are only given as much knowledge of the world
+/// as they have a use for, and are not sent the rest. This saves on what is
+/// sent rather than on the simulation's own work, as leaving a payload out of
+/// a [`SimulationEvent::BeginEpoch`] copies the rest of the epoch for each
+/// shepherd which does so
+#[non_exhaustive]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Payload {
+    /// The tags of each item introduced in [`SimulationEvent::BeginEpoch`],
+    /// without which the items are given with no tags
+    ItemTags,
+
+    /// The [`SimulationEvent::TagGroupsMerged`] and
+    /// [`SimulationEvent::TagGroupSplit`] events
+    TagGroupChanges,
+
+    /// The [`GraphDiff`] of each epoch, which is only ever included if
+    /// [`Settings::graph_diffs`] is enabled
+    ///
+    /// [`GraphDiff`]: crate::simulation::GraphDiff
+    /// [`Settings::graph_diffs`]: crate::simulation::Settings::graph_diffs
+    GraphDiffs,
+
+    /// The [`SimulationEvent::TagGroups`] event, which is only ever sent if
+    /// [`Settings::tag_group_snapshots`] is enabled
+    ///
+    /// [`Settings::tag_group_snapshots`]:
+    /// crate::simulation::Settings::tag_group_snapshots
+    TagGroups,
+}
+
+impl Payload {
+    /// Every payload the simulation can include
+    pub const ALL: [Self; 4] = [
+        Self::ItemTags,
+        Self::TagGroupChanges,
+        Self::GraphDiffs,
+        Self::TagGroups,
+    ];
+}
+
+/// An event sent from the simulation to a shepherd
+///
+/// The first event sent is always [`SimulationEvent::Hello`], and the shape
+/// of every other event is fixed by its [`PROTOCOL_VERSION`]
 #[non_exhaustive]
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "kind", content = "data")]
 pub enum SimulationEvent {
+    Hello {
+        protocol_version: u32,
+        capabilities: Vec<Capability>,
+        payloads: Vec<Payload>,
+        encodings: Vec<Encoding>,
+    },
+    RunConfig(RunConfig),
     BeginEpoch {
         id: EpochId,
         data: Epoch,
@@ -151,17 +1866,180 @@ pub enum SimulationEvent {
         associated_tags: Vec<TagId>,
     },
     FeedRequest {
+        request_id: RequestId,
+        sheep: SheepId,
+    },
+    FeedRequestBatch {
+        request_id: RequestId,
+        sheep: Vec<SheepId>,
+    },
+    RetrieveCandidates {
+        request_id: RequestId,
+        sheep: SheepId,
+        n_candidates: usize,
+    },
+    RankCandidates {
+        request_id: RequestId,
         sheep: SheepId,
+        candidates: Vec<ItemId>,
     },
     FeedResponses {
         sheep: SheepId,
+        epoch: EpochId,
         responses: LimitedResponses,
     },
+    ResponsesBatch {
+        responses: Vec<(SheepId, EpochId, LimitedResponses)>,
+    },
+    UnderDelivered {
+        sheep: SheepId,
+        n_missing: usize,
+    },
+    BudgetExceeded {
+        sheep: SheepId,
+        items: Vec<ItemId>,
+    },
+    TagGroupsMerged {
+        tags: (Vec<TagId>, Vec<TagId>),
+    },
+    TagGroupSplit {
+        tags: (Vec<TagId>, Vec<TagId>),
+    },
+    TagGroups {
+        groups: Vec<Vec<TagId>>,
+    },
+    EpochSummary {
+        id: EpochId,
+        sheep_stats: Vec<(SheepId, ResponseCounts)>,
+    },
+    EndEpoch {
+        id: EpochId,
+    },
+    Shutdown,
+    Ping,
 }
 
+/// An event sent from a shepherd to the simulation
+///
+/// The first event sent must be [`ShepherdEvent::Hello`], in response to the
+/// greeting of the simulation, and the shape of every other event is fixed
+/// by its [`PROTOCOL_VERSION`]
 #[non_exhaustive]
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "kind", content = "data")]
 pub enum ShepherdEvent {
-    Feed(Feed),
+    Hello {
+        protocol_version: u32,
+        capabilities: Vec<Capability>,
+        #[serde(default)]
+        payloads: Vec<Payload>,
+        #[serde(default)]
+        encoding: Encoding,
+    },
+    Feed {
+        request_id: RequestId,
+        feed: Feed,
+    },
+    JustifiedFeed {
+        request_id: RequestId,
+        items: Vec<(ItemId, Option<Justification>)>,
+    },
+    FeedBatch {
+        request_id: RequestId,
+        feeds: Vec<Feed>,
+    },
+    JustifiedFeedBatch {
+        request_id: RequestId,
+        feeds: Vec<Vec<(ItemId, Option<Justification>)>>,
+    },
+    Candidates {
+        request_id: RequestId,
+        items: Vec<ItemId>,
+    },
+    Pong,
+}
+
+impl ShepherdEvent {
+    /// Get the [`RequestId`] of the request this event responds to, or
+    /// [`None`] if it is not a response to one
+    pub fn request_id(&self) -> Option<RequestId> {
+        match self {
+            Self::Hello { .. } | Self::Pong => None,
+            Self::Feed { request_id, .. }
+            | Self::JustifiedFeed { request_id, .. }
+            | Self::FeedBatch { request_id, .. }
+            | Self::JustifiedFeedBatch { request_id, .. }
+            | Self::Candidates { request_id, .. } => Some(*request_id),
+        }
+    }
+}
+
+/// A borrowed [`TranscriptEvent`], which is serialized the same way
+#[derive(Serialize)]
+#[serde(tag = "kind", content = "data")]
+enum RecordedEvent<'e> {
+    Sent(&'e SimulationEvent),
+    Received(&'e ShepherdEvent),
+}
+
+/// An event exchanged between the simulation and a shepherd, as it appears
+/// in a transcript of their exchange
+///
+/// Transcripts are newline-delimited JSON, holding one of these on each
+/// line in the order the events were exchanged, regardless of the encoding
+/// the shepherd chose
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "kind", content = "data")]
+pub enum TranscriptEvent {
+    /// An event sent from the simulation to the shepherd
+    Sent(SimulationEvent),
+
+    /// An event received by the simulation from the shepherd
+    Received(ShepherdEvent),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a [`Shepherd`] offered only JSON, as those connected to over
+    /// gRPC are, which greets the simulation choosing the given encoding
+    fn json_only(encoding: Encoding) -> Shepherd {
+        let mut greeting = serde_json::to_vec(&ShepherdEvent::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: Vec::new(),
+            payloads: Vec::new(),
+            encoding,
+        })
+        .unwrap();
+        greeting.push(b'\n');
+
+        Shepherd {
+            encodings: &[Encoding::Json],
+            ..Shepherd::from_streams(
+                "json-only",
+                io::sink(),
+                io::Cursor::new(greeting),
+            )
+        }
+    }
+
+    #[test]
+    fn offered_encodings_are_accepted() {
+        let mut shepherd = json_only(Encoding::Json);
+        shepherd.greet().unwrap();
+        assert_eq!(shepherd.encoding(), Encoding::Json);
+    }
+
+    #[test]
+    fn encodings_not_offered_are_rejected() {
+        for encoding in [Encoding::MessagePack, Encoding::Cbor] {
+            assert!(
+                json_only(encoding).greet().is_err(),
+                "{encoding:?} was accepted without being offered"
+            );
+        }
+    }
 }
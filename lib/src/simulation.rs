@@ -1,19 +1,38 @@
-use rand::prelude::*;
+use anyhow::{ensure, Context};
+use petgraph::visit::EdgeRef;
+use rand::{distributions::WeightedIndex, prelude::*};
 use serde::{Deserialize, Serialize};
 use statrs::distribution::PoissonError;
-use std::collections::{HashMap, HashSet};
-use tracing::info;
+use std::{error, fmt, io::Write, mem, time::Duration};
+use tracing::{info, info_span, instrument, warn};
 
 use crate::{
-    feed::{Feed, Responses},
-    graph::Simulation as SimulationGraph,
-    ids::{EpochId, GraphId, ItemId, SheepId, ShepherdId, TagId},
-    sheep,
-    shepherd::{Shepherd, SimulationEvent},
+    collections::{HashMap, HashSet},
+    export,
+    feed::{
+        Feed, FeedViolation, Justification, Response, ResponseCounts,
+        Responses,
+    },
+    graph::{
+        EdgeSemantics, GraphGenerator, Simulation as SimulationGraph,
+        WorldStats,
+    },
+    ids::{EpochId, GraphId, ItemId, NodeType, SheepId, ShepherdId, TagId},
+    metrics::{self, Metric, RankingMetrics, Regret},
+    sheep::{self, Distances},
+    shepherd::{
+        Capability, Heartbeat, LimitedResponses, Shepherd, SimulationEvent,
+    },
 };
 
 /// Settings for the simulation
 pub struct Settings<'a> {
+    /// The number of items shepherds are expected to put in each feed
+    pub feed_size: usize,
+
+    /// The number of epochs the simulation is expected to run for, if known
+    pub n_epochs: Option<usize>,
+
     /// Bounds on the number of tags added at the start of each epoch
     pub n_tags_bounds: (usize, usize),
 
@@ -23,9 +42,6 @@ pub struct Settings<'a> {
     /// Bounds on the number of tags assigned to a new Item
     pub n_item_tags_bounds: (usize, usize),
 
-    /// Bounds on the number of tags a sheep has
-    pub n_sheep_tags_bounds: (usize, usize),
-
     /// Bounds on the initial number of tags used to seed the simulation
     pub initial_n_tags_bounds: (usize, usize),
 
@@ -45,11 +61,261 @@ pub struct Settings<'a> {
     /// lower than that
     pub average_tags_per_group: usize,
 
+    /// The model used to connect tags to one another
+    pub graph_generator: GraphGenerator,
+
+    /// How the edges of the graph relate the nodes they connect
+    ///
+    /// See [`EdgeSemantics`] for details. As sheep measure their distance to
+    /// items along the graph, this changes which items are considered close
+    pub edge_semantics: EdgeSemantics,
+
     /// The threshold of orphaned tags at which new groups will be formed
     ///
     /// This should be at most the lower bound of `initial_n_tags_bounds`
     pub orphaned_tag_threshold: usize,
 
+    /// The archetypes sheep are drawn from
+    ///
+    /// Each new sheep is assigned an archetype at random, weighted by the
+    /// archetypes' proportions, and takes on its tag count bounds, activity
+    /// probability and response temperature
+    pub archetypes: Vec<sheep::Archetype>,
+
+    /// The model used by sheep to respond to feeds
+    pub sheep_behavior: Box<dyn sheep::SheepBehavior + 'a>,
+
+    /// The fraction of the initial sheep which rate feeds noisily
+    ///
+    /// This is used to measure how robust shepherds are to label noise. The
+    /// sheep selected are recorded in [`SimulationParts::noisy_sheep`] so
+    /// that results can be reported with and without them
+    pub noisy_sheep_fraction: f64,
+
+    /// The kind of noise applied by noisy sheep
+    pub noise: sheep::Noise,
+
+    /// The fraction of each initial sheep's tags which are hidden from
+    /// shepherds
+    ///
+    /// Hidden tags are left out of sheep introductions, but are still used by
+    /// sheep when rating feeds. A hidden tag is considered discovered by a
+    /// [`Shepherd`] once it has shown the sheep an item with that tag which
+    /// the sheep responded positively to, which is reported through
+    /// [`Settings::discovery_hook`]
+    pub hidden_tag_fraction: f64,
+
+    /// The probability that two tag groups merge at the start of an epoch
+    ///
+    /// The connections between the groups are strengthened as done by
+    /// [`SimulationGraph::join_tags`], linking each tag of the absorbed group
+    /// to up to [`Settings::average_tags_per_group`] tags of the other. The
+    /// merged group keeps a single parent tag, if either group had one. The
+    /// merge is announced to each
+    /// [`Shepherd`] requesting [`Payload::TagGroupChanges`] through
+    /// [`SimulationEvent::TagGroupsMerged`]
+    ///
+    /// [`Payload::TagGroupChanges`]: crate::shepherd::Payload::TagGroupChanges
+    pub group_merge_probability: f64,
+
+    /// The probability that a tag group splits in two at the start of an
+    /// epoch
+    ///
+    /// The connections between the halves are removed as done by
+    /// [`SimulationGraph::separate_tags`], and the split is announced to
+    /// each [`Shepherd`] requesting [`Payload::TagGroupChanges`] through
+    /// [`SimulationEvent::TagGroupSplit`]
+    ///
+    /// [`Payload::TagGroupChanges`]: crate::shepherd::Payload::TagGroupChanges
+    pub group_split_probability: f64,
+
+    /// The probability that a newly formed tag group is given a parent tag
+    ///
+    /// A parent tag is a coarse tag connected to every member of its group,
+    /// including those added later. Items may be tagged with it like any
+    /// other tag, so item tagging ranges from coarse to fine-grained. See
+    /// [`SimulationGraph::connect_to_parent`] for details
+    pub parent_tag_probability: f64,
+
+    /// The factor the weights of edges to and from parent tags are
+    /// multiplied by, which must be within `(0, 1]`
+    pub parent_tag_discount: f64,
+
+    /// The fraction of their fatigue towards each tag group that sheep
+    /// recover from at the beginning of every epoch
+    ///
+    /// See [`sheep::Fatigue`] for details
+    pub fatigue_recovery_rate: f64,
+
+    /// The total time each [`Shepherd`] may spend building feeds over the
+    /// course of the run
+    ///
+    /// Once a [`Shepherd`] has exhausted its budget, it is no longer asked
+    /// for feeds and every remaining sheep is given an empty [`Feed`] in its
+    /// place. This keeps comparisons fair when the cost of a feed algorithm
+    /// is part of the evaluation
+    pub shepherd_compute_budget: Option<Duration>,
+
+    /// The maximum number of bytes read from a [`Shepherd`] in response to a
+    /// single feed request
+    ///
+    /// See [`Shepherd::set_output_quota`] for details
+    pub shepherd_output_quota: Option<usize>,
+
+    /// The maximum time to wait for a [`Shepherd`] to respond to each
+    /// request for a feed
+    ///
+    /// A sheep whose feed is not built in time is given a fallback [`Feed`]
+    /// in its place, as described by [`Settings::timeout_fallback`]. See
+    /// [`Shepherd::set_request_timeout`] for which shepherds can time out
+    pub shepherd_request_timeout: Option<Duration>,
+
+    /// How often a [`Shepherd`] is pinged while the simulation waits on a
+    /// request to it, and how many pings it may miss
+    ///
+    /// A shepherd which misses too many is handled as though the request
+    /// timed out. See [`Shepherd::set_heartbeat`] for which shepherds are
+    /// pinged
+    pub shepherd_heartbeat: Option<Heartbeat>,
+
+    /// The [`Feed`] given to a sheep in place of one a [`Shepherd`] failed
+    /// to build in time, or would have been asked to build after being
+    /// abandoned
+    pub timeout_fallback: FallbackFeed,
+
+    /// How each [`Feed`] built by a [`Shepherd`] is checked before it is
+    /// shown to a sheep
+    ///
+    /// See [`FeedValidation`] for details
+    pub feed_validation: FeedValidation,
+
+    /// Whether a [`Shepherd`] is abandoned as soon as a request to it times
+    /// out, rather than being given another chance with the next sheep
+    ///
+    /// See [`Shepherd::abandon`] for details
+    pub abandon_timed_out_shepherds: bool,
+
+    /// The number of candidates each [`Shepherd`] which supports
+    /// [`Capability::TwoStageFeeds`] is asked to retrieve for a feed
+    ///
+    /// When this is set, such shepherds build every feed in two stages:
+    /// they retrieve candidates, the simulation filters out those the sheep
+    /// cannot be shown, and they rank the rest into a feed. How well each
+    /// stage did is reported through [`Settings::stage_metrics_hook`].
+    /// Otherwise, every shepherd is asked for feeds directly
+    pub n_candidates: Option<usize>,
+
+    /// The number of items each [`Shepherd`] may access during an epoch
+    ///
+    /// When this is set, shepherds are no longer told about every item as
+    /// it is introduced. Instead, each [`Epoch`] sent to a shepherd holds a
+    /// sample of this many items drawn from every item in the simulation,
+    /// which are the only ones it may show sheep until the next epoch. Items
+    /// beyond the budget are dropped from its feeds, and it is sent a
+    /// [`SimulationEvent::BudgetExceeded`] notice naming them
+    pub item_access_budget: Option<usize>,
+
+    /// The maximum number of nodes the graph may have
+    ///
+    /// Before the graph is grown, the most it could grow to is checked
+    /// against this, and the simulation stops with [`Error::WorldTooLarge`]
+    /// rather than allocating the memory for it. This guards against runaway
+    /// configurations in parameter sweeps
+    pub max_nodes: Option<usize>,
+
+    /// The maximum number of edges the graph may have
+    ///
+    /// See [`Settings::max_nodes`] for details. The edges formed between tags
+    /// in tag groups can't be bounded before they are added, so those are
+    /// only checked once the graph has grown
+    pub max_edges: Option<usize>,
+
+    /// The probability that a positive response strengthens the connection
+    /// between a sheep and each of the rated item's tags
+    ///
+    /// See [`SimulationGraph::reinforce_preference`] for details. As the
+    /// graph is shared between shepherds, the feeds of each one influence
+    /// the preferences of sheep as seen by the others
+    pub reinforcement_probability: f64,
+
+    /// Whether sheep attach an explanation of each of their responses
+    ///
+    /// Explanations are only made available through
+    /// [`Settings::feed_rated_hook`], and are never sent to shepherds. See
+    /// [`sheep::process_feed`] for details
+    pub explain_responses: bool,
+
+    /// Whether each [`Shepherd`] is sent a summary of the responses it
+    /// received from each sheep at the end of every epoch
+    ///
+    /// This duplicates information already sent to shepherds, but spares
+    /// those which only need aggregate signals from keeping track of it
+    pub epoch_summaries: bool,
+
+    /// Whether each item missing from a short [`Feed`] built by a
+    /// [`Shepherd`] counts as a negative response in its epoch summaries
+    ///
+    /// A feed is short if it has fewer than [`Settings::feed_size`] items.
+    /// Without this, short feeds only lower the number of responses a
+    /// shepherd receives, which hides them from per-item averages
+    ///
+    /// Only the epoch summaries are penalized. The [`Stats`] of a simulation
+    /// and the ratings passed to [`Settings::feed_rated_hook`] never count
+    /// missing items as responses, which are instead measured by
+    /// [`EpochStats::fill_rate`] and [`Settings::under_delivery_hook`]
+    ///
+    /// [`Stats`]: crate::metrics::Stats
+    /// [`EpochStats::fill_rate`]: crate::metrics::EpochStats::fill_rate
+    pub under_delivery_penalty: bool,
+
+    /// Whether a [`Shepherd`] is sent a
+    /// [`SimulationEvent::UnderDelivered`] notice for each short [`Feed`]
+    /// it builds
+    pub under_delivery_notices: bool,
+
+    /// Whether each [`Epoch`] sent to shepherds carries a [`GraphDiff`]
+    /// describing how the structure of the tags changed since the previous
+    /// one
+    ///
+    /// This is opt-in, as it reveals far more of the tag graph than
+    /// shepherds are otherwise given, and diffs are only sent to shepherds
+    /// requesting [`Payload::GraphDiffs`]. The first epoch's diff describes
+    /// the entire structure of the initial tags
+    ///
+    /// [`Payload::GraphDiffs`]: crate::shepherd::Payload::GraphDiffs
+    pub graph_diffs: bool,
+
+    /// Whether shepherds are sent the members of every tag group through
+    /// [`SimulationEvent::TagGroups`] at the start of each epoch
+    ///
+    /// Like [`Settings::graph_diffs`], this is opt-in as it hands shepherds
+    /// structure they would otherwise have to infer, and it is only sent to
+    /// shepherds requesting [`Payload::TagGroups`]
+    ///
+    /// [`Payload::TagGroups`]: crate::shepherd::Payload::TagGroups
+    pub tag_group_snapshots: bool,
+
+    /// The number of epochs over which responses are collected before being
+    /// delivered to each [`Shepherd`] at once
+    ///
+    /// If this is [`None`], responses are delivered immediately after each
+    /// feed is rated. Otherwise, they are buffered and delivered in a single
+    /// [`SimulationEvent::ResponsesBatch`] at the end of every epoch whose
+    /// number is a multiple of the interval, modeling pipelines where
+    /// training data arrives in periodic dumps
+    pub response_batch_interval: Option<usize>,
+
+    /// Windows of simulated infrastructure degradation
+    ///
+    /// If windows overlap, the first one listed takes effect
+    pub degradations: Vec<Degradation>,
+
+    /// The model under which sheep use a [`Shepherd`] less as they grow
+    /// dissatisfied with its feeds, if any
+    ///
+    /// See [`Retention`] for details
+    pub retention: Option<Retention>,
+
     /// Hook that is called when a new epoch is started
     #[allow(clippy::type_complexity)]
     pub new_epoch_hook: Option<Box<dyn FnMut(EpochId, &Epoch) + 'a>>,
@@ -60,11 +326,159 @@ pub struct Settings<'a> {
     pub feed_generation_hook:
         Option<Box<dyn FnMut(ShepherdId, SheepId, &Feed) + 'a>>,
 
+    /// Hook that is called with the number of items missing from each short
+    /// [`Feed`] built by a [`Shepherd`]
+    ///
+    /// Feeds which were dropped, delayed or not built within the compute
+    /// budget are not the shepherd's doing, so they are never counted
+    #[allow(clippy::type_complexity)]
+    pub under_delivery_hook:
+        Option<Box<dyn FnMut(ShepherdId, SheepId, usize) + 'a>>,
+
+    /// Hook that is called with each [`FeedViolation`] in a [`Feed`] built
+    /// by a [`Shepherd`], whatever [`Settings::feed_validation`] is
+    #[allow(clippy::type_complexity)]
+    pub feed_violation_hook:
+        Option<Box<dyn FnMut(ShepherdId, SheepId, FeedViolation) + 'a>>,
+
+    /// Hook that is called with the [`StageMetrics`] of each [`Feed`] built
+    /// by a [`Shepherd`] in two stages (see [`Settings::n_candidates`])
+    #[allow(clippy::type_complexity)]
+    pub stage_metrics_hook:
+        Option<Box<dyn FnMut(ShepherdId, SheepId, StageMetrics) + 'a>>,
+
+    /// Hook that is called with the number of items dropped from a [`Feed`]
+    /// for being beyond the item access budget of the [`Shepherd`] which
+    /// built it (see [`Settings::item_access_budget`])
+    #[allow(clippy::type_complexity)]
+    pub item_budget_exceeded_hook:
+        Option<Box<dyn FnMut(ShepherdId, SheepId, usize) + 'a>>,
+
     /// Hook that is called when a sheep has finished rating a [`Feed`] given
-    /// by a [`Shepherd`]
+    /// by a [`Shepherd`], along with whether the shepherd built the feed
+    /// itself rather than it being a [`FallbackFeed`] given in its place
     #[allow(clippy::type_complexity)]
     pub feed_rated_hook:
-        Option<Box<dyn FnMut(ShepherdId, SheepId, &Responses) + 'a>>,
+        Option<Box<dyn FnMut(ShepherdId, SheepId, &Responses, bool) + 'a>>,
+
+    /// Hook that is called with each justification a [`Shepherd`] gives for
+    /// an item in a [`Feed`], along with whether it is faithful to the
+    /// shortest path from the sheep to the item
+    ///
+    /// See [`Justification::is_faithful`] for details
+    #[allow(clippy::type_complexity)]
+    pub justification_hook: Option<
+        Box<
+            dyn FnMut(ShepherdId, SheepId, ItemId, &Justification, bool) + 'a,
+        >,
+    >,
+
+    /// Hook that is called when a [`Shepherd`] does not respond to a request
+    /// for a sheep's feed within [`Settings::shepherd_request_timeout`], or
+    /// fails and is abandoned before it responds
+    #[allow(clippy::type_complexity)]
+    pub request_timeout_hook:
+        Option<Box<dyn FnMut(ShepherdId, SheepId) + 'a>>,
+
+    /// Hook that is called when a [`Shepherd`] exhausts its compute budget
+    #[allow(clippy::type_complexity)]
+    pub budget_exhausted_hook:
+        Option<Box<dyn FnMut(ShepherdId, EpochId, Duration) + 'a>>,
+
+    /// Hook that is called at the start of an epoch during which a
+    /// [`Degradation`] is in effect
+    #[allow(clippy::type_complexity)]
+    pub degradation_hook: Option<Box<dyn FnMut(EpochId, &Degradation) + 'a>>,
+
+    /// Hook that is called with structural statistics of the graph once it
+    /// has grown at the start of every epoch
+    #[allow(clippy::type_complexity)]
+    pub world_stats_hook: Option<Box<dyn FnMut(EpochId, &WorldStats) + 'a>>,
+
+    /// Hook that is called at the end of every epoch with the fraction of
+    /// hidden tags a [`Shepherd`] has discovered so far, if any tags are
+    /// hidden
+    #[allow(clippy::type_complexity)]
+    pub discovery_hook: Option<Box<dyn FnMut(ShepherdId, EpochId, f64) + 'a>>,
+
+    /// Hook that is called at the end of every epoch with the number of
+    /// sheep which requested a feed from a [`Shepherd`] and the number of
+    /// sheep which have not left it, if [`Settings::retention`] is set
+    #[allow(clippy::type_complexity)]
+    pub retention_hook:
+        Option<Box<dyn FnMut(EpochId, ShepherdId, usize, usize) + 'a>>,
+
+    /// Hook that is called at the end of every epoch with how widely the
+    /// items a [`Shepherd`] has recommended so far are spread over the items
+    /// present in the simulation
+    #[allow(clippy::type_complexity)]
+    pub catalog_coverage_hook: Option<
+        Box<dyn FnMut(EpochId, ShepherdId, metrics::CatalogCoverage) + 'a>,
+    >,
+
+    /// Hook that is called at the end of every epoch with how the items a
+    /// [`Shepherd`] showed during it were spread over the tag groups, against
+    /// how the items present in the simulation are
+    #[allow(clippy::type_complexity)]
+    pub group_exposure_hook: Option<
+        Box<dyn FnMut(EpochId, ShepherdId, &metrics::GroupExposure) + 'a>,
+    >,
+
+    /// Custom metrics computed over the responses each [`Shepherd`] receives
+    pub metrics: metrics::Registry<'a>,
+
+    /// Whether the [`RankingMetrics`] of each [`Feed`] built by a
+    /// [`Shepherd`] are computed and counted in its stats (see
+    /// [`Simulation::stats`])
+    ///
+    /// The relevance of an item to a sheep is the probability of the sheep
+    /// responding positively to it, going by its distance from the sheep
+    /// (see [`sheep::affinities`]). This is opt-in, as it requires finding
+    /// the distance from the sheep to every item for each feed
+    pub ranking_metrics: bool,
+
+    /// Whether the [`Regret`] of each [`Feed`] built by a [`Shepherd`]
+    /// against an oracle with full access to the graph is computed and
+    /// counted in its stats (see [`Simulation::stats`])
+    ///
+    /// As with [`Settings::ranking_metrics`], this is opt-in, as it requires
+    /// finding the distance from the sheep to every item for each feed
+    pub oracle_regret: bool,
+
+    /// Whether the intra-list diversity of each [`Feed`] built by a
+    /// [`Shepherd`] is computed and counted in its stats (see
+    /// [`metrics::intra_list_diversity`])
+    ///
+    /// This is opt-in, as it requires searching the tags around every item
+    /// of each feed
+    pub diversity_metrics: bool,
+
+    /// Whether the drift of each sheep away from the tags it started out
+    /// with is measured and counted in the stats of each [`Shepherd`] (see
+    /// [`metrics::EpochStats::drift`])
+    ///
+    /// The drift of a sheep during an epoch is the mean number of hops
+    /// between tags from its original tags to the tags of the items a
+    /// shepherd showed it, leaving out items which can't be reached. This is
+    /// opt-in, as it requires searching the tags around every sheep shown a
+    /// feed during each epoch
+    pub drift_metrics: bool,
+
+    /// Whether the number of epochs new items wait to be shown and new sheep
+    /// wait to be shown a relevant item by each [`Shepherd`] is measured and
+    /// counted in its stats (see [`metrics::ColdStart`])
+    ///
+    /// An item is relevant to a sheep if its relevance is at least
+    /// [`metrics::RELEVANCE_THRESHOLD`]. This is opt-in, as it requires
+    /// finding the distance from a sheep to every item for each feed it is
+    /// shown until one contains a relevant item
+    pub cold_start_metrics: bool,
+
+    /// Hook that is called with each custom metric in
+    /// [`Settings::metrics`] once it has been finalized at the end of every
+    /// epoch
+    #[allow(clippy::type_complexity)]
+    pub metric_hook: Option<Box<dyn FnMut(EpochId, &dyn Metric) + 'a>>,
 
     /// Hook that is called after IDs are assigned to [`Shepherd`]s
     #[allow(clippy::type_complexity)]
@@ -75,37 +489,448 @@ pub struct Settings<'a> {
 impl Default for Settings<'_> {
     fn default() -> Self {
         Self {
+            feed_size: 10,
+            n_epochs: None,
             n_tags_bounds: (0, 3),
             n_items_bounds: (0, 50),
             n_item_tags_bounds: (5, 7),
-            n_sheep_tags_bounds: (10, 15),
             initial_n_tags_bounds: (25, 50),
             initial_n_items_bounds: (40, 60),
             initial_n_sheep_bounds: (50, 100),
             average_tags_per_group: 4,
             orphaned_tag_threshold: 100,
+            graph_generator: GraphGenerator::PoissonGroups,
+            edge_semantics: EdgeSemantics::default(),
+            archetypes: vec![sheep::Archetype::default()],
+            sheep_behavior: Box::new(sheep::DistanceBehavior::default()),
+            noisy_sheep_fraction: 0.0,
+            noise: sheep::Noise::Uniform,
+            hidden_tag_fraction: 0.0,
+            group_merge_probability: 0.0,
+            group_split_probability: 0.0,
+            parent_tag_probability: 0.0,
+            parent_tag_discount: 0.5,
+            fatigue_recovery_rate: 0.5,
+            shepherd_compute_budget: None,
+            shepherd_output_quota: None,
+            shepherd_request_timeout: None,
+            shepherd_heartbeat: None,
+            timeout_fallback: FallbackFeed::Empty,
+            feed_validation: FeedValidation::Report,
+            abandon_timed_out_shepherds: false,
+            n_candidates: None,
+            item_access_budget: None,
+            max_nodes: None,
+            max_edges: None,
+            reinforcement_probability: 0.0,
+            explain_responses: false,
+            epoch_summaries: false,
+            under_delivery_penalty: false,
+            under_delivery_notices: false,
+            graph_diffs: false,
+            tag_group_snapshots: false,
+            response_batch_interval: None,
+            degradations: Vec::new(),
+            retention: None,
             new_epoch_hook: None,
             feed_generation_hook: None,
+            under_delivery_hook: None,
+            feed_violation_hook: None,
+            stage_metrics_hook: None,
+            item_budget_exceeded_hook: None,
             feed_rated_hook: None,
+            justification_hook: None,
+            request_timeout_hook: None,
+            budget_exhausted_hook: None,
+            degradation_hook: None,
+            world_stats_hook: None,
+            discovery_hook: None,
+            retention_hook: None,
+            catalog_coverage_hook: None,
+            group_exposure_hook: None,
+            metrics: metrics::Registry::default(),
+            ranking_metrics: false,
+            oracle_regret: false,
+            diversity_metrics: false,
+            drift_metrics: false,
+            cold_start_metrics: false,
+            metric_hook: None,
             shepherd_assignment_hook: None,
         }
     }
 }
 
+/// An error which stops the simulation
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    /// The Poisson distribution used to form tag groups could not be built
+    Poisson(PoissonError),
+
+    /// The graph grew, or would have grown, past [`Settings::max_nodes`] or
+    /// [`Settings::max_edges`]
+    WorldTooLarge {
+        /// The number of nodes in the graph, or the most it could have grown
+        /// to
+        n_nodes: usize,
+
+        /// The number of edges in the graph, or the most it could have grown
+        /// to
+        n_edges: usize,
+    },
+
+    /// A [`Shepherd`] built a [`Feed`] breaking the rules of the simulation
+    /// while [`Settings::feed_validation`] was [`FeedValidation::Strict`]
+    InvalidFeed {
+        /// The shepherd which built the feed
+        shepherd: ShepherdId,
+
+        /// The sheep the feed was built for
+        sheep: SheepId,
+
+        /// The first rule the feed broke
+        violation: FeedViolation,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Poisson(_) => {
+                write!(f, "Unable to sample the sizes of tag groups")
+            }
+            Self::WorldTooLarge { n_nodes, n_edges } => write!(
+                f,
+                "The graph grew, or would have grown, past its size limits, to {} nodes and {} edges",
+                n_nodes, n_edges
+            ),
+            Self::InvalidFeed {
+                shepherd,
+                sheep,
+                violation,
+            } => write!(
+                f,
+                "Shepherd {} built an invalid feed for sheep {}: {}",
+                shepherd.0, sheep.0, violation
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Poisson(error) => Some(error),
+            Self::WorldTooLarge { .. } | Self::InvalidFeed { .. } => None,
+        }
+    }
+}
+
+impl From<PoissonError> for Error {
+    fn from(error: PoissonError) -> Self {
+        Self::Poisson(error)
+    }
+}
+
 /// A representation of the tags and content introduced at the beginning of a
 /// new epoch within the simulation
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Epoch {
     /// Tags introduced at the beginning of this epoch
     pub tags: Vec<TagId>,
 
     /// Items introduced at the beginning of this epoch
     pub items: Vec<(ItemId, Vec<TagId>)>,
+
+    /// The changes to the structure of the tags since the previous epoch, if
+    /// [`Settings::graph_diffs`] is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<GraphDiff>,
+}
+
+/// The changes made to the edges between tags and to the tag groups between
+/// two epochs
+///
+/// The tags of new items are not included, as they are already given
+/// alongside the items in the [`Epoch`], and the tags of existing items never
+/// change
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct GraphDiff {
+    /// Edges between tags which were added or whose weight changed, along
+    /// with their current weight
+    pub tag_edges: Vec<(TagId, TagId, u32)>,
+
+    /// Edges between tags which were removed
+    pub removed_tag_edges: Vec<(TagId, TagId)>,
+
+    /// Tags whose group changed, along with the index of their current group
+    /// or [`None`] if they no longer belong to one
+    pub tag_groups: Vec<(TagId, Option<usize>)>,
+}
+
+/// A window of epochs during which the simulator interferes with the feeds
+/// returned by shepherds, independently of the shepherds themselves
+#[derive(Clone, PartialEq, Debug)]
+pub struct Degradation {
+    /// The first and last epochs during which the degradation is in effect
+    pub epochs: (usize, usize),
+
+    /// The probability that a feed is dropped, leaving the sheep with an
+    /// empty feed and the [`Shepherd`] without any responses to it
+    pub drop_probability: f64,
+
+    /// The probability that a feed is delayed until the next time the sheep
+    /// would be given a feed by the [`Shepherd`], leaving the sheep with an
+    /// empty feed in the meantime
+    pub delay_probability: f64,
+}
+
+/// A model of retention, under which sheep given mostly negative feeds by a
+/// [`Shepherd`] use it less often and eventually leave it
+///
+/// Each sheep's satisfaction with each shepherd starts at one, and is moved
+/// towards the fraction of each feed it is given which was not responded to
+/// negatively, with missing items from a short feed counted as negative. An
+/// active sheep requests a feed from a shepherd with a probability equal to
+/// its satisfaction with it, and never requests one again once it falls
+/// below the churn threshold
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Retention {
+    /// The weight given to each new feed when updating satisfaction, which
+    /// is an exponential moving average
+    pub smoothing: f64,
+
+    /// The satisfaction below which a sheep leaves a shepherd
+    pub churn_threshold: f64,
+}
+
+/// The [`Feed`] given to a sheep in place of one a [`Shepherd`] did not build
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum FallbackFeed {
+    /// An empty feed
+    #[default]
+    Empty,
+
+    /// A feed of items chosen uniformly at random from every item in the
+    /// simulation the sheep has yet to be shown, as a baseline a shepherd is
+    /// never worse than
+    Random,
+}
+
+impl FallbackFeed {
+    /// Build a fallback [`Feed`] of the given size from the given items,
+    /// leaving out those the sheep has already been shown
+    fn build(
+        self,
+        rng: &mut (impl Rng + ?Sized),
+        items: impl Iterator<Item = ItemId>,
+        seen: Option<&HashSet<ItemId>>,
+        feed_size: usize,
+    ) -> Feed {
+        match self {
+            Self::Empty => Feed(Vec::new()),
+            Self::Random => Feed(
+                items
+                    .filter(|item| {
+                        !seen.is_some_and(|seen| seen.contains(item))
+                    })
+                    .choose_multiple(rng, feed_size),
+            ),
+        }
+    }
+}
+
+/// How a [`Feed`] built by a [`Shepherd`] is checked for
+/// [`FeedViolation`]s before it is shown to a sheep
+///
+/// Whatever this is, every violation is passed to
+/// [`Settings::feed_violation_hook`]. Feeds which were not built by the
+/// shepherd, such as fallback feeds, are never checked
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum FeedValidation {
+    /// Feeds are shown exactly as they were built, and violations are only
+    /// reported
+    #[default]
+    Report,
+
+    /// Items breaking the rules are stripped from feeds before they are
+    /// shown, along with any justifications given for them
+    ///
+    /// Stripped items count towards under-delivery like any other missing
+    /// item
+    Sanitize,
+
+    /// The simulation stops with [`Error::InvalidFeed`] at the first
+    /// violation
+    Strict,
+}
+
+impl FeedValidation {
+    /// Decide what is shown of a feed and the justifications given for it,
+    /// given the items which broke none of the rules and the violations found
+    /// by [`validate_feed`]
+    ///
+    /// The first violation is returned instead if the feed may not be shown
+    /// at all
+    #[allow(clippy::type_complexity)]
+    fn apply(
+        self,
+        (feed, justifications): (Feed, Vec<(ItemId, Justification)>),
+        valid: Feed,
+        violations: &[FeedViolation],
+    ) -> Result<(Feed, Vec<(ItemId, Justification)>), FeedViolation> {
+        match self {
+            Self::Report => Ok((feed, justifications)),
+            Self::Strict => match violations.first() {
+                Some(violation) => Err(*violation),
+                None => Ok((feed, justifications)),
+            },
+            Self::Sanitize => {
+                let justifications = justifications
+                    .into_iter()
+                    .filter(|(item, _)| valid.0.contains(item))
+                    .collect();
+                Ok((valid, justifications))
+            }
+        }
+    }
+}
+
+/// Check the items of a feed built for a sheep in order, returning the
+/// items which broke none of the rules and every [`FeedViolation`] found
+///
+/// Only the first rule each item breaks is reported, and an item only
+/// counts against the feed size if it broke none of the others. Items are
+/// only checked against `candidates` if the feed was ranked from them
+fn validate_feed(
+    feed: &Feed,
+    item_epochs: &HashMap<ItemId, EpochId>,
+    seen: Option<&HashSet<ItemId>>,
+    candidates: Option<&HashSet<ItemId>>,
+    feed_size: usize,
+) -> (Feed, Vec<FeedViolation>) {
+    let mut valid = Vec::with_capacity(feed.0.len().min(feed_size));
+    let mut included = HashSet::default();
+    let mut violations = Vec::new();
+    for item in feed.0.iter().copied() {
+        if !item_epochs.contains_key(&item) {
+            violations.push(FeedViolation::UnknownItem(item));
+        } else if seen.is_some_and(|seen| seen.contains(&item)) {
+            violations.push(FeedViolation::AlreadyShown(item));
+        } else if candidates
+            .is_some_and(|candidates| !candidates.contains(&item))
+        {
+            violations.push(FeedViolation::NotCandidate(item));
+        } else if included.contains(&item) {
+            violations.push(FeedViolation::DuplicateItem(item));
+        } else if valid.len() >= feed_size {
+            violations.push(FeedViolation::Overlong(item));
+        } else {
+            included.insert(item);
+            valid.push(item);
+        }
+    }
+
+    (Feed(valid), violations)
+}
+
+/// How well each stage of building a [`Feed`] in two stages did (see
+/// [`Settings::n_candidates`])
+///
+/// Both stages are measured by the number of positive responses a sheep
+/// is expected to give a feed, going by how likely it is to respond
+/// positively to each item (see [`sheep::affinities`]), and compared
+/// against the best feed which could have been built at that stage
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct StageMetrics {
+    /// The number of candidates the [`Shepherd`] retrieved
+    pub n_retrieved: usize,
+
+    /// The number of candidates left to rank, once those the sheep could not
+    /// be shown were filtered out
+    pub n_candidates: usize,
+
+    /// The expected positive responses to the best feed which could have
+    /// been ranked from the candidates, as a fraction of those to the best
+    /// feed which could have been built from every item the sheep could be
+    /// shown
+    pub retrieval_recall: f64,
+
+    /// The expected positive responses to the ranked feed, as a fraction of
+    /// those to the best feed which could have been ranked from the
+    /// candidates
+    pub ranking_precision: f64,
+}
+
+impl StageMetrics {
+    /// Measure both stages of building a feed, given the items the sheep
+    /// could be shown, the number of candidates retrieved, the candidates
+    /// left to rank and the ranked feed
+    ///
+    /// A stage with nothing to gain is treated as though it did as well as
+    /// it could have
+    fn new(
+        affinities: &HashMap<ItemId, f64>,
+        eligible: impl IntoIterator<Item = ItemId>,
+        n_retrieved: usize,
+        candidates: &[ItemId],
+        feed: &Feed,
+        feed_size: usize,
+    ) -> Self {
+        let affinity =
+            |item: &ItemId| affinities.get(item).copied().unwrap_or(0.0);
+        // the best feed is made up of the items with the highest affinities
+        let best = |items: &mut dyn Iterator<Item = ItemId>| {
+            let mut affinities =
+                items.map(|item| affinity(&item)).collect::<Vec<_>>();
+            affinities.sort_unstable_by(|a, b| b.total_cmp(a));
+            affinities.into_iter().take(feed_size).sum::<f64>()
+        };
+        let fraction = |value: f64, best: f64| {
+            if best > 0.0 {
+                value / best
+            } else {
+                1.0
+            }
+        };
+
+        let best_possible = best(&mut eligible.into_iter());
+        let best_ranked = best(&mut candidates.iter().copied());
+        let mut included = HashSet::default();
+        let ranked = feed
+            .0
+            .iter()
+            .filter(|item| {
+                candidates.contains(item) && included.insert(**item)
+            })
+            .take(feed_size)
+            .map(affinity)
+            .sum();
+
+        Self {
+            n_retrieved,
+            n_candidates: candidates.len(),
+            retrieval_recall: fraction(best_ranked, best_possible),
+            ranking_precision: fraction(ranked, best_ranked),
+        }
+    }
+}
+
+/// The parameters of a simulation run which shepherds are allowed to know
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RunConfig {
+    /// The number of items shepherds are expected to put in each feed
+    pub feed_size: usize,
+
+    /// The number of epochs the simulation is expected to run for, if known
+    pub n_epochs: Option<usize>,
 }
 
 /// A container for the state associated with a simulation
 #[derive(Default)]
-pub struct Simulation<'a, 'de> {
+pub struct Simulation<'a> {
     /// The epoch counter
     current_epoch: EpochId,
 
@@ -124,15 +949,73 @@ pub struct Simulation<'a, 'de> {
     /// Items present in the simulation
     items: HashMap<ItemId, Vec<usize>>,
 
+    /// The epoch during which each item was introduced
+    item_epochs: HashMap<ItemId, EpochId>,
+
     /// Tag groups present in the simulation
     tag_groups: Vec<HashSet<TagId>>,
 
     /// Orphaned tags present in the simulation
     tag_orphans: HashSet<TagId>,
 
-    /// [`Shepherd`]s present within the simulation and a map keeping track of
-    /// the items each one has shown each sheep
-    shepherds: Vec<(Shepherd<'de>, HashMap<SheepId, HashSet<ItemId>>)>,
+    /// The parent tag of each tag group which has one, keyed by the index of
+    /// the group
+    tag_parents: HashMap<usize, TagId>,
+
+    /// The parameters of each sheep present in the simulation
+    profiles: HashMap<SheepId, sheep::Profile>,
+
+    /// Sheep which rate feeds noisily
+    noisy_sheep: HashSet<SheepId>,
+
+    /// The tags of each sheep which are hidden from shepherds
+    hidden_tags: HashMap<SheepId, HashSet<TagId>>,
+
+    /// The edges between tags and the group of each tag as of the last
+    /// [`GraphDiff`], if they are being sent to shepherds
+    #[allow(clippy::type_complexity)]
+    tag_structure:
+        Option<(HashMap<(TagId, TagId), u32>, HashMap<TagId, usize>)>,
+
+    /// The tags each sheep was connected to as it was introduced, which its
+    /// preferences may since have drifted from (see
+    /// [`Settings::reinforcement_probability`])
+    original_tags: HashMap<SheepId, Vec<TagId>>,
+
+    /// The stats of the feeds each [`Shepherd`] built during each epoch
+    stats: metrics::Stats,
+
+    /// The number of times each [`Shepherd`] has recommended each item
+    exposures: metrics::Exposures,
+
+    /// The epoch each item and sheep was introduced in, and which of them
+    /// each [`Shepherd`] has reached since
+    cold_start: metrics::ColdStart,
+
+    /// The time each [`Shepherd`] took to build each of its feeds during
+    /// each epoch
+    latencies: metrics::Latencies,
+
+    /// [`Shepherd`]s present within the simulation, a map keeping track of
+    /// the items each one has shown each sheep, the feeds from each one which
+    /// have been delayed by a [`Degradation`], the fatigue each sheep has
+    /// built up towards the feeds of each one, the hidden tags of each
+    /// sheep each one has discovered, the responses buffered for each one
+    /// until its next batch is delivered, the satisfaction of each sheep
+    /// with each one under the [`Retention`] model, and the items each one
+    /// may access during the current epoch under
+    /// [`Settings::item_access_budget`]
+    #[allow(clippy::type_complexity)]
+    shepherds: Vec<(
+        Shepherd,
+        HashMap<SheepId, HashSet<ItemId>>,
+        HashMap<SheepId, (Feed, Vec<(ItemId, Justification)>)>,
+        HashMap<SheepId, sheep::Fatigue>,
+        HashMap<SheepId, HashSet<TagId>>,
+        Vec<(SheepId, EpochId, LimitedResponses)>,
+        HashMap<SheepId, f64>,
+        HashSet<ItemId>,
+    )>,
 }
 
 /// A container for the deconstructed parts of a simulation
@@ -155,46 +1038,69 @@ pub struct SimulationParts<'a> {
     /// The items present in the simulation
     pub items: HashMap<ItemId, Vec<usize>>,
 
+    /// The epoch during which each item was introduced
+    pub item_epochs: HashMap<ItemId, EpochId>,
+
     /// The tag groups present in the simulation
     pub tag_groups: Vec<HashSet<TagId>>,
 
     /// The orphaned tags present in the simulation
     pub tag_orphans: HashSet<TagId>,
 
+    /// The parent tag of each tag group which had one, keyed by the index of
+    /// the group
+    pub tag_parents: HashMap<usize, TagId>,
+
+    /// The parameters of the sheep present in the simulation
+    pub profiles: HashMap<SheepId, sheep::Profile>,
+
+    /// The sheep which rated feeds noisily
+    pub noisy_sheep: HashSet<SheepId>,
+
+    /// The tags of each sheep which were hidden from shepherds
+    pub hidden_tags: HashMap<SheepId, HashSet<TagId>>,
+
     /// IDs of the shepherds present in the simulation
     pub shepherd_ids: Vec<ShepherdId>,
+
+    /// The stats of the feeds each shepherd built during each epoch
+    pub stats: metrics::Stats,
+
+    /// The epoch each item and sheep was introduced in, and which of them
+    /// each shepherd reached
+    pub cold_start: metrics::ColdStart,
+
+    /// The time each shepherd took to build each of its feeds during each
+    /// epoch
+    pub latencies: metrics::Latencies,
 }
 
-impl<'a, 'de> Simulation<'a, 'de> {
+impl<'a> Simulation<'a> {
+    #[instrument(skip_all)]
     pub fn new(
         rng: &mut (impl Rng + ?Sized),
-        shepherds: impl IntoIterator<Item = Shepherd<'de>>,
+        shepherds: impl IntoIterator<Item = Shepherd>,
         settings: Settings<'a>,
-    ) -> Result<Self, PoissonError> {
-        let mut simulation = Self {
-            settings,
-            shepherds: shepherds
-                .into_iter()
-                .map(|s| (s, Default::default()))
-                .collect(),
-            ..Default::default()
-        };
-
-        // NOTE: since for now we don't assign shepherds any additional id
-        //       this is just giving the hook indices into the shepherd vec,
-        //       but since this may change in the future this is probably a
-        //       good hook to have
-        if let Some(hook) = &mut simulation.settings.shepherd_assignment_hook
-        {
-            let mut shepherds =
-                HashMap::with_capacity(simulation.shepherds.len());
-            for (id, (shepherd, _)) in simulation.shepherds.iter().enumerate()
-            {
-                shepherds.insert(ShepherdId(id), shepherd.name().to_string());
-            }
-
-            hook(shepherds);
-        }
+    ) -> anyhow::Result<Self> {
+        let (mut simulation, archetypes) =
+            Self::prepare(shepherds, settings)?;
+
+        // the limits are checked against the largest world the settings
+        // allow before any of it is generated
+        let settings = &simulation.settings;
+        simulation.check_growth(
+            simulation.projected_growth(
+                settings.initial_n_tags_bounds.1,
+                settings.initial_n_sheep_bounds.1,
+                settings
+                    .archetypes
+                    .iter()
+                    .map(|archetype| archetype.n_tags_bounds.1)
+                    .max()
+                    .unwrap_or(0),
+                settings.initial_n_items_bounds.1,
+            ),
+        )?;
 
         simulation
             .tags
@@ -203,14 +1109,33 @@ impl<'a, 'de> Simulation<'a, 'de> {
                     ..=simulation.settings.initial_n_tags_bounds.1,
             )));
 
-        simulation.graph.add_new_tag_groups(
-            &mut *rng,
-            &mut simulation.tag_groups,
-            &mut simulation.tag_orphans,
-            simulation.tags.len()
-                / simulation.settings.average_tags_per_group,
-            simulation.tags.iter().copied(),
-        )?;
+        match simulation.settings.graph_generator {
+            GraphGenerator::PreferentialAttachment { n_edges } => {
+                simulation.graph.attach_preferentially(
+                    &mut *rng,
+                    &[],
+                    simulation.tags.iter().copied(),
+                    n_edges,
+                    simulation.settings.edge_semantics,
+                );
+                simulation
+                    .tag_groups
+                    .push(simulation.tags.iter().copied().collect());
+            }
+            generator => simulation.graph.add_new_tag_groups(
+                &mut *rng,
+                &mut simulation.tag_groups,
+                &mut simulation.tag_orphans,
+                simulation.tags.len()
+                    / simulation.settings.average_tags_per_group,
+                simulation.tags.iter().copied(),
+                generator
+                    .group_probabilities()
+                    .expect("The generator forms tag groups"),
+                simulation.settings.edge_semantics,
+            )?,
+        }
+        simulation.add_parent_tags(&mut *rng, 0);
 
         simulation.sheep.extend(simulation.graph.create_nodes(
             rng.gen_range(
@@ -218,14 +1143,23 @@ impl<'a, 'de> Simulation<'a, 'de> {
                     ..=simulation.settings.initial_n_sheep_bounds.1,
             ),
         ));
-        simulation.graph.connect_extremities(
-            &mut *rng,
-            simulation.sheep.iter().copied(),
-            simulation.tags.iter().copied(),
-            simulation.settings.n_sheep_tags_bounds.0
-                ..=simulation.settings.n_sheep_tags_bounds.1,
-            false,
-        );
+
+        let archetype_members =
+            simulation.assign_profiles(&mut *rng, &archetypes);
+        for (archetype, members) in
+            simulation.settings.archetypes.iter().zip(archetype_members)
+        {
+            simulation.graph.connect_extremities(
+                &mut *rng,
+                members,
+                simulation.tags.iter().copied(),
+                archetype.n_tags_bounds.0..=archetype.n_tags_bounds.1,
+                false,
+                simulation.settings.edge_semantics,
+            );
+        }
+
+        simulation.choose_noisy_sheep(&mut *rng);
 
         simulation.items.extend(simulation.graph.create_nodes(
             rng.gen_range(
@@ -240,76 +1174,812 @@ impl<'a, 'de> Simulation<'a, 'de> {
             simulation.settings.n_item_tags_bounds.0
                 ..=simulation.settings.n_item_tags_bounds.1,
             true,
+            simulation.settings.edge_semantics,
         );
 
-        let introduction_epoch = Epoch {
-            tags: simulation.tags.clone(),
-            items: simulation
-                .items
-                .clone()
-                .into_iter()
-                .map(|id| {
-                    (
-                        id,
-                        simulation
-                            .graph
-                            .0
-                            .neighbors_undirected(id.0.into())
-                            .map(|id| GraphId::new(id.index()))
-                            .collect(),
-                    )
-                })
-                .collect(),
-        };
+        simulation.check_size()?;
+        simulation.introduce(rng);
+
+        Ok(simulation)
+    }
 
-        if let Some(hook) = &mut simulation.settings.new_epoch_hook {
-            hook(simulation.current_epoch, &introduction_epoch);
+    /// Create a simulation seeded with a pre-built graph in place of a
+    /// randomly generated one
+    ///
+    /// Sheep, tags, and items are taken from the types of the nodes in the
+    /// graph. Tags connected to one another form tag groups, and tags without
+    /// any connections to other tags are orphaned. Sheep are assigned
+    /// archetypes as usual, but the tag count bounds of the archetypes are
+    /// ignored in favor of the edges already present in the graph. Unless
+    /// the graph is undirected (see [`Settings::edge_semantics`]), its edges
+    /// must run in the directions described by [`GraphDescription`]
+    ///
+    /// [`GraphDescription`]: crate::graph::GraphDescription
+    #[instrument(skip_all)]
+    pub fn with_graph(
+        rng: &mut (impl Rng + ?Sized),
+        graph: SimulationGraph,
+        shepherds: impl IntoIterator<Item = Shepherd>,
+        settings: Settings<'a>,
+    ) -> anyhow::Result<Self> {
+        let (mut simulation, archetypes) =
+            Self::prepare(shepherds, settings)?;
+
+        for node in graph.0.node_indices() {
+            match graph.0[node] {
+                NodeType::Sheep => {
+                    simulation.sheep.extend([GraphId::new(node.index())])
+                }
+                NodeType::Tag => {
+                    simulation.tags.push(GraphId::new(node.index()))
+                }
+                NodeType::Item => {
+                    simulation.items.extend([GraphId::new(node.index())])
+                }
+                NodeType::Removed => {}
+            }
         }
 
-        let introduction_epoch = SimulationEvent::BeginEpoch {
-            id: simulation.current_epoch,
-            data: introduction_epoch,
-        };
-        for (shepherd, _) in &mut simulation.shepherds {
-            shepherd.write_event(&introduction_epoch);
-            for sheep in simulation.sheep.iter().copied() {
-                shepherd.introduce_to(&simulation.graph, sheep);
+        // edges only run back to sheep or out of items when every edge is
+        // mirrored
+        if simulation.settings.edge_semantics != EdgeSemantics::Undirected {
+            for edge in graph.0.edge_references() {
+                let (source, target) =
+                    (graph.0[edge.source()], graph.0[edge.target()]);
+                ensure!(
+                    !matches!(
+                        (source, target),
+                        (NodeType::Tag, NodeType::Sheep)
+                            | (NodeType::Item, NodeType::Tag)
+                    ),
+                    "The seed graph has an edge from a {:?} to a {:?}, which only undirected graphs may have",
+                    source,
+                    target
+                );
             }
         }
 
+        let (tag_groups, tag_orphans) =
+            graph.tag_components(&simulation.tags);
+        simulation.tag_groups = tag_groups;
+        simulation.tag_orphans = tag_orphans;
+        simulation.graph = graph;
+
+        simulation.assign_profiles(&mut *rng, &archetypes);
+        simulation.choose_noisy_sheep(&mut *rng);
+        simulation.check_size()?;
+        simulation.introduce(rng);
+
         Ok(simulation)
     }
 
-    pub fn simulate_epoch(
-        &mut self,
-        rng: &mut (impl Rng + ?Sized),
-    ) -> Result<(), PoissonError> {
-        let new_tags = self
-            .graph
-            .create_nodes(rng.gen_range(
-                self.settings.n_tags_bounds.0..=self.settings.n_tags_bounds.1,
-            ))
-            .collect::<Vec<_>>();
-        self.graph.add_to_tag_groups(
-            &mut *rng,
-            &mut self.tag_groups,
-            &mut self.tag_orphans,
-            new_tags.iter().copied(),
-        )?;
-        self.tags.extend(new_tags.iter());
-
-        if self.tag_orphans.len() >= self.settings.orphaned_tag_threshold {
-            let orphans = self.tag_orphans.clone();
-            self.tag_orphans.clear();
-            self.graph.add_new_tag_groups(
-                &mut *rng,
-                &mut self.tag_groups,
-                &mut self.tag_orphans,
+    /// Validate the settings and set up the shepherds of a simulation which
+    /// has yet to be populated, returning it alongside the distribution sheep
+    /// archetypes are sampled from
+    fn prepare(
+        shepherds: impl IntoIterator<Item = Shepherd>,
+        settings: Settings<'a>,
+    ) -> anyhow::Result<(Self, WeightedIndex<f64>)> {
+        for archetype in &settings.archetypes {
+            ensure!(
+                (0.0..=1.0).contains(&archetype.activity_probability),
+                "The activity probability of the {} archetype is not within [0, 1]",
+                archetype.name
+            );
+            ensure!(
+                archetype.temperature_bounds.0 > 0.0
+                    && archetype.temperature_bounds.0
+                        <= archetype.temperature_bounds.1,
+                "The temperature bounds of the {} archetype are not positive and ordered",
+                archetype.name
+            );
+        }
+        for degradation in &settings.degradations {
+            ensure!(
+                degradation.drop_probability >= 0.0
+                    && degradation.delay_probability >= 0.0
+                    && degradation.drop_probability
+                        + degradation.delay_probability
+                        <= 1.0,
+                "The probabilities of a degradation are not within [0, 1]"
+            );
+            ensure!(
+                degradation.epochs.0 <= degradation.epochs.1,
+                "The first epoch of a degradation is after its last"
+            );
+        }
+        if let GraphGenerator::StochasticBlock {
+            within_probability,
+            between_probability,
+        } = settings.graph_generator
+        {
+            ensure!(
+                (0.0..=1.0).contains(&within_probability)
+                    && (0.0..=1.0).contains(&between_probability),
+                "The probabilities of the stochastic block model are not within [0, 1]"
+            );
+        }
+        ensure!(
+            (0.0..=1.0).contains(&settings.group_merge_probability)
+                && (0.0..=1.0).contains(&settings.group_split_probability),
+            "The tag group merge and split probabilities are not within [0, 1]"
+        );
+        ensure!(
+            (0.0..=1.0).contains(&settings.parent_tag_probability),
+            "The parent tag probability is not within [0, 1]"
+        );
+        ensure!(
+            settings.parent_tag_discount > 0.0
+                && settings.parent_tag_discount <= 1.0,
+            "The parent tag discount is not within (0, 1]"
+        );
+        ensure!(
+            (0.0..=1.0).contains(&settings.hidden_tag_fraction),
+            "The hidden tag fraction is not within [0, 1]"
+        );
+        ensure!(
+            (0.0..=1.0).contains(&settings.fatigue_recovery_rate),
+            "The fatigue recovery rate is not within [0, 1]"
+        );
+        ensure!(
+            (0.0..=1.0).contains(&settings.reinforcement_probability),
+            "The reinforcement probability is not within [0, 1]"
+        );
+        settings
+            .sheep_behavior
+            .validate()
+            .context("The sheep behavior's settings are invalid")?;
+        ensure!(
+            settings.response_batch_interval != Some(0),
+            "The response batch interval is zero"
+        );
+        if let Some(retention) = settings.retention {
+            ensure!(
+                (0.0..=1.0).contains(&retention.smoothing)
+                    && (0.0..=1.0).contains(&retention.churn_threshold),
+                "The retention smoothing and churn threshold are not within [0, 1]"
+            );
+        }
+        let archetypes = WeightedIndex::new(
+            settings
+                .archetypes
+                .iter()
+                .map(|archetype| archetype.proportion),
+        )
+        .context("Unable to weight the sheep archetypes by proportion")?;
+
+        let mut greeted = Vec::new();
+        for mut shepherd in shepherds {
+            shepherd.set_output_quota(settings.shepherd_output_quota);
+            shepherd.set_request_timeout(settings.shepherd_request_timeout);
+            shepherd.set_heartbeat(settings.shepherd_heartbeat);
+            shepherd.greet().with_context(|| {
+                format!(
+                    "Unable to complete the handshake with {}",
+                    shepherd.name()
+                )
+            })?;
+            ensure!(
+                settings.response_batch_interval.is_none()
+                    || shepherd.supports(Capability::ResponseBatches),
+                "Responses are batched, but {} does not support response batches",
+                shepherd.name()
+            );
+            greeted.push((
+                shepherd,
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+            ));
+        }
+
+        let mut simulation = Self {
+            settings,
+            shepherds: greeted,
+            ..Default::default()
+        };
+
+        // NOTE: since for now we don't assign shepherds any additional id
+        //       this is just giving the hook indices into the shepherd vec,
+        //       but since this may change in the future this is probably a
+        //       good hook to have
+        if let Some(hook) = &mut simulation.settings.shepherd_assignment_hook
+        {
+            let mut shepherds = HashMap::with_capacity_and_hasher(
+                simulation.shepherds.len(),
+                Default::default(),
+            );
+            for (id, (shepherd, ..)) in
+                simulation.shepherds.iter().enumerate()
+            {
+                shepherds.insert(ShepherdId(id), shepherd.name().to_string());
+            }
+
+            hook(shepherds);
+        }
+
+        Ok((simulation, archetypes))
+    }
+
+    /// Assign each sheep an archetype and a temperature sampled from it,
+    /// returning the members of each archetype
+    fn assign_profiles(
+        &mut self,
+        rng: &mut (impl Rng + ?Sized),
+        archetypes: &WeightedIndex<f64>,
+    ) -> Vec<Vec<SheepId>> {
+        let mut archetype_members =
+            vec![Vec::new(); self.settings.archetypes.len()];
+        for sheep in self.sheep.iter().copied() {
+            let archetype = archetypes.sample(&mut *rng);
+            let (min_temperature, max_temperature) =
+                self.settings.archetypes[archetype].temperature_bounds;
+            archetype_members[archetype].push(sheep);
+            self.profiles.insert(
+                sheep,
+                sheep::Profile {
+                    archetype,
+                    temperature: rng
+                        .gen_range(min_temperature..=max_temperature),
+                    noise: None,
+                },
+            );
+        }
+
+        archetype_members
+    }
+
+    /// Choose which sheep rate feeds noisily
+    fn choose_noisy_sheep(&mut self, rng: &mut (impl Rng + ?Sized)) {
+        let n_noisy_sheep = (self.sheep.len() as f64
+            * self.settings.noisy_sheep_fraction)
+            .round() as usize;
+        for sheep in self
+            .sheep
+            .iter()
+            .copied()
+            .choose_multiple(&mut *rng, n_noisy_sheep)
+        {
+            if let Some(profile) = self.profiles.get_mut(&sheep) {
+                profile.noise = Some(self.settings.noise);
+            }
+            self.noisy_sheep.insert(sheep);
+        }
+    }
+
+    /// Give each tag group from `first_group` onwards a parent tag with
+    /// probability [`Settings::parent_tag_probability`], returning the parent
+    /// tags which were added
+    fn add_parent_tags(
+        &mut self,
+        rng: &mut (impl Rng + ?Sized),
+        first_group: usize,
+    ) -> Vec<TagId> {
+        // the probability is checked first so that no randomness is consumed
+        // when hierarchical tags are disabled
+        if self.settings.parent_tag_probability <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut parents = Vec::new();
+        for group in first_group..self.tag_groups.len() {
+            if !rng.gen_bool(self.settings.parent_tag_probability) {
+                continue;
+            }
+
+            let parent = self
+                .graph
+                .create_nodes(1)
+                .next()
+                .expect("A node is always created");
+            self.graph.connect_to_parent(
+                &mut *rng,
+                parent,
+                self.tag_groups[group].iter().copied(),
+                self.settings.parent_tag_discount,
+                self.settings.edge_semantics,
+            );
+            self.tag_parents.insert(group, parent);
+            parents.push(parent);
+        }
+        self.tags.extend(parents.iter().copied());
+
+        parents
+    }
+
+    /// Possibly merge two tag groups and split one, as determined by
+    /// [`Settings::group_merge_probability`] and
+    /// [`Settings::group_split_probability`], returning events describing the
+    /// changes
+    ///
+    /// Groups which are merged into others are left empty rather than being
+    /// removed, so that the indices of the remaining groups stay stable
+    fn evolve_tag_groups(
+        &mut self,
+        rng: &mut (impl Rng + ?Sized),
+    ) -> Vec<SimulationEvent> {
+        let mut changes = Vec::new();
+        let sorted = |group: &HashSet<TagId>| {
+            let mut tags = group.iter().copied().collect::<Vec<_>>();
+            tags.sort_unstable_by_key(|tag| tag.0);
+            tags
+        };
+
+        // the probabilities are checked first so that no randomness is
+        // consumed when groups are static
+        if self.settings.group_merge_probability > 0.0
+            && rng.gen_bool(self.settings.group_merge_probability)
+        {
+            let candidates = (0..self.tag_groups.len())
+                .filter(|group| !self.tag_groups[*group].is_empty())
+                .choose_multiple(&mut *rng, 2);
+            if let [a, b] = candidates[..] {
+                let (a, b) = (a.min(b), a.max(b));
+                let absorbed = mem::take(&mut self.tag_groups[b]);
+                // each absorbed tag is linked to about as many tags as a
+                // member of a group has, so that merging two large groups
+                // doesn't add edges quadratically
+                self.graph.join_tags(
+                    &mut *rng,
+                    &self.tag_groups[a],
+                    &absorbed,
+                    self.settings.average_tags_per_group.max(1),
+                    self.settings.edge_semantics,
+                );
+
+                // the merged group keeps a single parent, which every one of
+                // its members is connected to. a parent left behind by the
+                // absorbed group is detached from it, and stays a tag of its
+                // own
+                match (
+                    self.tag_parents.get(&a).copied(),
+                    self.tag_parents.remove(&b),
+                ) {
+                    (Some(parent), absorbed_parent) => {
+                        if let Some(absorbed_parent) = absorbed_parent {
+                            self.graph.separate_tags(
+                                &absorbed,
+                                &[absorbed_parent].into_iter().collect(),
+                            );
+                        }
+                        self.graph.connect_to_parent(
+                            &mut *rng,
+                            parent,
+                            absorbed.iter().copied(),
+                            self.settings.parent_tag_discount,
+                            self.settings.edge_semantics,
+                        );
+                    }
+                    (None, Some(parent)) => {
+                        self.graph.connect_to_parent(
+                            &mut *rng,
+                            parent,
+                            self.tag_groups[a].iter().copied(),
+                            self.settings.parent_tag_discount,
+                            self.settings.edge_semantics,
+                        );
+                        self.tag_parents.insert(a, parent);
+                    }
+                    (None, None) => {}
+                }
+                for (_, _, _, fatigue, ..) in &mut self.shepherds {
+                    for fatigue in fatigue.values_mut() {
+                        fatigue.merge(b, a);
+                    }
+                }
+                info!(a, b, "two tag groups have merged");
+                changes.push(SimulationEvent::TagGroupsMerged {
+                    tags: (sorted(&self.tag_groups[a]), sorted(&absorbed)),
+                });
+
+                self.tag_groups[a].extend(absorbed);
+            }
+        }
+
+        if self.settings.group_split_probability > 0.0
+            && rng.gen_bool(self.settings.group_split_probability)
+        {
+            let candidate = (0..self.tag_groups.len())
+                .filter(|group| self.tag_groups[*group].len() >= 2)
+                .choose(&mut *rng);
+            if let Some(group) = candidate {
+                let mut tags = sorted(&self.tag_groups[group]);
+                tags.shuffle(&mut *rng);
+                let split = tags.split_off(tags.len() / 2);
+                let (kept, split) = (
+                    tags.into_iter().collect::<HashSet<_>>(),
+                    split.into_iter().collect::<HashSet<_>>(),
+                );
+                self.graph.separate_tags(&kept, &split);
+                // the new group is left without a parent, as the halves would
+                // otherwise stay a hop apart through the old one
+                if let Some(parent) = self.tag_parents.get(&group) {
+                    self.graph.separate_tags(
+                        &split,
+                        &[*parent].into_iter().collect(),
+                    );
+                }
+                info!(group, "a tag group has split");
+                changes.push(SimulationEvent::TagGroupSplit {
+                    tags: (sorted(&kept), sorted(&split)),
+                });
+
+                for (_, _, _, fatigue, ..) in &mut self.shepherds {
+                    for fatigue in fatigue.values_mut() {
+                        fatigue.split(group, self.tag_groups.len());
+                    }
+                }
+                self.tag_groups[group] = kept;
+                self.tag_groups.push(split);
+            }
+        }
+
+        changes
+    }
+
+    /// Check that the graph is within the size limits given in the settings
+    fn check_size(&self) -> Result<(), Error> {
+        self.check_growth((0, 0))
+    }
+
+    /// Check that the graph would stay within the size limits given in the
+    /// settings if it grew by the given numbers of nodes and edges
+    fn check_growth(
+        &self,
+        (n_new_nodes, n_new_edges): (usize, usize),
+    ) -> Result<(), Error> {
+        let (n_nodes, n_edges) = (
+            self.graph.0.node_count().saturating_add(n_new_nodes),
+            self.graph.0.edge_count().saturating_add(n_new_edges),
+        );
+        if self.settings.max_nodes.is_some_and(|max| n_nodes > max)
+            || self.settings.max_edges.is_some_and(|max| n_edges > max)
+        {
+            return Err(Error::WorldTooLarge { n_nodes, n_edges });
+        }
+
+        Ok(())
+    }
+
+    /// An upper bound on the numbers of nodes and edges added to the graph by
+    /// adding up to `n_tags` tags, `n_sheep` sheep with up to `n_sheep_tags`
+    /// tags each, and `n_items` items
+    ///
+    /// The edges formed between tags by generators which form tag groups are
+    /// left out, as their number depends on the groups the tags join, and so
+    /// are the edges strengthened when tag groups merge
+    fn projected_growth(
+        &self,
+        n_tags: usize,
+        n_sheep: usize,
+        n_sheep_tags: usize,
+        n_items: usize,
+    ) -> (usize, usize) {
+        let per_connection =
+            if self.settings.edge_semantics == EdgeSemantics::Undirected {
+                2
+            } else {
+                1
+            };
+
+        // every tag joins at most one new group, and each new group is given
+        // at most one parent tag, which is connected to each of its members
+        // by a pair of edges
+        let n_grouped = self.tag_orphans.len().saturating_add(n_tags);
+        let (n_parents, n_parent_edges) =
+            if self.settings.parent_tag_probability > 0.0 {
+                (
+                    (n_grouped / self.settings.average_tags_per_group.max(1))
+                        .saturating_add(1),
+                    n_grouped.saturating_mul(2),
+                )
+            } else {
+                (0, 0)
+            };
+        let n_tag_edges = match self.settings.graph_generator {
+            GraphGenerator::PreferentialAttachment { n_edges } => {
+                n_tags.saturating_mul(n_edges).saturating_mul(2)
+            }
+            _ => 0,
+        };
+
+        let n_nodes = n_tags
+            .saturating_add(n_sheep)
+            .saturating_add(n_items)
+            .saturating_add(n_parents);
+        let n_edges = n_sheep
+            .saturating_mul(n_sheep_tags)
+            .saturating_add(
+                n_items.saturating_mul(self.settings.n_item_tags_bounds.1),
+            )
+            .saturating_mul(per_connection)
+            .saturating_add(n_tag_edges)
+            .saturating_add(n_parent_edges);
+
+        (n_nodes, n_edges)
+    }
+
+    /// Find the changes to the structure of the tags since the last call, if
+    /// [`Settings::graph_diffs`] is enabled
+    fn diff_graph(&mut self) -> Option<GraphDiff> {
+        if !self.settings.graph_diffs {
+            return None;
+        }
+
+        // parallel edges are collapsed into the lightest of them, as that is
+        // the only one a shortest path would take
+        let mut edges = HashMap::<(TagId, TagId), u32>::default();
+        for edge in self.graph.0.edge_references() {
+            let (source, target) = (edge.source(), edge.target());
+            if self.graph.0[source] != NodeType::Tag
+                || self.graph.0[target] != NodeType::Tag
+            {
+                continue;
+            }
+
+            let weight = edges
+                .entry((
+                    GraphId::new(source.index()),
+                    GraphId::new(target.index()),
+                ))
+                .or_insert(u32::MAX);
+            *weight = (*weight).min(edge.weight().affinity);
+        }
+
+        let groups = self
+            .tag_groups
+            .iter()
+            .enumerate()
+            .flat_map(|(i, group)| group.iter().map(move |tag| (*tag, i)))
+            .collect::<HashMap<_, _>>();
+
+        let (previous_edges, previous_groups) =
+            self.tag_structure.take().unwrap_or_default();
+
+        let mut diff = GraphDiff {
+            tag_edges: edges
+                .iter()
+                .filter(|(edge, weight)| {
+                    previous_edges.get(edge) != Some(weight)
+                })
+                .map(|((a, b), weight)| (*a, *b, *weight))
+                .collect(),
+            removed_tag_edges: previous_edges
+                .keys()
+                .filter(|edge| !edges.contains_key(edge))
+                .copied()
+                .collect(),
+            tag_groups: groups
+                .iter()
+                .filter(|(tag, group)| {
+                    previous_groups.get(tag) != Some(group)
+                })
+                .map(|(tag, group)| (*tag, Some(*group)))
+                .chain(
+                    previous_groups
+                        .keys()
+                        .filter(|tag| !groups.contains_key(tag))
+                        .map(|tag| (*tag, None)),
+                )
+                .collect(),
+        };
+        diff.tag_edges.sort_unstable_by_key(|(a, b, _)| (a.0, b.0));
+        diff.removed_tag_edges
+            .sort_unstable_by_key(|(a, b)| (a.0, b.0));
+        diff.tag_groups.sort_unstable_by_key(|(tag, _)| tag.0);
+
+        self.tag_structure = Some((edges, groups));
+        Some(diff)
+    }
+
+    /// Describe the members of every tag group, if
+    /// [`Settings::tag_group_snapshots`] is enabled
+    fn snapshot_tag_groups(&self) -> Option<SimulationEvent> {
+        if !self.settings.tag_group_snapshots {
+            return None;
+        }
+
+        Some(SimulationEvent::TagGroups {
+            groups: self
+                .tag_groups
+                .iter()
+                .map(|group| {
+                    let mut tags = group.iter().copied().collect::<Vec<_>>();
+                    tags.sort_unstable_by_key(|tag| tag.0);
+                    tags
+                })
+                .collect(),
+        })
+    }
+
+    /// Hide the tags of sheep, then introduce the shepherds to the initial
+    /// state of the simulation
+    fn introduce(&mut self, rng: &mut (impl Rng + ?Sized)) {
+        // the fraction is checked first so that no randomness is consumed when
+        // no tags are hidden
+        if self.settings.hidden_tag_fraction > 0.0 {
+            for sheep in self.sheep.iter().copied() {
+                let tags =
+                    self.graph.associated_tags(sheep).collect::<Vec<_>>();
+                let n_hidden = (tags.len() as f64
+                    * self.settings.hidden_tag_fraction)
+                    .round() as usize;
+                self.hidden_tags.insert(
+                    sheep,
+                    tags.into_iter()
+                        .choose_multiple(&mut *rng, n_hidden)
+                        .into_iter()
+                        .collect(),
+                );
+            }
+        }
+
+        self.item_epochs.extend(
+            self.items.iter().map(|item| (*item, self.current_epoch)),
+        );
+        for item in self.items.iter().copied() {
+            self.cold_start.introduce_item(item, self.current_epoch);
+        }
+        for sheep in self.sheep.iter().copied() {
+            self.cold_start.introduce_sheep(sheep, self.current_epoch);
+        }
+        self.original_tags = self
+            .sheep
+            .iter()
+            .map(|sheep| {
+                (*sheep, self.graph.associated_tags(*sheep).collect())
+            })
+            .collect();
+
+        let introduction_epoch = Epoch {
+            tags: self.tags.clone(),
+            items: self
+                .items
+                .clone()
+                .into_iter()
+                .map(|id| (id, self.graph.associated_tags(id).collect()))
+                .collect(),
+            diff: self.diff_graph(),
+        };
+
+        if let Some(hook) = &mut self.settings.new_epoch_hook {
+            hook(self.current_epoch, &introduction_epoch);
+        }
+
+        let run_config = SimulationEvent::RunConfig(RunConfig {
+            feed_size: self.settings.feed_size,
+            n_epochs: self.settings.n_epochs,
+        });
+        let introduction_epoch = SimulationEvent::BeginEpoch {
+            id: self.current_epoch,
+            data: introduction_epoch,
+        };
+        let tag_groups = self.snapshot_tag_groups();
+        for (shepherd, .., accessible) in &mut self.shepherds {
+            shepherd.write_event(&run_config);
+            match (self.settings.item_access_budget, &introduction_epoch) {
+                (Some(budget), SimulationEvent::BeginEpoch { id, data }) => {
+                    let (items, data) = restrict_epoch(
+                        &mut *rng,
+                        &self.graph,
+                        self.items.iter().copied(),
+                        budget,
+                        data,
+                    );
+                    *accessible = items;
+                    shepherd.begin(*id, data);
+                }
+                _ => shepherd.write_event(&introduction_epoch),
+            }
+            if let Some(tag_groups) = &tag_groups {
+                shepherd.write_event(tag_groups);
+            }
+            for sheep in self.sheep.iter().copied() {
+                shepherd.introduce_to(
+                    sheep,
+                    visible_tags(&self.graph, &self.hidden_tags, sheep),
+                );
+            }
+            shepherd.flush();
+        }
+    }
+
+    #[instrument(skip_all, fields(epoch = self.current_epoch.0 + 1))]
+    pub fn simulate_epoch(
+        &mut self,
+        rng: &mut (impl Rng + ?Sized),
+    ) -> Result<(), Error> {
+        // the limits are checked against the most the graph could grow by
+        // before any of it is grown, which never includes new sheep
+        self.check_growth(self.projected_growth(
+            self.settings.n_tags_bounds.1,
+            0,
+            0,
+            self.settings.n_items_bounds.1,
+        ))?;
+
+        // everything added to the graph from here on belongs to the epoch
+        // being simulated
+        self.graph.1 = EpochId(self.current_epoch.0 + 1);
+
+        let growth = info_span!("grow_graph").entered();
+        let mut new_tags = self
+            .graph
+            .create_nodes(rng.gen_range(
+                self.settings.n_tags_bounds.0..=self.settings.n_tags_bounds.1,
+            ))
+            .collect::<Vec<_>>();
+        match self.settings.graph_generator {
+            GraphGenerator::PreferentialAttachment { n_edges } => {
+                self.graph.attach_preferentially(
+                    &mut *rng,
+                    &self.tags,
+                    new_tags.iter().copied(),
+                    n_edges,
+                    self.settings.edge_semantics,
+                );
+                if let Some(group) = self.tag_groups.first_mut() {
+                    group.extend(new_tags.iter().copied());
+                } else {
+                    self.tag_groups.push(new_tags.iter().copied().collect());
+                }
+            }
+            generator => self.graph.add_to_tag_groups(
+                &mut *rng,
+                &mut self.tag_groups,
+                &mut self.tag_orphans,
+                new_tags.iter().copied(),
+                generator
+                    .group_probabilities()
+                    .expect("The generator forms tag groups"),
+                self.settings.edge_semantics,
+            )?,
+        }
+        self.tags.extend(new_tags.iter());
+
+        if !self.tag_parents.is_empty() {
+            for tag in new_tags.iter().copied() {
+                let parent = self
+                    .tag_groups
+                    .iter()
+                    .position(|group| group.contains(&tag))
+                    .and_then(|group| self.tag_parents.get(&group));
+                if let Some(parent) = parent {
+                    self.graph.connect_to_parent(
+                        &mut *rng,
+                        *parent,
+                        [tag],
+                        self.settings.parent_tag_discount,
+                        self.settings.edge_semantics,
+                    );
+                }
+            }
+        }
+
+        if self.tag_orphans.len() >= self.settings.orphaned_tag_threshold {
+            let first_group = self.tag_groups.len();
+            let orphans = self.tag_orphans.clone();
+            self.tag_orphans.clear();
+            self.graph.add_new_tag_groups(
+                &mut *rng,
+                &mut self.tag_groups,
+                &mut self.tag_orphans,
                 orphans.len() / self.settings.average_tags_per_group,
                 orphans,
+                self.settings.graph_generator.group_probabilities().expect(
+                    "Only generators which form tag groups orphan tags",
+                ),
+                self.settings.edge_semantics,
             )?;
+            new_tags.extend(self.add_parent_tags(&mut *rng, first_group));
         }
 
+        let group_changes = self.evolve_tag_groups(&mut *rng);
+
         let new_items = self
             .graph
             .create_nodes(rng.gen_range(
@@ -324,25 +1994,27 @@ impl<'a, 'de> Simulation<'a, 'de> {
             self.settings.n_item_tags_bounds.0
                 ..=self.settings.n_item_tags_bounds.1,
             true,
+            self.settings.edge_semantics,
         );
         self.items.extend(new_items.iter());
+        drop(growth);
+        self.check_size()?;
+        self.settings.sheep_behavior.graph_changed(None);
 
         self.current_epoch.0 += 1;
+        self.item_epochs
+            .extend(new_items.iter().map(|item| (*item, self.current_epoch)));
+        for item in new_items.iter().copied() {
+            self.cold_start.introduce_item(item, self.current_epoch);
+        }
+
         let current_epoch = Epoch {
             tags: new_tags,
             items: new_items
                 .into_iter()
-                .map(|id| {
-                    (
-                        id,
-                        self.graph
-                            .0
-                            .neighbors_undirected(id.0.into())
-                            .map(|id| GraphId::new(id.index()))
-                            .collect(),
-                    )
-                })
+                .map(|id| (id, self.graph.associated_tags(id).collect()))
                 .collect(),
+            diff: self.diff_graph(),
         };
 
         if let Some(hook) = &mut self.settings.new_epoch_hook {
@@ -355,40 +2027,548 @@ impl<'a, 'de> Simulation<'a, 'de> {
             n_groups = self.tag_groups.len(),
             n_items = self.items.len(),
             n_sheep = self.sheep.len(),
-            p_edges = (self.graph.0.edge_count() as f64
-                / (self.graph.0.node_count() as f64
-                    * (self.graph.0.node_count() - 1) as f64))
+            n_edges = self.graph.0.edge_count(),
+            "the graph has grown"
         );
 
+        // the statistics walk the whole graph, so they are only computed
+        // when something is listening for them
+        if let Some(hook) = &mut self.settings.world_stats_hook {
+            hook(self.current_epoch, &self.graph.stats());
+        }
+
         // TODO: alter sheep preferences here by some minute amount
         // TODO: add new sheep here
 
+        // inactive sheep are still introduced, but don't request feeds
+        let active_sheep = self
+            .sheep
+            .iter()
+            .copied()
+            .filter(|sheep| {
+                rng.gen_bool(
+                    self.settings.archetypes[self.profiles[sheep].archetype]
+                        .activity_probability,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let degradation =
+            self.settings.degradations.iter().find(|degradation| {
+                (degradation.epochs.0..=degradation.epochs.1)
+                    .contains(&self.current_epoch.0)
+            });
+        if let Some(degradation) = degradation {
+            info!(
+                epoch = self.current_epoch.0,
+                drop_probability = degradation.drop_probability,
+                delay_probability = degradation.delay_probability,
+                "a degradation is in effect"
+            );
+
+            if let Some(hook) = &mut self.settings.degradation_hook {
+                hook(self.current_epoch, degradation);
+            }
+        }
+
         let current_epoch = SimulationEvent::BeginEpoch {
             id: self.current_epoch,
             data: current_epoch,
         };
-        for (id, (shepherd, sheep_seen)) in self
+        let tag_groups = self.snapshot_tag_groups();
+        let n_hidden_tags =
+            self.hidden_tags.values().map(HashSet::len).sum::<usize>();
+
+        // only the edges between tags are followed, which don't change
+        // during the epoch, so these are shared between shepherds
+        let mut original_distances =
+            HashMap::<SheepId, HashMap<TagId, u32>>::default();
+
+        // the tag groups only change at the start of an epoch, so the items
+        // of each group in the catalog are the same for every shepherd
+        let tag_group_indices = self
+            .tag_groups
+            .iter()
+            .enumerate()
+            .flat_map(|(i, group)| group.iter().map(move |tag| (*tag, i)))
+            .collect::<HashMap<_, _>>();
+        let mut group_catalog = vec![0; self.tag_groups.len()];
+        for item in self.item_epochs.keys() {
+            for tag in self.graph.associated_tags(*item) {
+                if let Some(i) = tag_group_indices.get(&tag) {
+                    group_catalog[*i] += 1;
+                }
+            }
+        }
+
+        for (
+            id,
+            (
+                shepherd,
+                sheep_seen,
+                delayed_feeds,
+                fatigue,
+                discovered,
+                batched_responses,
+                satisfaction,
+                accessible,
+            ),
+        ) in self
             .shepherds
             .iter_mut()
             .enumerate()
             .map(|(id, data)| (ShepherdId(id), data))
         {
-            shepherd.write_event(&current_epoch);
-            for sheep in self.sheep.iter().copied() {
-                shepherd.introduce_to(&self.graph, sheep);
+            let _span =
+                info_span!("shepherd", id = id.0, name = %shepherd.name())
+                    .entered();
+
+            for fatigue in fatigue.values_mut() {
+                fatigue.recover(self.settings.fatigue_recovery_rate);
             }
 
+            info_span!("introductions").in_scope(|| {
+                match (self.settings.item_access_budget, &current_epoch) {
+                    (
+                        Some(budget),
+                        SimulationEvent::BeginEpoch { id, data },
+                    ) => {
+                        let (items, data) = restrict_epoch(
+                            &mut *rng,
+                            &self.graph,
+                            self.items.iter().copied(),
+                            budget,
+                            data,
+                        );
+                        *accessible = items;
+                        shepherd.begin(*id, data);
+                    }
+                    _ => shepherd.write_event(&current_epoch),
+                }
+                for change in &group_changes {
+                    shepherd.write_event(change);
+                }
+                if let Some(tag_groups) = &tag_groups {
+                    shepherd.write_event(tag_groups);
+                }
+                for sheep in self.sheep.iter().copied() {
+                    shepherd.introduce_to(
+                        sheep,
+                        visible_tags(&self.graph, &self.hidden_tags, sheep),
+                    );
+                }
+                shepherd.flush();
+            });
+
             // we don't merge the loop above into the one below as we want to
             // make sure the shepherd has the full picture prior to building
             // feeds
 
-            for sheep in self.sheep.iter().copied() {
-                let feed = shepherd.build_feed(sheep);
+            let mut sheep_stats = HashMap::<_, ResponseCounts>::default();
+            let mut sheep_tags =
+                HashMap::<SheepId, HashMap<TagId, usize>>::default();
+            let mut sheep_drift = HashMap::<SheepId, (u64, usize)>::default();
+            let mut n_active = 0;
+
+            // shepherds which take requests in batches are asked for every
+            // feed up front, so which sheep take part has to be decided
+            // before any feed is built
+            let budget = self.settings.shepherd_compute_budget;
+            let two_stage = self
+                .settings
+                .n_candidates
+                .filter(|_| shepherd.supports(Capability::TwoStageFeeds));
+            // feeds built in two stages are built one sheep at a time
+            let batched = two_stage.is_none()
+                && shepherd.supports(Capability::FeedBatches);
+            let participants = if batched {
+                active_sheep
+                    .iter()
+                    .copied()
+                    .filter(|sheep| {
+                        is_retained(
+                            self.settings.retention,
+                            satisfaction,
+                            *sheep,
+                            &mut *rng,
+                        )
+                    })
+                    .collect()
+            } else {
+                active_sheep.clone()
+            };
+            if batched && has_budget_left(budget, shepherd) {
+                shepherd.prefetch_feeds(
+                    participants
+                        .iter()
+                        .copied()
+                        .filter(|sheep| !delayed_feeds.contains_key(sheep))
+                        .collect(),
+                );
+                check_budget(
+                    budget,
+                    &mut self.settings.budget_exhausted_hook,
+                    id,
+                    self.current_epoch,
+                    shepherd,
+                );
+            }
+
+            for sheep in participants {
+                if !batched
+                    && !is_retained(
+                        self.settings.retention,
+                        satisfaction,
+                        sheep,
+                        &mut *rng,
+                    )
+                {
+                    continue;
+                }
+                n_active += 1;
+
+                let prefetched = shepherd.has_prefetched_feed(sheep);
+                let within_budget =
+                    prefetched || has_budget_left(budget, shepherd);
+
+                // the candidates a feed was ranked from, if it was built in
+                // two stages
+                let mut candidates = None;
+
+                // `from_shepherd` is false whenever the sheep is given a
+                // fallback feed in place of one built by the shepherd, and
+                // `timed_out` is set when that is because the shepherd didn't
+                // build one in time, so that the missing feed is counted
+                // against it
+                let mut timed_out = false;
+                let ((feed, justifications), from_shepherd) = if let Some(
+                    feed,
+                ) =
+                    delayed_feeds.remove(&sheep)
+                {
+                    (feed, true)
+                } else if shepherd.is_abandoned() {
+                    timed_out = true;
+                    let feed = self.settings.timeout_fallback.build(
+                        &mut *rng,
+                        self.items.iter().copied(),
+                        sheep_seen.get(&sheep),
+                        self.settings.feed_size,
+                    );
+                    ((feed, Vec::new()), false)
+                } else if within_budget {
+                    let feed = match two_stage {
+                        Some(n_candidates) => shepherd
+                            .retrieve_candidates(sheep, n_candidates)
+                            .and_then(|retrieved| {
+                                // candidates the sheep couldn't be shown are
+                                // filtered out before they are ranked
+                                let seen = sheep_seen.get(&sheep);
+                                let (filtered, _) = validate_feed(
+                                    &Feed(retrieved.clone()),
+                                    &self.item_epochs,
+                                    seen,
+                                    None,
+                                    usize::MAX,
+                                );
+                                let feed = shepherd.rank_candidates(
+                                    sheep,
+                                    filtered.0.clone(),
+                                )?;
+
+                                let metrics = StageMetrics::new(
+                                    &sheep::affinities(
+                                        &self.graph,
+                                        sheep,
+                                        &self.profiles[&sheep],
+                                    ),
+                                    self.item_epochs.keys().copied().filter(
+                                        |item| {
+                                            !seen.is_some_and(|seen| {
+                                                seen.contains(item)
+                                            })
+                                        },
+                                    ),
+                                    retrieved.len(),
+                                    &filtered.0,
+                                    &feed.0,
+                                    self.settings.feed_size,
+                                );
+                                info!(
+                                    shepherd = id.0,
+                                    sheep = sheep.0,
+                                    n_retrieved = metrics.n_retrieved,
+                                    n_candidates = metrics.n_candidates,
+                                    retrieval_recall =
+                                        metrics.retrieval_recall,
+                                    ranking_precision =
+                                        metrics.ranking_precision,
+                                    "a feed was built in two stages"
+                                );
+                                if let Some(hook) =
+                                    &mut self.settings.stage_metrics_hook
+                                {
+                                    hook(id, sheep, metrics);
+                                }
+
+                                candidates = Some(
+                                    filtered
+                                        .0
+                                        .into_iter()
+                                        .collect::<HashSet<_>>(),
+                                );
+                                Some(feed)
+                            }),
+                        None => shepherd.build_feed(sheep),
+                    };
+                    if !prefetched {
+                        check_budget(
+                            budget,
+                            &mut self.settings.budget_exhausted_hook,
+                            id,
+                            self.current_epoch,
+                            shepherd,
+                        );
+                    }
+
+                    match feed.map(|feed| {
+                        (feed, degradation.map(|d| (d, rng.gen::<f64>())))
+                    }) {
+                        None => {
+                            // shepherds which failed outright have already
+                            // been abandoned, and warned about
+                            if !shepherd.is_abandoned() {
+                                warn!(
+                                    shepherd = id.0,
+                                    sheep = sheep.0,
+                                    timeout = ?self.settings.shepherd_request_timeout,
+                                    "a shepherd did not respond to a feed request in time"
+                                );
+                            }
+
+                            if let Some(hook) =
+                                &mut self.settings.request_timeout_hook
+                            {
+                                hook(id, sheep);
+                            }
+
+                            if self.settings.abandon_timed_out_shepherds
+                                && !shepherd.is_abandoned()
+                            {
+                                warn!(
+                                    shepherd = id.0,
+                                    "a shepherd has been abandoned"
+                                );
+                                shepherd.abandon();
+                            }
+
+                            timed_out = true;
+                            let feed = self.settings.timeout_fallback.build(
+                                &mut *rng,
+                                self.items.iter().copied(),
+                                sheep_seen.get(&sheep),
+                                self.settings.feed_size,
+                            );
+                            ((feed, Vec::new()), false)
+                        }
+                        Some((_, Some((degradation, c))))
+                            if c < degradation.drop_probability =>
+                        {
+                            info!(
+                                shepherd = id.0,
+                                sheep = sheep.0,
+                                "a feed was dropped by a degradation"
+                            );
+                            ((Feed(Vec::new()), Vec::new()), false)
+                        }
+                        Some((feed, Some((degradation, c))))
+                            if c < degradation.drop_probability
+                                + degradation.delay_probability =>
+                        {
+                            info!(
+                                shepherd = id.0,
+                                sheep = sheep.0,
+                                "a feed was delayed by a degradation"
+                            );
+                            delayed_feeds.insert(sheep, feed);
+                            ((Feed(Vec::new()), Vec::new()), false)
+                        }
+                        Some((feed, _)) => (feed, true),
+                    }
+                } else {
+                    ((Feed(Vec::new()), Vec::new()), false)
+                };
+
+                // items beyond the shepherd's access budget are dropped
+                // before the feed is checked, as it could never have shown
+                // them. unknown items are left to be reported as violations
+                let (feed, justifications) = if from_shepherd
+                    && self.settings.item_access_budget.is_some()
+                {
+                    let (allowed, denied): (Vec<_>, Vec<_>) =
+                        feed.0.into_iter().partition(|item| {
+                            accessible.contains(item)
+                                || !self.item_epochs.contains_key(item)
+                        });
+                    let justifications = justifications
+                        .into_iter()
+                        .filter(|(item, _)| !denied.contains(item))
+                        .collect();
+
+                    if !denied.is_empty() {
+                        info!(
+                            shepherd = id.0,
+                            sheep = sheep.0,
+                            n_items = denied.len(),
+                            "a feed exceeded the item access budget"
+                        );
+                        if let Some(hook) =
+                            &mut self.settings.item_budget_exceeded_hook
+                        {
+                            hook(id, sheep, denied.len());
+                        }
+                        shepherd.notify_budget_exceeded(sheep, denied);
+                    }
+                    (Feed(allowed), justifications)
+                } else {
+                    (feed, justifications)
+                };
+
+                let (feed, justifications) = if from_shepherd {
+                    let (valid, violations) = validate_feed(
+                        &feed,
+                        &self.item_epochs,
+                        sheep_seen.get(&sheep),
+                        candidates.as_ref(),
+                        self.settings.feed_size,
+                    );
+                    if let Some(violation) = violations.first() {
+                        info!(
+                            shepherd = id.0,
+                            sheep = sheep.0,
+                            n_violations = violations.len(),
+                            first = %violation,
+                            "a feed broke the rules of the simulation"
+                        );
+                    }
+                    if let Some(hook) = &mut self.settings.feed_violation_hook
+                    {
+                        for violation in violations.iter().copied() {
+                            hook(id, sheep, violation);
+                        }
+                    }
+
+                    self.settings
+                        .feed_validation
+                        .apply((feed, justifications), valid, &violations)
+                        .map_err(|violation| Error::InvalidFeed {
+                            shepherd: id,
+                            sheep,
+                            violation,
+                        })?
+                } else {
+                    (feed, justifications)
+                };
 
                 if let Some(hook) = &mut self.settings.feed_generation_hook {
                     hook(id, sheep, &feed);
                 }
 
+                let n_missing = if from_shepherd {
+                    self.settings.feed_size.saturating_sub(feed.0.len())
+                } else if timed_out {
+                    self.settings.feed_size
+                } else {
+                    0
+                };
+                if n_missing > 0 {
+                    info!(
+                        shepherd = id.0,
+                        sheep = sheep.0,
+                        n_items = feed.0.len(),
+                        n_missing = n_missing,
+                        "a feed was under-delivered"
+                    );
+
+                    if let Some(hook) = &mut self.settings.under_delivery_hook
+                    {
+                        hook(id, sheep, n_missing);
+                    }
+
+                    if self.settings.under_delivery_notices
+                        && !shepherd.is_abandoned()
+                    {
+                        shepherd.notify_under_delivery(sheep, n_missing);
+                    }
+                }
+
+                // this has to be measured before the items of the feed are
+                // marked as seen, as they could be shown until then
+                let (ranking, regret) = if from_shepherd
+                    && (self.settings.ranking_metrics
+                        || self.settings.oracle_regret)
+                {
+                    let affinities = sheep::affinities(
+                        &self.graph,
+                        sheep,
+                        &self.profiles[&sheep],
+                    );
+                    let seen = sheep_seen.get(&sheep);
+                    let eligible = self
+                        .item_epochs
+                        .keys()
+                        .filter(|item| {
+                            !seen.is_some_and(|seen| seen.contains(*item))
+                        })
+                        .map(|item| {
+                            (
+                                *item,
+                                affinities.get(item).copied().unwrap_or(0.0),
+                            )
+                        })
+                        .collect::<HashMap<_, _>>();
+
+                    // items which could not have been shown, including any
+                    // repeated within the feed, are irrelevant
+                    let mut included = HashSet::default();
+                    let relevances = feed
+                        .0
+                        .iter()
+                        .map(|item| match eligible.get(item) {
+                            Some(relevance) if included.insert(*item) => {
+                                *relevance
+                            }
+                            _ => 0.0,
+                        })
+                        .collect::<Vec<_>>();
+
+                    (
+                        self.settings.ranking_metrics.then(|| {
+                            RankingMetrics::new(
+                                &relevances,
+                                eligible.values().copied(),
+                                self.settings.feed_size,
+                            )
+                        }),
+                        self.settings.oracle_regret.then(|| {
+                            Regret::new(
+                                &relevances,
+                                &eligible,
+                                self.settings.feed_size,
+                            )
+                        }),
+                    )
+                } else {
+                    (None, None)
+                };
+
+                let diversity = (from_shepherd
+                    && self.settings.diversity_metrics)
+                    .then(|| {
+                        metrics::intra_list_diversity(&self.graph, &feed.0)
+                    })
+                    .flatten();
+
                 if let Some(seen) = sheep_seen.get_mut(&sheep) {
                     seen.extend(feed.0.iter().copied());
                 } else {
@@ -396,20 +2576,590 @@ impl<'a, 'de> Simulation<'a, 'de> {
                         .insert(sheep, feed.0.iter().copied().collect());
                 }
 
-                let responses =
-                    sheep::process_feed(&mut *rng, &self.graph, sheep, feed);
+                // the extra reference lets a possibly unsized rng be passed
+                // on as a trait object
+                let responses = info_span!("rate_feed", sheep = sheep.0)
+                    .in_scope(|| {
+                        self.settings.sheep_behavior.respond(
+                            &mut &mut *rng,
+                            &self.graph,
+                            &self.tag_groups,
+                            sheep,
+                            &self.profiles[&sheep],
+                            fatigue.entry(sheep).or_default(),
+                            self.settings.explain_responses,
+                            feed,
+                        )
+                    });
 
                 if let Some(hook) = &mut self.settings.feed_rated_hook {
-                    hook(id, sheep, &responses);
+                    hook(id, sheep, &responses, from_shepherd);
+                }
+
+                if from_shepherd {
+                    self.settings.metrics.update(id, sheep, &responses);
+                    self.stats.record(
+                        id,
+                        self.current_epoch,
+                        self.settings.feed_size,
+                        &responses,
+                    );
+                    let items = responses.0.iter().map(|(item, ..)| *item);
+                    if let Some(novelty) =
+                        self.exposures.novelty(id, items.clone())
+                    {
+                        self.stats.record_novelty(
+                            id,
+                            self.current_epoch,
+                            novelty,
+                        );
+                    }
+                    self.exposures.record(id, items);
+
+                    let tags = sheep_tags.entry(sheep).or_default();
+                    for (item, ..) in &responses.0 {
+                        for tag in self.graph.associated_tags(*item) {
+                            *tags.entry(tag).or_default() += 1;
+                        }
+                    }
+
+                    if self.settings.drift_metrics {
+                        let distances = original_distances
+                            .entry(sheep)
+                            .or_insert_with(|| {
+                                self.graph.tag_distances(
+                                    self.original_tags[&sheep]
+                                        .iter()
+                                        .copied(),
+                                )
+                            });
+                        let (total_hops, n_reachable) =
+                            sheep_drift.entry(sheep).or_default();
+                        for (item, ..) in &responses.0 {
+                            if let Some(hops) = self
+                                .graph
+                                .associated_tags(*item)
+                                .filter_map(|tag| distances.get(&tag))
+                                .min()
+                            {
+                                *total_hops += u64::from(*hops);
+                                *n_reachable += 1;
+                            }
+                        }
+                    }
+
+                    if self.settings.cold_start_metrics {
+                        for (item, ..) in &responses.0 {
+                            if let Some(latency) =
+                                self.cold_start.record_exposure(
+                                    id,
+                                    *item,
+                                    self.current_epoch,
+                                )
+                            {
+                                self.stats.record_item_latency(
+                                    id,
+                                    self.current_epoch,
+                                    latency,
+                                );
+                            }
+                        }
+
+                        if !self.cold_start.is_served(id, sheep) {
+                            let affinities = sheep::affinities(
+                                &self.graph,
+                                sheep,
+                                &self.profiles[&sheep],
+                            );
+                            let relevant =
+                                responses.0.iter().any(|(item, ..)| {
+                                    affinities.get(item).is_some_and(|r| {
+                                        *r >= metrics::RELEVANCE_THRESHOLD
+                                    })
+                                });
+                            if let Some(latency) = relevant
+                                .then(|| {
+                                    self.cold_start.record_relevant_feed(
+                                        id,
+                                        sheep,
+                                        self.current_epoch,
+                                    )
+                                })
+                                .flatten()
+                            {
+                                self.stats.record_sheep_latency(
+                                    id,
+                                    self.current_epoch,
+                                    latency,
+                                );
+                            }
+                        }
+                    }
+                } else if timed_out {
+                    self.stats.record_timeout(
+                        id,
+                        self.current_epoch,
+                        self.settings.feed_size,
+                    );
+                }
+                if let Some(ranking) = ranking {
+                    self.stats.record_ranking(
+                        id,
+                        self.current_epoch,
+                        ranking,
+                    );
+                }
+                if let Some(regret) = regret {
+                    self.stats.record_regret(id, self.current_epoch, regret);
+                }
+                if let Some(diversity) = diversity {
+                    self.stats.record_diversity(
+                        id,
+                        self.current_epoch,
+                        diversity,
+                    );
+                }
+
+                if let Some(retention) =
+                    self.settings.retention.filter(|_| from_shepherd)
+                {
+                    let n_satisfying = responses
+                        .0
+                        .iter()
+                        .filter(|(_, response, ..)| {
+                            *response != Response::Negative
+                        })
+                        .count();
+                    let fraction = n_satisfying as f64
+                        / self
+                            .settings
+                            .feed_size
+                            .max(responses.0.len())
+                            .max(1) as f64;
+                    let level = satisfaction.entry(sheep).or_insert(1.0);
+                    *level += retention.smoothing * (fraction - *level);
+
+                    if *level < retention.churn_threshold {
+                        info!(
+                            shepherd = id.0,
+                            sheep = sheep.0,
+                            satisfaction = *level,
+                            "a sheep has left a shepherd"
+                        );
+                    }
                 }
 
-                shepherd.incorporate_responses(sheep, responses);
+                // justifications are scored against the graph as the sheep
+                // saw it, before any reinforcement, though without any fading
+                // of its interests, which only its behavior knows of
+                if from_shepherd && !justifications.is_empty() {
+                    let paths =
+                        sheep::ShortestPaths::new(&self.graph, sheep, 0.0);
+                    for (item, justification) in &justifications {
+                        let path = paths
+                            .path(*item)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .filter(|node| {
+                                self.graph.0[*node] == NodeType::Tag
+                            })
+                            .map(|node| GraphId::new(node.index()))
+                            .collect::<Vec<_>>();
+                        let faithful =
+                            justification.is_faithful(&self.graph, &path);
+                        info!(
+                            shepherd = id.0,
+                            sheep = sheep.0,
+                            item = item.0,
+                            justification = ?justification,
+                            faithful = faithful,
+                            "a feed item was justified"
+                        );
+
+                        if let Some(hook) =
+                            &mut self.settings.justification_hook
+                        {
+                            hook(id, sheep, *item, justification, faithful);
+                        }
+                    }
+                }
+
+                // the probability is checked first so that no randomness is
+                // consumed when reinforcement is disabled
+                if self.settings.reinforcement_probability > 0.0 {
+                    for (item, response, ..) in &responses.0 {
+                        if *response == Response::Positive {
+                            self.graph.reinforce_preference(
+                                &mut *rng,
+                                sheep,
+                                *item,
+                                self.settings.reinforcement_probability,
+                                self.settings.edge_semantics,
+                            );
+                            // with undirected edges, the sheep's new edges
+                            // are mirrored back into it, so other sheep's
+                            // paths may now pass through it
+                            self.settings.sheep_behavior.graph_changed(
+                                (self.settings.edge_semantics
+                                    != EdgeSemantics::Undirected)
+                                    .then_some(sheep),
+                            );
+                        }
+                    }
+                }
+
+                // shepherds are only told about feeds they built themselves
+                if from_shepherd {
+                    if let Some(hidden) = self.hidden_tags.get(&sheep) {
+                        for (item, response, ..) in &responses.0 {
+                            if *response != Response::Positive {
+                                continue;
+                            }
+
+                            discovered.entry(sheep).or_default().extend(
+                                self.graph
+                                    .associated_tags(*item)
+                                    .filter(|tag| hidden.contains(tag)),
+                            );
+                        }
+                    }
+
+                    if self.settings.epoch_summaries {
+                        let counts = sheep_stats.entry(sheep).or_default();
+                        for (_, response, ..) in &responses.0 {
+                            counts.record(*response);
+                        }
+
+                        if self.settings.under_delivery_penalty {
+                            counts.negative += n_missing;
+                        }
+                    }
+
+                    // this is `item_age`, borrowing only the fields it
+                    // needs as the shepherds are borrowed mutably
+                    let responses =
+                        LimitedResponses::new(responses, |item| {
+                            self.item_epochs.get(&item).map(
+                                |EpochId(epoch)| self.current_epoch.0 - epoch,
+                            )
+                        });
+                    if self.settings.response_batch_interval.is_some() {
+                        batched_responses.push((
+                            sheep,
+                            self.current_epoch,
+                            responses,
+                        ));
+                    } else {
+                        shepherd.incorporate_responses(
+                            sheep,
+                            self.current_epoch,
+                            responses,
+                        );
+                    }
+                } else if timed_out
+                    && self.settings.epoch_summaries
+                    && self.settings.under_delivery_penalty
+                {
+                    sheep_stats.entry(sheep).or_default().negative +=
+                        n_missing;
+                }
+            }
+
+            if self
+                .settings
+                .response_batch_interval
+                .is_some_and(|interval| {
+                    self.current_epoch.0.is_multiple_of(interval)
+                })
+            {
+                info!(
+                    shepherd = id.0,
+                    epoch = self.current_epoch.0,
+                    n_responses = batched_responses.len(),
+                    "a batch of responses was delivered"
+                );
+                shepherd.incorporate_batch(mem::take(batched_responses));
+            }
+
+            let latencies = shepherd.take_feed_latencies();
+            if let Some(percentiles) =
+                metrics::LatencyPercentiles::new(latencies.iter().copied())
+            {
+                info!(
+                    shepherd = id.0,
+                    epoch = self.current_epoch.0,
+                    p50 = ?percentiles.p50,
+                    p95 = ?percentiles.p95,
+                    p99 = ?percentiles.p99,
+                    "the latency of the shepherd's feeds has been measured"
+                );
+            }
+            self.latencies.record(id, self.current_epoch, latencies);
+
+            for tags in sheep_tags.values().filter(|tags| !tags.is_empty()) {
+                self.stats.record_tag_entropy(
+                    id,
+                    self.current_epoch,
+                    metrics::entropy(tags.values().copied()),
+                );
+            }
+
+            let mut group_exposure = metrics::GroupExposure {
+                exposures: vec![0; group_catalog.len()],
+                catalog: group_catalog.clone(),
+            };
+            for (tag, count) in sheep_tags.values().flatten() {
+                if let Some(i) = tag_group_indices.get(tag) {
+                    group_exposure.exposures[*i] += count;
+                }
+            }
+            if let Some(disparity) = group_exposure.disparity() {
+                info!(
+                    shepherd = id.0,
+                    epoch = self.current_epoch.0,
+                    disparity = disparity,
+                    "the exposure of the tag groups has been measured"
+                );
+                self.stats.record_disparity(
+                    id,
+                    self.current_epoch,
+                    disparity,
+                );
+            }
+            if let Some(hook) = &mut self.settings.group_exposure_hook {
+                hook(self.current_epoch, id, &group_exposure);
+            }
+
+            for (total_hops, n_reachable) in sheep_drift.into_values() {
+                if n_reachable > 0 {
+                    self.stats.record_drift(
+                        id,
+                        self.current_epoch,
+                        total_hops as f64 / n_reachable as f64,
+                    );
+                }
+            }
+
+            if self.settings.epoch_summaries {
+                let mut sheep_stats =
+                    sheep_stats.into_iter().collect::<Vec<_>>();
+                sheep_stats.sort_unstable_by_key(|(sheep, _)| sheep.0);
+                shepherd.summarize_epoch(self.current_epoch, sheep_stats);
+            }
+
+            // this is the last event of the epoch, so that shepherds which
+            // train on what they have been given can do so between epochs
+            shepherd.end(self.current_epoch);
+
+            if n_hidden_tags > 0 {
+                let discovery =
+                    discovered.values().map(HashSet::len).sum::<usize>()
+                        as f64
+                        / n_hidden_tags as f64;
+                info!(
+                    shepherd = id.0,
+                    epoch = self.current_epoch.0,
+                    discovery = discovery,
+                    "hidden tags have been discovered"
+                );
+
+                if let Some(hook) = &mut self.settings.discovery_hook {
+                    hook(id, self.current_epoch, discovery);
+                }
+            }
+
+            // without a model of retention, every sheep is always retained
+            if let Some(retention) = self.settings.retention {
+                let n_retained = self.sheep.len()
+                    - satisfaction
+                        .values()
+                        .filter(|level| **level < retention.churn_threshold)
+                        .count();
+                info!(
+                    shepherd = id.0,
+                    epoch = self.current_epoch.0,
+                    n_active = n_active,
+                    n_retained = n_retained,
+                    "sheep have been retained"
+                );
+
+                if let Some(hook) = &mut self.settings.retention_hook {
+                    hook(self.current_epoch, id, n_active, n_retained);
+                }
+            }
+
+            let coverage = self
+                .exposures
+                .coverage(id, self.item_epochs.keys().copied());
+            info!(
+                shepherd = id.0,
+                epoch = self.current_epoch.0,
+                n_items = coverage.n_items,
+                n_recommended = coverage.n_recommended,
+                coverage = coverage.coverage(),
+                gini = coverage.gini,
+                "the coverage of the catalog has been measured"
+            );
+
+            if let Some(hook) = &mut self.settings.catalog_coverage_hook {
+                hook(self.current_epoch, id, coverage);
+            }
+        }
+
+        self.settings.metrics.finalize_epoch(self.current_epoch);
+        if let Some(hook) = &mut self.settings.metric_hook {
+            for metric in self.settings.metrics.iter() {
+                hook(self.current_epoch, metric);
             }
         }
 
         Ok(())
     }
 
+    /// Get the current epoch of the simulation
+    pub fn current_epoch(&self) -> EpochId {
+        self.current_epoch
+    }
+
+    /// Get the graph of the simulation
+    pub fn graph(&self) -> &SimulationGraph {
+        &self.graph
+    }
+
+    /// Get the stats of the feeds each [`Shepherd`] has built during each
+    /// epoch so far
+    pub fn stats(&self) -> &metrics::Stats {
+        &self.stats
+    }
+
+    /// Get the time each [`Shepherd`] took to build each of its feeds during
+    /// each epoch so far
+    pub fn latencies(&self) -> &metrics::Latencies {
+        &self.latencies
+    }
+
+    /// Iterate over the sheep present in the simulation
+    pub fn sheep(&self) -> impl Iterator<Item = SheepId> + '_ {
+        self.sheep.iter().copied()
+    }
+
+    /// Iterate over the items present in the simulation
+    pub fn items(&self) -> impl Iterator<Item = ItemId> + '_ {
+        self.items.iter().copied()
+    }
+
+    /// Iterate over the tags present in the simulation
+    pub fn tags(&self) -> impl Iterator<Item = TagId> + '_ {
+        self.tags.iter().copied()
+    }
+
+    /// Get the tag groups present in the simulation
+    pub fn tag_groups(&self) -> &[HashSet<TagId>] {
+        &self.tag_groups
+    }
+
+    /// Get the orphaned tags present in the simulation
+    pub fn tag_orphans(&self) -> &HashSet<TagId> {
+        &self.tag_orphans
+    }
+
+    /// Get the parent tag of a tag group, or [`None`] if it has none
+    pub fn tag_parent(&self, group: usize) -> Option<TagId> {
+        self.tag_parents.get(&group).copied()
+    }
+
+    /// Get the parameters of a sheep, or [`None`] if it is not present in the
+    /// simulation
+    pub fn profile(&self, sheep: SheepId) -> Option<&sheep::Profile> {
+        self.profiles.get(&sheep)
+    }
+
+    /// Check whether a sheep rates feeds noisily
+    pub fn is_noisy(&self, sheep: SheepId) -> bool {
+        self.noisy_sheep.contains(&sheep)
+    }
+
+    /// Get the tags of a sheep which are hidden from shepherds, or [`None`]
+    /// if none of its tags are hidden
+    pub fn hidden_tags(&self, sheep: SheepId) -> Option<&HashSet<TagId>> {
+        self.hidden_tags.get(&sheep)
+    }
+
+    /// Get the epoch during which an item was introduced, or [`None`] if it
+    /// is not present in the simulation
+    pub fn item_epoch(&self, item: ItemId) -> Option<EpochId> {
+        self.item_epochs.get(&item).copied()
+    }
+
+    /// Get the number of epochs since an item was introduced, or [`None`] if
+    /// it is not present in the simulation
+    pub fn item_age(&self, item: ItemId) -> Option<usize> {
+        self.item_epochs
+            .get(&item)
+            .map(|EpochId(epoch)| self.current_epoch.0 - epoch)
+    }
+
+    /// Get the probability that a sheep responds positively to an item, as
+    /// given by the world model
+    ///
+    /// See [`sheep::affinities`] for details. [`None`] is returned if either
+    /// the sheep or the item is not present in the simulation
+    pub fn affinity(&self, sheep: SheepId, item: ItemId) -> Option<f64> {
+        if self.graph.0.node_weight(item.0.into()) != Some(&NodeType::Item) {
+            return None;
+        }
+
+        Some(
+            sheep::affinities(&self.graph, sheep, self.profiles.get(&sheep)?)
+                .get(&item)
+                .copied()
+                .unwrap_or(0.0),
+        )
+    }
+
+    /// Export the affinity of every sheep for every item as a dense matrix
+    /// in the `.npy` format
+    ///
+    /// Rows correspond to sheep and columns to items, both in ascending order
+    /// of their identifiers, which are returned alongside. Since the matrix
+    /// grows with the product of the number of sheep and items, this fails
+    /// instead of writing anything if it would have more than `max_cells`
+    /// cells
+    pub fn export_affinities(
+        &self,
+        writer: impl Write,
+        max_cells: usize,
+    ) -> anyhow::Result<(Vec<SheepId>, Vec<ItemId>)> {
+        let mut sheep = self.sheep.iter().copied().collect::<Vec<_>>();
+        let mut items = self.items.iter().copied().collect::<Vec<_>>();
+        sheep.sort_unstable_by_key(|GraphId(id, _)| *id);
+        items.sort_unstable_by_key(|GraphId(id, _)| *id);
+
+        ensure!(
+            sheep.len().saturating_mul(items.len()) <= max_cells,
+            "An affinity matrix of {} sheep and {} items exceeds the limit of {} cells",
+            sheep.len(),
+            items.len(),
+            max_cells
+        );
+
+        let mut data = Vec::with_capacity(sheep.len() * items.len());
+        for sheep in &sheep {
+            let affinities =
+                sheep::affinities(&self.graph, *sheep, &self.profiles[sheep]);
+            data.extend(
+                items
+                    .iter()
+                    .map(|item| affinities.get(item).copied().unwrap_or(0.0)),
+            );
+        }
+
+        export::write_npy(writer, (sheep.len(), items.len()), data)
+            .context("Unable to write the affinity matrix")?;
+
+        Ok((sheep, items))
+    }
+
     /// Stop the simulation, terminating all [`Shepherd`]s and return the
     /// simulation graph with associated metadata
     pub fn stop(self) -> anyhow::Result<SimulationParts<'a>> {
@@ -420,17 +3170,43 @@ impl<'a, 'de> Simulation<'a, 'de> {
             tags,
             sheep,
             items,
+            item_epochs,
             tag_groups,
             tag_orphans,
+            tag_parents,
+            profiles,
+            noisy_sheep,
+            hidden_tags,
+            tag_structure: _,
+            original_tags: _,
+            stats,
+            exposures: _,
+            cold_start,
+            latencies,
             shepherds,
         } = self;
         let mut shepherd_ids = Vec::with_capacity(shepherds.len());
 
-        for (id, (shepherd, _)) in shepherds
-            .into_iter()
-            .enumerate()
-            .map(|(id, data)| (ShepherdId(id), data))
+        for (id, (mut shepherd, _, _, _, _, batched_responses, ..)) in
+            shepherds
+                .into_iter()
+                .enumerate()
+                .map(|(id, data)| (ShepherdId(id), data))
         {
+            // responses still waiting on a batch when the run ends are
+            // delivered before the shepherd is told to shut down, rather than
+            // being dropped
+            if !batched_responses.is_empty() {
+                info!(
+                    shepherd = id.0,
+                    epoch = final_epoch.0,
+                    n_responses = batched_responses.len(),
+                    "a batch of responses was delivered"
+                );
+                shepherd.incorporate_batch(batched_responses);
+                shepherd.flush();
+            }
+
             shepherd.stop()?;
             shepherd_ids.push(id);
         }
@@ -442,9 +3218,281 @@ impl<'a, 'de> Simulation<'a, 'de> {
             tags,
             sheep,
             items,
+            item_epochs,
             tag_groups,
             tag_orphans,
+            tag_parents,
+            profiles,
+            noisy_sheep,
+            hidden_tags,
             shepherd_ids,
+            stats,
+            cold_start,
+            latencies,
         })
     }
 }
+
+/// Decide whether a sheep requests a feed from a [`Shepherd`] it has the
+/// given satisfaction with, which consumes no randomness when retention is
+/// disabled
+fn is_retained(
+    retention: Option<Retention>,
+    satisfaction: &HashMap<SheepId, f64>,
+    sheep: SheepId,
+    rng: &mut (impl Rng + ?Sized),
+) -> bool {
+    retention.is_none_or(|retention| {
+        let level = satisfaction.get(&sheep).copied().unwrap_or(1.0);
+        level >= retention.churn_threshold && rng.gen_bool(level)
+    })
+}
+
+/// Check whether a [`Shepherd`] has time left in its compute budget
+fn has_budget_left(budget: Option<Duration>, shepherd: &Shepherd) -> bool {
+    budget.is_none_or(|budget| shepherd.compute_time() < budget)
+}
+
+/// Report a [`Shepherd`] which has exhausted its compute budget, if it has,
+/// through [`Settings::budget_exhausted_hook`]
+#[allow(clippy::type_complexity)]
+fn check_budget(
+    budget: Option<Duration>,
+    hook: &mut Option<Box<dyn FnMut(ShepherdId, EpochId, Duration) + '_>>,
+    id: ShepherdId,
+    epoch: EpochId,
+    shepherd: &Shepherd,
+) {
+    if has_budget_left(budget, shepherd) {
+        return;
+    }
+
+    warn!(
+        shepherd = id.0,
+        epoch = epoch.0,
+        compute_time = ?shepherd.compute_time(),
+        "a shepherd has exhausted its compute budget"
+    );
+
+    if let Some(hook) = hook {
+        hook(id, epoch, shepherd.compute_time());
+    }
+}
+
+/// Sample the items a shepherd may access during an epoch under
+/// [`Settings::item_access_budget`], returning them along with the [`Epoch`]
+/// it is sent in place of the given one, which lists them in place of the
+/// items introduced during the epoch
+fn restrict_epoch(
+    rng: &mut (impl Rng + ?Sized),
+    graph: &SimulationGraph,
+    items: impl Iterator<Item = ItemId>,
+    budget: usize,
+    epoch: &Epoch,
+) -> (HashSet<ItemId>, Epoch) {
+    let sampled = items.choose_multiple(rng, budget);
+    let epoch = Epoch {
+        tags: epoch.tags.clone(),
+        items: sampled
+            .iter()
+            .map(|id| (*id, graph.associated_tags(*id).collect()))
+            .collect(),
+        diff: epoch.diff.clone(),
+    };
+
+    (sampled.into_iter().collect(), epoch)
+}
+
+/// Get the tags of a sheep which shepherds are allowed to know about
+fn visible_tags(
+    graph: &SimulationGraph,
+    hidden_tags: &HashMap<SheepId, HashSet<TagId>>,
+    sheep: SheepId,
+) -> Vec<TagId> {
+    let hidden = hidden_tags.get(&sheep);
+    graph
+        .associated_tags(sheep)
+        .filter(|tag| hidden.is_none_or(|hidden| !hidden.contains(tag)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [FeedValidation; 3] = [
+        FeedValidation::Report,
+        FeedValidation::Sanitize,
+        FeedValidation::Strict,
+    ];
+
+    fn items(ids: &[usize]) -> Vec<ItemId> {
+        ids.iter().copied().map(GraphId::new).collect()
+    }
+
+    /// Validate a feed of the given items against a world holding items 0
+    /// through 4, in which the sheep has already been shown item 4, then
+    /// check what each mode does with it
+    ///
+    /// Every item of the feed is justified, so that what becomes of the
+    /// justifications can be checked as well
+    fn check(
+        feed: &[usize],
+        feed_size: usize,
+        expected_valid: &[usize],
+        expected_violations: &[FeedViolation],
+    ) {
+        let item_epochs = items(&[0, 1, 2, 3, 4])
+            .into_iter()
+            .map(|item| (item, EpochId::default()))
+            .collect();
+        let seen = items(&[4]).into_iter().collect::<HashSet<_>>();
+        let feed = Feed(items(feed));
+        let justifications = feed
+            .0
+            .iter()
+            .map(|item| (*item, Justification::SimilarItem(*item)))
+            .collect::<Vec<_>>();
+
+        let (valid, violations) =
+            validate_feed(&feed, &item_epochs, Some(&seen), None, feed_size);
+        assert_eq!(valid.0, items(expected_valid));
+        assert_eq!(violations, expected_violations);
+
+        for mode in MODES {
+            let shown = mode.apply(
+                (feed.clone(), justifications.clone()),
+                valid.clone(),
+                &violations,
+            );
+            match mode {
+                FeedValidation::Report => {
+                    assert_eq!(
+                        shown,
+                        Ok((feed.clone(), justifications.clone()))
+                    );
+                }
+                FeedValidation::Sanitize => {
+                    let (shown, shown_justifications) = shown.unwrap();
+                    assert_eq!(shown, valid);
+                    assert!(shown_justifications
+                        .iter()
+                        .all(|(item, _)| valid.0.contains(item)));
+                    assert_eq!(
+                        shown_justifications.len(),
+                        justifications
+                            .iter()
+                            .filter(|(item, _)| valid.0.contains(item))
+                            .count()
+                    );
+                }
+                FeedValidation::Strict => match violations.first() {
+                    Some(violation) => assert_eq!(shown, Err(*violation)),
+                    None => assert_eq!(
+                        shown,
+                        Ok((feed.clone(), justifications.clone()))
+                    ),
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn valid_feeds_are_shown_as_built() {
+        check(&[0, 1, 2], 3, &[0, 1, 2], &[]);
+    }
+
+    #[test]
+    fn duplicate_items_are_caught() {
+        check(
+            &[0, 1, 0],
+            3,
+            &[0, 1],
+            &[FeedViolation::DuplicateItem(GraphId::new(0))],
+        );
+    }
+
+    #[test]
+    fn unknown_items_are_caught() {
+        check(
+            &[0, 7, 1],
+            3,
+            &[0, 1],
+            &[FeedViolation::UnknownItem(GraphId::new(7))],
+        );
+    }
+
+    #[test]
+    fn items_already_shown_are_caught() {
+        check(
+            &[4, 0],
+            3,
+            &[0],
+            &[FeedViolation::AlreadyShown(GraphId::new(4))],
+        );
+    }
+
+    #[test]
+    fn oversized_feeds_are_caught() {
+        check(
+            &[0, 1, 2, 3],
+            2,
+            &[0, 1],
+            &[
+                FeedViolation::Overlong(GraphId::new(2)),
+                FeedViolation::Overlong(GraphId::new(3)),
+            ],
+        );
+    }
+
+    #[test]
+    fn only_valid_items_count_against_the_feed_size() {
+        check(
+            &[0, 0, 7, 4, 1, 2],
+            2,
+            &[0, 1],
+            &[
+                FeedViolation::DuplicateItem(GraphId::new(0)),
+                FeedViolation::UnknownItem(GraphId::new(7)),
+                FeedViolation::AlreadyShown(GraphId::new(4)),
+                FeedViolation::Overlong(GraphId::new(2)),
+            ],
+        );
+    }
+
+    #[test]
+    fn only_the_first_rule_broken_is_reported() {
+        // an unknown item repeated is reported as unknown both times, rather
+        // than as a duplicate the second time
+        check(
+            &[7, 7],
+            3,
+            &[],
+            &[
+                FeedViolation::UnknownItem(GraphId::new(7)),
+                FeedViolation::UnknownItem(GraphId::new(7)),
+            ],
+        );
+    }
+
+    #[test]
+    fn items_outside_the_candidates_are_caught() {
+        let item_epochs = items(&[0, 1, 2])
+            .into_iter()
+            .map(|item| (item, EpochId::default()))
+            .collect();
+        let candidates = items(&[0, 1]).into_iter().collect::<HashSet<_>>();
+        let (valid, violations) = validate_feed(
+            &Feed(items(&[2, 0])),
+            &item_epochs,
+            None,
+            Some(&candidates),
+            3,
+        );
+        assert_eq!(valid.0, items(&[0]));
+        assert_eq!(
+            violations,
+            [FeedViolation::NotCandidate(GraphId::new(2))]
+        );
+    }
+}
@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+"""a reference shepherd which shows each sheep the items other sheep have
+responded to most positively, that it has not been shown yet
+
+run it with `shepherd 'popular=python3 python/examples/popular.py'` from the
+root of the repository
+"""
+
+import os
+import random
+import sys
+from collections import defaultdict
+
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), ".."))
+
+from shepherd_sdk import (  # noqa: E402
+    BeginEpoch,
+    Capability,
+    FeedResponses,
+    Handler,
+    Response,
+    ResponsesBatch,
+    RunConfig,
+    run,
+)
+
+# how much each response moves the score of an item
+SCORES = {
+    Response.POSITIVE: 1.0,
+    Response.NEUTRAL: 0.0,
+    Response.NEGATIVE: -1.0,
+}
+
+
+class Popular(Handler):
+    def __init__(self) -> None:
+        self.feed_size = 0
+        self.scores: dict[int, float] = {}
+        self.seen: dict[int, set[int]] = defaultdict(set)
+
+    def capabilities(self):
+        return [Capability.RESPONSE_BATCHES, Capability.FEED_BATCHES]
+
+    def handle(self, event) -> None:
+        if isinstance(event, RunConfig):
+            self.feed_size = event.feed_size
+        elif isinstance(event, BeginEpoch):
+            for item, _ in event.items:
+                self.scores[item] = 0.0
+        elif isinstance(event, FeedResponses):
+            self.record(event)
+        elif isinstance(event, ResponsesBatch):
+            for responses in event.responses:
+                self.record(responses)
+
+    def record(self, responses: FeedResponses) -> None:
+        for rating in responses.responses:
+            if rating.item in self.scores:
+                self.scores[rating.item] += SCORES[rating.response]
+
+    def build_feed(self, sheep: int) -> list[int]:
+        seen = self.seen[sheep]
+        # ties are broken at random, so that new items get a chance to be
+        # rated at all
+        unseen = [item for item in self.scores if item not in seen]
+        random.shuffle(unseen)
+        unseen.sort(key=lambda item: self.scores[item], reverse=True)
+
+        feed = unseen[: self.feed_size]
+        seen.update(feed)
+        return feed
+
+
+if __name__ == "__main__":
+    run(Popular())
@@ -0,0 +1,265 @@
+"""a helper for writing shepherds in python
+
+subclass `Handler`, implement `build_feed`, and pass an instance of it to
+`run`, which takes care of the handshake and of reading and writing events
+over standard input and output. this mirrors shepherd_lib::sdk on the rust
+side
+"""
+
+from __future__ import annotations
+
+import json
+import queue
+import sys
+import threading
+from typing import IO, Optional, Union
+
+from .events import (
+    PROTOCOL_VERSION,
+    BeginEpoch,
+    BudgetExceeded,
+    Capability,
+    EndEpoch,
+    EpochSummary,
+    FeedRequest,
+    FeedRequestBatch,
+    FeedResponses,
+    GraphDiff,
+    Hello,
+    Justification,
+    Payload,
+    Ping,
+    RankCandidates,
+    Rating,
+    Response,
+    ResponseCounts,
+    ResponsesBatch,
+    RetrieveCandidates,
+    RunConfig,
+    SheepIntroduction,
+    Shutdown,
+    SimilarItem,
+    SimulationEvent,
+    Tag,
+    TagGroups,
+    TagGroupSplit,
+    TagGroupsMerged,
+    UnderDelivered,
+    Unknown,
+    decode,
+    encode_justified,
+)
+
+__all__ = [
+    "PROTOCOL_VERSION",
+    "BeginEpoch",
+    "BudgetExceeded",
+    "Capability",
+    "EndEpoch",
+    "EpochSummary",
+    "FeedRequest",
+    "FeedRequestBatch",
+    "FeedResponses",
+    "GraphDiff",
+    "Handler",
+    "Hello",
+    "Justification",
+    "Payload",
+    "Ping",
+    "RankCandidates",
+    "Rating",
+    "Response",
+    "ResponseCounts",
+    "ResponsesBatch",
+    "RetrieveCandidates",
+    "RunConfig",
+    "SheepIntroduction",
+    "Shutdown",
+    "SimilarItem",
+    "SimulationEvent",
+    "Tag",
+    "TagGroups",
+    "TagGroupSplit",
+    "TagGroupsMerged",
+    "UnderDelivered",
+    "Unknown",
+    "run",
+]
+
+
+class Handler:
+    """a feed algorithm which is run as a shepherd by `run`
+
+    batches of feed requests are answered one feed at a time through
+    `build_feed`, so declaring Capability.FEED_BATCHES never requires any
+    more work. pings are answered by `run` on a thread of its own, so the
+    same goes for Capability.HEARTBEATS. if Capability.JUSTIFIED_FEEDS is
+    declared, feeds are built through `build_justified_feed` instead. if
+    Capability.TWO_STAGE_FEEDS is declared, `retrieve_candidates` and
+    `rank_candidates` must be implemented as well
+    """
+
+    def capabilities(self) -> list[Capability]:
+        """get the capabilities declared during the handshake"""
+        return []
+
+    def payloads(self) -> list[Payload]:
+        """get the payloads requested during the handshake"""
+        return []
+
+    def handle(self, event: SimulationEvent) -> None:
+        """handle an event which does not call for a response
+
+        every event other than the handshake and requests for feeds is
+        passed on here, including Shutdown, after which no more events are
+        handled
+        """
+
+    def build_feed(self, sheep: int) -> list[int]:
+        """build a feed of items for the specified sheep"""
+        raise NotImplementedError
+
+    def build_justified_feed(
+        self, sheep: int
+    ) -> list[tuple[int, Optional[Justification]]]:
+        """build a feed for the specified sheep, along with the
+        justification for any of its items
+
+        this is only used if Capability.JUSTIFIED_FEEDS is declared, and
+        builds a feed without any justifications by default
+        """
+        return [(item, None) for item in self.build_feed(sheep)]
+
+    def retrieve_candidates(self, sheep: int, n_candidates: int) -> list[int]:
+        """retrieve up to the given number of candidates for the feed of the
+        specified sheep, which is the first stage of building a feed in two
+        stages"""
+        raise NotImplementedError
+
+    def rank_candidates(self, sheep: int, candidates: list[int]) -> list[int]:
+        """build the feed of the specified sheep out of the given candidates,
+        which are those retrieved by `retrieve_candidates` less any the sheep
+        can't be shown"""
+        raise NotImplementedError
+
+
+def run(
+    handler: Handler,
+    input: IO[str] = sys.stdin,
+    output: IO[str] = sys.stdout,
+) -> None:
+    """run a handler as a shepherd until the simulation shuts it down or its
+    input runs out"""
+
+    lock = threading.Lock()
+
+    def send(kind: str, data: object) -> None:
+        with lock:
+            output.write(json.dumps({"kind": kind, "data": data}) + "\n")
+            output.flush()
+
+    # events are read on a thread of their own, so that pings are answered
+    # even while the handler is busy. anything which goes wrong there is
+    # raised again here, and None marks the end of the input
+    events: queue.Queue[Union[SimulationEvent, Exception, None]]
+    events = queue.Queue()
+
+    def read() -> None:
+        try:
+            for line in input:
+                if not line.strip():
+                    continue
+
+                event = decode(json.loads(line))
+                if isinstance(event, Ping):
+                    send("Pong", None)
+                else:
+                    events.put(event)
+        except Exception as error:
+            events.put(error)
+        events.put(None)
+
+    threading.Thread(target=read, daemon=True).start()
+
+    justified = False
+    while (event := events.get()) is not None:
+        if isinstance(event, Exception):
+            raise event
+        if isinstance(event, Hello):
+            capabilities = handler.capabilities()
+            justified = Capability.JUSTIFIED_FEEDS in capabilities
+            send(
+                "Hello",
+                {
+                    "protocol_version": PROTOCOL_VERSION,
+                    "capabilities": [c.value for c in capabilities],
+                    "payloads": [p.value for p in handler.payloads()],
+                    "encoding": "Json",
+                },
+            )
+        elif isinstance(event, FeedRequest):
+            if justified:
+                items = handler.build_justified_feed(event.sheep)
+                send(
+                    "JustifiedFeed",
+                    {
+                        "request_id": event.request_id,
+                        "items": encode_justified(items),
+                    },
+                )
+            else:
+                send(
+                    "Feed",
+                    {
+                        "request_id": event.request_id,
+                        "feed": list(handler.build_feed(event.sheep)),
+                    },
+                )
+        elif isinstance(event, FeedRequestBatch):
+            if justified:
+                feeds = [
+                    encode_justified(handler.build_justified_feed(sheep))
+                    for sheep in event.sheep
+                ]
+                send(
+                    "JustifiedFeedBatch",
+                    {"request_id": event.request_id, "feeds": feeds},
+                )
+            else:
+                feeds = [
+                    list(handler.build_feed(sheep)) for sheep in event.sheep
+                ]
+                send(
+                    "FeedBatch",
+                    {"request_id": event.request_id, "feeds": feeds},
+                )
+        elif isinstance(event, RetrieveCandidates):
+            send(
+                "Candidates",
+                {
+                    "request_id": event.request_id,
+                    "items": list(
+                        handler.retrieve_candidates(
+                            event.sheep, event.n_candidates
+                        )
+                    ),
+                },
+            )
+        elif isinstance(event, RankCandidates):
+            feed = list(handler.rank_candidates(event.sheep, event.candidates))
+            if justified:
+                send(
+                    "JustifiedFeed",
+                    {
+                        "request_id": event.request_id,
+                        "items": encode_justified(
+                            [(item, None) for item in feed]
+                        ),
+                    },
+                )
+            else:
+                send("Feed", {"request_id": event.request_id, "feed": feed})
+        else:
+            handler.handle(event)
+            if isinstance(event, Shutdown):
+                return
@@ -0,0 +1,368 @@
+"""the events exchanged between the simulation and shepherds
+
+these mirror `SimulationEvent` and `ShepherdEvent` in lib/src/shepherd.rs,
+and are only ever exchanged as json. `shepherd schema` prints the full json
+schema of either direction
+"""
+
+from __future__ import annotations
+
+from dataclasses import dataclass, field
+from enum import Enum
+from typing import Any, Optional, Union
+
+# the version of the protocol these events belong to, which is kept in step
+# with PROTOCOL_VERSION in lib/src/shepherd.rs
+PROTOCOL_VERSION = 12
+
+
+class Capability(str, Enum):
+    """an optional part of the protocol, declared during the handshake"""
+
+    # sending justified feeds in place of plain ones
+    JUSTIFIED_FEEDS = "JustifiedFeeds"
+
+    # receiving responses in batches rather than after every feed
+    RESPONSE_BATCHES = "ResponseBatches"
+
+    # receiving requests for the feeds of many sheep at once
+    FEED_BATCHES = "FeedBatches"
+
+    # answering pings while the simulation waits on a request
+    HEARTBEATS = "Heartbeats"
+
+    # retrieving candidates and ranking them in place of building feeds
+    TWO_STAGE_FEEDS = "TwoStageFeeds"
+
+
+class Payload(str, Enum):
+    """an optional part of the events sent to a shepherd, requested during
+    the handshake"""
+
+    ITEM_TAGS = "ItemTags"
+    TAG_GROUP_CHANGES = "TagGroupChanges"
+    GRAPH_DIFFS = "GraphDiffs"
+    TAG_GROUPS = "TagGroups"
+
+
+class Response(str, Enum):
+    """a sheep's response to an item of a feed"""
+
+    POSITIVE = "Positive"
+    NEUTRAL = "Neutral"
+    NEGATIVE = "Negative"
+
+
+@dataclass
+class Tag:
+    """a justification citing a tag as the reason for showing an item"""
+
+    tag: int
+
+
+@dataclass
+class SimilarItem:
+    """a justification citing a similar item as the reason for showing an
+    item"""
+
+    item: int
+
+
+Justification = Union[Tag, SimilarItem]
+
+
+@dataclass
+class GraphDiff:
+    """the changes made to the tags between two epochs"""
+
+    # (source, target, weight) for every added or reweighted edge
+    tag_edges: list[tuple[int, int, int]]
+    # (source, target) for every removed edge
+    removed_tag_edges: list[tuple[int, int]]
+    # (tag, group) for every tag which changed group, where the group is None
+    # if it no longer belongs to one
+    tag_groups: list[tuple[int, Optional[int]]]
+
+
+@dataclass
+class Rating:
+    """a sheep's response to an item, along with the number of epochs since
+    the item was introduced, the number of hops from the sheep to the item
+    (if it is reachable) and the position of the item within the feed"""
+
+    item: int
+    response: Response
+    age: Optional[int]
+    hops: Optional[int]
+    position: int
+
+
+@dataclass
+class ResponseCounts:
+    positive: int
+    neutral: int
+    negative: int
+
+
+@dataclass
+class Hello:
+    protocol_version: int
+    capabilities: list[Capability]
+    payloads: list[Payload]
+    encodings: list[str]
+
+
+@dataclass
+class RunConfig:
+    feed_size: int
+    n_epochs: Optional[int]
+
+
+@dataclass
+class BeginEpoch:
+    id: int
+    tags: list[int]
+    # (item, tags) for every new item, where the tags are empty unless
+    # Payload.ITEM_TAGS was requested
+    items: list[tuple[int, list[int]]]
+    diff: Optional[GraphDiff] = None
+
+
+@dataclass
+class SheepIntroduction:
+    sheep: int
+    associated_tags: list[int]
+
+
+@dataclass
+class FeedRequest:
+    request_id: int
+    sheep: int
+
+
+@dataclass
+class FeedRequestBatch:
+    request_id: int
+    sheep: list[int]
+
+
+@dataclass
+class RetrieveCandidates:
+    request_id: int
+    sheep: int
+    n_candidates: int
+
+
+@dataclass
+class RankCandidates:
+    request_id: int
+    sheep: int
+    candidates: list[int]
+
+
+@dataclass
+class FeedResponses:
+    sheep: int
+    # the epoch during which the feed was rated
+    epoch: int
+    responses: list[Rating]
+
+
+@dataclass
+class ResponsesBatch:
+    responses: list[FeedResponses]
+
+
+@dataclass
+class UnderDelivered:
+    sheep: int
+    n_missing: int
+
+
+@dataclass
+class BudgetExceeded:
+    sheep: int
+    items: list[int]
+
+
+@dataclass
+class TagGroupsMerged:
+    tags: tuple[list[int], list[int]]
+
+
+@dataclass
+class TagGroupSplit:
+    tags: tuple[list[int], list[int]]
+
+
+@dataclass
+class TagGroups:
+    groups: list[list[int]]
+
+
+@dataclass
+class EpochSummary:
+    id: int
+    sheep_stats: dict[int, ResponseCounts]
+
+
+@dataclass
+class EndEpoch:
+    id: int
+
+
+@dataclass
+class Shutdown:
+    pass
+
+
+@dataclass
+class Ping:
+    pass
+
+
+@dataclass
+class Unknown:
+    """an event this version of the helper doesn't know about"""
+
+    kind: str
+    data: Any = field(default=None)
+
+
+SimulationEvent = Union[
+    Hello,
+    RunConfig,
+    BeginEpoch,
+    SheepIntroduction,
+    FeedRequest,
+    FeedRequestBatch,
+    RetrieveCandidates,
+    RankCandidates,
+    FeedResponses,
+    ResponsesBatch,
+    UnderDelivered,
+    BudgetExceeded,
+    TagGroupsMerged,
+    TagGroupSplit,
+    TagGroups,
+    EpochSummary,
+    EndEpoch,
+    Shutdown,
+    Ping,
+    Unknown,
+]
+
+
+def _feed_responses(data: dict) -> FeedResponses:
+    return FeedResponses(
+        sheep=data["sheep"],
+        epoch=data["epoch"],
+        responses=[
+            Rating(item, Response(response), age, hops, position)
+            for item, response, age, hops, position in data["responses"]
+        ],
+    )
+
+
+def decode(event: dict) -> SimulationEvent:
+    """convert an event sent by the simulation from its json form"""
+
+    kind, data = event["kind"], event.get("data")
+    if kind == "Hello":
+        return Hello(
+            protocol_version=data["protocol_version"],
+            capabilities=[
+                Capability(capability)
+                for capability in data["capabilities"]
+                if capability in Capability._value2member_map_
+            ],
+            payloads=[
+                Payload(payload)
+                for payload in data["payloads"]
+                if payload in Payload._value2member_map_
+            ],
+            encodings=data["encodings"],
+        )
+    if kind == "RunConfig":
+        return RunConfig(data["feed_size"], data.get("n_epochs"))
+    if kind == "BeginEpoch":
+        epoch = data["data"]
+        diff = epoch.get("diff")
+        return BeginEpoch(
+            id=data["id"],
+            tags=epoch["tags"],
+            items=[(item, tags) for item, tags in epoch["items"]],
+            diff=diff
+            and GraphDiff(
+                tag_edges=[tuple(edge) for edge in diff["tag_edges"]],
+                removed_tag_edges=[
+                    tuple(edge) for edge in diff["removed_tag_edges"]
+                ],
+                tag_groups=[tuple(change) for change in diff["tag_groups"]],
+            ),
+        )
+    if kind == "SheepIntroduction":
+        return SheepIntroduction(data["sheep"], data["associated_tags"])
+    if kind == "FeedRequest":
+        return FeedRequest(data["request_id"], data["sheep"])
+    if kind == "FeedRequestBatch":
+        return FeedRequestBatch(data["request_id"], data["sheep"])
+    if kind == "RetrieveCandidates":
+        return RetrieveCandidates(
+            data["request_id"], data["sheep"], data["n_candidates"]
+        )
+    if kind == "RankCandidates":
+        return RankCandidates(
+            data["request_id"], data["sheep"], data["candidates"]
+        )
+    if kind == "FeedResponses":
+        return _feed_responses(data)
+    if kind == "ResponsesBatch":
+        return ResponsesBatch(
+            [
+                _feed_responses(
+                    {"sheep": sheep, "epoch": epoch, "responses": responses}
+                )
+                for sheep, epoch, responses in data["responses"]
+            ]
+        )
+    if kind == "UnderDelivered":
+        return UnderDelivered(data["sheep"], data["n_missing"])
+    if kind == "BudgetExceeded":
+        return BudgetExceeded(data["sheep"], data["items"])
+    if kind == "TagGroupsMerged":
+        return TagGroupsMerged(tuple(data["tags"]))
+    if kind == "TagGroupSplit":
+        return TagGroupSplit(tuple(data["tags"]))
+    if kind == "TagGroups":
+        return TagGroups(data["groups"])
+    if kind == "EpochSummary":
+        return EpochSummary(
+            id=data["id"],
+            sheep_stats={
+                sheep: ResponseCounts(**counts)
+                for sheep, counts in data["sheep_stats"]
+            },
+        )
+    if kind == "EndEpoch":
+        return EndEpoch(data["id"])
+    if kind == "Shutdown":
+        return Shutdown()
+    if kind == "Ping":
+        return Ping()
+    return Unknown(kind, data)
+
+
+def encode_justified(
+    items: list[tuple[int, Optional[Justification]]],
+) -> list:
+    """convert the items of a justified feed into their json form"""
+
+    def justification(reason: Optional[Justification]) -> Optional[dict]:
+        if isinstance(reason, Tag):
+            return {"kind": "Tag", "data": reason.tag}
+        if isinstance(reason, SimilarItem):
+            return {"kind": "SimilarItem", "data": reason.item}
+        return None
+
+    return [[item, justification(reason)] for item, reason in items]